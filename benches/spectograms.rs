@@ -0,0 +1,72 @@
+// Benchmarks of the STFT and the comparisons; Run with `cargo bench`. The tracks are synthetic and built in memory,
+// so that decoding and file I/O don't show up in the numbers.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+
+use speccomp::types::*;
+use speccomp::spectograms::*;
+
+const SAMPLE_RATE: u32 = 44100;
+
+// Interleaved stereo track of `seconds` seconds: a chord of three sines, slightly different in each channel
+fn synthetic_track(seconds: f32) -> TrackBuffer {
+    let frames: usize = (seconds * SAMPLE_RATE as f32) as usize;
+    let mut track: TrackBuffer = Vec::with_capacity(2 * frames);
+    for i in 0..frames {
+        let t: f32 = i as f32 / SAMPLE_RATE as f32;
+        let chord: f32 = [220.0f32, 277.2, 329.6].iter().map(|f| (2.0 * std::f32::consts::PI * f * t).sin()).sum::<f32>() / 3.0;
+        track.push(0.5 * chord);
+        track.push(0.4 * chord + 0.1 * (2.0 * std::f32::consts::PI * 440.0 * t).sin());
+    }
+    track
+}
+
+// STFT ------------------------------------------------------------------------------------------------------------------
+fn bench_track_to_spec(c: &mut Criterion) {
+    let mut group = c.benchmark_group("track_to_spec");
+    group.sample_size(10);
+    for seconds in [10.0f32, 60.0] {
+        let track: TrackBuffer = synthetic_track(seconds);
+        for fft_size in [1024u32, 4096, 16384] {
+            group.bench_with_input(BenchmarkId::new(format!("{}s", seconds), fft_size), &fft_size, |b, fft_size| {
+                b.iter(|| track_to_spec(*fft_size, WindowFunction::Hann, black_box(&track)))
+            });
+        }
+    }
+    group.finish();
+}
+
+// Comparisons -----------------------------------------------------------------------------------------------------------
+fn bench_compare(c: &mut Criterion) {
+    let fft_size: u32 = 4096;
+    let bins: u32 = fft_size / 2;
+    let track: TrackBuffer = synthetic_track(60.0);
+    let spec_a: StereoSpectogram = track_to_spec(fft_size, WindowFunction::Hann, &track);
+    let spec_b: StereoSpectogram = track_to_spec(fft_size, WindowFunction::Hamming, &track);
+
+    let mut group = c.benchmark_group("compare_60s");
+    group.bench_function("time_compare_spectogram", |b| {
+        b.iter(|| time_compare_spectogram(bins, black_box(&spec_a), black_box(&spec_b)).unwrap())
+    });
+    group.bench_function("freq_compare_spectogram", |b| {
+        b.iter(|| freq_compare_spectogram(bins, black_box(&spec_a), black_box(&spec_b)).unwrap())
+    });
+    // Both channels on their own, relative errors
+    group.bench_function("time_compare_channels", |b| {
+        b.iter(|| time_compare_channels(bins, black_box(&spec_a), black_box(&spec_b), ChannelMode::Independent, ErrorScale::Relative).unwrap())
+    });
+    group.bench_function("freq_compare_channels", |b| {
+        b.iter(|| freq_compare_channels(bins, SAMPLE_RATE, black_box(&spec_a), black_box(&spec_b), ChannelMode::Independent, FrequencyWeighting::Cosine, ErrorScale::Relative).unwrap())
+    });
+    group.finish();
+}
+
+fn benches(c: &mut Criterion) {
+    // The STFT and the comparisons print their progress
+    set_verbosity(Verbosity::Quiet);
+    bench_track_to_spec(c);
+    bench_compare(c);
+}
+
+criterion_group!(spectograms, benches);
+criterion_main!(spectograms);
@@ -0,0 +1,558 @@
+use crate::types::*;
+use crate::error::SpecCompError;
+use crate::workers::*;
+use crate::spectograms::{WindowFunction, ChannelMode, ErrorScale, FrameMismatch, FrequencyWeighting, DEFAULT_DB_FLOOR};
+use crate::graphs::{Colormap, DEFAULT_PLOT_WIDTH};
+use crate::results::{MAX_PRECISION, Threshold, ThresholdMetric};
+use crate::importerts::{check_stem_pattern, stem_display_name, STEM_COUNT, STEM_NAMES};
+
+// Settings of a run; `Config::default()` gives the settings used when no flags are passed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    // Positional arguments: the two sources to compare, or a reference and several candidates (none in batch mode)
+    pub sources: Vec<String>,
+    // For testing purposes, serial execution is available and enabled with the "--serial" flag
+    pub in_parallel: bool,
+    // Comparisons can run on Mel-scaled spectograms instead of linear bins
+    pub use_mel: bool,
+    // Upper limit of threads running at the same time
+    pub threads: usize,
+    // Also transform the two channels of each track in parallel (parallel execution only)
+    pub split_channels: bool,
+    // Number of samples per STFT frame; Each frame has `fft_size / 2` bins. Always even, so that the bins are exactly
+    // `sample_rate / fft_size` Hz wide wherever the frequency of a bin is worked out from the number of bins
+    pub fft_size: u32,
+    // Window applied to every STFT frame
+    pub window: WindowFunction,
+    // Whether the channels are averaged, compared independently or the worse one is kept
+    pub channel_mode: ChannelMode,
+    // Whether bins are compared by their difference, by their difference relative to the first source or in dB
+    pub error_scale: ErrorScale,
+    // What to do with the frames only the longer spectogram of a pair has
+    pub frame_mismatch: FrameMismatch,
+    // Weighting of the bins in the frequency comparison
+    pub weighting: FrequencyWeighting,
+    // Weight of each stem (Bass, Drums, Vocals, Other) in the totals
+    pub stem_weights: Vec<f32>,
+    // Optional section of the tracks to compare, in seconds
+    pub range: Option<(f32, f32)>,
+    // FFT sizes of the multi-resolution comparison and the weight of each; No such comparison if empty
+    pub resolutions: Vec<u32>,
+    pub resolution_weights: Vec<f32>,
+    // Only decode the first seconds of every track; `compare_tracks()` also shortens tracks that were passed from memory
+    pub limit_duration: Option<f32>,
+    // Where the stems are in a source directory, e.g. "{stem}/*.wav"; The flat layout (`bass.mp3`...) if None
+    pub stem_pattern: Option<String>,
+    // Share of the time total in the combined score (the rest goes to the frequency total); No score if None
+    pub alpha: Option<f32>,
+    // Print the final results as a Markdown table
+    pub markdown: bool,
+    // Manifest of source pairs to compare in batch mode
+    pub batch: Option<String>,
+    // Compare two live PCM streams instead of two sets of stems
+    pub live: bool,
+    // Only save the spectogram of every stem of a single source to this directory, without comparing anything
+    pub spectogram_only: Option<String>,
+    // Samples between the starts of two frames in live mode; None uses the FFT size (no overlap, as in batch runs)
+    pub hop: Option<u32>,
+    // Pad shorter tracks with silence so that every track has the length of the longest one
+    pub pad: bool,
+    // Scale every track to the same RMS level, so that only the spectral shape is compared
+    pub normalize: bool,
+    // Give every spectogram frame the same total power, so that only the spectral balance is compared
+    pub normalize_frames: bool,
+    // Invert the stems of the second source whose polarity is opposite to the first source's
+    pub fix_polarity: bool,
+    // Samples (per channel) to skip at the start of tracks whose metadata doesn't give the encoder delay
+    pub encoder_delay: Option<usize>,
+    // How many frames away a frame may be matched by the jitter tolerant time comparison; 0 disables it
+    pub jitter: usize,
+    // Width (in frames) of the band of the time warping comparison; 0 disables it
+    pub warp: usize,
+    // Remove leading and trailing samples below this amplitude before the comparison
+    pub trim_silence: bool,
+    // Amplitude (linear, full scale is 1.0) below which samples count as silent, for every silence-related option
+    pub silence_threshold: f32,
+    // Source holding the ground truth for asymmetric metrics (SNR); Defaults to the first source
+    pub reference: Option<String>,
+    // Directory the plots are saved to; No plots are made if None
+    pub plot: Option<String>,
+    // Width (in frames) of the moving average drawn in the time error plot; 0 or 1 plots the raw errors
+    pub smooth: usize,
+    // Also plot the spectograms of every stem (and their difference) along with the error plots
+    pub plot_spectograms: bool,
+    // Also save the spectogram of every stem as a .csv file next to the plots
+    pub export_spectograms: bool,
+    // Directory the per-frame and per-bin errors of every stem are saved to as .npy files; None saves nothing
+    pub npy: Option<String>,
+    // Largest width of a spectogram plot in pixels; Longer spectograms are downsampled to fit
+    pub plot_width: u32,
+    // Colormap of the spectogram plots
+    pub colormap: Colormap,
+    // Decimals of the printed results and the .csv files; None keeps the default of every table
+    pub precision: Option<usize>,
+    // Largest acceptable mean errors; The program exits with an error code if any of them is exceeded
+    pub thresholds: Vec<Threshold>,
+    // Only probe the stems of every source, without comparing anything
+    pub check: bool,
+    // How much is printed to the console
+    pub verbosity: Verbosity
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            sources: vec![],
+            in_parallel: true,
+            use_mel: false,
+            threads: default_thread_count(),
+            split_channels: false,
+            fft_size: 4096,
+            window: WindowFunction::Hann,
+            channel_mode: ChannelMode::Averaged,
+            error_scale: ErrorScale::Absolute,
+            frame_mismatch: FrameMismatch::Truncate,
+            weighting: FrequencyWeighting::Cosine,
+            stem_weights: vec![1.0; STEM_COUNT],
+            range: Option::None,
+            resolutions: vec![],
+            resolution_weights: vec![],
+            limit_duration: Option::None,
+            stem_pattern: Option::None,
+            alpha: Option::None,
+            markdown: false,
+            batch: Option::None,
+            live: false,
+            spectogram_only: Option::None,
+            hop: Option::None,
+            pad: false,
+            normalize: false,
+            normalize_frames: false,
+            fix_polarity: false,
+            trim_silence: false,
+            silence_threshold: dbfs_to_amplitude(DEFAULT_SILENCE_DBFS),
+            jitter: 0,
+            warp: 0,
+            encoder_delay: Option::None,
+            reference: Option::None,
+            plot: Option::None,
+            smooth: 0,
+            plot_spectograms: false,
+            export_spectograms: false,
+            npy: Option::None,
+            plot_width: DEFAULT_PLOT_WIDTH,
+            colormap: Colormap::Viridis,
+            precision: Option::None,
+            thresholds: vec![],
+            check: false,
+            verbosity: Verbosity::Normal
+        }
+    }
+}
+
+impl Config {
+    // Parses the command line; `args` includes the program's name, like `std::env::args()`.
+    // Sources are positional, everything starting with "--" is an optional flag. Fails on unknown flags, missing or
+    // invalid flag values and a wrong number of sources (two, or none in batch mode).
+    pub fn from_args(args: &[String]) -> Result<Config, SpecCompError> {
+        let mut config = Config::default();
+
+        let mut a: usize = 1;
+        while a < args.len() {
+            match args[a].as_str() {
+                "--serial"  => { config.in_parallel = false; }
+                "--mel"     => { config.use_mel = true; }
+                "--pad"     => { config.pad = true; }
+                "--normalize" => { config.normalize = true; }
+                "--normalize-frames" => { config.normalize_frames = true; }
+                "--fix-polarity" => { config.fix_polarity = true; }
+                "--markdown" => { config.markdown = true; }
+                "--live"    => { config.live = true; }
+                "--relative" => { config.error_scale = ErrorScale::Relative; }
+                "--check"   => { config.check = true; }
+                "--split-channels" => { config.split_channels = true; }
+                "--plot-spectograms" => { config.plot_spectograms = true; }
+                "--export-spectograms" => { config.export_spectograms = true; }
+                "--plot"    => {
+                    a += 1;
+                    config.plot = match args.get(a) {
+                        Some(d) => { Option::Some(d.clone()) }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--plot expects a directory."))); }
+                    };
+                }
+                "--npy"     => {
+                    a += 1;
+                    config.npy = match args.get(a) {
+                        Some(d) => { Option::Some(d.clone()) }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--npy expects a directory."))); }
+                    };
+                }
+                "--smooth"  => {
+                    a += 1;
+                    config.smooth = match args.get(a).and_then(|n| n.parse::<usize>().ok()) {
+                        Some(n) => { n }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--smooth expects a number of frames."))); }
+                    };
+                }
+                "--plot-width" => {
+                    a += 1;
+                    config.plot_width = match args.get(a).and_then(|n| n.parse::<u32>().ok()) {
+                        Some(n) if n > 0 => { n }
+                        _ => { return Result::Err(SpecCompError::InvalidArgument(String::from("--plot-width expects a positive number of pixels."))); }
+                    };
+                }
+                "--colormap" => {
+                    a += 1;
+                    config.colormap = match args.get(a).and_then(|c| Colormap::from_name(c)) {
+                        Some(c) => { c }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--colormap expects viridis, magma, grayscale or mandelbrot."))); }
+                    };
+                }
+                "--precision" => {
+                    a += 1;
+                    config.precision = match args.get(a).and_then(|n| n.parse::<usize>().ok()) {
+                        Some(n) if n <= MAX_PRECISION => { Option::Some(n) }
+                        _ => { return Result::Err(SpecCompError::InvalidArgument(format!("--precision expects a number of decimals from 0 to {}.", MAX_PRECISION))); }
+                    };
+                }
+                "--threshold" => {
+                    a += 1;
+                    match args.get(a).and_then(|t| parse_thresholds(t)) {
+                        Some(t) => { config.thresholds.extend(t); }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--threshold expects comma separated metric=limit pairs, e.g. time=0.05,freq=0.03 or vocals.time=0.02 (metrics: time, freq)."))); }
+                    };
+                }
+                "--quiet"   => { config.verbosity = Verbosity::Quiet; }
+                "--verbose" => { config.verbosity = Verbosity::Verbose; }
+                "--threads" => {
+                    a += 1;
+                    config.threads = match args.get(a).and_then(|n| n.parse::<usize>().ok()) {
+                        Some(n) if n > 0 => { n }
+                        _ => { return Result::Err(SpecCompError::InvalidArgument(String::from("--threads expects a positive number."))); }
+                    };
+                }
+                "--fft-size" => {
+                    a += 1;
+                    config.fft_size = match args.get(a).and_then(|n| n.parse::<u32>().ok()) {
+                        Some(n) if n >= 2 && n.is_multiple_of(2) => { n }
+                        _ => { return Result::Err(SpecCompError::InvalidArgument(String::from("--fft-size expects an even number of samples (ideally a power of two)."))); }
+                    };
+                }
+                "--hop"     => {
+                    a += 1;
+                    config.hop = match args.get(a).and_then(|n| n.parse::<u32>().ok()) {
+                        Some(n) if n > 0 => { Option::Some(n) }
+                        _ => { return Result::Err(SpecCompError::InvalidArgument(String::from("--hop expects a positive number of samples."))); }
+                    };
+                }
+                "--resolutions" => {
+                    a += 1;
+                    config.resolutions = match args.get(a).and_then(|s| s.split(',').map(|n| n.trim().parse::<u32>().ok()).collect::<Option<Vec<u32>>>()) {
+                        Some(r) if !r.is_empty() && r.iter().all(|n| *n >= 2 && n.is_multiple_of(2)) => { r }
+                        _ => { return Result::Err(SpecCompError::InvalidArgument(String::from("--resolutions expects comma separated even FFT sizes."))); }
+                    };
+                }
+                "--resolution-weights" => {
+                    a += 1;
+                    config.resolution_weights = match args.get(a).and_then(|s| s.split(',').map(|w| w.trim().parse::<f32>().ok()).collect::<Option<Vec<f32>>>()) {
+                        Some(w) if w.iter().all(|w| *w >= 0.0 && w.is_finite()) => { w }
+                        _ => { return Result::Err(SpecCompError::InvalidArgument(String::from("--resolution-weights expects comma separated, non-negative weights."))); }
+                    };
+                }
+                "--window"  => {
+                    a += 1;
+                    config.window = match args.get(a).and_then(|w| WindowFunction::from_name(w)) {
+                        Some(w) => { w }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--window expects hann, hann:symmetric, hann:legacy, hamming, tukey or tukey:alpha (0 <= alpha <= 1)."))); }
+                    };
+                }
+                "--channels" => {
+                    a += 1;
+                    config.channel_mode = match args.get(a).and_then(|m| ChannelMode::from_name(m)) {
+                        Some(m) => { m }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--channels expects averaged, independent or max."))); }
+                    };
+                }
+                "--frame-mismatch" => {
+                    a += 1;
+                    config.frame_mismatch = match args.get(a).and_then(|m| FrameMismatch::from_name(m)) {
+                        Some(m) => { m }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--frame-mismatch expects truncate or extend."))); }
+                    };
+                }
+                "--weighting" => {
+                    a += 1;
+                    config.weighting = match args.get(a).and_then(|w| FrequencyWeighting::from_name(w)) {
+                        Some(w) => { w }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--weighting expects cosine or a."))); }
+                    };
+                }
+                "--weights" => {
+                    a += 1;
+                    config.stem_weights = match args.get(a).and_then(|w| parse_weights(w)) {
+                        Some(w) => { w }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(format!("--weights expects {} comma separated, non-negative weights that don't all equal 0.", STEM_COUNT))); }
+                    };
+                }
+                "--encoder-delay" => {
+                    a += 1;
+                    config.encoder_delay = match args.get(a).and_then(|d| d.parse::<usize>().ok()) {
+                        Some(d) => { Option::Some(d) }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--encoder-delay expects a number of samples."))); }
+                    };
+                }
+                "--alpha"   => {
+                    a += 1;
+                    config.alpha = match args.get(a).and_then(|n| n.parse::<f32>().ok()) {
+                        Some(n) if (0.0..=1.0).contains(&n) => { Option::Some(n) }
+                        _ => { return Result::Err(SpecCompError::InvalidArgument(String::from("--alpha expects a number between 0 and 1."))); }
+                    };
+                }
+                "--jitter"  => {
+                    a += 1;
+                    config.jitter = match args.get(a).and_then(|n| n.parse::<usize>().ok()) {
+                        Some(n) => { n }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--jitter expects a number of frames."))); }
+                    };
+                }
+                "--warp" => {
+                    a += 1;
+                    config.warp = match args.get(a).and_then(|n| n.parse::<usize>().ok()) {
+                        Some(n) => { n }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--warp expects a number of frames."))); }
+                    };
+                }
+                "--db"      => {
+                    // The floor may follow, e.g. "--db -50"
+                    let mut floor_db: f32 = DEFAULT_DB_FLOOR;
+                    if let Some(f) = args.get(a + 1).and_then(|f| f.parse::<f32>().ok()) {
+                        if !f.is_finite() {
+                            return Result::Err(SpecCompError::InvalidArgument(String::from("--db expects a finite floor in dB.")));
+                        }
+                        floor_db = f;
+                        a += 1;
+                    }
+                    config.error_scale = ErrorScale::Decibel { floor_db };
+                }
+                "--trim-silence" => {
+                    config.trim_silence = true;
+                    // A linear threshold may still follow, as in older versions
+                    if let Some(t) = args.get(a + 1).and_then(|t| t.parse::<f32>().ok()) {
+                        if t < 0.0 {
+                            return Result::Err(SpecCompError::InvalidArgument(String::from("--trim-silence expects a non-negative threshold.")));
+                        }
+                        config.silence_threshold = t;
+                        a += 1;
+                    }
+                }
+                "--silence-threshold" => {
+                    a += 1;
+                    config.silence_threshold = match args.get(a).and_then(|t| t.parse::<f32>().ok()) {
+                        Some(db) if db.is_finite() && db <= 0.0 => { dbfs_to_amplitude(db) }
+                        _ => { return Result::Err(SpecCompError::InvalidArgument(String::from("--silence-threshold expects a level in dBFS (0 or less)."))); }
+                    };
+                }
+                "--limit-duration" => {
+                    a += 1;
+                    config.limit_duration = match args.get(a).and_then(|s| s.parse::<f32>().ok()) {
+                        Some(s) if s > 0.0 && s.is_finite() => { Option::Some(s) }
+                        _ => { return Result::Err(SpecCompError::InvalidArgument(String::from("--limit-duration expects a positive number of seconds."))); }
+                    };
+                }
+                "--stem-pattern" => {
+                    a += 1;
+                    config.stem_pattern = match args.get(a) {
+                        Some(p) if check_stem_pattern(p).is_ok() => { Option::Some(p.clone()) }
+                        _ => { return Result::Err(SpecCompError::InvalidArgument(String::from("--stem-pattern expects a path inside the source directory containing {stem}, e.g. {stem}/*.wav."))); }
+                    };
+                }
+                "--range"   => {
+                    a += 1;
+                    config.range = match args.get(a).and_then(|r| parse_range(r)) {
+                        Some(r) => { Option::Some(r) }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--range expects start_sec:end_sec."))); }
+                    };
+                }
+                "--reference" => {
+                    a += 1;
+                    config.reference = match args.get(a) {
+                        Some(r) => { Option::Some(r.clone()) }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--reference expects one of the sources."))); }
+                    };
+                }
+                "--spectogram-only" | "--spectrogram-only" => {
+                    a += 1;
+                    config.spectogram_only = match args.get(a) {
+                        Some(d) => { Option::Some(d.clone()) }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--spectogram-only expects a directory."))); }
+                    };
+                }
+                "--batch"   => {
+                    a += 1;
+                    config.batch = match args.get(a) {
+                        Some(m) => { Option::Some(m.clone()) }
+                        None    => { return Result::Err(SpecCompError::InvalidArgument(String::from("--batch expects a manifest file."))); }
+                    };
+                }
+                f if f.starts_with("--") => { return Result::Err(SpecCompError::InvalidArgument(format!("Unknown option: {}", f))); }
+                source => { config.sources.push(String::from(source)); }
+            }
+            a += 1;
+        }
+
+        // Two sources (or a reference and more candidates), two live streams, a manifest or a single source to plot
+        let source_count: usize = config.sources.len();
+        match (config.spectogram_only.is_some(), config.batch.is_some(), config.live) {
+            (true, true, _) | (true, _, true) => {
+                return Result::Err(SpecCompError::InvalidArgument(String::from("--spectogram-only can't be combined with --batch or --live.")));
+            }
+            (true, false, false) if source_count != 1 => {
+                return Result::Err(SpecCompError::InvalidArgument(format!("Expected 1 source, got {}.", source_count)));
+            }
+            (false, true, _) if source_count != 0 => {
+                return Result::Err(SpecCompError::InvalidArgument(format!("Expected 0 sources, got {}.", source_count)));
+            }
+            (false, false, true) if source_count != 2 => {
+                return Result::Err(SpecCompError::InvalidArgument(format!("Expected 2 sources, got {}.", source_count)));
+            }
+            (false, false, false) if source_count < 2 => {
+                return Result::Err(SpecCompError::InvalidArgument(format!("Expected at least 2 sources, got {}.", source_count)));
+            }
+            _ => {}
+        }
+
+        // Plain grayscale spectograms unless asked otherwise
+        if config.spectogram_only.is_some() && !args.iter().any(|a| a == "--colormap") {
+            config.colormap = Colormap::Grayscale;
+        }
+
+        if config.live && config.batch.is_some() {
+            return Result::Err(SpecCompError::InvalidArgument(String::from("--live can't be combined with --batch.")));
+        }
+        if let Some(hop) = config.hop {
+            if !config.live {
+                return Result::Err(SpecCompError::InvalidArgument(String::from("--hop needs --live.")));
+            }
+            if hop > config.fft_size {
+                return Result::Err(SpecCompError::InvalidArgument(format!("--hop can't be larger than the FFT size ({}).", config.fft_size)));
+            }
+        }
+
+        if config.smooth > 1 && config.plot.is_none() {
+            return Result::Err(SpecCompError::InvalidArgument(String::from("--smooth needs --plot.")));
+        }
+        if config.plot_spectograms && config.plot.is_none() {
+            return Result::Err(SpecCompError::InvalidArgument(String::from("--plot-spectograms needs --plot.")));
+        }
+        if config.export_spectograms && config.plot.is_none() {
+            return Result::Err(SpecCompError::InvalidArgument(String::from("--export-spectograms needs --plot.")));
+        }
+
+        // One weight per FFT size; Without weights every size counts the same
+        if !config.resolution_weights.is_empty() && config.resolution_weights.len() != config.resolutions.len() {
+            return Result::Err(SpecCompError::InvalidArgument(format!("--resolution-weights expects one weight for each of the {} sizes of --resolutions.", config.resolutions.len())));
+        }
+        if config.resolution_weights.is_empty() && !config.resolutions.is_empty() {
+            config.resolution_weights = vec![1.0 / config.resolutions.len() as f32; config.resolutions.len()];
+        }
+
+        if args.iter().any(|a| a == "--relative") && args.iter().any(|a| a == "--db") {
+            return Result::Err(SpecCompError::InvalidArgument(String::from("--relative can't be combined with --db.")));
+        }
+
+        // A-weighting needs linear bins
+        if config.use_mel && config.weighting == FrequencyWeighting::A {
+            return Result::Err(SpecCompError::InvalidArgument(String::from("--weighting a can't be combined with --mel.")));
+        }
+
+        Result::Ok(config)
+    }
+}
+
+// Parses "w1,w2,w3,w4"; There has to be one weight per stem
+fn parse_weights(s: &str) -> Option<Vec<f32>> {
+    let weights: Vec<f32> = s.split(',').map(|w| w.trim().parse::<f32>().ok()).collect::<Option<Vec<f32>>>()?;
+    let valid: bool = weights.len() == STEM_COUNT && weights.iter().all(|w| *w >= 0.0 && w.is_finite()) && weights.iter().sum::<f32>() > 0.0;
+    match valid {
+        true  => { Option::Some(weights) }
+        false => { Option::None }
+    }
+}
+
+// Parses "time=0.05,freq=0.03,vocals.time=0.02"; A metric may be prefixed with a stem to only apply to that stem,
+// and every limit has to be non-negative
+fn parse_thresholds(s: &str) -> Option<Vec<Threshold>> {
+    let mut thresholds: Vec<Threshold> = vec![];
+    for pair in s.split(',') {
+        let (key, limit) = pair.split_once('=')?;
+        let (stem, metric) = match key.trim().split_once('.') {
+            Some((stem, metric)) => {
+                let stem: &str = STEM_NAMES.iter().find(|n| n.eq_ignore_ascii_case(stem))?;
+                (Option::Some(stem_display_name(stem)), metric)
+            }
+            None => { (Option::None, key.trim()) }
+        };
+        let limit: f32 = limit.trim().parse::<f32>().ok().filter(|l| *l >= 0.0 && l.is_finite())?;
+        thresholds.push(Threshold { stem, metric: ThresholdMetric::from_name(metric)?, limit });
+    }
+    Option::Some(thresholds)
+}
+
+// Parses "start_sec:end_sec"
+fn parse_range(s: &str) -> Option<(f32, f32)> {
+    let (start, end) = s.split_once(':')?;
+    Option::Some((start.parse::<f32>().ok()?, end.parse::<f32>().ok()?))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Config, SpecCompError> {
+        let args: Vec<String> = std::iter::once("speccomp").chain(args.split_whitespace()).map(String::from).collect();
+        Config::from_args(&args)
+    }
+
+    #[test]
+    fn every_field_parses() {
+        let config = parse("a b --serial --mel --threads 3 --fft-size 1024 --window hann:symmetric --channels independent \
+            --frame-mismatch extend --weights 1,2,3,4 --range 1.5:9 --limit-duration 30 --stem-pattern {stem}/*.wav \
+            --jitter 2 --plot out --smooth 5 --precision 6 --threshold vocals.time=0.02,freq=0.1 --quiet").unwrap();
+
+        assert_eq!(config.sources, vec![String::from("a"), String::from("b")]);
+        assert!(!config.in_parallel);
+        assert!(config.use_mel);
+        assert_eq!(config.threads, 3);
+        assert_eq!(config.fft_size, 1024);
+        assert_eq!(config.window, WindowFunction::HannSymmetric);
+        assert_eq!(config.channel_mode, ChannelMode::Independent);
+        assert_eq!(config.frame_mismatch, FrameMismatch::ZeroExtend);
+        assert_eq!(config.stem_weights, vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(config.range, Option::Some((1.5, 9.0)));
+        assert_eq!(config.limit_duration, Option::Some(30.0));
+        assert_eq!(config.stem_pattern.as_deref(), Option::Some("{stem}/*.wav"));
+        assert_eq!(config.jitter, 2);
+        assert_eq!(config.plot.as_deref(), Option::Some("out"));
+        assert_eq!(config.smooth, 5);
+        assert_eq!(config.precision, Option::Some(6));
+        assert_eq!(config.verbosity, Verbosity::Quiet);
+        assert_eq!(config.thresholds, vec![
+            Threshold { stem: Option::Some(String::from("Vocals")), metric: ThresholdMetric::Time, limit: 0.02 },
+            Threshold { stem: Option::None, metric: ThresholdMetric::Freq, limit: 0.1 }
+        ]);
+
+        // Everything else keeps its default
+        let defaults = Config::default();
+        assert_eq!(config.error_scale, defaults.error_scale);
+        assert_eq!(config.weighting, defaults.weighting);
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        for args in ["a b --frobnicate", "a b --fft-size 1023", "a b --threads 0", "a b --weights 1,2,3", "a b --window square",
+                     "a b --stem-pattern ../{stem}.wav", "a b --smooth 3", "a b --relative --db", "a b --limit-duration -1", "a"] {
+            assert!(matches!(parse(args), Err(SpecCompError::InvalidArgument(_))), "{}", args);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::fmt;
+
+// Errors returned by the library; Every variant but MissingStems and DuplicateStem holds a message that already says
+// which function failed, e.g. "import_from_file(): Could not open bass.mp3."
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpecCompError {
+    // A file or directory couldn't be opened, read or written
+    Io(String),
+    // The container or codec isn't supported
+    UnsupportedFormat(String),
+    // A directory or archive doesn't contain every stem; `missing` lists the stems that weren't found (e.g. "bass.*")
+    MissingStems { location: String, found: usize, expected: usize, missing: Vec<String> },
+    // A directory or archive contains more than one file for the same stem
+    DuplicateStem { location: String, stem: String },
+    // Sizes that have to agree don't, e.g. the number of bins and the length of a spectogram
+    DimensionMismatch(String),
+    // An input has nothing to work with
+    EmptyInput(String),
+    // A file could be opened but not decoded
+    Decode(String),
+    // A parameter or command line argument is out of range
+    InvalidArgument(String),
+    // The plotting backend failed
+    Plot(String)
+}
+
+impl fmt::Display for SpecCompError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpecCompError::MissingStems { location, found, expected, missing } => {
+                write!(f, "import_from_directory(): Could not find all separated stems in {} (found {}/{}, missing: {})",
+                    location, found, expected, missing.join(", "))
+            }
+            SpecCompError::DuplicateStem { location, stem } => {
+                write!(f, "import_from_directory(): Found more than one file for {} in {}", stem, location)
+            }
+            SpecCompError::Io(m) | SpecCompError::UnsupportedFormat(m) | SpecCompError::DimensionMismatch(m) |
+            SpecCompError::EmptyInput(m) | SpecCompError::Decode(m) | SpecCompError::InvalidArgument(m) |
+            SpecCompError::Plot(m) => { write!(f, "{}", m) }
+        }
+    }
+}
+
+impl std::error::Error for SpecCompError {}
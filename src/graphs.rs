@@ -0,0 +1,546 @@
+use crate::types::*;
+use crate::error::SpecCompError;
+use crate::spectograms::{bin_frequencies, frame_timestamp};
+use std::cmp::min;
+use std::path::Path;
+
+// Plotting
+use plotters::prelude::*;
+use plotters::coord::Shift;
+use plotters::style::colors::colormaps::ViridisRGB;
+
+// Colormaps for spectogram images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Grayscale,
+    MandelbrotHSL
+}
+
+impl Colormap {
+    // Parses the name given on the command line
+    pub fn from_name(name: &str) -> Option<Colormap> {
+        match name.to_lowercase().as_str() {
+            "viridis"    => { Option::Some(Colormap::Viridis) }
+            "magma"      => { Option::Some(Colormap::Magma) }
+            "grayscale"  => { Option::Some(Colormap::Grayscale) }
+            "mandelbrot" => { Option::Some(Colormap::MandelbrotHSL) }
+            _ => { Option::None }
+        }
+    }
+
+    // Color of a normalized magnitude in [0, 1]
+    pub fn color(&self, value: f32) -> RGBColor {
+        let value = value.clamp(0.0, 1.0);
+        match self {
+            Colormap::Viridis => { ViridisRGB::get_color(value) }
+            Colormap::Magma => {
+                // A few points of matplotlib's magma, linearly interpolated
+                let anchors: [(f32, f32, f32); 5] = [
+                    (0.0, 0.0, 4.0), (81.0, 18.0, 124.0), (183.0, 55.0, 121.0), (252.0, 137.0, 97.0), (252.0, 253.0, 191.0)
+                ];
+                let position: f32 = value * (anchors.len() - 1) as f32;
+                let i: usize = (position as usize).min(anchors.len() - 2);
+                let t: f32 = position - i as f32;
+                let (a, b) = (anchors[i], anchors[i+1]);
+                RGBColor(
+                    (a.0 + (b.0 - a.0) * t) as u8,
+                    (a.1 + (b.1 - a.1) * t) as u8,
+                    (a.2 + (b.2 - a.2) * t) as u8
+                )
+            }
+            Colormap::Grayscale => {
+                let v: u8 = (value * 255.0) as u8;
+                RGBColor(v, v, v)
+            }
+            Colormap::MandelbrotHSL => {
+                let (r, g, b) = MandelbrotHSL::get_color(value).rgb();
+                RGBColor(r, g, b)
+            }
+        }
+    }
+}
+
+// How bin power is mapped to the [0, 1] range of a colormap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MagnitudeScale {
+    // Power relative to the loudest bin
+    Linear,
+    // Power in dB relative to the loudest bin; anything below `floor_db` (e.g. -80.0) gets the first color
+    Decibel { floor_db: f32 }
+}
+
+impl MagnitudeScale {
+    // Normalizes `power` given the loudest bin's power `max`
+    pub fn normalize(&self, power: f32, max: f32) -> f32 {
+        if max <= 0.0 { return 0.0; }
+        match self {
+            MagnitudeScale::Linear => { safe_ratio(power, max, DEFAULT_EPSILON).clamp(0.0, 1.0) }
+            MagnitudeScale::Decibel { floor_db } => {
+                let db: f32 = power_to_db(power, max, DEFAULT_EPSILON);
+                (1.0 - safe_ratio(db, *floor_db, DEFAULT_EPSILON)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+// Width of a spectogram image when no other width is asked for
+pub const DEFAULT_PLOT_WIDTH: u32 = 4000;
+
+// Pools the frames (of `bins` values each) into `columns` frames; Column c gets the frames
+// [c*frames/columns, (c+1)*frames/columns) and keeps the value of each bin with the largest magnitude, so short
+// transients stay visible. If there are fewer frames than columns, frames are repeated instead.
+pub fn downsample_frames(frames: &[f32], bins: usize, columns: usize) -> Vec<f32> {
+    if bins == 0 { return vec![]; }
+    let frame_count: usize = frames.len() / bins;
+    if frame_count == 0 { return vec![]; }
+
+    let mut pooled: Vec<f32> = vec![0.0; columns * bins];
+    for (c, column) in pooled.chunks_exact_mut(bins).enumerate() {
+        let start: usize = c * frame_count / columns;
+        let end: usize = ((c+1) * frame_count / columns).max(start + 1);
+        for frame in frames[start*bins..end*bins].chunks_exact(bins) {
+            for (pooled_bin, bin) in column.iter_mut().zip(frame.iter()) {
+                if bin.abs() > pooled_bin.abs() { *pooled_bin = *bin; }
+            }
+        }
+    }
+    pooled
+}
+
+// Pixel columns of a spectogram image; 4 pixels per frame, unless that's wider than `max_width`, in which case the
+// frames are pooled into exactly `max_width` columns of one pixel. Returns the (pooled) frames and the pixels per frame.
+fn fit_to_width(frames: Vec<f32>, bins: usize, max_width: u32) -> (Vec<f32>, usize) {
+    let frame_count: usize = frames.len() / bins;
+    match frame_count * 4 > max_width as usize {
+        true  => { (downsample_frames(&frames, bins, max_width as usize), 1) }
+        false => { (frames, 4) }
+    }
+}
+
+// Largest number of pixels of a spectogram image; The bitmap takes 3 bytes per pixel, so this is about 200 MB
+pub const MAX_PLOT_PIXELS: u64 = 64 * 1024 * 1024;
+
+// Makes sure the image of `frame_count` frames of `bins` bins, laid out by `fit_to_width()`, stays within
+// `MAX_PLOT_PIXELS`; Checked before anything is allocated, since a large FFT (one pixel row per bin) and a wide
+// `max_width` easily ask for an image of several GB. `caller` (e.g. "plot_spectogram()") goes in the message.
+fn check_pixel_budget(caller: &str, filename: &String, frame_count: usize, bins: u32, max_width: u32) -> Result<(), SpecCompError> {
+    let width: u64 = (frame_count as u64 * 4).min(max_width as u64);
+    let pixels: u64 = width * bins as u64;
+    match pixels > MAX_PLOT_PIXELS {
+        true  => {
+            Result::Err(SpecCompError::Plot(format!("{}: The image of {} would be {}x{} pixels ({} MB), more than the limit of {} pixels; Downsample it with a smaller --plot-width or use a smaller FFT size",
+                caller, filename, width, bins, pixels * 3 / (1024 * 1024), MAX_PLOT_PIXELS)))
+        }
+        false => { Result::Ok(()) }
+    }
+}
+
+// Draws a spectogram as a .png image; Time runs from left to right (4 pixels per frame, or `max_width` pixels for
+// long spectograms, see `fit_to_width()`) and frequency from bottom to top (one pixel per bin). The two channels are averaged.
+pub fn plot_spectogram(spec: &StereoSpectogram, bins: u32, colormap: Colormap, scale: MagnitudeScale, max_width: u32, filename: &String) -> Result<(), SpecCompError> {
+    let bins_us = bins as usize;
+    if bins_us == 0 || spec.left.len() < bins_us {
+        return Result::Err(SpecCompError::EmptyInput(format!("plot_spectogram(): Nothing to plot in {} ({} values, {} bins)", filename, spec.left.len(), bins)));
+    }
+
+    if max_width == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(format!("plot_spectogram(): The image of {} has to be at least one pixel wide", filename)));
+    }
+    check_pixel_budget("plot_spectogram()", filename, spec.left.len() / bins_us, bins, max_width)?;
+
+    // Mono version of the spectogram
+    let frames: Vec<f32> = spec.left.iter().zip(spec.right.iter()).map(|(l, r)| (l + r) / 2.0).collect();
+    let (frames, column_width) = fit_to_width(frames, bins_us, max_width);
+    let frame_count: usize = frames.len() / bins_us;
+    let max: f32 = frames.iter().fold(0.0, |m, p| if *p > m {*p} else {m});
+
+    let (width, height) = ((frame_count * column_width) as u32, bins);
+    let root = BitMapBackend::new(filename, (width, height)).into_drawing_area();
+    if let Err(e) = root.fill(&colormap.color(0.0)) {
+        return Result::Err(SpecCompError::Plot(format!("plot_spectogram(): Could not draw {} ({:?})", filename, e)));
+    }
+
+    for (f, frame) in frames.chunks_exact(bins_us).enumerate() {
+        for (b, power) in frame.iter().enumerate() {
+            let color = colormap.color(scale.normalize(*power, max));
+            // Low frequencies at the bottom
+            let y: i32 = (height as usize - 1 - b) as i32;
+            for x in 0..column_width {
+                let _ = root.draw_pixel(((column_width*f + x) as i32, y), &color);
+            }
+        }
+    }
+
+    match root.present() {
+        Ok(_)  => { Result::Ok(()) }
+        Err(e) => { Result::Err(SpecCompError::Plot(format!("plot_spectogram(): Could not save {} ({:?})", filename, e))) }
+    }
+}
+
+// Diverging colormap for differences in [-1, 1]: blue for negative, white for 0, red for positive
+fn diverging_color(value: f32) -> RGBColor {
+    let value = value.clamp(-1.0, 1.0);
+    let fade = |v: f32| (255.0 * (1.0 - v)).round() as u8;
+    match value < 0.0 {
+        true  => { RGBColor(fade(-value), fade(-value), 255) }
+        false => { RGBColor(255, fade(value), fade(value)) }
+    }
+}
+
+// Draws the difference between two spectograms as a .png image, laid out (and pooled) like `plot_spectogram()`. If `signed` is set,
+// bins where `spec_a` is louder are red and bins where `spec_b` is louder are blue; Otherwise the absolute difference
+// goes from white to red. Colors are relative to the largest difference. A dotted line marks every kHz, using
+// `sample_rate` to place them (no lines if it is 0). Only the frames both spectograms have are drawn.
+pub fn plot_spectogram_diff(spec_a: &StereoSpectogram, spec_b: &StereoSpectogram, bins: u32, sample_rate: u32, signed: bool, max_width: u32, filename: &String) -> Result<(), SpecCompError> {
+    let bins_us = bins as usize;
+    let usable_frames: usize = match bins_us {
+        0 => { 0 }
+        _ => { min(spec_a.left.len(), spec_b.left.len()) / bins_us }
+    };
+    if usable_frames == 0 {
+        return Result::Err(SpecCompError::EmptyInput(format!("plot_spectogram_diff(): Nothing to plot in {} ({} and {} values, {} bins)",
+            filename, spec_a.left.len(), spec_b.left.len(), bins)));
+    }
+    if max_width == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(format!("plot_spectogram_diff(): The image of {} has to be at least one pixel wide", filename)));
+    }
+    check_pixel_budget("plot_spectogram_diff()", filename, usable_frames, bins, max_width)?;
+
+    // Mono difference of the two spectograms
+    let mono = |spec: &StereoSpectogram| -> Vec<f32> {
+        spec.left.iter().zip(spec.right.iter()).take(usable_frames * bins_us).map(|(l, r)| (l + r) / 2.0).collect()
+    };
+    let difference: Vec<f32> = mono(spec_a).iter().zip(mono(spec_b).iter()).map(|(a, b)| a - b).collect();
+    let (difference, column_width) = fit_to_width(difference, bins_us, max_width);
+    let frame_count: usize = difference.len() / bins_us;
+    let max: f32 = difference.iter().fold(0.0, |m, d| if d.abs() > m {d.abs()} else {m});
+
+    let (width, height) = ((frame_count * column_width) as u32, bins);
+    let root = BitMapBackend::new(filename, (width, height)).into_drawing_area();
+    if let Err(e) = root.fill(&WHITE) {
+        return Result::Err(SpecCompError::Plot(format!("plot_spectogram_diff(): Could not draw {} ({:?})", filename, e)));
+    }
+
+    for (f, frame) in difference.chunks_exact(bins_us).enumerate() {
+        for (b, d) in frame.iter().enumerate() {
+            let value: f32 = match signed {
+                true  => { safe_ratio(*d, max, DEFAULT_EPSILON) }
+                false => { safe_ratio(d.abs(), max, DEFAULT_EPSILON) }
+            };
+            let color = diverging_color(value);
+            // Low frequencies at the bottom
+            let y: i32 = (height as usize - 1 - b) as i32;
+            for x in 0..column_width {
+                let _ = root.draw_pixel(((column_width*f + x) as i32, y), &color);
+            }
+        }
+    }
+
+    // kHz guides, on the last bin below each multiple of 1KHz
+    if sample_rate > 0 {
+        let frequencies: Vec<f32> = bin_frequencies(bins_us as u32, sample_rate, 2 * bins_us as u32);
+        let mut khz: usize = 1;
+        loop {
+            let hz: f32 = (khz * 1000) as f32;
+            if 2.0 * hz >= sample_rate as f32 { break; }
+            let b: usize = frequencies.partition_point(|f| *f <= hz).saturating_sub(1);
+            let y: i32 = (height as usize - 1 - b) as i32;
+            for x in (0..width as i32).step_by(8) {
+                let _ = root.draw_pixel((x, y), &RGBColor(128, 128, 128));
+            }
+            khz += 1;
+        }
+    }
+
+    match root.present() {
+        Ok(_)  => { Result::Ok(()) }
+        Err(e) => { Result::Err(SpecCompError::Plot(format!("plot_spectogram_diff(): Could not save {} ({:?})", filename, e))) }
+    }
+}
+
+// Line charts ------------------------------------------------------------------------------------------------------------
+// X axis of the plots over time: the distance between two frames and the axis label. Frames start every `hop`
+// samples, so with overlapping frames they are less than a frame's length apart (see `frame_timestamp()`); The axis
+// is in seconds, or in frames if the sample rate isn't known (0).
+pub fn time_axis(hop: u32, sample_rate: u32) -> (f32, &'static str) {
+    match sample_rate {
+        0 => { (1.0, "Frame") }
+        _ => { (frame_timestamp(1, hop, sample_rate), "Time (s)") }
+    }
+}
+
+// Draws the per-frame errors of every stem (one GraphData each, as returned by `time_compare_spectogram()`) as
+// `time-error.png` in `export_dir`; Frames start every `hop` samples at `sample_rate` (see `time_axis()`).
+// With a `smooth_window` above 1 a moving average over that many frames is drawn instead of the raw errors.
+pub fn plot_time_error(data: Vec<GraphData>, hop: u32, sample_rate: u32, smooth_window: usize, export_dir: &String) -> Result<(), SpecCompError> {
+    let filename: String = Path::new(export_dir).join("time-error.png").to_string_lossy().to_string();
+    let data: Vec<GraphData> = match smooth_window > 1 {
+        true  => { data.iter().map(|d| d.smoothed(smooth_window)).collect() }
+        false => { data }
+    };
+    let (x_step, x_label) = time_axis(hop, sample_rate);
+    plot_error_lines(data, x_step, x_label, "Error", "Error over time", &filename)
+}
+
+// Lowest level drawn by `plot_frame_energy()`, relative to the loudest frame
+const ENERGY_FLOOR_DB: f32 = -80.0;
+
+// Draws the energy of every frame of each stem (as returned by `StereoSpectogram::frame_energies()`) as
+// `time-energy.png` in `export_dir`, on the same time axis as `plot_time_error()`; Levels are in dB relative to the
+// loudest frame of each stem, down to -80 dB. Next to the error plot it shows whether a low error is only silence.
+pub fn plot_frame_energy(data: Vec<GraphData>, hop: u32, sample_rate: u32, export_dir: &String) -> Result<(), SpecCompError> {
+    let filename: String = Path::new(export_dir).join("time-energy.png").to_string_lossy().to_string();
+    let levels: Vec<GraphData> = data.into_iter().map(|d| {
+        let loudest: f32 = d.max_value();
+        let label: String = d.get_label().clone();
+        let db: Vec<f32> = d.map(|(_, e)| power_to_db(e, loudest, DEFAULT_EPSILON).max(ENERGY_FLOOR_DB)).collect();
+        GraphData::new(db, label)
+    }).collect();
+    let (x_step, x_label) = time_axis(hop, sample_rate);
+    plot_error_lines(levels, x_step, x_label, "Level (dB)", "Energy over time", &filename)
+}
+
+// Draws the per-bin errors of every stem (as returned by `freq_compare_spectogram()`) as `freq-error.png` in
+// `export_dir`; Bins are `hz_per_bin` apart, or numbered if None (e.g. Mel bands, which aren't evenly spaced).
+pub fn plot_freq_error(data: Vec<GraphData>, hz_per_bin: Option<f32>, export_dir: &String) -> Result<(), SpecCompError> {
+    let filename: String = Path::new(export_dir).join("freq-error.png").to_string_lossy().to_string();
+    match hz_per_bin {
+        Some(step) => { plot_error_lines(data, step, "Frequency (Hz)", "Error", "Error by frequency", &filename) }
+        None       => { plot_error_lines(data, 1.0, "Band", "Error", "Error by band", &filename) }
+    }
+}
+
+// Draws the histograms of `error_histogram()` (one per stem, labeled with its name) as `time-error-histogram.png` in
+// `export_dir`; Every histogram should use the same buckets.
+pub fn plot_error_histogram(histograms: &[(String, Vec<(f32, u32)>)], export_dir: &String) -> Result<(), SpecCompError> {
+    let filename: String = Path::new(export_dir).join("time-error-histogram.png").to_string_lossy().to_string();
+    if histograms.iter().all(|(_, h)| h.is_empty()) {
+        return Result::Err(SpecCompError::EmptyInput(format!("plot_error_histogram(): Nothing to plot in {}", filename)));
+    }
+    let plot_error = |e: String| SpecCompError::Plot(format!("plot_error_histogram(): Could not draw {} ({})", filename, e));
+
+    // Buckets are drawn as steps around their centers
+    let all_buckets = || histograms.iter().flat_map(|(_, h)| h.iter());
+    let half_width: f32 = histograms.iter().find(|(_, h)| h.len() > 1).map(|(_, h)| (h[1].0 - h[0].0) / 2.0).unwrap_or(0.5);
+    let x_min: f32 = all_buckets().map(|b| b.0).fold(f32::INFINITY, f32::min) - half_width;
+    let x_max: f32 = all_buckets().map(|b| b.0).fold(f32::NEG_INFINITY, f32::max) + half_width;
+    let y_max: u32 = all_buckets().map(|b| b.1).max().unwrap_or(1).max(1);
+
+    let root = BitMapBackend::new(&filename, (1280, 720)).into_drawing_area();
+    root.fill(&WHITE).map_err(|e| plot_error(e.to_string()))?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Distribution of the error over time", ("sans-serif", 28))
+        .margin(12)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(x_min..x_max, 0.0f32..y_max as f32 * 1.05)
+        .map_err(|e| plot_error(e.to_string()))?;
+    chart.configure_mesh().x_desc("Error").y_desc("Frames").draw().map_err(|e| plot_error(e.to_string()))?;
+
+    for (i, (label, histogram)) in histograms.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        let steps: Vec<(f32, f32)> = histogram.iter()
+            .flat_map(|(center, count)| [(center - half_width, *count as f32), (center + half_width, *count as f32)])
+            .collect();
+        chart.draw_series(LineSeries::new(steps, color.stroke_width(2)))
+            .map_err(|e| plot_error(e.to_string()))?
+            .label(label.clone())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+    }
+    chart.configure_series_labels().background_style(WHITE.mix(0.8)).border_style(BLACK).draw()
+        .map_err(|e| plot_error(e.to_string()))?;
+
+    root.present().map_err(|e| plot_error(e.to_string()))
+}
+
+// One line per GraphData, labeled with its label; The i-th value is drawn at x = i * x_step. The y axis starts at 0.0,
+// or lower if there are negative values.
+fn plot_error_lines(data: Vec<GraphData>, x_step: f32, x_label: &str, y_label: &str, caption: &str, filename: &String) -> Result<(), SpecCompError> {
+    if data.is_empty() || data.iter().all(|d| d.is_empty()) {
+        return Result::Err(SpecCompError::EmptyInput(format!("plot_error_lines(): Nothing to plot in {}", filename)));
+    }
+    let root = BitMapBackend::new(filename, (1280, 720)).into_drawing_area();
+    draw_error_lines(&root, data, x_step, x_label, y_label, caption, filename)
+}
+
+// End of the x axis of `plot_error_lines()`: the length of the longest series (at least 2) times `x_step`, e.g.
+// `frame_count * hop / sample_rate` seconds with the step of `time_axis()`
+fn x_axis_end(data: &[GraphData], x_step: f32) -> f32 {
+    data.iter().map(|d| d.data_len()).max().unwrap_or(1).max(2) as f32 * x_step
+}
+
+// Does the drawing of `plot_error_lines()` on any drawing area; `filename` only names it in errors.
+fn draw_error_lines<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>, data: Vec<GraphData>, x_step: f32, x_label: &str, y_label: &str, caption: &str, filename: &String) -> Result<(), SpecCompError> {
+    let plot_error = |e: String| SpecCompError::Plot(format!("plot_error_lines(): Could not draw {} ({})", filename, e));
+
+    let x_max: f32 = x_axis_end(&data, x_step);
+    let y_max: f32 = data.iter().map(|d| d.max_value()).fold(0.0, f32::max).max(DEFAULT_EPSILON);
+    let y_min: f32 = data.iter().map(|d| d.min_value()).fold(0.0, f32::min);
+
+    root.fill(&WHITE).map_err(|e| plot_error(e.to_string()))?;
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, ("sans-serif", 28))
+        .margin(12)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0f32..x_max, y_min * 1.05..y_max * 1.05)
+        .map_err(|e| plot_error(e.to_string()))?;
+    chart.configure_mesh().x_desc(x_label).y_desc(y_label).draw().map_err(|e| plot_error(e.to_string()))?;
+
+    for (i, d) in data.into_iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        let label: String = d.get_label().clone();
+        chart.draw_series(LineSeries::new(d.map(|(x, y)| (x as f32 * x_step, y)), color.stroke_width(2)))
+            .map_err(|e| plot_error(e.to_string()))?
+            .label(label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+    }
+    chart.configure_series_labels().background_style(WHITE.mix(0.8)).border_style(BLACK).draw()
+        .map_err(|e| plot_error(e.to_string()))?;
+
+    root.present().map_err(|e| plot_error(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Empty directory of the system's temporary directory for the images of a test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir: PathBuf = std::env::temp_dir().join(format!("speccomp-graphs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Spectogram of `frames` frames of `bins` bins whose power rises with the bin and the frame
+    fn gradient(frames: usize, bins: u32) -> StereoSpectogram {
+        let values: Vec<f32> = (0..frames * bins as usize).map(|i| ((i % bins as usize) * (1 + i / bins as usize)) as f32).collect();
+        StereoSpectogram { left: values.clone(), right: values, bins }
+    }
+
+    #[test]
+    fn six_stems_get_their_own_colors() {
+        let (width, height): (u32, u32) = (640, 360);
+        let mut buffer: Vec<u8> = vec![0; (width * height * 3) as usize];
+        let data: Vec<GraphData> = (0..6)
+            .map(|s| GraphData::new((0..50).map(|i| ((i + s * 7) % 13) as f32 / 13.0).collect(), format!("Stem {}", s + 1)))
+            .collect();
+        {
+            let root = BitMapBackend::with_buffer(&mut buffer, (width, height)).into_drawing_area();
+            draw_error_lines(&root, data, 0.1, "Time (s)", "Error", "Error over time", &"memory".to_string()).unwrap();
+        }
+
+        // Every series (and its legend entry) is drawn in a color of its own
+        let colors: Vec<(u8, u8, u8)> = (0..6).map(|i| { let c = Palette99::pick(i).to_rgba(); (c.0, c.1, c.2) }).collect();
+        for (i, color) in colors.iter().enumerate() {
+            assert!(colors[..i].iter().all(|c| c != color));
+            assert!(buffer.chunks(3).any(|p| (p[0], p[1], p[2]) == *color), "no pixel in the color of series {}", i);
+        }
+    }
+
+    #[test]
+    fn nothing_to_plot() {
+        let empty: Vec<GraphData> = vec![GraphData::new(Vec::new(), "Bass".to_string())];
+        let result = plot_error_lines(empty, 1.0, "Band", "Error", "Error by band", &"unused.png".to_string());
+        assert!(matches!(result, Result::Err(SpecCompError::EmptyInput(_))));
+    }
+
+    #[test]
+    fn colormaps_give_different_images() {
+        let dir: PathBuf = scratch_dir("colormaps");
+        let spec: StereoSpectogram = gradient(20, 64);
+        let image = |colormap: Colormap| -> Vec<u8> {
+            let filename: String = dir.join(format!("{:?}.png", colormap)).to_string_lossy().to_string();
+            plot_spectogram(&spec, 64, colormap, MagnitudeScale::Linear, DEFAULT_PLOT_WIDTH, &filename).unwrap();
+            std::fs::read(&filename).unwrap()
+        };
+
+        let (viridis, magma) = (image(Colormap::Viridis), image(Colormap::Magma));
+        assert!(!viridis.is_empty() && !magma.is_empty());
+        assert_ne!(viridis, magma);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn diff_of_a_known_region() {
+        let dir: PathBuf = scratch_dir("diff");
+        let spec_a: StereoSpectogram = gradient(20, 64);
+        let mut spec_b: StereoSpectogram = gradient(20, 64);
+        // Frames 5..10, bins 10..20 are louder in `spec_b`
+        for frame in 5..10 {
+            for bin in 10..20 {
+                spec_b.left[frame * 64 + bin] += 50.0;
+                spec_b.right[frame * 64 + bin] += 50.0;
+            }
+        }
+
+        for signed in [true, false] {
+            let filename: String = dir.join(format!("diff-{}.png", signed)).to_string_lossy().to_string();
+            plot_spectogram_diff(&spec_a, &spec_b, 64, 8000, signed, DEFAULT_PLOT_WIDTH, &filename).unwrap();
+            assert!(std::fs::metadata(&filename).unwrap().len() > 0);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn long_spectograms_are_pooled_to_the_width() {
+        // 100k frames of 8 bins; Frame f holds f % 25, and a spike every 1000 frames
+        let frames: Vec<f32> = (0..100_000usize)
+            .flat_map(|f| { let v: f32 = if f % 1000 == 500 { 100.0 } else { (f % 25) as f32 }; [v; 8] })
+            .collect();
+
+        // 25 frames per column, of which the largest is kept
+        let pooled: Vec<f32> = downsample_frames(&frames, 8, 4000);
+        assert_eq!(pooled.len(), 4000 * 8);
+        assert_eq!(pooled[0..8], [24.0; 8]);
+        assert_eq!(pooled[20 * 8], 100.0);
+        assert_eq!(pooled.iter().filter(|v| **v == 100.0).count(), 100 * 8);
+
+        let dir: PathBuf = scratch_dir("width");
+        let filename: String = dir.join("long.png").to_string_lossy().to_string();
+        let spec: StereoSpectogram = StereoSpectogram { left: frames.clone(), right: frames, bins: 8 };
+        plot_spectogram(&spec, 8, Colormap::Viridis, MagnitudeScale::Linear, 600, &filename).unwrap();
+        // Width and height are the first fields of the PNG's IHDR chunk
+        let png: Vec<u8> = std::fs::read(&filename).unwrap();
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 600);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 8);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn oversized_plots_are_refused() {
+        let filename: String = String::from("bass-1.png");
+        // 2048 bins over 1000 frames fit at the default width, but not with a width of 40000 pixels
+        assert!(check_pixel_budget("plot_spectogram()", &filename, 1000, 2048, 1200).is_ok());
+        let message: String = match check_pixel_budget("plot_spectogram()", &filename, 10000, 2048, 40000) {
+            Result::Err(SpecCompError::Plot(m)) => { m }
+            other => { panic!("{:?}", other); }
+        };
+        assert!(message.starts_with("plot_spectogram(): The image of bass-1.png would be 40000x2048 pixels (234 MB)"), "{}", message);
+        assert!(message.contains("--plot-width") && message.contains("smaller FFT size"), "{}", message);
+
+        // Exactly at the limit is still drawn
+        assert!(check_pixel_budget("plot_spectogram()", &filename, 1024, 16384, 4096).is_ok());
+        assert!(check_pixel_budget("plot_spectogram()", &filename, 1025, 16384, 4100).is_err());
+    }
+
+    #[test]
+    fn time_axis_ends_after_the_last_frame() {
+        let data: Vec<GraphData> = vec![GraphData::new(vec![0.5; 100], String::from("Bass")), GraphData::new(vec![0.5; 60], String::from("Drums"))];
+
+        // 100 frames every 1024 samples (half of a 2048 sample frame) at 44.1KHz
+        let (x_step, x_label) = time_axis(1024, 44100);
+        assert_eq!(x_label, "Time (s)");
+        assert!((x_axis_end(&data, x_step) - 100.0 * 1024.0 / 44100.0).abs() < 1e-5);
+        let (x_step, _) = time_axis(2048, 44100);
+        assert!((x_axis_end(&data, x_step) - 100.0 * 2048.0 / 44100.0).abs() < 1e-5);
+
+        // Without a sample rate the axis counts frames
+        let (x_step, x_label) = time_axis(1024, 0);
+        assert_eq!((x_axis_end(&data, x_step), x_label), (100.0, "Frame"));
+    }
+}
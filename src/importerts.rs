@@ -1,80 +1,814 @@
 use std::{
-    fs::File, path::PathBuf, time::{Duration, Instant},
-    thread::JoinHandle, sync::{Arc, Mutex},
-    thread, sync::mpsc::{Sender, Receiver, channel}
+    fs::File, path::{Path, PathBuf}, io::{Cursor, Read}, time::{Duration, Instant},
+    sync::{Arc, Mutex}, cmp::min, panic::{self, AssertUnwindSafe},
+    thread, sync::mpsc::{Sender, Receiver, TryRecvError, channel}
 };
 
 // Multimedia format handling
 use symphonia::core::{
-    io::MediaSourceStream, formats::FormatOptions, meta::MetadataOptions,
-    probe::Hint, codecs::DecoderOptions, audio::SampleBuffer
+    io::{MediaSource, MediaSourceStream}, formats::FormatOptions, meta::MetadataOptions,
+    probe::Hint, codecs::{CodecParameters, DecoderOptions}, audio::{SampleBuffer, SignalSpec}
 };
 
 use crate::types::*;
+use crate::error::SpecCompError;
+use crate::{status, statusln, verboseln};
+use crate::workers::*;
+use crate::spectograms::{StreamingSpectogram, WindowFunction};
+
+// File access --------------------------------------------------------------------------------------------------------------------
+// Times a file is tried to be opened when the failure may be temporary (interrupted or timed out, e.g. on network drives)
+const OPEN_ATTEMPTS: usize = 3;
+const OPEN_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+// Opens `path` for reading; Temporary failures are retried. On failure the message, prefixed with `caller`, tells
+// whether the file is missing, not readable or a directory, along with the OS error,
+// e.g. "import_from_file(): Could not open bass.mp3, no such file (No such file or directory (os error 2))."
+pub fn open_file<P: AsRef<Path>>(path: P, caller: &str) -> Result<File, SpecCompError> {
+    let path: &Path = path.as_ref();
+    // A directory can be opened like a file on some systems, and only fails once it is read
+    if path.is_dir() {
+        return Result::Err(SpecCompError::Io(format!("{}: Could not open {}, it is a directory, not a file.", caller, path.display())));
+    }
 
-// Multithreaded ---------------------------------------------------------------------------------------------------
-// Imports the 4 separated tracks from a directory; The names of the .mp3 files must be {bass, drums, vocals, other}.mp3
-// Returns TrackBuffers and true if the directory contains the original stems.
-pub fn mt_import_from_directory(path: &String) -> Result<Vec<TrackBuffer>, String> {
-    println!("Looking into {} for separated stems...", path);
+    let mut attempt: usize = 1;
+    loop {
+        let e: std::io::Error = match File::open(path) {
+            Ok(f)  => { return Result::Ok(f); }
+            Err(e) => { e }
+        };
+
+        let reason: &str = match e.kind() {
+            std::io::ErrorKind::Interrupted | std::io::ErrorKind::TimedOut if attempt < OPEN_ATTEMPTS => {
+                attempt += 1;
+                thread::sleep(OPEN_RETRY_DELAY);
+                continue;
+            }
+            std::io::ErrorKind::NotFound         => { "no such file" }
+            std::io::ErrorKind::PermissionDenied => { "permission denied" }
+            std::io::ErrorKind::IsADirectory     => { "it is a directory, not a file" }
+            _ => { "I/O error" }
+        };
+        return Result::Err(SpecCompError::Io(format!("{}: Could not open {}, {} ({}).", caller, path.display(), reason, e)));
+    }
+}
+
+// Directory scanning ------------------------------------------------------------------------------------------------------------
+// Extensions of the formats Symphonia is built with; Each stem may use a different one
+const STEM_EXTENSIONS: [&str; 8] = ["mp3", "wav", "flac", "ogg", "aac", "mka", "mkv", "webm"];
+
+// Number of separated stems in every source
+pub const STEM_COUNT: usize = 4;
+// Base names of the stems, in the order every importer returns them and the results report them
+pub const STEM_NAMES: [&str; STEM_COUNT] = ["bass", "drums", "vocals", "other"];
+
+// Name of a stem as it is displayed, e.g. "Bass" for "bass"
+pub fn stem_display_name(stem: &str) -> String {
+    let mut chars = stem.chars();
+    match chars.next() {
+        Some(c) => { c.to_uppercase().chain(chars).collect() }
+        None    => { String::new() }
+    }
+}
+
+// Largest difference between the durations of the stems of one source (in seconds) that is not warned about
+pub const DURATION_TOLERANCE: f32 = 0.1;
 
-    // Check this directory has all the required files
+// The importers of directories take the layout of the stems as a `pattern`, e.g. "{stem}/*.wav" for `bass/audio.wav`:
+// a path relative to the directory in which `{stem}` stands for the name of the stem. Within a file or directory name
+// `*` matches any characters and `?` a single one; A `**` component matches any number of directories. A pattern of
+// None is the flat layout of `find_stem_files()`.
+pub fn check_stem_pattern(pattern: &str) -> Result<(), SpecCompError> {
+    if !pattern.contains("{stem}") {
+        return Result::Err(SpecCompError::InvalidArgument(format!("check_stem_pattern(): The pattern must contain {{stem}} (got {})", pattern)));
+    }
+    if pattern.starts_with('/') || pattern.split('/').any(|c| c == "..") {
+        return Result::Err(SpecCompError::InvalidArgument(format!("check_stem_pattern(): The pattern must stay inside the source directory (got {})", pattern)));
+    }
+    Result::Ok(())
+}
+
+// Splits a pattern or a relative path into its components
+fn path_components(path: &str) -> Vec<String> {
+    path.split('/').filter(|c| !c.is_empty() && *c != ".").map(String::from).collect()
+}
+
+// Whether a file or directory name matches one component of a stem pattern; Case-insensitive, like the stem names
+fn component_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => { name.is_empty() }
+        Some(('*', rest)) => { (0..=name.len()).any(|i| component_matches(rest, &name[i..])) }
+        Some(('?', rest)) => { !name.is_empty() && component_matches(rest, &name[1..]) }
+        Some((c, rest)) => {
+            name.first().is_some_and(|n| n.to_lowercase().eq(c.to_lowercase())) && component_matches(rest, &name[1..])
+        }
+    }
+}
+
+// Whether the components of a relative path match those of a stem pattern
+fn path_matches(pattern: &[String], path: &[String]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, _) => { path.is_empty() }
+        (Some((p, rest)), _) if p == "**" => { (0..=path.len()).any(|i| path_matches(rest, &path[i..])) }
+        (Some(_), None) => { false }
+        (Some((p, rest)), Some((name, path_rest))) => {
+            let (p, name): (Vec<char>, Vec<char>) = (p.chars().collect(), name.chars().collect());
+            component_matches(&p, &name) && path_matches(rest, path_rest)
+        }
+    }
+}
+
+// Every file below `dir`, as the components of its path relative to `dir`; Goes at most `max_depth` components deep.
+// Unreadable directories and names that aren't UTF-8 are skipped, symbolic links to directories aren't followed.
+fn list_files(dir: &Path, max_depth: usize) -> Vec<Vec<String>> {
+    let mut files: Vec<Vec<String>> = vec![];
+    if max_depth == 0 {
+        return files;
+    }
+    let entries = match std::fs::read_dir(dir) {
+        Ok(d)  => { d }
+        Err(_) => { return files; }
+    };
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name: String = match entry.file_name().to_str() {
+            Some(n) => { n.to_string() }
+            None    => { continue; }
+        };
+        match entry.file_type().map(|t| t.is_dir()) {
+            Ok(true)  => {
+                for mut below in list_files(&entry.path(), max_depth - 1) {
+                    below.insert(0, name.clone());
+                    files.push(below);
+                }
+            }
+            Ok(false) => { files.push(vec![name]); }
+            Err(_)    => {}
+        }
+    }
+    files
+}
+
+// `find_stem_files()` with a stem pattern (see `check_stem_pattern()`); Every stem must match exactly one file with one
+// of the extensions in `STEM_EXTENSIONS`.
+fn find_stem_files_by_pattern(path: &String, pattern: &str) -> Result<Vec<PathBuf>, SpecCompError> {
+    check_stem_pattern(pattern)?;
+    let max_depth: usize = match pattern.contains("**") {
+        true  => { usize::MAX }
+        false => { path_components(pattern).len() }
+    };
+    let files: Vec<Vec<String>> = list_files(Path::new(path), max_depth);
+
+    let mut paths: Vec<PathBuf> = vec![];
+    let mut missing: Vec<String> = vec![];
+    for stem in STEM_NAMES {
+        let stem_path: String = pattern.replace("{stem}", stem);
+        let components: Vec<String> = path_components(&stem_path);
+        let matches: Vec<&Vec<String>> = files.iter()
+            .filter(|f| path_matches(&components, f))
+            .filter(|f| Path::new(&f[f.len() - 1]).extension().and_then(|e| e.to_str()).is_some_and(|e| STEM_EXTENSIONS.contains(&e.to_lowercase().as_str())))
+            .collect();
+
+        match matches.len() {
+            0 => { missing.push(stem_path); }
+            1 => { paths.push(matches[0].iter().fold(PathBuf::from(path), |p, c| p.join(c))); }
+            _ => { return Result::Err(SpecCompError::DuplicateStem { location: path.clone(), stem: stem.to_string() }); }
+        }
+    }
+
+    if !missing.is_empty() {
+        return Result::Err(SpecCompError::MissingStems { location: path.clone(), found: STEM_NAMES.len() - missing.len(), expected: STEM_NAMES.len(), missing });
+    }
+    Result::Ok(paths)
+}
+
+// Looks into `path` for the 4 separated stems; The base names of the files must be {bass, drums, vocals, other}, with
+// any of the extensions in `STEM_EXTENSIONS` (e.g. bass.flac and drums.mp3).
+// Names and extensions are matched case-insensitively (e.g. `Bass.MP3`).
+// Returns the path of each stem in the order above. The whole directory is scanned so that every missing stem
+// can be reported, as well as stems that appear more than once (even with different extensions, which is ambiguous).
+// With a stem `pattern` (see `check_stem_pattern()`), the stems are looked for there instead; A directory in which the
+// pattern doesn't match a single stem falls back to the flat layout, so that e.g. a nested candidate can be compared
+// against a flat reference.
+fn find_stem_files(path: &String, pattern: Option<&str>) -> Result<Vec<PathBuf>, SpecCompError> {
     let dir_contents = match std::fs::read_dir(path) {
         Ok(d) => { d }
-        Err(_) => { return Result::Err(format!("import_from_directory():\n\tread_dir({}): Failed to open directory (insufficient access rights?)", path)); }
+        Err(_) => { return Result::Err(SpecCompError::Io(format!("import_from_directory():\n\tread_dir({}): Failed to open directory (insufficient access rights?)", path))); }
     };
 
-    let mut paths: Vec<Option<PathBuf>> = Vec::new();
-    paths.resize(4, Option::None);
+    // Bad entries will be silently skipped
+    let candidates: Vec<PathBuf> = dir_contents.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    let pattern: &str = match pattern {
+        Some(p) => { p }
+        None    => { return match_stem_files(candidates, path); }
+    };
+    match find_stem_files_by_pattern(path, pattern) {
+        Err(SpecCompError::MissingStems { found: 0, .. }) if candidates.iter().any(|c| is_flat_stem(c)) => { match_stem_files(candidates, path) }
+        result => { result }
+    }
+}
 
-    let required_files: Vec<&str> = vec!["bass.mp3", "drums.mp3", "vocals.mp3", "other.mp3"];
-    let mut hits = 0;
-    
-    // Try finding all four files in `path`
-    for e in dir_contents {
-        let entry = match e {
-            Ok(r)  => { r }
-            Err(_) => { continue; } // Bad entries will be silently skipped
+// Whether `path` is named like a stem of the flat layout, e.g. `bass.mp3`
+fn is_flat_stem(path: &Path) -> bool {
+    let stem_name: bool = path.file_stem().and_then(|n| n.to_str()).is_some_and(|n| STEM_NAMES.contains(&n.to_lowercase().as_str()));
+    let extension: bool = path.extension().and_then(|e| e.to_str()).is_some_and(|e| STEM_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+    stem_name && extension
+}
+
+// Picks the 4 separated stems out of `candidates` (see `find_stem_files()`); `location` is only used in messages
+fn match_stem_files(candidates: Vec<PathBuf>, location: &str) -> Result<Vec<PathBuf>, SpecCompError> {
+    let required_stems: Vec<&str> = STEM_NAMES.to_vec();
+    let required_files: Vec<String> = required_stems.iter().map(|s| format!("{}.*", s)).collect();
+    let mut paths: Vec<Option<PathBuf>> = vec![Option::None; required_stems.len()];
+
+    for item_path in candidates {
+        // Get entry's base name and extension; Non UTF-8 names can't be stems anyway
+        let item_stem = match item_path.file_stem().and_then(|n| n.to_str()) {
+            Some(n) => { n.to_lowercase() }
+            None    => { continue; }
+        };
+        let item_extension = match item_path.extension().and_then(|n| n.to_str()) {
+            Some(e) => { e.to_lowercase() }
+            None    => { continue; }
         };
+        if !STEM_EXTENSIONS.contains(&item_extension.as_str()) { continue; }
+
+        // Search for the base name in `required_stems`
+        if let Some(index) = required_stems.iter().position(|&x| x == item_stem) {
+            if paths[index].is_some() {
+                return Result::Err(SpecCompError::DuplicateStem { location: location.to_string(), stem: required_stems[index].to_string() });
+            }
+            paths[index] = Option::Some(item_path);
+        }
+    }
+
+    // List every stem that wasn't found
+    let missing: Vec<&str> = required_files.iter().zip(paths.iter())
+        .filter(|(_, p)| p.is_none())
+        .map(|(name, _)| name.as_str())
+        .collect();
+
+    if !missing.is_empty() {
+        return Result::Err(SpecCompError::MissingStems {
+            location: location.to_string(),
+            found: required_files.len() - missing.len(),
+            expected: required_files.len(),
+            missing: missing.iter().map(|m| m.to_string()).collect()
+        });
+    }
+
+    Result::Ok(paths.into_iter().flatten().collect())
+}
 
-        let mut req_files_it = required_files.iter();
-        // Get entry's path and filename
-        let item_path = &entry.path();
-        let item_name = item_path.file_name().unwrap();
+// Pairs the stem files of a reference directory with those of a candidate directory by their base names (bass with
+// bass, and so on), so that the order of the files and any extra files in either directory don't matter.
+// Returns (stem name, reference file, candidate file) for every stem, in the order of `STEM_NAMES`.
+pub fn pair_stem_files(reference: &String, candidate: &String, pattern: Option<&str>) -> Result<Vec<(String, PathBuf, PathBuf)>, SpecCompError> {
+    let reference_files: Vec<PathBuf> = find_stem_files(reference, pattern)?;
+    let candidate_files: Vec<PathBuf> = find_stem_files(candidate, pattern)?;
+    Result::Ok(STEM_NAMES.iter().zip(reference_files.into_iter().zip(candidate_files))
+        .map(|(name, (r, c))| (name.to_string(), r, c))
+        .collect())
+}
+
+// Checks that the stems of a source (in the order of `STEM_NAMES`) last about as long; A separation that broke off
+// early leaves one stem shorter than the others. Returns a message naming the duration of every stem if the longest
+// and the shortest one are more than `tolerance` seconds apart.
+pub fn check_stem_durations(tracks: &[Track], tolerance: f32) -> Option<String> {
+    let durations: Vec<f32> = tracks.iter().map(|t| t.duration()).collect();
+    let longest: f32 = durations.iter().fold(0.0, |m: f32, d| m.max(*d));
+    let shortest: f32 = durations.iter().fold(f32::INFINITY, |m: f32, d| m.min(*d));
+    if durations.is_empty() || longest - shortest <= tolerance {
+        return Option::None;
+    }
 
-        // Search for the filename in `required_files`
-        match req_files_it.position(|&x| x == item_name) {
-            Some(index) => {
-                paths[index] = Option::Some(item_path.clone());
-                hits += 1;
+    let listed: Vec<String> = STEM_NAMES.iter().zip(durations.iter()).map(|(name, d)| format!("{} {:.3}s", name, d)).collect();
+    Option::Some(format!("The stems differ by {:.3}s ({})", longest - shortest, listed.join(", ")))
+}
+
+// Every comparison expects interleaved stereo tracks; Mono stems (in the order of `STEM_NAMES`) of `location` are
+// turned into stereo ones with a warning, and stems with more than 2 channels are an error.
+fn upmix_mono_stems(tracks: &mut [Track], location: &String) -> Result<(), SpecCompError> {
+    for (name, track) in STEM_NAMES.iter().zip(tracks.iter_mut()) {
+        match track.channels {
+            1 => {
+                track.upmix_mono();
+                statusln!("\nWarning: The {} stem of {} is mono; Both channels were given its samples.", name, location);
+            }
+            0 | 2 => {}
+            n => {
+                return Result::Err(SpecCompError::UnsupportedFormat(format!("upmix_mono_stems(): The {} stem of {} has {} channels; Only mono and stereo stems can be compared.", name, location, n)));
             }
-            None => { continue; }
         }
-        if hits == 4 { break; }
     }
+    Result::Ok(())
+}
 
-    if hits != 4 {
-        return Result::Err(format!("import_from_directory(): Could not find all separated stems (found {}/4)", hits));
+// Prints the warning of `check_stem_durations()`, if any, for the stems of `location`
+fn warn_on_duration_mismatch(tracks: &[Track], location: &String) {
+    if let Some(m) = check_stem_durations(tracks, DURATION_TOLERANCE) {
+        statusln!("\nWarning: {} in {}.", m, location);
     }
+}
+
+// Reads a batch manifest; Each line lists a reference directory, a test directory and a name for the pair,
+// separated by commas. Empty lines, lines starting with '#' and a `reference_dir,test_dir,name` header are skipped.
+// The name is optional and defaults to the test directory.
+pub fn read_batch_manifest(path: &String) -> Result<Vec<(String, String, String)>, SpecCompError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(c)  => { c }
+        Err(e) => { return Result::Err(SpecCompError::Io(format!("read_batch_manifest(): Could not read {} ({}).", path, e))); }
+    };
+
+    let mut entries: Vec<(String, String, String)> = vec![];
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        if fields[0] == "reference_dir" { continue; }
+
+        match fields.len() {
+            2 => { entries.push((fields[0].to_string(), fields[1].to_string(), fields[1].to_string())); }
+            3 => { entries.push((fields[0].to_string(), fields[1].to_string(), fields[2].to_string())); }
+            _ => { return Result::Err(SpecCompError::InvalidArgument(format!("read_batch_manifest(): Line {} of {} should be `reference_dir,test_dir,name`.", n+1, path))); }
+        }
+    }
+
+    if entries.is_empty() {
+        return Result::Err(SpecCompError::EmptyInput(format!("read_batch_manifest(): {} doesn't list any pairs.", path)));
+    }
+    Result::Ok(entries)
+}
+
+
+// Whether all `samples` are below `threshold` (a linear amplitude, see `dbfs_to_amplitude()`) in absolute value; A
+// sample at exactly the threshold isn't silent. Every silence check goes through this, so they all agree.
+pub fn is_silent(samples: &[f32], threshold: f32) -> bool {
+    samples.iter().all(|s| s.abs() < threshold)
+}
+
+// Removes the silent L/R pairs at the start and the end of `track`; A pair is silent if both samples are below
+// `threshold` (see `is_silent()`). Whole pairs are removed so the channels stay interleaved, and everything between the
+// first and the last pair that isn't silent is kept as is.
+pub fn trim_silence(track: &mut TrackBuffer, threshold: f32) {
+    let is_silent = |pair: &[f32]| is_silent(pair, threshold);
+    let pairs: usize = track.len() / 2;
+
+    let first: usize = match track.chunks_exact(2).position(|p| !is_silent(p)) {
+        Some(f) => { f }
+        None    => { track.clear(); return; }
+    };
+    let last: usize = pairs - 1 - track.chunks_exact(2).rev().position(|p| !is_silent(p)).unwrap_or(0);
+
+    track.truncate(2*(last + 1));
+    track.drain(..2*first);
+}
+
+// Which frames of `track_to_spec(fft_size, ...)` are silent, i.e. all of their samples are below `threshold` (see
+// `is_silent()`); One value per frame, the last one covering the leftover samples.
+pub fn silent_frames(track: &TrackBuffer, fft_size: u32, threshold: f32) -> Vec<bool> {
+    if fft_size == 0 {
+        return vec![];
+    }
+    let pairs: usize = track.len() / 2;
+    track[..2*pairs].chunks(2 * fft_size as usize).map(|frame| is_silent(frame, threshold)).collect()
+}
+
+// Pads every track with silence (zeros) up to the length of the longest one, so that the spectograms of all tracks
+// end up with the same number of frames. Separation tools sometimes emit stems that differ by a few samples.
+pub fn pad_to_longest(tracks: &mut [Track]) {
+    let longest: usize = tracks.iter().map(|t| t.samples.len()).max().unwrap_or(0);
+    for t in tracks.iter_mut() {
+        t.samples.resize(longest, 0.0);
+    }
+}
+
+// Target of `rms_normalize()` when normalizing from the command line; 0.1 is -20 dBFS
+pub const DEFAULT_TARGET_RMS: f32 = 0.1;
+
+// Root mean square of all samples of `track` (both channels); 0.0 for an empty track
+pub fn rms(track: &TrackBuffer) -> f32 {
+    match track.len() {
+        0 => { 0.0 }
+        n => { (track.iter().map(|s| (*s as f64).powi(2)).sum::<f64>() / n as f64).sqrt() as f32 }
+    }
+}
+
+// Scales `track` so that its RMS becomes `target_rms`, so that tracks that only differ in level compare as equal.
+// Returns the gain that was applied; Silent tracks (RMS below epsilon) are left untouched and get a gain of 1.0.
+pub fn rms_normalize(track: &mut TrackBuffer, target_rms: f32) -> f32 {
+    let current: f32 = rms(track);
+    if current < DEFAULT_EPSILON {
+        return 1.0;
+    }
+
+    let gain: f32 = target_rms / current;
+    for s in track.iter_mut() {
+        *s *= gain;
+    }
+    gain
+}
+
+// Whether `test` has the opposite polarity of `reference`, i.e. most of its samples are negated; Uses the sign of
+// their correlation over the samples both tracks have. Inverted stems sound the same and have the same spectogram,
+// but wreck any comparison of the waveforms (e.g. a difference track).
+pub fn detect_polarity(reference: &TrackBuffer, test: &TrackBuffer) -> bool {
+    let correlation: f64 = reference.iter().zip(test.iter()).map(|(r, t)| *r as f64 * *t as f64).sum();
+    correlation < 0.0
+}
+
+// Negates every sample of `track`
+pub fn invert_polarity(track: &mut TrackBuffer) {
+    for s in track.iter_mut() {
+        *s = -*s;
+    }
+}
+
+// Taps of the low-pass `resample()` applies before downsampling, i.e. a filter of order 126; Past a transition band
+// of about 3 % of the source rate above the cutoff, content is attenuated by some 40 dB or more.
+pub const LOW_PASS_TAPS: usize = 127;
+
+// Coefficients of a linear-phase FIR low-pass with its cutoff at `cutoff` (a fraction of the sample rate, up to 0.5):
+// a sinc shaped by a Hann window, scaled so that 0 Hz passes unchanged.
+pub fn low_pass_taps(cutoff: f32, taps: usize) -> Vec<f32> {
+    let center: f64 = (taps as f64 - 1.0) / 2.0;
+    let mut coefficients: Vec<f64> = (0..taps).map(|n| {
+        let x: f64 = n as f64 - center;
+        let sinc: f64 = match x == 0.0 {
+            true  => { 2.0 * cutoff as f64 }
+            false => { (2.0 * std::f64::consts::PI * cutoff as f64 * x).sin() / (std::f64::consts::PI * x) }
+        };
+        let window: f64 = 0.5 * (1.0 - (2.0 * std::f64::consts::PI * n as f64 / (taps as f64 - 1.0).max(1.0)).cos());
+        sinc * window
+    }).collect();
+
+    let sum: f64 = coefficients.iter().sum();
+    if sum != 0.0 {
+        coefficients.iter_mut().for_each(|c| *c /= sum);
+    }
+    coefficients.into_iter().map(|c| c as f32).collect()
+}
+
+// Filters both channels of `track` (interleaved stereo) with `taps`; The filter is centered on each sample so the
+// output isn't delayed, and the samples past either end count as 0.
+fn filter_stereo(track: &TrackBuffer, taps: &[f32]) -> TrackBuffer {
+    let pairs: isize = (track.len() / 2) as isize;
+    let center: isize = (taps.len() / 2) as isize;
+    let mut filtered: TrackBuffer = vec![0.0; 2 * pairs as usize];
+    for i in 0..pairs {
+        for (k, tap) in taps.iter().enumerate() {
+            let j: isize = i + center - k as isize;
+            if j < 0 || j >= pairs { continue; }
+            filtered[2*i as usize] += tap * track[2*j as usize];
+            filtered[2*i as usize + 1] += tap * track[2*j as usize + 1];
+        }
+    }
+    filtered
+}
+
+// Converts `track` (interleaved stereo) from `from_rate` to `to_rate` Hz by linear interpolation. When downsampling,
+// a low-pass at the new Nyquist frequency (see `LOW_PASS_TAPS`) is applied first, so that content the new rate can't
+// hold is removed instead of aliasing into lower frequencies. The track is returned as is if a rate is 0.
+pub fn resample(track: &TrackBuffer, from_rate: u32, to_rate: u32) -> TrackBuffer {
+    if from_rate == to_rate || from_rate == 0 || to_rate == 0 {
+        return track.clone();
+    }
+
+    let filtered: TrackBuffer;
+    let source: &TrackBuffer = match to_rate < from_rate {
+        true  => {
+            filtered = filter_stereo(track, &low_pass_taps(to_rate as f32 / (2 * from_rate) as f32, LOW_PASS_TAPS));
+            &filtered
+        }
+        false => { track }
+    };
+
+    let pairs: usize = source.len() / 2;
+    let out_pairs: usize = (pairs as u64 * to_rate as u64 / from_rate as u64) as usize;
+    let step: f64 = from_rate as f64 / to_rate as f64;
+    let mut resampled: TrackBuffer = Vec::with_capacity(2 * out_pairs);
+    for i in 0..out_pairs {
+        let position: f64 = i as f64 * step;
+        let before: usize = position as usize;
+        let after: usize = min(before + 1, pairs - 1);
+        let fraction: f32 = (position - before as f64) as f32;
+        for c in 0..2 {
+            let (a, b) = (source[2*before + c], source[2*after + c]);
+            resampled.push(a + (b - a) * fraction);
+        }
+    }
+    resampled
+}
+
+// Archives --------------------------------------------------------------------------------------------------------------
+// Whether a source is a .zip archive instead of a directory
+pub fn is_zip(path: &String) -> bool {
+    Path::new(path).extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("zip"))
+}
+
+// Imports the 4 separated tracks from a .zip archive; The stems may be in any folder of the archive but have to follow
+// the same naming rules as in a directory. Every stem is read into memory and decoded from there, nothing is extracted
+// to the disk. Returns the decoded Tracks.
+pub fn import_from_zip(path: &String, limit: Option<f32>) -> Result<Vec<Track>, SpecCompError> {
+    statusln!("Looking into {} for separated stems...", path);
+    let f: File = open_file(path, "import_from_zip()")?;
+    let mut archive = match zip::ZipArchive::new(f) {
+        Ok(a)  => { a }
+        Err(e) => { return Result::Err(SpecCompError::UnsupportedFormat(format!("import_from_zip(): {} is not a valid .zip archive ({})", path, e))); }
+    };
+
+    // Entry names are only accepted if they stay inside the archive
+    let candidates: Vec<PathBuf> = (0..archive.len())
+        .filter_map(|i| archive.by_index(i).ok().and_then(|e| e.enclosed_name()))
+        .collect();
+    let entries: Vec<PathBuf> = match_stem_files(candidates, path)?;
+
+    let mut tracks: Vec<Track> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let name: String = entry.to_string_lossy().to_string();
+        let mut bytes: Vec<u8> = vec![];
+        let read = archive.by_name(&name).map_err(|e| e.to_string())
+            .and_then(|mut e| e.read_to_end(&mut bytes).map_err(|e| e.to_string()));
+        if let Err(e) = read {
+            return Result::Err(SpecCompError::Io(format!("import_from_zip(): Could not read {} from {} ({})", name, path, e)));
+        }
+
+        let display_name: String = format!("{}:{}", path, name);
+        let mut track: Track = Track::new();
+        decode_source(&display_name, extension_hint(&display_name), Box::new(Cursor::new(bytes)), limit, &mut |chunk: &[f32]| track.extend_sanitized(chunk))?
+            .apply_to(&mut track);
+        tracks.push(track);
+    }
+
+    upmix_mono_stems(&mut tracks, path)?;
+    warn_on_duration_mismatch(&tracks, path);
+    Result::Ok(tracks)
+}
+
+// Source detection -------------------------------------------------------------------------------------------------
+// What a source given on the command line is; See `detect_source_kind()`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceKind {
+    // A directory with a file for every stem
+    StemDirectory,
+    // A .zip archive with a file for every stem
+    StemArchive,
+    // A single file with one audio track per stem; Holds the file's path, which is inside the source if it is a directory.
+    // Such files are recognized so that they can be reported, but they can't be imported
+    MultiTrackFile(String),
+    // Both layouts would fit partly; Holds the reason, nothing is guessed
+    Ambiguous(String)
+}
+
+// Tells how the stems of `path` are laid out. A file is either a .zip archive or a multi-track file (a file with more
+// than one audio track); Any other file, e.g. a single stem, is an UnsupportedFormat error.
+// In a directory the separated stems take priority: if all of them are there, multi-track files next to them are
+// ignored. Otherwise the directory falls back to a multi-track file (a file with more than one audio track), as long
+// as there is exactly one and none of the stems is there. A directory with neither is a StemDirectory, so that the
+// missing stems get reported when importing it.
+pub fn detect_source_kind(path: &String, pattern: Option<&str>) -> Result<SourceKind, SpecCompError> {
+    let metadata = match std::fs::metadata(path) {
+        Ok(m)  => { m }
+        Err(_) => { return Result::Err(SpecCompError::Io(format!("detect_source_kind(): Could not open {}.", path))); }
+    };
+    if !metadata.is_dir() {
+        if is_zip(path) {
+            return Result::Ok(SourceKind::StemArchive);
+        }
+        return match count_tracks(path) {
+            Option::Some(c) if c > 1 => { Result::Ok(SourceKind::MultiTrackFile(path.clone())) }
+            Option::Some(_) => {
+                Result::Err(SpecCompError::UnsupportedFormat(format!("detect_source_kind(): {} is a single audio track, not a source; Pass a directory or a .zip archive with the 4 stems", path)))
+            }
+            Option::None => {
+                Result::Err(SpecCompError::UnsupportedFormat(format!("detect_source_kind(): {} is neither a .zip archive nor an audio file that can be read", path)))
+            }
+        };
+    }
+
+    let found_stems: usize = match find_stem_files(path, pattern) {
+        Ok(_) => { return Result::Ok(SourceKind::StemDirectory); }
+        Err(SpecCompError::MissingStems { found, .. }) => { found }
+        Err(e) => { return Result::Err(e); }
+    };
+
+    // Audio files that aren't named after a stem and hold more than one track
+    let multitrack_files: Vec<String> = match std::fs::read_dir(path) {
+        Ok(d)  => {
+            d.filter_map(|e| e.ok()).map(|e| e.path())
+                .filter(|p| p.is_file())
+                .filter(|p| p.extension().and_then(|e| e.to_str()).is_some_and(|e| STEM_EXTENSIONS.contains(&e.to_lowercase().as_str())))
+                .filter(|p| p.file_stem().and_then(|n| n.to_str()).is_some_and(|n| !STEM_NAMES.contains(&n.to_lowercase().as_str())))
+                .map(|p| p.to_string_lossy().to_string())
+                .filter(|p| count_tracks(p).is_some_and(|c| c > 1))
+                .collect()
+        }
+        Err(_) => { return Result::Err(SpecCompError::Io(format!("detect_source_kind(): Could not read the directory {}.", path))); }
+    };
+
+    match (multitrack_files.len(), found_stems) {
+        (0, _) => { Result::Ok(SourceKind::StemDirectory) }
+        (1, 0) => { Result::Ok(SourceKind::MultiTrackFile(multitrack_files[0].clone())) }
+        (1, f) => {
+            Result::Ok(SourceKind::Ambiguous(format!("{} holds {} of the 4 stems as well as the multi-track file {}; Either add the missing stems or remove them",
+                path, f, multitrack_files[0])))
+        }
+        (n, _) => {
+            Result::Ok(SourceKind::Ambiguous(format!("{} holds {} multi-track files ({}); Pass the one to compare instead of the directory",
+                path, n, multitrack_files.join(", "))))
+        }
+    }
+}
+
+// Error for a source that is a multi-track file (see `SourceKind`); `caller` prefixes the message
+pub fn multitrack_unsupported(caller: &str, file: &String) -> SpecCompError {
+    SpecCompError::UnsupportedFormat(format!("{}: {} is a multi-track file, which can't be read; Extract its stems into a directory", caller, file))
+}
+
+// Number of tracks in the container of a file; None if it can't be opened or probed
+fn count_tracks(path: &String) -> Option<usize> {
+    let f = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(f), Default::default());
+    let probe = symphonia::default::get_probe().format(&extension_hint(path), mss, &format_options(), &Default::default()).ok()?;
+    Option::Some(probe.format.tracks().len())
+}
+
+// Checking ---------------------------------------------------------------------------------------------------------
+// What probing a stem tells without decoding it
+#[derive(Debug, Clone, PartialEq)]
+pub struct StemInfo {
+    // Path of the file (for archives: "archive.zip:entry")
+    pub path: String,
+    // Short name of the codec, e.g. "mp3" or "flac"
+    pub codec: String,
+    pub sample_rate: u32,
+    pub channels: usize,
+    // Duration in seconds, if the container tells the number of frames
+    pub duration: Option<f32>
+}
+
+// Finds the 4 separated stems of a source (see `detect_source_kind()`) and reads the header of each of them; Only
+// the container and the codec parameters are read, nothing is decoded. Used to validate inputs before a long run.
+// Returns the info of each stem in the usual order (see `find_stem_files()`).
+pub fn probe_stems(path: &String, pattern: Option<&str>) -> Result<Vec<StemInfo>, SpecCompError> {
+    match detect_source_kind(path, pattern)? {
+        SourceKind::StemArchive => {}
+        SourceKind::MultiTrackFile(file) => { return Result::Err(multitrack_unsupported("probe_stems()", &file)); }
+        SourceKind::Ambiguous(reason) => { return Result::Err(SpecCompError::InvalidArgument(format!("probe_stems(): {}", reason))); }
+        SourceKind::StemDirectory => {
+            return find_stem_files(path, pattern)?.iter()
+            .map(|p| {
+                let name: String = p.to_string_lossy().to_string();
+                probe_source(&name, Box::new(open_file(p, "probe_stems()")?))
+            })
+            .collect();
+        }
+    }
+
+    // Archive entries can't be seeked, so they are read into memory (but still not decoded)
+    let f: File = open_file(path, "probe_stems()")?;
+    let mut archive = match zip::ZipArchive::new(f) {
+        Ok(a)  => { a }
+        Err(e) => { return Result::Err(SpecCompError::UnsupportedFormat(format!("probe_stems(): {} is not a valid .zip archive ({})", path, e))); }
+    };
+    let candidates: Vec<PathBuf> = (0..archive.len())
+        .filter_map(|i| archive.by_index(i).ok().and_then(|e| e.enclosed_name()))
+        .collect();
+
+    let mut infos: Vec<StemInfo> = vec![];
+    for entry in match_stem_files(candidates, path)? {
+        let name: String = entry.to_string_lossy().to_string();
+        let mut bytes: Vec<u8> = vec![];
+        let read = archive.by_name(&name).map_err(|e| e.to_string())
+            .and_then(|mut e| e.read_to_end(&mut bytes).map_err(|e| e.to_string()));
+        if let Err(e) = read {
+            return Result::Err(SpecCompError::Io(format!("probe_stems(): Could not read {} from {} ({})", name, path, e)));
+        }
+        infos.push(probe_source(&format!("{}:{}", path, name), Box::new(Cursor::new(bytes)))?);
+    }
+    Result::Ok(infos)
+}
+
+// Reads the container of `source` up to the codec parameters of its track; `path` is used for the hint and in messages
+fn probe_source(path: &String, source: Box<dyn MediaSource>) -> Result<StemInfo, SpecCompError> {
+    let mss = MediaSourceStream::new(source, Default::default());
+    let probe = match symphonia::default::get_probe().format(&extension_hint(path), mss, &format_options(), &Default::default()) {
+        Result::Ok(p)  => { p }
+        Result::Err(_) => { return Result::Err(SpecCompError::UnsupportedFormat(format!("symphonia::default::get_probe(): Unsupported format ({})", path))); }
+    };
+
+    let tracks = probe.format.tracks();
+    if tracks.len() != 1 {
+        return Result::Err(SpecCompError::UnsupportedFormat(format!("probe_stems(): {} doesn't contain just one audio track (contains {})", path, tracks.len())));
+    }
+    stem_info(path, &tracks[0].codec_params)
+}
+
+// Short name of a track's codec, or an empty string if Symphonia doesn't know it
+fn codec_name(params: &CodecParameters) -> String {
+    match symphonia::default::get_codecs().get_codec(params.codec) {
+        Some(d) => { String::from(d.short_name) }
+        None    => { String::new() }
+    }
+}
+
+// Whether a codec (as named by Symphonia) is lossy; Lossy encoders pad the start and the end of a track, which shifts
+// it against a lossless version of the same audio unless the padding is known and removed
+pub fn is_lossy_codec(codec: &str) -> bool {
+    matches!(codec, "mp1" | "mp2" | "mp3" | "aac" | "vorbis" | "opus")
+}
+
+// Stems whose codecs differ between the two sources when either of them is lossy; The encoder's padding then likely
+// shifts the frames of one stem against the other's, which inflates the errors. `tracks` holds the stems of the first
+// source, then those of the second one. Returns the index of every such stem.
+pub fn codec_mismatches(tracks: &[Track]) -> Vec<usize> {
+    let stems: usize = tracks.len() / 2;
+    (0..stems).filter(|s| {
+        let pair: [&Track; 2] = [&tracks[*s], &tracks[stems + s]];
+        pair[0].codec != pair[1].codec && pair.iter().any(|t| is_lossy_codec(&t.codec))
+    }).collect()
+}
+
+// Warns about the stems of `codec_mismatches()`; The offset is given when the metadata tells it
+pub fn warn_on_codec_mismatch(tracks: &[Track], stem_names: &[String], sources: [&String; 2], manual_delay: Option<usize>) {
+    let stems: usize = tracks.len() / 2;
+    for s in codec_mismatches(tracks) {
+        let pair: [&Track; 2] = [&tracks[s], &tracks[stems + s]];
+        statusln!("\nWarning: {} is {} in {} but {} in {}; Encoder padding may shift one against the other and inflate the errors.",
+            stem_names[s % stem_names.len()], pair[0].codec, sources[0], pair[1].codec, sources[1]);
+        for (t, source) in pair.iter().zip(sources.iter()).filter(|(t, _)| is_lossy_codec(&t.codec)) {
+            match (t.encoder_delay, manual_delay) {
+                (Some(d), _) => {
+                    statusln!("\t{}: The metadata gives an encoder delay of {} samples, which has been removed.", source, d);
+                }
+                (None, Some(d)) => {
+                    statusln!("\t{}: No encoder delay in the metadata, skipped {} samples as asked by --encoder-delay.", source, d);
+                }
+                (None, None) => {
+                    statusln!("\t{}: No encoder delay in the metadata, so the track may be shifted by an unknown offset (e.g. 1105 samples for LAME); Align it with --encoder-delay or compare with --jitter.", source);
+                }
+            }
+        }
+    }
+}
+
+// Info of a stem out of its codec parameters; The codec has to be known for the stem to be decodable later on
+fn stem_info(path: &String, params: &CodecParameters) -> Result<StemInfo, SpecCompError> {
+    let codec: String = match symphonia::default::get_codecs().get_codec(params.codec) {
+        Some(d) => { String::from(d.short_name) }
+        None    => { return Result::Err(SpecCompError::UnsupportedFormat(format!("probe_stems(): The codec of {} is not supported", path))); }
+    };
+    let sample_rate: u32 = params.sample_rate.unwrap_or(0);
+    let duration: Option<f32> = match (params.n_frames, sample_rate) {
+        (Some(n), r) if r > 0 => { Option::Some(n as f32 / r as f32) }
+        _ => { Option::None }
+    };
+
+    Result::Ok(StemInfo {
+        path: path.clone(),
+        codec,
+        sample_rate,
+        channels: params.channels.or(params.channel_layout.map(|l| l.into_channels())).map(|c| c.count()).unwrap_or(0),
+        duration
+    })
+}
+
+
+// Multithreaded ---------------------------------------------------------------------------------------------------
+// Imports the 4 separated tracks from a directory; The stems are found by `find_stem_files()`
+// At most `threads` tracks are decoded at the same time.
+// Returns the decoded Tracks.
+pub fn mt_import_from_directory(path: &String, pattern: Option<&str>, threads: usize, limit: Option<f32>) -> Result<Vec<Track>, SpecCompError> {
+    statusln!("Looking into {} for separated stems...", path);
+    let paths: Vec<PathBuf> = find_stem_files(path, pattern)?;
+    mt_import_files(path, &paths, threads, limit, mt_import_track)
+}
+
+// Decodes a file into the given Track, reporting its progress through the sender (see `mt_import_track()`)
+type DecodeJob = fn(&String, Option<f32>, Sender<i32>, Arc<Mutex<Track>>);
+
+// Decodes the 4 stems at `paths` with one `decode` job each; `path` is the source and only used in messages
+fn mt_import_files(path: &String, paths: &[PathBuf], threads: usize, limit: Option<f32>, decode: DecodeJob) -> Result<Vec<Track>, SpecCompError> {
+    let decode_start = Instant::now();
 
     // Use 4 MPSC pairs, one for each thread
-    let mut receivers: Vec<Receiver<i32>> = vec![];
-    receivers.reserve(4);
+    let mut receivers: Vec<Receiver<i32>> = Vec::with_capacity(4);
 
     // Create 4 shared vectors; Each thread should have its own Arc
-    let mut shared_buffers: Vec<Arc<Mutex<TrackBuffer>>> = vec![];
-    shared_buffers.reserve(4);
+    let mut shared_buffers: Vec<Arc<Mutex<Track>>> = Vec::with_capacity(4);
+
+    // Message of every job that panicked; A panic would otherwise leave an empty track behind
+    let panics: Arc<Mutex<Vec<Option<String>>>> = Arc::new(Mutex::new(vec![Option::None; 4]));
 
-    // Spawn threads
-    let mut handles: Vec<JoinHandle<_>> = vec![];
-    handles.reserve(4);
+    // Queue one job per track
+    let mut jobs: Vec<Job> = Vec::with_capacity(4);
 
-    for filename in paths {
-        let filename = filename.unwrap();
+    for (i, filename) in paths.iter().enumerate() {
         let filename_string: String = filename.to_str().unwrap().to_string();
 
         // Create a buffer behind an Arc and keep a copy
-        let new_buffer: Arc<Mutex<TrackBuffer>> = Arc::new(Mutex::<TrackBuffer>::new(vec![]));
+        let new_buffer: Arc<Mutex<Track>> = Arc::new(Mutex::<Track>::new(Track::new()));
         shared_buffers.push(
             Arc::clone(&new_buffer)
         );
@@ -83,88 +817,115 @@ pub fn mt_import_from_directory(path: &String) -> Result<Vec<TrackBuffer>, Strin
         let (tx, rx) = channel();
         receivers.push(rx);
 
-        // Start thread
-        handles.push(
-            thread::spawn(move || { mt_import_track(
+        // The job's own sender is dropped while unwinding, so a copy reports the panic
+        let panic_tx: Sender<i32> = tx.clone();
+        let panics = Arc::clone(&panics);
+        jobs.push(Box::new(move || {
+            let decoded = panic::catch_unwind(AssertUnwindSafe(|| decode(
                 &filename_string, 
+                limit,
                 tx,
-                Arc::clone(&new_buffer)) }    
-            )
-        );
+                Arc::clone(&new_buffer))
+            ));
+            if let Err(payload) = decoded {
+                panics.lock().unwrap()[i] = Option::Some(panic_message(payload.as_ref()));
+                let _ = panic_tx.send(-6);
+            }
+        }));
     }
 
+    // Start the worker threads
+    let handles = spawn_workers(jobs, threads);
+
     // Create a vector to store each thread's state
     let mut samples_decoded: Vec<(i32, i32)> = vec![];
     samples_decoded.resize(4, (-100, 0));
 
-    let mut threads_finished: u32 = 0;
-    while threads_finished < 4 {
+    // A job is over once its channel is closed
+    let mut jobs_finished: Vec<bool> = vec![false; 4];
+    while jobs_finished.contains(&false) {
         for i in 0..4 {
-            // Check if the thread finished
-            if handles[i].is_finished() { 
-                threads_finished += 1; 
-                continue;
-            }
-
-            // Read thread's channel
-            match receivers[i].recv() {
-                Ok(r) => {
-                    samples_decoded[i] = (samples_decoded[i].1, r);
-                }
-                Err(_) => {
-                    println!("\nWarning: Thread {} is not responding.\n", i);
-                    samples_decoded[i] = (-6, -6);
-                    threads_finished += 1;
+            if jobs_finished[i] { continue; }
+
+            // Read everything the job sent since the last poll
+            loop {
+                match receivers[i].try_recv() {
+                    Ok(r) => { samples_decoded[i] = (samples_decoded[i].1, r); }
+                    Err(TryRecvError::Empty) => { break; }
+                    Err(TryRecvError::Disconnected) => { jobs_finished[i] = true; break; }
                 }
             }
         }
 
-        // Print state
-        print!("\r Decoding... [ ");
+        // Print state; queued jobs that haven't started yet are shown with "--"
+        status!("\r Decoding... [ ");
         for i in 0..4 {
-            if samples_decoded[i].1 < 1 { print!("ER\t"); }
-            else if samples_decoded[i].0 == samples_decoded[i].1 { print!("OK\t"); }
-            else { print!("{}\t", samples_decoded[i].1); }
+            if samples_decoded[i].1 == 0 && !jobs_finished[i] { status!("--\t"); }
+            else if samples_decoded[i].1 < 1 { status!("ER\t"); }
+            else if samples_decoded[i].0 == samples_decoded[i].1 { status!("OK\t"); }
+            else { status!("{}\t", samples_decoded[i].1); }
         }
-        print!("]");
+        status!("]");
 
         // Sleep
         thread::sleep(Duration::from_millis(10));
     }
 
-    print!("\rDone decoding.                                                                   \n");
+    // The jobs catch their own panics, so a worker only panics if the queue itself failed
+    let joined: Vec<bool> = handles.into_iter().map(|h| h.join().is_ok()).collect();
+    status!("\rDone decoding.                                                                   \n");
+    if joined.contains(&false) {
+        return Result::Err(SpecCompError::Decode(format!("mt_import_from_directory(): A worker thread panicked while decoding {}.", path)));
+    }
+
+    // A job that panicked or failed leaves an empty (or partial) track; The reason was already printed
+    for i in 0..4 {
+        if let Some(m) = &panics.lock().unwrap()[i] {
+            return Result::Err(SpecCompError::Decode(format!("mt_import_from_directory(): The thread decoding {} panicked ({}).", paths[i].display(), m)));
+        }
+        if samples_decoded[i].1 < 0 {
+            return Result::Err(SpecCompError::Decode(format!("mt_import_from_directory(): Could not decode {}.", paths[i].display())));
+        }
+    }
 
     // Return the shared buffers
     let mut tracks_interleaved_vec = vec![];
     for i in 0..4 {
-        let track: TrackBuffer = shared_buffers[i].lock().unwrap().to_vec();
+        let track: Track = std::mem::take(&mut *shared_buffers[i].lock().unwrap());
+        if track.samples.is_empty() {
+            return Result::Err(SpecCompError::Decode(format!("mt_import_from_directory(): {} was decoded without errors but holds no samples.", paths[i].display())));
+        }
+        verboseln!("\t{}: {} samples per channel at {} Hz", paths[i].display(), track.samples.len() / track.channels.max(1), track.sample_rate);
         tracks_interleaved_vec.push(track);
     }
+    verboseln!("\tDecoded {} in {} ms", path, decode_start.elapsed().as_millis());
 
-    return Result::Ok(tracks_interleaved_vec)
+    upmix_mono_stems(&mut tracks_interleaved_vec, path)?;
+    warn_on_duration_mismatch(&tracks_interleaved_vec, path);
+    Result::Ok(tracks_interleaved_vec)
 }
 
 
 // Multithread variant. This function should be executed by a single thread.
-// Loads a track from a file and returns a TrackBuffer (vector of 32-bit floats); Channels are interleaved in the output
-fn mt_import_track(path: &String, tx: Sender<i32>, buffer: Arc<Mutex<TrackBuffer>>) {
+// Loads a track from a file into a Track (vector of 32-bit floats and sample rate); Channels are interleaved in the output
+fn mt_import_track(path: &String, limit: Option<f32>, tx: Sender<i32>, buffer: Arc<Mutex<Track>>) {
     // Check this file is an .mp4
-    let f = File::open(path);
-    if f.is_err() { 
-        println!("\nimport_from_file(): Could not open {}.", path);
-        let _ = tx.send(-1);
-        return;
-    }
-    let f = f.unwrap();
+    let f: File = match open_file(path, "import_from_file()") {
+        Ok(f)  => { f }
+        Err(e) => {
+            println!("\n{e}");
+            let _ = tx.send(-1);
+            return;
+        }
+    };
 
     // Media Source Stream, metadata and format readers
     let mss = MediaSourceStream::new(Box::new(f), Default::default());
     let meta_opts:  MetadataOptions = Default::default();
-    let fmt_opts:   FormatOptions   = Default::default();
+    let fmt_opts:   FormatOptions   = format_options();
 
-    // Create a hint
-    let mut hint = Hint::new();
-    hint.with_extension("mp3");
+    // The extension is only a hint; Probing looks at the contents
+    let hint = extension_hint(path);
 
     // Probe
     let probe = match symphonia::default::get_probe().format(&hint, mss, &fmt_opts, &meta_opts) {
@@ -186,7 +947,7 @@ fn mt_import_track(path: &String, tx: Sender<i32>, buffer: Arc<Mutex<TrackBuffer
     }
 
     // Create a decoder 
-    let track = format_reader.tracks().get(0).unwrap();
+    let track = format_reader.tracks().first().unwrap();
     let dec_opts: DecoderOptions = Default::default();
     let mut decoder = match symphonia::default::get_codecs().make(&track.codec_params, &dec_opts){
         Result::Ok(d)  => { d }
@@ -200,9 +961,14 @@ fn mt_import_track(path: &String, tx: Sender<i32>, buffer: Arc<Mutex<TrackBuffer
     // Start decoding
     let mut sample_count: usize = 0;
     let mut temp_buffer = Option::None;
+    let mut max_samples: Option<usize> = Option::None;
+    let mut errors: DecodeErrors = DecodeErrors::default();
+    let mut channels: usize = track.codec_params.channels.map(|c| c.count()).unwrap_or(0);
 
     // Get the buffer behind the mutex; The buffer will be automatically unlocked at the end of the function
     let mut return_buffer = buffer.lock().unwrap(); // .get_mut() implies .lock()
+    return_buffer.encoder_delay = track.codec_params.delay;
+    return_buffer.codec = codec_name(&track.codec_params);
 
     // Read the first packet
     loop {
@@ -226,27 +992,42 @@ fn mt_import_track(path: &String, tx: Sender<i32>, buffer: Arc<Mutex<TrackBuffer
         // Decode to audio sample
         match decoder.decode(&packet) {
             Ok(new_buffer) => {
+                errors.decoded();
                 if temp_buffer.is_none() {
                     let spec = *new_buffer.spec();
                     let duration = new_buffer.capacity() as u64;
                     temp_buffer = Some(SampleBuffer::<f32>::new(duration, spec));
+                    return_buffer.sample_rate = spec.rate;
+                    channels = spec.channels.count();
+                    return_buffer.channels = channels;
+                    max_samples = sample_limit(&spec, limit);
                 }
 
                 if let Some(buf) = &mut temp_buffer {
                     buf.copy_interleaved_ref(new_buffer);
 
-                    return_buffer.extend_from_slice(buf.samples());
-                    sample_count += buf.samples().len();
+                    let chunk: &[f32] = within_limit(buf.samples(), sample_count, max_samples);
+                    return_buffer.extend_sanitized(chunk);
+                    sample_count += chunk.len();
                 }
 
                 break;
             }
-            Err(symphonia::core::errors::Error::DecodeError(_)) => { break; }
+            Err(symphonia::core::errors::Error::DecodeError(_)) => {
+                if !errors.skip() { break; }
+                let gap: Vec<f32> = packet_gap(packet.dur, channels);
+                let chunk: &[f32] = within_limit(&gap, sample_count, max_samples);
+                return_buffer.extend_sanitized(chunk);
+                sample_count += chunk.len();
+            }
             Err(_) => { break; }
         }
     }
 
     loop {
+        // Nothing past the duration limit is decoded
+        if max_samples.is_some_and(|m| sample_count >= m) { break; }
+
         let packet = match format_reader.next_packet() {
             Ok(packet) => packet,
             Err(_) => { break; }
@@ -259,128 +1040,250 @@ fn mt_import_track(path: &String, tx: Sender<i32>, buffer: Arc<Mutex<TrackBuffer
         // Decode to audio sample
         match decoder.decode(&packet) {
             Ok(new_buffer) => {
+                errors.decoded();
                 if let Some(buf) = &mut temp_buffer {
                     buf.copy_interleaved_ref(new_buffer);
-                    sample_count += buf.samples().len();
+                    let chunk: &[f32] = within_limit(buf.samples(), sample_count, max_samples);
+                    sample_count += chunk.len();
 
-                    return_buffer.extend_from_slice(buf.samples());
-                    if sample_count % 64 == 0 { let _ = tx.send(sample_count as i32); }
+                    return_buffer.extend_sanitized(chunk);
+                    if sample_count.is_multiple_of(64) { let _ = tx.send(sample_count as i32); }
                 }
             }
-            Err(symphonia::core::errors::Error::DecodeError(_)) => { break; }
+            Err(symphonia::core::errors::Error::DecodeError(_)) => {
+                if !errors.skip() { break; }
+                let gap: Vec<f32> = packet_gap(packet.dur, channels);
+                let chunk: &[f32] = within_limit(&gap, sample_count, max_samples);
+                return_buffer.extend_sanitized(chunk);
+                sample_count += chunk.len();
+            }
             Err(_) => { break; }
         }
     }
 
+    return_buffer.skipped_packets = errors.skipped;
     let _ = tx.send(sample_count as i32);
     let _ = tx.send(sample_count as i32);
-    return;
 }
 
 
 // Single Thread ------------------------------------------------------------------------------------------------------------------
-// Imports the 4 separated tracks from a directory; The names of the .mp3 files must be {bass, drums, vocals, other}.mp3
-// Returns TrackBuffers
-pub fn import_from_directory(path: &String) -> Result<Vec<TrackBuffer>, String> {
-    println!("Looking into {} for separated stems...", path);
+// Imports the 4 separated tracks from a directory; The stems are found by `find_stem_files()`
+// Returns the decoded Tracks
+pub fn import_from_directory(path: &String, pattern: Option<&str>, limit: Option<f32>) -> Result<Vec<Track>, SpecCompError> {
+    statusln!("Looking into {} for separated stems...", path);
+    let paths: Vec<PathBuf> = find_stem_files(path, pattern)?;
 
-    // Check this directory has all the required files
-    let dir_contents = match std::fs::read_dir(path) {
-        Ok(d) => { d }
-        Err(_) => { return Result::Err(format!("import_from_directory():\n\tread_dir({}): Failed to open firectory (insufficient access rights?)", path)); }
-    };
+    // Import each file's track
+    let mut tracks_interleaved_vec: Vec<Track> = Vec::with_capacity(4);
 
-    let mut paths: Vec<Option<PathBuf>> = Vec::new();
-    paths.resize(4, Option::None);
+    for filename in paths { // PARALLEL
+        let filename_string: String = filename.to_str().unwrap().to_string();
+        tracks_interleaved_vec.push(import_track(&filename_string, limit)?);
+    } 
 
-    let required_files: Vec<&str> = vec!["bass.mp3", "drums.mp3", "vocals.mp3", "other.mp3"];
-    let mut hits = 0;
-    
-    // Try finding all four files in `path`
-    for e in dir_contents {
-        let entry = match e {
-            Ok(r)  => { r }
-            Err(_) => { continue; } // Bad entries will be silently skipped
-        };
+    upmix_mono_stems(&mut tracks_interleaved_vec, path)?;
+    warn_on_duration_mismatch(&tracks_interleaved_vec, path);
+    Result::Ok(tracks_interleaved_vec)
+}
 
-        let mut req_files_it = required_files.iter();
-        // Get entry's path and filename
-        let item_path = &entry.path();
-        let item_name = item_path.file_name().unwrap();
-        
-        // Search for the filename in `required_files`
-        match req_files_it.position(|&x| x == item_name) {
-            Some(index) => {
-                paths[index] = Option::Some(item_path.clone());
-                hits += 1;
-            }
-            None => { continue; }
-        }
-        if hits == 4 { break; }
+
+// Loads a track from a file and returns a Track (vector of 32-bit floats and sample rate); Channels are interleaved in the output
+pub fn import_track(path: &String, limit: Option<f32>) -> Result<Track, SpecCompError> {
+    let mut track: Track = Track::new();
+    decode_track(path, limit, &mut |chunk: &[f32]| track.extend_sanitized(chunk))?.apply_to(&mut track);
+    Result::Ok(track)
+}
+
+// Same as `import_track()` for audio that isn't in a file, e.g. an in-memory buffer (`std::io::Cursor`) or stdin
+// (`symphonia::core::io::ReadOnlySource`); `hint_ext` is the extension the data would have as a file ("wav", "mp3"...)
+// and may be empty.
+pub fn import_track_from_reader(reader: Box<dyn MediaSource>, hint_ext: &str, limit: Option<f32>) -> Result<Track, SpecCompError> {
+    let mut hint = Hint::new();
+    if !hint_ext.is_empty() {
+        hint.with_extension(hint_ext);
     }
 
-    if hits != 4 {
-        return Result::Err(format!("import_from_directory(): Could not find all separated stems (found {}/4)", hits));
+    let mut track: Track = Track::new();
+    let name: String = format!("<reader .{}>", hint_ext);
+    decode_source(&name, hint, reader, limit, &mut |chunk: &[f32]| track.extend_sanitized(chunk))?.apply_to(&mut track);
+    Result::Ok(track)
+}
+
+// Loads a headerless file of little-endian 32-bit float samples (e.g. an "f32le" dump), which Symphonia can't probe,
+// so the number of channels and the sample rate have to be given. The samples are read as they are, already
+// interleaved; Like decoded samples, NaN, infinite and subnormal ones are replaced with 0.0 and the duration limit
+// applies. The file must hold a whole number of frames of `channels` samples.
+pub fn import_raw_pcm(path: &String, channels: usize, sample_rate: u32, limit: Option<f32>) -> Result<Track, SpecCompError> {
+    if channels == 0 || sample_rate == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(format!("import_raw_pcm(): The channels and the sample rate must be positive (got {} and {})", channels, sample_rate)));
     }
 
-    // Import each file's track
-    let mut tracks_interleaved_vec: Vec<TrackBuffer> = vec![];
-    tracks_interleaved_vec.reserve(4);
+    let mut bytes: Vec<u8> = vec![];
+    match open_file(path, "import_raw_pcm()")?.read_to_end(&mut bytes) {
+        Ok(_)  => {}
+        Err(e) => { return Result::Err(SpecCompError::Io(format!("import_raw_pcm(): Could not read {} ({}).", path, e))); }
+    }
+    if bytes.is_empty() {
+        return Result::Err(SpecCompError::EmptyInput(format!("import_raw_pcm(): {} is empty.", path)));
+    }
+    if !bytes.len().is_multiple_of(4 * channels) {
+        return Result::Err(SpecCompError::Decode(format!("import_raw_pcm(): {} isn't made of whole {} channel frames of f32 samples ({} bytes is not a multiple of {}).",
+            path, channels, bytes.len(), 4 * channels)));
+    }
 
-    for filename in paths { // PARALLEL
-        let filename = filename.unwrap();
-        let filename_string: String = filename.to_str().unwrap().to_string();
-        match import_track(&filename_string) {
-            Ok(ret_buffer) => {
-                tracks_interleaved_vec.push(ret_buffer);
-            }
-            Err(e) => { return Result::Err(format!("import_from_directory():\n\t{}", e)); }
-        }
-    } 
+    let samples: Vec<f32> = bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+    let max_samples: Option<usize> = limit.map(|seconds| (seconds.max(0.0) as f64 * sample_rate as f64) as usize * channels);
 
-    return Result::Ok(tracks_interleaved_vec)
+    let mut track: Track = Track::new();
+    track.extend_sanitized(within_limit(&samples, 0, max_samples));
+    track.sample_rate = sample_rate;
+    track.channels = channels;
+    Result::Ok(track)
 }
 
+// Decodes a track chunk by chunk and computes its spectogram on the fly; The decoded samples are discarded as soon as
+// they have been used, so this needs far less memory than `import_track` for long files.
+// Returns the spectogram (same as `track_to_spec` on the whole track) and the sample rate.
+pub fn import_track_spectogram(path: &String, fft_size: u32, window: WindowFunction, limit: Option<f32>) -> Result<(StereoSpectogram, u32), SpecCompError> {
+    let mut builder: StreamingSpectogram = StreamingSpectogram::new(fft_size, window);
+    let info: DecodeInfo = decode_track(path, limit, &mut |chunk: &[f32]| builder.push(chunk))?;
+    Result::Ok((builder.finish(), info.sample_rate))
+}
 
-// Loads a track from a file and returns a TrackBuffer (vector of 32-bit floats); Channels are interleaved in the output
-pub fn import_track(path: &String) -> Result<TrackBuffer, String> {
+// Duration limit ---------------------------------------------------------------------------------------------------------
+// Every importer takes a `limit` (in seconds) and stops decoding a track once it has that many seconds of samples; None
+// decodes whole tracks. Returns the number of interleaved samples the limit leaves of a track with the given spec.
+fn sample_limit(spec: &SignalSpec, limit: Option<f32>) -> Option<usize> {
+    limit.map(|seconds| (seconds.max(0.0) as f64 * spec.rate as f64) as usize * spec.channels.count())
+}
+
+// The part of a decoded chunk that is still within `max_samples`, given the `decoded` samples before it
+fn within_limit(chunk: &[f32], decoded: usize, max_samples: Option<usize>) -> &[f32] {
+    match max_samples {
+        Some(m) => { &chunk[..min(chunk.len(), m.saturating_sub(decoded))] }
+        None    => { chunk }
+    }
+}
+
+
+// What decoding a track tells about it besides its samples
+struct DecodeInfo {
+    sample_rate: u32,
+    // Channels of the decoded audio; The samples handed to the sink are interleaved
+    channels: usize,
+    codec: String,
+    // Encoder delay that was removed, if the file tells it (see `format_options()`)
+    encoder_delay: Option<u32>,
+    // Corrupt packets that were skipped (see `DecodeErrors`)
+    skipped_packets: usize
+}
+
+impl DecodeInfo {
+    fn apply_to(&self, track: &mut Track) {
+        track.sample_rate = self.sample_rate;
+        track.channels = self.channels;
+        track.codec = self.codec.clone();
+        track.encoder_delay = self.encoder_delay;
+        track.skipped_packets = self.skipped_packets;
+    }
+}
+
+// Decode errors ----------------------------------------------------------------------------------------------------------
+// Consecutive packets that may fail to decode before a track is given up on; A single corrupt packet is replaced by
+// silence (see `packet_gap()`), but a long streak means the rest of the file is unreadable
+const MAX_CONSECUTIVE_DECODE_ERRORS: usize = 16;
+
+// Silence standing in for a packet of `packet_dur` frames that failed to decode, so that everything after it keeps
+// its place in the track instead of moving ahead against the other source
+fn packet_gap(packet_dur: u64, channels: usize) -> Vec<f32> {
+    vec![0.0; packet_dur as usize * channels]
+}
+
+// Keeps track of the packets that failed to decode
+#[derive(Debug, Default)]
+struct DecodeErrors {
+    // Packets skipped so far
+    skipped: usize,
+    // Packets that failed in a row
+    consecutive: usize
+}
+
+impl DecodeErrors {
+    // A packet was decoded; Ends the streak
+    fn decoded(&mut self) {
+        self.consecutive = 0;
+    }
+
+    // A packet failed to decode and is skipped; Returns false once too many packets failed in a row
+    fn skip(&mut self) -> bool {
+        self.skipped += 1;
+        self.consecutive += 1;
+        self.consecutive < MAX_CONSECUTIVE_DECODE_ERRORS
+    }
+}
+
+
+// Options of every format reader; With gapless playback the encoder delay and padding that some encoders (e.g. LAME)
+// write to the file's metadata are trimmed by the decoder, so that every source starts at its true first sample
+fn format_options() -> FormatOptions {
+    FormatOptions { enable_gapless: true, ..Default::default() }
+}
+
+// Probe hint with the real extension of `path`, if it has one
+fn extension_hint(path: &str) -> Hint {
+    let mut hint = Hint::new();
+    if let Some(e) = Path::new(path).extension().and_then(|e| e.to_str()) {
+        hint.with_extension(e);
+    }
+    hint
+}
+
+// Decodes a file and hands each decoded chunk of interleaved samples to `sink`; Returns what was learned about the
+// track while decoding (see `DecodeInfo`)
+fn decode_track(path: &String, limit: Option<f32>, sink: &mut dyn FnMut(&[f32])) -> Result<DecodeInfo, SpecCompError> {
     // Check this file is an .mp4
-    let f = File::open(path);
-    if f.is_err() { return Result::Err(format!("import_from_file(): Could not open {}.", path)); }
-    let f = f.unwrap();
+    let f: File = open_file(path, "import_from_file()")?;
+
+    decode_source(path, extension_hint(path), Box::new(f), limit, sink)
+}
 
+// Decodes any media source (file, in-memory buffer...) like `decode_track()`; `path` is only used in messages
+fn decode_source(path: &String, hint: Hint, source: Box<dyn MediaSource>, limit: Option<f32>, sink: &mut dyn FnMut(&[f32])) -> Result<DecodeInfo, SpecCompError> {
     // Media Source Stream, metadata and format readers
-    let mss = MediaSourceStream::new(Box::new(f), Default::default());
+    let mss = MediaSourceStream::new(source, Default::default());
     let meta_opts:  MetadataOptions = Default::default();
-    let fmt_opts:   FormatOptions   = Default::default();
-
-    // Create a hint
-    let mut hint = Hint::new();
-    hint.with_extension("mp3");
+    let fmt_opts:   FormatOptions   = format_options();
 
-    // Probe
+    // Probe; The hint only helps, probing looks at the contents
     let probe = match symphonia::default::get_probe().format(&hint, mss, &fmt_opts, &meta_opts) {
         Result::Ok(p)  => { p }
-        Result::Err(_) => {  return Result::Err(String::from("symphonia::default::get_probe(): Unsupported format"));  }
+        Result::Err(_) => {  return Result::Err(SpecCompError::UnsupportedFormat(format!("symphonia::default::get_probe(): Unsupported format ({})", path)));  }
     };
 
     // Handle format info
     let mut format_reader = probe.format;
     let track_count = format_reader.tracks().len();
-    if track_count != 1 { return Result::Err(format!("import_from_file(): This file doesn't contain just one audio track (containts {})", track_count)); }
+    if track_count != 1 { return Result::Err(SpecCompError::UnsupportedFormat(format!("import_from_file(): This file doesn't contain just one audio track (containts {})", track_count))); }
 
     // Create a decoder 
-    let track = format_reader.tracks().get(0).unwrap();
+    let track = format_reader.tracks().first().unwrap();
+    let encoder_delay: Option<u32> = track.codec_params.delay;
+    let codec: String = codec_name(&track.codec_params);
     let dec_opts: DecoderOptions = Default::default();
     let mut decoder = match symphonia::default::get_codecs().make(&track.codec_params, &dec_opts){
         Result::Ok(d)  => { d }
-        Result::Err(_) => {  return Result::Err(format!("import_from_file():\n\tget_codecs(): Unsupported format."));  }
+        Result::Err(_) => {  return Result::Err(SpecCompError::UnsupportedFormat(String::from("import_from_file():\n\tget_codecs(): Unsupported format.")));  }
     };
 
     // Start decoding
     let mut sample_count: usize = 0;
     let mut temp_buffer = Option::None;
-    let mut return_buffer: Vec<f32> = vec![];
+    let mut max_samples: Option<usize> = Option::None;
+    let mut errors: DecodeErrors = DecodeErrors::default();
+    let mut sample_rate: u32 = 0;
+    // Known from the codec for most formats, otherwise from the first decoded packet
+    let mut channels: usize = track.codec_params.channels.map(|c| c.count()).unwrap_or(0);
 
 
     // Read the first packet
@@ -388,7 +1291,7 @@ pub fn import_track(path: &String) -> Result<TrackBuffer, String> {
     loop {
         let packet = match format_reader.next_packet()  {
             Ok(packet) => packet,
-            Err(_) => { return Result::Err(String::from("import_from_file(): The first packet caused an error.")); }
+            Err(_) => { return Result::Err(SpecCompError::Decode(String::from("import_from_file(): The first packet caused an error."))); }
         };
     
         // Consume any new metadata that has been read since the last packet.
@@ -402,27 +1305,41 @@ pub fn import_track(path: &String) -> Result<TrackBuffer, String> {
         // Decode to audio sample
         match decoder.decode(&packet) {
             Ok(new_buffer) => {
+                errors.decoded();
                 if temp_buffer.is_none() {
                     let spec = *new_buffer.spec();
                     let duration = new_buffer.capacity() as u64;
                     temp_buffer = Some(SampleBuffer::<f32>::new(duration, spec));
+                    sample_rate = spec.rate;
+                    channels = spec.channels.count();
+                    max_samples = sample_limit(&spec, limit);
                 }
 
                 if let Some(buf) = &mut temp_buffer {
                     buf.copy_interleaved_ref(new_buffer);
 
-                    return_buffer.extend_from_slice(buf.samples());
-                    sample_count += buf.samples().len();
+                    let chunk: &[f32] = within_limit(buf.samples(), sample_count, max_samples);
+                    sink(chunk);
+                    sample_count += chunk.len();
                 }
 
                 break;
             }
-            Err(symphonia::core::errors::Error::DecodeError(_)) => { break; }
+            Err(symphonia::core::errors::Error::DecodeError(_)) => {
+                if !errors.skip() { break; }
+                let gap: Vec<f32> = packet_gap(packet.dur, channels);
+                let chunk: &[f32] = within_limit(&gap, sample_count, max_samples);
+                sink(chunk);
+                sample_count += chunk.len();
+            }
             Err(_) => { break; }
         }
     }
 
     loop {
+        // Nothing past the duration limit is decoded
+        if max_samples.is_some_and(|m| sample_count >= m) { break; }
+
         let packet = match format_reader.next_packet() {
             Ok(packet) => packet,
             Err(_) => { break; }
@@ -435,13 +1352,15 @@ pub fn import_track(path: &String) -> Result<TrackBuffer, String> {
         // Decode to audio sample
         match decoder.decode(&packet) {
             Ok(new_buffer) => {
+                errors.decoded();
                 if let Some(buf) = &mut temp_buffer {
                     buf.copy_interleaved_ref(new_buffer);
-                    sample_count += buf.samples().len();
+                    let chunk: &[f32] = within_limit(buf.samples(), sample_count, max_samples);
+                    sample_count += chunk.len();
 
-                    return_buffer.extend_from_slice(buf.samples());
-                    if sample_count % 64 == 0 {
-                        print!("\rDecoding... {}", match (sample_count/32768) % 4 {
+                    sink(chunk);
+                    if sample_count.is_multiple_of(64) {
+                        status!("\rDecoding... {}", match (sample_count/32768) % 4 {
                             0_usize => "|",
                             1_usize => "/",
                             2_usize => "-",
@@ -450,18 +1369,766 @@ pub fn import_track(path: &String) -> Result<TrackBuffer, String> {
                     }
                 }
             }
-            Err(symphonia::core::errors::Error::DecodeError(_)) => { break; }
+            Err(symphonia::core::errors::Error::DecodeError(_)) => {
+                if !errors.skip() { break; }
+                let gap: Vec<f32> = packet_gap(packet.dur, channels);
+                let chunk: &[f32] = within_limit(&gap, sample_count, max_samples);
+                sink(chunk);
+                sample_count += chunk.len();
+            }
             Err(_) => { break; }
         }
     }
     let decode_time = decode_start.elapsed();
 
     match sample_count == 0 {
-        true  => { return Result::Err( String::from("import_from_file(): No problems detected but nothing was decoded.")); }
+        true  => { Result::Err(SpecCompError::Decode(String::from("import_from_file(): No problems detected but nothing was decoded."))) }
         false => {
-            println!("\r {}:\n\tDecoded {} samples per channel.\t[{} ms]", path, sample_count/2, decode_time.as_millis());
-            return Result::Ok(return_buffer);
+            let per_channel: usize = sample_count / channels.max(1);
+            statusln!("\r {}:\n\tDecoded {} samples per channel ({:.3}s).\t[{} ms]", path, per_channel, per_channel as f32 / sample_rate.max(1) as f32, decode_time.as_millis());
+            Result::Ok(DecodeInfo { sample_rate, channels, codec, encoder_delay, skipped_packets: errors.skipped })
         }
     }
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectograms::track_to_spec;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(|n| PathBuf::from("song").join(n)).collect()
+    }
+
+    // Empty directory of the system's temporary directory for the files of a test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir: PathBuf = std::env::temp_dir().join(format!("speccomp-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Writes a 16-bit stereo WAV file of `frames` samples per channel (a quiet ramp)
+    fn write_wav(path: &Path, sample_rate: u32, frames: usize) {
+        write_wav_channels(path, sample_rate, 2, frames);
+    }
+
+    // Writes a 16-bit WAV file of `channels` channels holding the ramp of `write_wav()`
+    fn write_wav_channels(path: &Path, sample_rate: u32, channels: u16, frames: usize) {
+        let data_size: u32 = (frames * 2 * channels as usize) as u32;
+        let mut bytes: Vec<u8> = Vec::with_capacity(44 + data_size as usize);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * 2 * channels as u32).to_le_bytes());
+        bytes.extend_from_slice(&(2 * channels).to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_size.to_le_bytes());
+        for i in 0..channels as usize * frames {
+            bytes.extend_from_slice(&((i % 1000) as i16).to_le_bytes());
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    // Writes a 16-bit stereo FLAC file holding the same ramp as `write_wav()`, in a single frame of verbatim subframes
+    fn write_flac(path: &Path, sample_rate: u32, frames: usize) {
+        let crc8 = |data: &[u8]| -> u8 {
+            data.iter().fold(0u8, |crc, b| (0..8).fold(crc ^ b, |c, _| if c & 0x80 != 0 { (c << 1) ^ 0x07 } else { c << 1 }))
+        };
+        let crc16 = |data: &[u8]| -> u16 {
+            data.iter().fold(0u16, |crc, b| (0..8).fold(crc ^ ((*b as u16) << 8), |c, _| if c & 0x8000 != 0 { (c << 1) ^ 0x8005 } else { c << 1 }))
+        };
+
+        // STREAMINFO: block sizes, unknown frame sizes, then 20 bits of sample rate, 3 of channels - 1, 5 of bits per
+        // sample - 1 and 36 of total samples, and no MD5 signature
+        let mut bytes: Vec<u8> = b"fLaC".to_vec();
+        bytes.extend_from_slice(&[0x80, 0, 0, 34]);
+        bytes.extend_from_slice(&(frames as u16).to_be_bytes());
+        bytes.extend_from_slice(&(frames as u16).to_be_bytes());
+        bytes.extend_from_slice(&[0; 6]);
+        bytes.extend_from_slice(&(((sample_rate as u64) << 44) | (1 << 41) | (15 << 36) | frames as u64).to_be_bytes());
+        bytes.extend_from_slice(&[0; 16]);
+
+        // Frame header: fixed blocking, 16-bit block size at its end, the stream's sample rate, independent left and
+        // right channels of 16 bits, frame number 0
+        let mut frame: Vec<u8> = vec![0xFF, 0xF8, 0x70, 0x18, 0x00];
+        frame.extend_from_slice(&((frames - 1) as u16).to_be_bytes());
+        frame.push(crc8(&frame));
+        for channel in 0..2 {
+            frame.push(0x02);
+            for n in 0..frames {
+                frame.extend_from_slice(&(((2 * n + channel) % 1000) as i16).to_be_bytes());
+            }
+        }
+        let crc: u16 = crc16(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes());
+        bytes.extend(frame);
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    // Writes an MPEG-1 Layer III file (44.1 kHz, stereo, 128 kbps) of `frames` silent frames, after an Info frame whose
+    // LAME extension (as written by libavformat) gives an encoder delay of `delay` samples and no padding
+    fn write_mp3(path: &Path, frames: u32, delay: u32) {
+        // Every frame is 417 bytes: The header, 32 bytes of zeroed side info and an empty main data
+        let frame = |body: &[u8]| -> Vec<u8> {
+            let mut f: Vec<u8> = vec![0xFF, 0xFB, 0x90, 0x00];
+            f.extend_from_slice(&[0; 32]);
+            f.extend_from_slice(body);
+            f.resize(417, 0);
+            f
+        };
+
+        let mut info: Vec<u8> = b"Info".to_vec();
+        info.extend_from_slice(&1u32.to_be_bytes());
+        info.extend_from_slice(&frames.to_be_bytes());
+        info.extend_from_slice(b"Lavf58.76");
+        info.extend_from_slice(&[0; 12]);
+        // The delay and the padding are both stored minus the decoder's own delay of 529 samples
+        info.extend_from_slice(&(((delay - 529) << 12) | 529).to_be_bytes()[1..]);
+
+        let mut bytes: Vec<u8> = frame(&info);
+        for _ in 0..frames {
+            bytes.extend(frame(&[]));
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    // EBML element with an 8-byte size
+    fn ebml(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut element: Vec<u8> = id.to_vec();
+        element.push(0x01);
+        element.extend_from_slice(&(body.len() as u64).to_be_bytes()[1..]);
+        element.extend_from_slice(body);
+        element
+    }
+
+    // Writes a Matroska file with `tracks` 16-bit stereo PCM tracks of a few silent samples each
+    fn write_multitrack(path: &Path, tracks: u8) {
+        let header: Vec<u8> = [ebml(&[0x42, 0x86], &[1]), ebml(&[0x42, 0xF7], &[1]), ebml(&[0x42, 0xF2], &[4]), ebml(&[0x42, 0xF3], &[8]),
+            ebml(&[0x42, 0x82], b"matroska"), ebml(&[0x42, 0x87], &[4]), ebml(&[0x42, 0x85], &[2])].concat();
+        let info: Vec<u8> = ebml(&[0x15, 0x49, 0xA9, 0x66], &ebml(&[0x2A, 0xD7, 0xB1], &1_000_000u32.to_be_bytes()));
+
+        let mut entries: Vec<u8> = vec![];
+        let mut cluster: Vec<u8> = ebml(&[0xE7], &[0]);
+        for n in 1..=tracks {
+            let audio: Vec<u8> = [ebml(&[0xB5], &8000f64.to_be_bytes()), ebml(&[0x9F], &[2]), ebml(&[0x62, 0x64], &[16])].concat();
+            entries.extend(ebml(&[0xAE], &[ebml(&[0xD7], &[n]), ebml(&[0x73, 0xC5], &[n]), ebml(&[0x83], &[2]),
+                ebml(&[0x86], b"A_PCM/INT/LIT"), ebml(&[0xE1], &audio)].concat()));
+            cluster.extend(ebml(&[0xA3], &[&[0x80 | n, 0, 0, 0x80][..], &[0; 64]].concat()));
+        }
+        let segment: Vec<u8> = [info, ebml(&[0x16, 0x54, 0xAE, 0x6B], &entries), ebml(&[0x1F, 0x43, 0xB6, 0x75], &cluster)].concat();
+        std::fs::write(path, [ebml(&[0x1A, 0x45, 0xDF, 0xA3], &header), ebml(&[0x18, 0x53, 0x80, 0x67], &segment)].concat()).unwrap();
+    }
+
+    #[test]
+    fn source_kinds() {
+        let dir: PathBuf = scratch_dir("kinds");
+        let source = |name: &str| -> String {
+            let source: PathBuf = dir.join(name);
+            std::fs::create_dir_all(&source).unwrap();
+            source.to_string_lossy().to_string()
+        };
+
+        // Every stem, next to a multi-track file that is ignored
+        let stems: String = source("stems");
+        for stem in STEM_NAMES {
+            write_wav(&Path::new(&stems).join(format!("{}.wav", stem)), 8000, 64);
+        }
+        write_multitrack(&Path::new(&stems).join("song.mkv"), 5);
+        assert_eq!(detect_source_kind(&stems, Option::None).unwrap(), SourceKind::StemDirectory);
+
+        // Only a multi-track file
+        let multitrack: String = source("multitrack");
+        let file: String = Path::new(&multitrack).join("song.mkv").to_string_lossy().to_string();
+        write_multitrack(Path::new(&file), 4);
+        assert_eq!(count_tracks(&file), Option::Some(4));
+        assert_eq!(detect_source_kind(&multitrack, Option::None).unwrap(), SourceKind::MultiTrackFile(file.clone()));
+        assert_eq!(detect_source_kind(&file, Option::None).unwrap(), SourceKind::MultiTrackFile(file.clone()));
+
+        // A single track isn't a source, whatever its container
+        let single: String = dir.join("song.wav").to_string_lossy().to_string();
+        write_wav(Path::new(&single), 8000, 64);
+        assert_eq!(count_tracks(&single), Option::Some(1));
+        assert!(matches!(detect_source_kind(&single, Option::None), Err(SpecCompError::UnsupportedFormat(_))));
+        let single_mkv: String = dir.join("single.mkv").to_string_lossy().to_string();
+        write_multitrack(Path::new(&single_mkv), 1);
+        assert!(matches!(detect_source_kind(&single_mkv, Option::None), Err(SpecCompError::UnsupportedFormat(_))));
+
+        // Some of the stems and a multi-track file, or two multi-track files
+        let partial: String = source("partial");
+        write_wav(&Path::new(&partial).join("bass.wav"), 8000, 64);
+        write_multitrack(&Path::new(&partial).join("song.mkv"), 4);
+        assert!(matches!(detect_source_kind(&partial, Option::None).unwrap(), SourceKind::Ambiguous(_)));
+        let two: String = source("two");
+        write_multitrack(&Path::new(&two).join("a.mkv"), 4);
+        write_multitrack(&Path::new(&two).join("b.mkv"), 4);
+        assert!(matches!(detect_source_kind(&two, Option::None).unwrap(), SourceKind::Ambiguous(_)));
+
+        // A directory with neither reports its missing stems when imported
+        let empty: String = source("empty");
+        write_wav(&Path::new(&empty).join("mix.wav"), 8000, 64);
+        assert_eq!(detect_source_kind(&empty, Option::None).unwrap(), SourceKind::StemDirectory);
+
+        let archive: String = dir.join("stems.zip").to_string_lossy().to_string();
+        std::fs::write(&archive, b"").unwrap();
+        assert_eq!(detect_source_kind(&archive, Option::None).unwrap(), SourceKind::StemArchive);
+
+        assert!(matches!(probe_stems(&multitrack, Option::None), Err(SpecCompError::UnsupportedFormat(_))));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn duration_limit_stops_decoding() {
+        let dir: PathBuf = scratch_dir("limit");
+        let file: String = dir.join("long.wav").to_string_lossy().to_string();
+        let sample_rate: u32 = 8000;
+        write_wav(Path::new(&file), sample_rate, 60 * sample_rate as usize);
+
+        // Chunks handed over by the decoder with and without the limit
+        let mut full_chunks: usize = 0;
+        decode_track(&file, Option::None, &mut |_: &[f32]| full_chunks += 1).unwrap();
+        let mut chunks: usize = 0;
+        let mut samples: usize = 0;
+        decode_track(&file, Option::Some(5.0), &mut |c: &[f32]| { chunks += 1; samples += c.len(); }).unwrap();
+
+        assert_eq!(samples, 2 * 5 * sample_rate as usize);
+        assert!(chunks <= full_chunks / 12 + 1, "decoded {} of {} chunks", chunks, full_chunks);
+
+        let track: Track = import_track(&file, Option::Some(5.0)).unwrap();
+        assert_eq!(track.samples.len(), 2 * 5 * sample_rate as usize);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn missing_stems_are_counted() {
+        let result = match_stem_files(paths(&["bass.wav", "drums.mp3", "mix.wav"]), "song");
+        match result {
+            Err(SpecCompError::MissingStems { location, found, expected, missing }) => {
+                assert_eq!(location, "song");
+                assert_eq!(found, 2);
+                assert_eq!(expected, 4);
+                assert_eq!(missing, vec![String::from("vocals.*"), String::from("other.*")]);
+            }
+            other => { panic!("expected MissingStems, got {:?}", other); }
+        }
+    }
+
+    #[test]
+    fn lossy_against_lossless_is_a_mismatch() {
+        let track = |codec: &str| Track { codec: codec.to_string(), ..Track::from_buffer(&[0.0; 64], 44100) };
+        let mut tracks: Vec<Track> = ["pcm_s16le", "flac", "mp3", "mp3"].iter().map(|c| track(c)).collect();
+        tracks.extend(["mp3", "flac", "mp3", "vorbis"].iter().map(|c| track(c)));
+
+        // Bass is a WAV against an MP3, Other an MP3 against an Ogg; Drums and Vocals have the same codecs
+        assert_eq!(codec_mismatches(&tracks), vec![0, 3]);
+        assert!(is_lossy_codec("mp3"));
+        assert!(!is_lossy_codec("pcm_s16le"));
+    }
+
+    #[test]
+    fn stems_in_a_nested_layout() {
+        let dir: PathBuf = scratch_dir("nested");
+        for (i, stem) in STEM_NAMES.iter().enumerate() {
+            std::fs::create_dir_all(dir.join(stem)).unwrap();
+            write_wav(&dir.join(stem).join("audio.wav"), 8000, 100 * (i + 1));
+        }
+        std::fs::write(dir.join("bass").join("notes.txt"), "not audio").unwrap();
+        let path: String = dir.to_string_lossy().to_string();
+
+        let tracks: Vec<Track> = import_from_directory(&path, Option::Some("{stem}/*.wav"), Option::None).unwrap();
+        let lengths: Vec<usize> = tracks.iter().map(|t| t.samples.len()).collect();
+        assert_eq!(lengths, vec![200, 400, 600, 800]);
+
+        // Without the pattern the stems aren't found
+        assert!(matches!(import_from_directory(&path, Option::None, Option::None), Err(SpecCompError::MissingStems { found: 0, .. })));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stem_patterns_stay_inside_the_source() {
+        assert!(check_stem_pattern("{stem}/*.wav").is_ok());
+        assert!(check_stem_pattern("**/{stem}.*").is_ok());
+        assert!(check_stem_pattern("stems/*.wav").is_err());
+        assert!(check_stem_pattern("../{stem}.wav").is_err());
+        assert!(check_stem_pattern("/{stem}.wav").is_err());
+    }
+
+    #[test]
+    fn stem_names_are_displayed_capitalized() {
+        let names: Vec<String> = STEM_NAMES.iter().map(|n| stem_display_name(n)).collect();
+        assert_eq!(names, vec!["Bass", "Drums", "Vocals", "Other"]);
+    }
+
+    // Stand-ins for `mt_import_track()`; Vocals panics or comes out empty, the other stems are decoded as usual
+    fn decode_or_panic(path: &String, _: Option<f32>, tx: Sender<i32>, buffer: Arc<Mutex<Track>>) {
+        if path.ends_with("vocals.wav") { panic!("unexpected end of stream"); }
+        decode_or_nothing(path, Option::None, tx, buffer);
+    }
+
+    // Takes a `&String` like any `DecodeJob`
+    #[allow(clippy::ptr_arg)]
+    fn decode_or_nothing(path: &String, _: Option<f32>, tx: Sender<i32>, buffer: Arc<Mutex<Track>>) {
+        if !path.ends_with("vocals.wav") {
+            *buffer.lock().unwrap() = Track::from_buffer(&[0.25; 200], 8000);
+        }
+        let _ = tx.send(100);
+    }
+
+    #[test]
+    fn failed_stem_jobs_are_errors() {
+        let source: String = String::from("song");
+        let stems: Vec<PathBuf> = paths(&["bass.wav", "drums.wav", "vocals.wav", "other.wav"]);
+
+        match mt_import_files(&source, &stems, 2, Option::None, decode_or_panic) {
+            Err(SpecCompError::Decode(m)) => { assert!(m.contains("vocals.wav") && m.contains("panicked"), "{}", m); }
+            other => { panic!("expected a Decode error, got {:?}", other.map(|t| t.len())); }
+        }
+        match mt_import_files(&source, &stems, 2, Option::None, decode_or_nothing) {
+            Err(SpecCompError::Decode(m)) => { assert!(m.contains("vocals.wav") && m.contains("no samples"), "{}", m); }
+            other => { panic!("expected a Decode error, got {:?}", other.map(|t| t.len())); }
+        }
+    }
+
+    #[test]
+    fn every_stem_found() {
+        let found = match_stem_files(paths(&["Other.FLAC", "vocals.wav", "drums.mp3", "bass.ogg"]), "song").unwrap();
+        assert_eq!(found, paths(&["bass.ogg", "drums.mp3", "vocals.wav", "Other.FLAC"]));
+    }
+
+    #[test]
+    fn missing_stems_are_named() {
+        let dir: PathBuf = scratch_dir("missing");
+        write_wav(&dir.join("bass.wav"), 8000, 64);
+        write_wav(&dir.join("drums.wav"), 8000, 64);
+        write_wav(&dir.join("mix.wav"), 8000, 64);
+        let path: String = dir.to_string_lossy().to_string();
+
+        let message: String = import_from_directory(&path, Option::None, Option::None).unwrap_err().to_string();
+        assert!(message.contains("vocals.*") && message.contains("other.*"), "{}", message);
+        assert!(message.contains("2/4"), "{}", message);
+
+        // Two files for the same stem are an error of their own
+        write_wav(&dir.join("Bass.flac"), 8000, 64);
+        assert!(matches!(find_stem_files(&path, Option::None), Err(SpecCompError::DuplicateStem { .. })));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stem_names_match_in_any_case() {
+        let dir: PathBuf = scratch_dir("case");
+        for name in ["Bass.MP3", "Drums.Mp3", "VOCALS.mp3", "other.mp3", "Mix.mp3"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let path: String = dir.to_string_lossy().to_string();
+
+        let found: Vec<String> = find_stem_files(&path, Option::None).unwrap().iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(found, vec!["Bass.MP3", "Drums.Mp3", "VOCALS.mp3", "other.mp3"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn one_thread_imports_the_same_stems() {
+        let dir: PathBuf = scratch_dir("threads");
+        for (i, stem) in STEM_NAMES.iter().enumerate() {
+            write_wav(&dir.join(format!("{}.wav", stem)), 8000, 500 + 100 * i);
+        }
+        let path: String = dir.to_string_lossy().to_string();
+
+        let serial: Vec<Track> = mt_import_from_directory(&path, Option::None, 1, Option::None).unwrap();
+        let parallel: Vec<Track> = mt_import_from_directory(&path, Option::None, 4, Option::None).unwrap();
+        let samples = |tracks: &[Track]| -> Vec<Vec<f32>> { tracks.iter().map(|t| t.samples.clone()).collect() };
+        assert_eq!(samples(&serial), samples(&parallel));
+        assert_eq!(serial.iter().map(|t| t.samples.len()).collect::<Vec<usize>>(), vec![1000, 1200, 1400, 1600]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn batch_manifest_lists_every_pair() {
+        let dir: PathBuf = scratch_dir("manifest");
+        let manifest: String = dir.join("pairs.csv").to_string_lossy().to_string();
+        std::fs::write(&manifest, "reference_dir,test_dir,name\n# Two songs\nref/a, test/a, First\n\nref/b,test/b\n").unwrap();
+
+        let entries = read_batch_manifest(&manifest).unwrap();
+        assert_eq!(entries, vec![
+            (String::from("ref/a"), String::from("test/a"), String::from("First")),
+            (String::from("ref/b"), String::from("test/b"), String::from("test/b"))
+        ]);
+
+        std::fs::write(&manifest, "ref/a\n").unwrap();
+        assert!(matches!(read_batch_manifest(&manifest), Err(SpecCompError::InvalidArgument(_))));
+        std::fs::write(&manifest, "# Nothing\n").unwrap();
+        assert!(matches!(read_batch_manifest(&manifest), Err(SpecCompError::EmptyInput(_))));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn chunked_import_gives_the_same_spectogram() {
+        let dir: PathBuf = scratch_dir("chunked");
+        let file: String = dir.join("track.wav").to_string_lossy().to_string();
+        write_wav(Path::new(&file), 8000, 5000);
+
+        let (chunked, sample_rate) = import_track_spectogram(&file, 256, WindowFunction::Hann, Option::None).unwrap();
+        let track: Track = import_track(&file, Option::None).unwrap();
+        let whole: StereoSpectogram = track_to_spec(256, WindowFunction::Hann, &track.samples);
+        assert_eq!(sample_rate, 8000);
+        assert_eq!((chunked.left, chunked.right), (whole.left, whole.right));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn padding_brings_every_stem_to_the_longest() {
+        let dir: PathBuf = scratch_dir("pad");
+        for (stem, frames) in STEM_NAMES.iter().zip([500, 600, 500, 550]) {
+            write_wav(&dir.join(format!("{}.wav", stem)), 8000, frames);
+        }
+        let path: String = dir.to_string_lossy().to_string();
+
+        let mut tracks: Vec<Track> = import_from_directory(&path, Option::None, Option::None).unwrap();
+        let bass: Vec<f32> = tracks[0].samples.clone();
+        pad_to_longest(&mut tracks);
+        assert!(tracks.iter().all(|t| t.samples.len() == 2 * 600));
+        assert_eq!(tracks[0].samples[..bass.len()], bass[..]);
+        assert!(tracks[0].samples[bass.len()..].iter().all(|s| *s == 0.0));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stems_from_a_zip_archive() {
+        let dir: PathBuf = scratch_dir("zip");
+        let archive: PathBuf = dir.join("stems.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&archive).unwrap());
+        for (i, stem) in STEM_NAMES.iter().enumerate() {
+            let file: PathBuf = dir.join(format!("{}.wav", stem));
+            write_wav(&file, 8000, 100 * (i + 1));
+            let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+            writer.start_file(format!("song/{}.wav", stem), options).unwrap();
+            std::io::Write::write_all(&mut writer, &std::fs::read(&file).unwrap()).unwrap();
+        }
+        writer.finish().unwrap();
+        let path: String = archive.to_string_lossy().to_string();
+
+        let tracks: Vec<Track> = import_from_zip(&path, Option::None).unwrap();
+        assert_eq!(tracks.iter().map(|t| t.samples.len()).collect::<Vec<usize>>(), vec![200, 400, 600, 800]);
+        assert!(tracks.iter().all(|t| t.sample_rate == 8000 && t.channels == 2));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn silence_is_trimmed_from_both_ends() {
+        let threshold: f32 = dbfs_to_amplitude(DEFAULT_SILENCE_DBFS);
+        // The middle starts and ends with a pair where only one channel is loud, and has silence inside
+        let middle: Vec<f32> = [&[0.0, 0.3][..], &[0.5, -0.25, 0.0, 0.0, 1e-5, 0.125][..], &[-0.75, 0.0][..]].concat();
+        let mut track: TrackBuffer = [vec![0.0; 200], vec![1e-4; 20], middle.clone(), vec![-1e-4; 40], vec![0.0; 100]].concat();
+
+        trim_silence(&mut track, threshold);
+        assert_eq!(track, middle);
+
+        let mut silent: TrackBuffer = vec![0.0; 64];
+        trim_silence(&mut silent, threshold);
+        assert!(silent.is_empty());
+    }
+
+    #[test]
+    fn level_difference_is_normalized_away() {
+        let mut loud: TrackBuffer = (0..8192).map(|i| 0.5 * (2.0 * std::f32::consts::PI * 440.0 * (i / 2) as f32 / 8000.0).cos()).collect();
+        let mut quiet: TrackBuffer = loud.iter().map(|s| s * 0.25).collect();
+
+        let loud_gain: f32 = rms_normalize(&mut loud, DEFAULT_TARGET_RMS);
+        let quiet_gain: f32 = rms_normalize(&mut quiet, DEFAULT_TARGET_RMS);
+        assert!((quiet_gain / loud_gain - 4.0).abs() < 1e-3);
+        assert!((rms(&loud) - DEFAULT_TARGET_RMS).abs() < 1e-5 && (rms(&quiet) - DEFAULT_TARGET_RMS).abs() < 1e-5);
+
+        let (spec_a, spec_b) = (track_to_spec(512, WindowFunction::Hann, &loud), track_to_spec(512, WindowFunction::Hann, &quiet));
+        let (_, error) = crate::spectograms::time_compare_spectogram(256, &spec_a, &spec_b).unwrap();
+        assert!(error < 1e-6, "{}", error);
+
+        // Silence is left alone
+        let mut silence: TrackBuffer = vec![0.0; 64];
+        assert_eq!(rms_normalize(&mut silence, DEFAULT_TARGET_RMS), 1.0);
+        assert!(silence.iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn flac_and_wav_stems_in_one_directory() {
+        let dir: PathBuf = scratch_dir("mixed");
+        write_flac(&dir.join("bass.flac"), 8000, 600);
+        write_wav(&dir.join("drums.wav"), 8000, 600);
+        write_flac(&dir.join("Vocals.FLAC"), 8000, 600);
+        write_wav(&dir.join("other.wav"), 8000, 600);
+        let path: String = dir.to_string_lossy().to_string();
+
+        // Both formats decode to the same samples
+        let tracks: Vec<Track> = import_from_directory(&path, Option::None, Option::None).unwrap();
+        assert_eq!(tracks.len(), 4);
+        assert_eq!(tracks[0].samples.len(), 1200);
+        for track in &tracks[1..] {
+            assert_eq!(track.sample_rate, 8000);
+            assert_eq!(track.samples, tracks[0].samples);
+        }
+
+        // A stem in both formats is ambiguous
+        write_wav(&dir.join("bass.wav"), 8000, 600);
+        assert!(matches!(import_from_directory(&path, Option::None, Option::None), Err(SpecCompError::DuplicateStem { .. })));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn encoder_delay_from_the_metadata_is_removed() {
+        let dir: PathBuf = scratch_dir("delay");
+        let path: String = dir.join("bass.mp3").to_string_lossy().to_string();
+        write_mp3(Path::new(&path), 10, 1105);
+
+        // 10 frames of 1152 samples per channel, the first 1105 of which are the encoder's
+        let track: Track = import_track(&path, Option::None).unwrap();
+        assert_eq!(track.encoder_delay, Option::Some(1105));
+        assert_eq!(track.samples.len(), 2 * (10 * 1152 - 1105));
+
+        // Without the metadata a fixed number of samples per channel is skipped instead
+        let mut fallback: Track = Track::new();
+        fallback.samples = (0..20).map(|i| i as f32).collect();
+        fallback.skip_samples(3);
+        assert_eq!(fallback.samples[0], 6.0);
+        assert_eq!(fallback.samples.len(), 14);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn in_memory_wav_matches_the_file() {
+        let dir: PathBuf = scratch_dir("reader");
+        let path: PathBuf = dir.join("vocals.wav");
+        write_wav(&path, 8000, 700);
+
+        let from_file: Track = import_track(&path.to_string_lossy().to_string(), Option::None).unwrap();
+        let bytes: Vec<u8> = std::fs::read(&path).unwrap();
+        let from_memory: Track = import_track_from_reader(Box::new(Cursor::new(bytes)), "wav", Option::None).unwrap();
+        assert_eq!(from_memory.samples.len(), 1400);
+        assert_eq!(from_memory.samples, from_file.samples);
+        assert_eq!(from_memory.sample_rate, from_file.sample_rate);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stems_of_different_durations_are_named() {
+        let dir: PathBuf = scratch_dir("durations");
+        for (stem, frames) in STEM_NAMES.iter().zip([8000, 8000, 12000, 16000]) {
+            write_wav(&dir.join(format!("{}.wav", stem)), 8000, frames);
+        }
+        let path: String = dir.to_string_lossy().to_string();
+
+        let tracks: Vec<Track> = import_from_directory(&path, Option::None, Option::None).unwrap();
+        assert_eq!(tracks.iter().map(|t| t.duration()).collect::<Vec<f32>>(), vec![1.0, 1.0, 1.5, 2.0]);
+        let warning: String = check_stem_durations(&tracks, DURATION_TOLERANCE).unwrap();
+        assert_eq!(warning, "The stems differ by 1.000s (bass 1.000s, drums 1.000s, vocals 1.500s, other 2.000s)");
+
+        // Half a tenth of a second is within the tolerance
+        let close: Vec<Track> = [8000, 8400, 8000, 8000].iter().map(|frames| {
+            let mut t: Track = Track::new();
+            t.sample_rate = 8000;
+            t.samples = vec![0.0; 2 * frames];
+            t
+        }).collect();
+        assert_eq!(check_stem_durations(&close, DURATION_TOLERANCE), Option::None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stems_are_paired_by_name() {
+        let dir: PathBuf = scratch_dir("pairing");
+        let (reference, candidate) = (dir.join("reference"), dir.join("candidate"));
+        std::fs::create_dir_all(&reference).unwrap();
+        std::fs::create_dir_all(&candidate).unwrap();
+        for name in ["bass.wav", "drums.wav", "vocals.wav", "other.wav"] {
+            std::fs::write(reference.join(name), b"").unwrap();
+        }
+        // Another order, other extensions and extra files
+        for name in ["Other.flac", "mix.wav", "VOCALS.mp3", "drums.ogg", "notes.txt", "Bass.wav"] {
+            std::fs::write(candidate.join(name), b"").unwrap();
+        }
+
+        let pairs = pair_stem_files(&reference.to_string_lossy().to_string(), &candidate.to_string_lossy().to_string(), Option::None).unwrap();
+        let names: Vec<(String, String, String)> = pairs.iter()
+            .map(|(stem, r, c)| (stem.clone(), r.file_name().unwrap().to_string_lossy().to_string(), c.file_name().unwrap().to_string_lossy().to_string()))
+            .collect();
+        assert_eq!(names, vec![
+            (String::from("bass"), String::from("bass.wav"), String::from("Bass.wav")),
+            (String::from("drums"), String::from("drums.wav"), String::from("drums.ogg")),
+            (String::from("vocals"), String::from("vocals.wav"), String::from("VOCALS.mp3")),
+            (String::from("other"), String::from("other.wav"), String::from("Other.flac"))
+        ]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn inverted_copy_is_detected_and_fixed() {
+        let reference: TrackBuffer = (0..4000).map(|i| (0.01 * i as f32).sin() * 0.5 + ((i * 7919) % 13) as f32 / 130.0).collect();
+        let mut test: TrackBuffer = reference.iter().map(|s| -s).collect();
+        assert!(detect_polarity(&reference, &test));
+        assert!(!detect_polarity(&reference, &reference));
+
+        invert_polarity(&mut test);
+        assert!(!detect_polarity(&reference, &test));
+        let error: f32 = reference.iter().zip(test.iter()).map(|(r, t)| (r - t).abs()).sum::<f32>() / reference.len() as f32;
+        assert!(error < 1e-9, "{}", error);
+    }
+
+    #[test]
+    fn a_corrupt_packet_is_skipped() {
+        let dir: PathBuf = scratch_dir("corrupt");
+        let path: PathBuf = dir.join("drums.mp3");
+        write_mp3(&path, 10, 1105);
+        // The side info of the 5th audio frame asks for more scale factor bits (scalefac_compress 15) than its first
+        // granule has (part2_3_length 1)
+        let mut bytes: Vec<u8> = std::fs::read(&path).unwrap();
+        let frame: usize = 417 * 5;
+        bytes[frame + 4 + 3] = 0x01;
+        bytes[frame + 4 + 6] = 0x78;
+        std::fs::write(&path, bytes).unwrap();
+
+        // Decoding goes on after it, and the corrupt frame is silence of the same length, so the track keeps its timeline
+        let track: Track = import_track(&path.to_string_lossy().to_string(), Option::None).unwrap();
+        assert_eq!(track.skipped_packets, 1);
+        assert_eq!(track.samples.len(), 2 * (10 * 1152 - 1105));
+        let gap = 2 * (4 * 1152 - 1105)..2 * (5 * 1152 - 1105);
+        assert!(track.samples[gap].iter().all(|s| *s == 0.0));
+        // The same on the parallel path, where the stem stays as long as the intact ones
+        for stem in ["bass", "vocals", "other"] {
+            write_mp3(&dir.join(format!("{}.mp3", stem)), 10, 1105);
+        }
+        let tracks: Vec<Track> = mt_import_from_directory(&dir.to_string_lossy().to_string(), Option::None, 4, Option::None).unwrap();
+        assert_eq!(tracks[1].skipped_packets, 1);
+        assert!(tracks.iter().all(|t| t.samples.len() == track.samples.len()));
+
+        // Only a streak of failures gives up on the track
+        let mut errors: DecodeErrors = DecodeErrors::default();
+        assert!(errors.skip());
+        errors.decoded();
+        assert!((1..MAX_CONSECUTIVE_DECODE_ERRORS).all(|_| errors.skip()));
+        assert!(!errors.skip());
+        assert_eq!(errors.skipped, MAX_CONSECUTIVE_DECODE_ERRORS + 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn mono_stems_are_upmixed_and_more_channels_rejected() {
+        let dir: PathBuf = scratch_dir("channels");
+        write_wav(&dir.join("bass.wav"), 8000, 500);
+        write_wav_channels(&dir.join("drums.wav"), 8000, 1, 500);
+        write_wav(&dir.join("vocals.wav"), 8000, 500);
+        write_wav_channels(&dir.join("other.wav"), 8000, 1, 500);
+        let path: String = dir.to_string_lossy().to_string();
+
+        // Both channels of a mono stem get its samples
+        let tracks: Vec<Track> = import_from_directory(&path, Option::None, Option::None).unwrap();
+        assert!(tracks.iter().all(|t| t.channels == 2 && t.samples.len() == 1000));
+        assert!(tracks[1].samples.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+        assert_eq!(tracks[1].samples[2], tracks[0].samples[1]);
+
+        write_wav_channels(&dir.join("vocals.wav"), 8000, 3, 500);
+        let message: String = import_from_directory(&path, Option::None, Option::None).unwrap_err().to_string();
+        assert!(message.contains("vocals") && message.contains("3 channels"), "{}", message);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn raw_pcm_round_trip() {
+        let dir: PathBuf = scratch_dir("raw");
+        let samples: Vec<f32> = (0..600).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let path: String = dir.join("mix.f32").to_string_lossy().to_string();
+        std::fs::write(&path, &bytes).unwrap();
+
+        let track: Track = import_raw_pcm(&path, 2, 8000, Option::None).unwrap();
+        assert_eq!(track.samples, samples);
+        assert_eq!((track.channels, track.sample_rate), (2, 8000));
+
+        // The limit is counted in frames of all channels
+        let track: Track = import_raw_pcm(&path, 2, 100, Option::Some(1.0)).unwrap();
+        assert_eq!(track.samples, samples[..200]);
+
+        // 600 samples of 4 bytes aren't whole frames of 7 channels, and a truncated sample is rejected
+        assert!(matches!(import_raw_pcm(&path, 7, 8000, Option::None), Result::Err(SpecCompError::Decode(_))));
+        std::fs::write(&path, &bytes[..bytes.len() - 2]).unwrap();
+        assert!(matches!(import_raw_pcm(&path, 2, 8000, Option::None), Result::Err(SpecCompError::Decode(_))));
+        assert!(matches!(import_raw_pcm(&path, 0, 8000, Option::None), Result::Err(SpecCompError::InvalidArgument(_))));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_frame_at_the_threshold_is_not_silent_for_either_check() {
+        let threshold: f32 = dbfs_to_amplitude(DEFAULT_SILENCE_DBFS);
+        assert!((threshold - 0.001).abs() < 1e-9);
+        let below: f32 = threshold * 0.5;
+
+        // Frames of 4 pairs: silent, one sample exactly at the threshold, silent
+        let mut track: TrackBuffer = vec![below; 24];
+        track[9] = threshold;
+        assert_eq!(silent_frames(&track, 4, threshold), vec![true, false, true]);
+        trim_silence(&mut track, threshold);
+        assert_eq!(track, vec![below, threshold]);
+
+        // Just below the threshold everything is silent
+        let mut track: TrackBuffer = vec![below; 24];
+        track[9] = threshold * 0.999;
+        assert_eq!(silent_frames(&track, 4, threshold), vec![true; 3]);
+        trim_silence(&mut track, threshold);
+        assert!(track.is_empty());
+    }
+
+    #[test]
+    fn opening_a_missing_file_or_a_directory() {
+        let dir: PathBuf = scratch_dir("open");
+        let missing: PathBuf = dir.join("missing.wav");
+
+        let message: String = match open_file(&missing, "import_from_file()") {
+            Result::Err(SpecCompError::Io(m)) => { m }
+            other => { panic!("{:?}", other.map(|_| ())); }
+        };
+        assert!(message.starts_with("import_from_file(): Could not open") && message.contains("no such file"), "{}", message);
+
+        let message: String = match open_file(&dir, "import_from_file()") {
+            Result::Err(SpecCompError::Io(m)) => { m }
+            other => { panic!("{:?}", other.map(|_| ())); }
+        };
+        assert!(message.contains("it is a directory"), "{}", message);
+
+        // The importers pass the error on
+        let message: String = import_track(&missing.to_string_lossy().to_string(), Option::None).unwrap_err().to_string();
+        assert!(message.contains("no such file"), "{}", message);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn downsampling_attenuates_what_the_new_rate_cant_hold() {
+        let tone = |frequency: f32| -> TrackBuffer {
+            (0..8000).flat_map(|i| { let s = (2.0 * std::f32::consts::PI * frequency * i as f32 / 8000.0).sin(); [s, s] }).collect()
+        };
+        // Away from the edges, where the filter runs out of samples
+        let inner_rms = |track: &TrackBuffer| -> f32 { rms(&track[400..track.len() - 400].to_vec()) };
+
+        // 3 kHz is above the new Nyquist frequency (2 kHz); Taking every other sample would fold it to 1 kHz
+        let high: TrackBuffer = tone(3000.0);
+        let decimated: TrackBuffer = high.chunks_exact(4).flat_map(|p| [p[0], p[1]]).collect();
+        assert!(inner_rms(&decimated) > 0.5);
+        let resampled: TrackBuffer = resample(&high, 8000, 4000);
+        assert_eq!(resampled.len(), high.len() / 2);
+        assert!(inner_rms(&resampled) < 0.01 * inner_rms(&high), "{}", inner_rms(&resampled));
+
+        // A tone well below it passes
+        let low: TrackBuffer = tone(500.0);
+        let resampled: TrackBuffer = resample(&low, 8000, 4000);
+        assert!((inner_rms(&resampled) / inner_rms(&low) - 1.0).abs() < 0.05, "{}", inner_rms(&resampled));
+
+        // Upsampling only interpolates
+        assert_eq!(resample(&low, 8000, 16000).len(), 2 * low.len());
+        assert_eq!(resample(&low, 8000, 8000), low);
+    }
+}
@@ -2,3 +2,10 @@ pub mod types;
 
 pub mod importerts;
 pub mod spectograms;
+pub mod results;
+pub mod graphs;
+pub mod workers;
+pub mod live;
+pub mod pipeline;
+pub mod config;
+pub mod error;
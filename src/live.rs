@@ -0,0 +1,362 @@
+use std::{
+    io::{Read, Write}, net::TcpStream, sync::mpsc, thread
+};
+
+use crate::error::SpecCompError;
+use crate::importerts::open_file;
+use crate::spectograms::{StreamingSpectogram, WindowFunction, time_compare_spectogram};
+use crate::types::StereoSpectogram;
+
+// Live comparison of two PCM streams, e.g. for a monitoring dashboard. Both streams carry interleaved L/R samples as
+// little-endian 32-bit floats; The spectograms are built as the samples arrive and every frame both streams have is
+// compared right away. The results are written as one JSON object per line:
+//     {"frame":12,"error":0.001234,"mean":0.001100}
+// `error` is the frame's error as `time_compare_spectogram()` computes it, `mean` is the mean error so far; Frames
+// without a finite error (e.g. NaN) are left out of the mean.
+
+// Size of the reads from a stream; Reads may return less, down to a fraction of a sample
+const READ_SIZE: usize = 16384;
+
+// Opens a live source; "tcp://host:port" connects to a socket, anything else is opened as a file (e.g. a named pipe)
+pub fn open_live_source(source: &str) -> Result<Box<dyn Read + Send>, SpecCompError> {
+    match source.strip_prefix("tcp://") {
+        Some(address) => {
+            match TcpStream::connect(address) {
+                Ok(s)  => { Result::Ok(Box::new(s)) }
+                Err(e) => { Result::Err(SpecCompError::Io(format!("open_live_source(): Could not connect to {} ({}).", address, e))) }
+            }
+        }
+        None => { Result::Ok(Box::new(open_file(source, "open_live_source()")?)) }
+    }
+}
+
+// Turns the bytes of a stream into samples; A read may end in the middle of a sample, its first bytes are kept
+// until the rest arrives.
+pub struct SampleDecoder {
+    partial: Vec<u8>
+}
+
+impl SampleDecoder {
+    pub fn new() -> SampleDecoder {
+        SampleDecoder { partial: Vec::with_capacity(4) }
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> Vec<f32> {
+        let mut samples: Vec<f32> = Vec::with_capacity((self.partial.len() + bytes.len()) / 4);
+        let mut rest: &[u8] = bytes;
+
+        // Complete the sample the last read ended in
+        if !self.partial.is_empty() {
+            let missing: usize = (4 - self.partial.len()).min(rest.len());
+            self.partial.extend_from_slice(&rest[..missing]);
+            rest = &rest[missing..];
+            if self.partial.len() < 4 { return samples; }
+            samples.push(f32::from_le_bytes([self.partial[0], self.partial[1], self.partial[2], self.partial[3]]));
+            self.partial.clear();
+        }
+
+        let whole = rest.chunks_exact(4);
+        self.partial.extend_from_slice(whole.remainder());
+        samples.extend(whole.map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])));
+        samples
+    }
+
+    // Bytes of an incomplete sample; A stream that ends with some is cut short
+    pub fn leftover(&self) -> usize {
+        self.partial.len()
+    }
+}
+
+impl Default for SampleDecoder {
+    fn default() -> SampleDecoder {
+        SampleDecoder::new()
+    }
+}
+
+// Incremental comparison of two streams; Samples are pushed to either stream in chunks of any size and the frames
+// both streams have are compared by `compare_ready()`. Frames only one stream has wait until the other catches up.
+pub struct LiveComparison {
+    bins: u32,
+    builders: [Option<StreamingSpectogram>; 2],
+    // Transformed frames that haven't been compared yet
+    backlog: [StereoSpectogram; 2],
+    frames_compared: usize,
+    // Frames with a finite error, which are the ones `error_sum` and the mean hold
+    finite_frames: usize,
+    error_sum: f32
+}
+
+impl LiveComparison {
+    pub fn new(fft_size: u32, hop: u32, window: WindowFunction) -> Result<LiveComparison, SpecCompError> {
+        if fft_size < 2 {
+            return Result::Err(SpecCompError::InvalidArgument(format!("LiveComparison::new(): The FFT size must be at least 2 (got {})", fft_size)));
+        }
+        if hop == 0 || hop > fft_size {
+            return Result::Err(SpecCompError::InvalidArgument(format!("LiveComparison::new(): The hop must be between 1 and the FFT size (got {})", hop)));
+        }
+
+        Result::Ok(LiveComparison {
+            bins: fft_size / 2,
+            builders: [
+                Option::Some(StreamingSpectogram::with_hop(fft_size, hop, window)),
+                Option::Some(StreamingSpectogram::with_hop(fft_size, hop, window))
+            ],
+            backlog: [StereoSpectogram::new(fft_size / 2), StereoSpectogram::new(fft_size / 2)],
+            frames_compared: 0,
+            finite_frames: 0,
+            error_sum: 0.0
+        })
+    }
+
+    // Feeds interleaved samples to stream 0 or 1; Samples pushed after the stream ended are ignored
+    pub fn push(&mut self, stream: usize, samples: &[f32]) {
+        if let Some(builder) = &mut self.builders[stream] {
+            builder.push(samples);
+            let frames: StereoSpectogram = builder.take_frames();
+            self.backlog[stream].left.extend(frames.left);
+            self.backlog[stream].right.extend(frames.right);
+        }
+    }
+
+    // Marks a stream as ended; Its leftover samples are transformed (padded with 0) like `track_to_spec` does
+    pub fn end(&mut self, stream: usize) {
+        if let Some(builder) = self.builders[stream].take() {
+            let frames: StereoSpectogram = builder.finish();
+            self.backlog[stream].left.extend(frames.left);
+            self.backlog[stream].right.extend(frames.right);
+        }
+    }
+
+    pub fn ended(&self, stream: usize) -> bool {
+        self.builders[stream].is_none()
+    }
+
+    // Whether no more frames can be compared, i.e. a stream ended and all of its frames were compared
+    pub fn is_done(&self) -> bool {
+        (0..2).any(|s| self.ended(s) && self.backlog[s].left.is_empty())
+    }
+
+    // Compares the frames both streams have; Returns the index and error of each, in order, along with the mean error
+    // up to that frame (see `mean_error()`)
+    pub fn compare_ready(&mut self) -> Result<Vec<(usize, f32, f32)>, SpecCompError> {
+        let bins_us: usize = self.bins as usize;
+        let ready: usize = self.backlog[0].left.len().min(self.backlog[1].left.len()) / bins_us;
+        if ready == 0 {
+            return Result::Ok(vec![]);
+        }
+
+        // Split the ready frames off the backlogs
+        let take = |spec: &mut StereoSpectogram| -> StereoSpectogram {
+            StereoSpectogram {
+                left: spec.left.drain(..ready*bins_us).collect(),
+                right: spec.right.drain(..ready*bins_us).collect(),
+                bins: spec.bins
+            }
+        };
+        let frames_a: StereoSpectogram = take(&mut self.backlog[0]);
+        let frames_b: StereoSpectogram = take(&mut self.backlog[1]);
+
+        let (errors, _) = time_compare_spectogram(self.bins, &frames_a, &frames_b)?;
+        let mut compared: Vec<(usize, f32, f32)> = Vec::with_capacity(errors.len());
+        for e in errors {
+            if e.is_finite() {
+                self.finite_frames += 1;
+                self.error_sum += e;
+            }
+            compared.push((self.frames_compared, e, self.mean_error()));
+            self.frames_compared += 1;
+        }
+        Result::Ok(compared)
+    }
+
+    pub fn frames_compared(&self) -> usize {
+        self.frames_compared
+    }
+
+    // Frames compared so far that have no finite error and are left out of the mean
+    pub fn excluded_frames(&self) -> usize {
+        self.frames_compared - self.finite_frames
+    }
+
+    // Mean error of the frames compared so far that have a finite error; 0.0 before the first one
+    pub fn mean_error(&self) -> f32 {
+        match self.finite_frames {
+            0 => { 0.0 }
+            n => { self.error_sum / n as f32 }
+        }
+    }
+}
+
+// One line of output
+pub fn format_frame_json(frame: usize, error: f32, mean: f32) -> String {
+    // JSON has no NaN or infinity
+    let number = |v: f32| -> String {
+        match v.is_finite() {
+            true  => { format!("{:.6}", v) }
+            false => { String::from("null") }
+        }
+    };
+    format!("{{\"frame\":{},\"error\":{},\"mean\":{}}}", frame, number(error), number(mean))
+}
+
+// What the reader threads send to the comparing thread
+enum StreamEvent {
+    Data(usize, Vec<u8>),
+    End(usize),
+    Failed(usize, String)
+}
+
+// Reads both streams until no more frames can be compared and writes a JSON line for every compared frame to `output`;
+// Each line is flushed right away. Returns the number of frames compared. The streams are read on their own threads,
+// so a stream that stalls doesn't hold up reading the other one; Those threads are left behind if a stream is still
+// open when the comparison ends.
+pub fn run_live_comparison(streams: [Box<dyn Read + Send>; 2], fft_size: u32, hop: u32, window: WindowFunction, output: &mut dyn Write) -> Result<usize, SpecCompError> {
+    let mut comparison: LiveComparison = LiveComparison::new(fft_size, hop, window)?;
+
+    let (sender, receiver) = mpsc::channel::<StreamEvent>();
+    for (s, mut stream) in streams.into_iter().enumerate() {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let mut buffer: Vec<u8> = vec![0; READ_SIZE];
+            loop {
+                let event: StreamEvent = match stream.read(&mut buffer) {
+                    Ok(0)  => { StreamEvent::End(s) }
+                    Ok(n)  => { StreamEvent::Data(s, buffer[..n].to_vec()) }
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => { continue; }
+                    Err(e) => { StreamEvent::Failed(s, e.to_string()) }
+                };
+                let last: bool = !matches!(event, StreamEvent::Data(_, _));
+                if sender.send(event).is_err() || last { break; }
+            }
+        });
+    }
+    drop(sender);
+
+    let mut decoders: [SampleDecoder; 2] = [SampleDecoder::new(), SampleDecoder::new()];
+    while !comparison.is_done() {
+        let event: StreamEvent = match receiver.recv() {
+            Ok(e)  => { e }
+            Err(_) => { break; }
+        };
+
+        match event {
+            StreamEvent::Data(s, bytes) => {
+                let samples: Vec<f32> = decoders[s].decode(&bytes);
+                comparison.push(s, &samples);
+            }
+            StreamEvent::End(s) => {
+                // Warnings go to stderr, stdout only holds the results
+                if decoders[s].leftover() > 0 {
+                    eprintln!("Warning: Stream {} ended in the middle of a sample, dropping its last {} bytes.", s + 1, decoders[s].leftover());
+                }
+                comparison.end(s);
+            }
+            StreamEvent::Failed(s, e) => {
+                return Result::Err(SpecCompError::Io(format!("run_live_comparison(): Could not read stream {} ({}).", s + 1, e)));
+            }
+        }
+
+        for (frame, error, mean) in comparison.compare_ready()? {
+            let line: String = format_frame_json(frame, error, mean);
+            if let Err(e) = writeln!(output, "{}", line).and_then(|_| output.flush()) {
+                return Result::Err(SpecCompError::Io(format!("run_live_comparison(): Could not write the results ({}).", e)));
+            }
+        }
+    }
+
+    if comparison.excluded_frames() > 0 {
+        eprintln!("Warning: {} of {} frames have no finite error (e.g. NaN) and were left out of the mean.",
+            comparison.excluded_frames(), comparison.frames_compared());
+    }
+    Result::Ok(comparison.frames_compared())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectograms::track_to_spec;
+    use crate::types::TrackBuffer;
+
+    // In-memory stream that hands out at most `chunk` bytes per read, so that reads end in the middle of samples
+    struct Trickle {
+        bytes: Vec<u8>,
+        position: usize,
+        chunk: usize
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            let n: usize = self.chunk.min(buffer.len()).min(self.bytes.len() - self.position);
+            buffer[..n].copy_from_slice(&self.bytes[self.position..self.position + n]);
+            self.position += n;
+            Result::Ok(n)
+        }
+    }
+
+    fn stream(samples: &TrackBuffer, chunk: usize) -> Box<dyn Read + Send> {
+        Box::new(Trickle { bytes: samples.iter().flat_map(|s| s.to_le_bytes()).collect(), position: 0, chunk })
+    }
+
+    #[test]
+    fn live_errors_match_the_batch_comparison() {
+        let track_a: TrackBuffer = (0..2 * 3000).map(|i| ((i * 7919) % 101) as f32 / 101.0 - 0.5).collect();
+        let track_b: TrackBuffer = (0..2 * 2600).map(|i| ((i * 104729) % 89) as f32 / 89.0 - 0.5).collect();
+
+        // Windows next to each other, then overlapping by half
+        let batch_spec = |hop: u32, track: &TrackBuffer| -> StereoSpectogram {
+            match hop {
+                256 => { track_to_spec(256, WindowFunction::Hann, track) }
+                _ => {
+                    let mut builder: StreamingSpectogram = StreamingSpectogram::with_hop(256, hop, WindowFunction::Hann);
+                    builder.push(track);
+                    builder.finish()
+                }
+            }
+        };
+        for hop in [256, 128] {
+            let mut output: Vec<u8> = vec![];
+            let frames: usize = run_live_comparison([stream(&track_a, 7), stream(&track_b, 1000)], 256, hop, WindowFunction::Hann, &mut output).unwrap();
+
+            let (batch, _) = time_compare_spectogram(128, &batch_spec(hop, &track_a), &batch_spec(hop, &track_b)).unwrap();
+            assert_eq!(frames, batch.len(), "hop {}", hop);
+
+            let lines: Vec<String> = String::from_utf8(output).unwrap().lines().map(String::from).collect();
+            assert_eq!(lines.len(), batch.len(), "hop {}", hop);
+            for (f, (line, error)) in lines.iter().zip(batch.iter()).enumerate() {
+                let mean: f32 = batch[..=f].iter().sum::<f32>() / (f + 1) as f32;
+                assert_eq!(*line, format_frame_json(f, *error, mean), "hop {}", hop);
+            }
+        }
+    }
+
+    #[test]
+    fn non_finite_frames_are_left_out_of_the_mean() {
+        let mut comparison: LiveComparison = LiveComparison::new(8, 8, WindowFunction::Hann).unwrap();
+        let mut track_a: TrackBuffer = (0..2 * 24).map(|i| (i % 5) as f32 / 5.0).collect();
+        let track_b: TrackBuffer = vec![0.25; 2 * 24];
+        // The second frame of stream 0 is NaN
+        track_a[2 * 8] = f32::NAN;
+        comparison.push(0, &track_a);
+        comparison.push(1, &track_b);
+
+        let compared: Vec<(usize, f32, f32)> = comparison.compare_ready().unwrap();
+        assert_eq!(compared.len(), 3);
+        assert!(compared[1].1.is_nan());
+        assert_eq!(comparison.excluded_frames(), 1);
+        assert_eq!(compared[1].2, compared[0].1);
+        assert_eq!(comparison.mean_error(), (compared[0].1 + compared[2].1) / 2.0);
+        assert_eq!(compared[2].2, comparison.mean_error());
+    }
+
+    #[test]
+    fn samples_split_across_reads() {
+        let bytes: Vec<u8> = [1.5f32, -2.0, 0.25].iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut decoder: SampleDecoder = SampleDecoder::new();
+        assert_eq!(decoder.decode(&bytes[..3]), Vec::<f32>::new());
+        assert_eq!(decoder.decode(&bytes[3..5]), vec![1.5]);
+        assert_eq!(decoder.leftover(), 1);
+        assert_eq!(decoder.decode(&bytes[5..]), vec![-2.0, 0.25]);
+        assert_eq!(decoder.leftover(), 0);
+    }
+}
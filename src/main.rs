@@ -1,10 +1,18 @@
 use std::fs::File;
 use std::io::Write;
 use std::env;
+use std::path::Path;
 
 use speccomp::types::*;
 use speccomp::importerts::*;
 use speccomp::spectograms::*;
+use speccomp::config::*;
+use speccomp::error::SpecCompError;
+use speccomp::results::*;
+use speccomp::graphs::*;
+use speccomp::live::*;
+use speccomp::pipeline::*;
+use speccomp::statusln;
 
 use std::time::Instant; // for benchmarking
 
@@ -13,210 +21,521 @@ use std::time::Instant; // for benchmarking
 fn main() {
     let args: Vec<String>  = env::args().collect();
 
-    if (args.len() != 3) && (args.len() != 4) {
-        println!("usage: spec-compare source1 source2 [--serial]\n    A source can be either a file with multiple tracks or a directory with separated stems.\n");
+    let mut config: Config = match Config::from_args(&args) {
+        Ok(c)  => { c }
+        Err(e) => {
+            println!("{}", e);
+            print_usage();
+            return;
+        }
+    };
+    set_verbosity(config.verbosity);
+
+    // Live mode writes JSON lines to stdout, so nothing else may be printed there
+    if config.live {
+        set_verbosity(Verbosity::Quiet);
+        run_live(&config);
         return;
     }
 
-    println!("\n=== Spectogram Compare for X-UMX =======================================================================================");
-    println!(  "  Aias Karioris, 2023-2025\n");
+    // The reference always goes first
+    if let Some(reference) = &config.reference {
+        match config.sources.iter().position(|s| same_source(s, reference)) {
+            Some(i) => { config.sources.swap(0, i); }
+            None => {
+                println!("--reference must be one of the sources ({} is not).", reference);
+                print_usage();
+                return;
+            }
+        }
+    }
 
-    // For testing purposes, serial execution is available and enabled with the "--serial" flag
-    let in_parallel: bool = match args.get(3) {
-        Option::Some(s) => { s != "--serial" }
-        Option::None => { true }
-    };
-    if !in_parallel { println!("Serial execution is enabled."); }
+    // Fail before any work is done if the plots or error curves can't be saved
+    for dir in config.plot.iter().chain(config.npy.iter()) {
+        if let Err(e) = prepare_output_dir(dir) {
+            println!("{e}");
+            std::process::exit(1);
+        }
+    }
+
+    statusln!("\n=== Spectogram Compare for X-UMX =======================================================================================");
+    statusln!(  "  Aias Karioris, 2023-2025\n");
+
+    if !config.in_parallel { statusln!("Serial execution is enabled."); }
+    else { statusln!("Using up to {} threads.", config.threads); }
+    if config.use_mel { statusln!("Comparing in Mel space ({} bands).", MEL_BANDS); }
+    if let Some(size) = std::iter::once(&config.fft_size).chain(config.resolutions.iter()).find(|s| !s.is_power_of_two()) {
+        statusln!("\nWarning: FFT size {} is not a power of two, the spectograms may take longer to calculate.", size);
+    }
+    if let Some((start, end)) = config.range { statusln!("Comparing from {}s to {}s.", start, end); }
+
+    // Create a look-up vector with target names
+    let stem_names: Vec<String> = STEM_NAMES.iter().map(|n| stem_display_name(n)).collect();
+
+    if config.check {
+        run_check(&config, &stem_names);
+        return;
+    }
+
+    if let Some(manifest) = &config.batch {
+        run_batch(manifest, &stem_names, &config);
+        return;
+    }
+
+    if let Some(dir) = &config.spectogram_only {
+        if let Err(e) = run_spectogram_only(&stem_names, dir, &config) {
+            println!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if config.sources.len() > 2 {
+        run_candidates(&stem_names, &config);
+        return;
+    }
 
     // Start a timer
     let start_time = Instant::now();
 
-    // Import files; every track will be loaded into `input_tracks`.
-    let mut input_tracks: Vec<TrackBuffer> = vec![];
-    match in_parallel {
-        true => {
-            // Load 4+4 tracks in parallel
-            match mt_import_from_directory(&args[1]) {
-                Ok(mut o)  => { input_tracks.append(&mut o); }
-                Err(e) => { println!("{e}"); panic!("{e}"); }
-            }
-        
-            match mt_import_from_directory(&args[2]) {
-                Ok(mut o)  => { input_tracks.append(&mut o); }
-                Err(e) => { println!("{e}"); panic!("{e}"); }
-            };
-        }
-        
-        false => {
-            // Load everything sequentially
-            match import_from_directory(&args[1]) {
-                Ok(mut o)  => { input_tracks.append(&mut o); }
-                Err(e) => { println!("{e}"); panic!("{e}"); }
-            }
+    let results = match compare_sources(&config.sources[0], &config.sources[1], &stem_names, &config) {
+        Ok(r)  => { r }
+        Err(e) => { println!("{e}"); std::process::exit(1); }
+    };
 
-            match import_from_directory(&args[2]) {
-                Ok(mut o)  => { input_tracks.append(&mut o); }
-                Err(e) => { println!("{e}"); panic!("{e}"); }
-            }
+    // Stop the timer and display execution time
+    statusln!("\rDone processing! Time elapsed: {:.2} ms\n", start_time.elapsed().as_millis());
+
+    // Display final results
+    print!("\n-- Final Results ----------------------------------------\n");
+    let format_table = match config.markdown {
+        true  => { format_results_markdown }
+        false => { format_results_table }
+    };
+    let tables = match &results.right_channel {
+        None => {
+            format_table(&stem_names, &results.time_mean_error, &results.freq_mean_error, &config.stem_weights, config.precision)
         }
-    }
-    
-    // Create a look-up vector with target names
-    let stem_names: Vec<String> = vec![
-        String::from("Bass"), 
-        String::from("Drums"), 
-        String::from("Volcals"), 
-        String::from("Other")
-    ];
-
-    println!("");
-    let fft_size: u32 = 4096;
-
-    // Calculate spectograms
-    let mut spectograms_ret = match in_parallel {
-        // All spectograms are calculated in parallel
-        true  => { mt_track_to_spec(fft_size, input_tracks) }
-
-        // Sequential...
-        false => {
-            // Create a return buffer and allocate memory for it
-            let mut ret: Vec<StereoSpectogram> = vec![];
-            ret.reserve(input_tracks.len());
-
-            for i in &input_tracks {
-                ret.push(track_to_spec(fft_size, i));
-            }
-            ret
+        Some((time_right, freq_right)) => {
+            format_table(&stem_names, &results.time_mean_error, &results.freq_mean_error, &config.stem_weights, config.precision)
+                .and_then(|left| Result::Ok(format!("Left channel\n{}\nRight channel\n{}", left,
+                    format_table(&stem_names, time_right, freq_right, &config.stem_weights, config.precision)?)))
         }
     };
-    
-
-    // Unwrap
-    let mut spectograms_1: Vec<StereoSpectogram> = vec![];
-    for _ in 0..4 {
-        spectograms_1.push(spectograms_ret.pop().unwrap());
-    }
-
-    let mut spectograms_2: Vec<StereoSpectogram> = vec![];
-    for _ in 0..4 {
-        spectograms_2.push(spectograms_ret.pop().unwrap());
-    }
-
-    // Compare spectograms
-    // Two methods are used: In "Time Mode" all bin differences influene the final result in the same way
-    // In "Frequency Mode" bin differences of higher frequencies influence the final result less, since they are less
-    // noticable by the human ear. 
-    println!("");
-    let mut time_mean_error: Vec<f32> = vec![];
-    let mut freq_mean_error: Vec<f32> = vec![];
-
-    // Vectors for graph exporting
-    let mut graphdata_time: Vec<GraphData> = vec![];  
-    let mut graphdata_freq: Vec<GraphData> = vec![];
-    for i in 0..4 {
-        // Comparison through time
-        match time_compare_spectogram(fft_size/2, &spectograms_1[i], &spectograms_2[i]) {
-            Ok((v, e)) => {            
-                time_mean_error.push(e);
-                graphdata_time.push(
-                    GraphData::new(v, stem_names[i].clone())
-                );
+    match tables {
+        Ok(t)  => { println!("{}", t); }
+        Err(e) => { println!("{e}"); std::process::exit(1); }
+    }
+
+    // Both totals in one number
+    if let Some(alpha) = config.alpha {
+        let mut channels: Vec<(&str, &[f32], &[f32])> = vec![("", &results.time_mean_error, &results.freq_mean_error)];
+        if let Some((time_right, freq_right)) = &results.right_channel {
+            channels = vec![(" (L)", &results.time_mean_error, &results.freq_mean_error), (" (R)", time_right, freq_right)];
+        }
+        for (label, time_errs, freq_errs) in channels {
+            let score = weighted_mean(time_errs, &config.stem_weights)
+                .and_then(|t| Result::Ok((t, weighted_mean(freq_errs, &config.stem_weights)?)))
+                .and_then(|(t, f)| combined_score(t, f, alpha, results.freq_scale));
+            match score {
+                Ok(s)  => { println!("Combined score{} (alpha {}): {}", label, alpha, format_value(s, config.precision, 4)); }
+                Err(e) => { println!("{e}"); }
             }
-            Err(e) => { panic!("{e}") }
         }
+        println!();
+    }
 
-        // Comparison through frequencies
-        match freq_compare_spectogram(fft_size/2, &spectograms_1[i], &spectograms_2[i]) {
-            Ok((v, e)) => { 
-                graphdata_freq.push(
-                    GraphData::new(v, stem_names[i].clone())    
-                );
-                freq_mean_error.push(e);
-            }
-            Err(e) => { panic!("{e}") }
+    // Compact view of the frequency errors
+    if !results.freq_bands.is_empty() {
+        println!("Frequency error per octave band\n{}", format_band_table(&stem_names, &results.freq_bands, config.precision));
+    }
+
+    println!("Time error per frame\n{}", format_stats_table(&stem_names, &results.time_stats, config.precision));
+
+    // Point to the worst moment of each stem in time mode; Timestamps are relative to the start of the tracks
+    let range_start: f32 = config.range.map(|r| r.0).unwrap_or(0.0);
+    for (name, (frame, error, timestamp)) in stem_names.iter().zip(results.worst_frames.iter()) {
+        println!("{:<8} worst at {} (frame {}, error {})", name, format_timestamp(timestamp + range_start), frame, format_value(*error, config.precision, 4));
+    }
+
+    // Frames only one source has are left out of the comparison (or compared against silence with --frame-mismatch extend)
+    if results.coverage.iter().any(|c| c.spec_a_frames != c.spec_b_frames) {
+        println!("\nFrames compared (of each source):");
+        for (name, c) in stem_names.iter().zip(results.coverage.iter()) {
+            println!("{:<8} {} of {} / {} ({:.1}% / {:.1}%)", name, c.usable_frames, c.spec_a_frames, c.spec_b_frames, c.coverage_a * 100.0, c.coverage_b * 100.0);
         }
     }
 
-    // Calculate final results by getting the mean error from all tracks
-    let mut time_me: f32 = 0.0;
-    let mut freq_me: f32 = 0.0;
-    for i in 0..4 {
-        time_me += time_mean_error[i];
-        freq_me += freq_mean_error[i];
+    // SNR treats the first source as the ground truth
+    println!("\nSNR against {}:", config.sources[0]);
+    for (name, snr) in stem_names.iter().zip(results.snr.iter()) {
+        println!("{:<8} {:.2} dB", name, snr);
     }
-    time_me /= 4.0;
-    freq_me /= 4.0;
 
-    // Stop the timer and display execution time
-    println!("\rDone processing! Time elapsed: {:.2} ms\n", start_time.elapsed().as_millis());
+    // Stereo image
+    println!("\nPanning error:");
+    for (name, panning) in stem_names.iter().zip(results.panning.iter()) {
+        println!("{:<8} {}", name, format_value(*panning, config.precision, 4));
+    }
 
-    // Display final results
-    print!("\n-- Final Results ----------------------------------------\n");
-    print!("     |   Vocals    Drums    Bass    Other\t|  Total\n");
-    print!("Time |   {:.4}    {:.4}    {:.4}   {:.4}\t|   {:.3}\n", 
-        time_mean_error[0], time_mean_error[1], time_mean_error[2], time_mean_error[3], time_me);
-    print!("Freq |   {:.4}    {:.4}    {:.4}   {:.4}\t|   {:.3}\n\n", 
-        freq_mean_error[0], freq_mean_error[1], freq_mean_error[2], freq_mean_error[3], freq_me); 
+    if !results.jitter.is_empty() {
+        println!("\nTime error within {} frames of jitter:", config.jitter);
+        for (name, (error, shifted)) in stem_names.iter().zip(results.jitter.iter()) {
+            println!("{:<8} {} ({:.1}% of the frames matched a neighbour)", name, format_value(*error, config.precision, 4), shifted * 100.0);
+        }
+    }
+    if !results.warped.is_empty() {
+        println!("\nTime error along a warping path within {} frames:", config.warp);
+        for (name, (error, drift)) in stem_names.iter().zip(results.warped.iter()) {
+            println!("{:<8} {} (paired frames up to {} frames apart)", name, format_value(*error, config.precision, 4), drift);
+        }
+    }
+    if !results.multi_resolution.is_empty() {
+        let sizes: Vec<String> = config.resolutions.iter().map(|s| s.to_string()).collect();
+        println!("\nMulti-resolution time error ({}):", sizes.join(", "));
+        for (name, (errors, score)) in stem_names.iter().zip(results.multi_resolution.iter()) {
+            let per_size: Vec<String> = errors.iter().map(|e| format_value(*e, config.precision, 4)).collect();
+            println!("{:<8} {} ({})", name, format_value(*score, config.precision, 4), per_size.join(", "));
+        }
+    }
+    println!();
+
+    let mut gated: Vec<(String, &[f32], &[f32])> = vec![(String::new(), &results.time_mean_error, &results.freq_mean_error)];
+    if let Some((time_right, freq_right)) = &results.right_channel {
+        gated = vec![(String::from("Left channel"), &results.time_mean_error, &results.freq_mean_error), (String::from("Right channel"), time_right, freq_right)];
+    }
+    gate_thresholds(&gated, &stem_names, &config);
 }
 
-// --- Unused functions ------------------------------------------------------------------------------
-// Exports comparison results into a csv file
-fn export_error_csv(path: &String, data: &Vec<f32>) -> Result<(), String> {
-    // Create the buffer first
-    let mut write_buffer: Vec<u8> = vec![];
-    write_buffer.reserve(data.len() * 8);
-
-    // Open file
-    let f = File::create(path);
-    if f.is_err() { return Result::Err(format!("export_error_csv(): Could not create {}.", path)); }
-    let mut f = f.unwrap();
-
-    // Write and close
-    match f.write(&write_buffer) {
-        Ok(_) => {}
-        Err(e) => {
-            return Result::Err(format!("export_error_csv(): I/O Error ({}).", e));
+// Compares the two live streams given as sources and prints the error of every frame as a JSON line; Errors go to
+// stderr so they can't be mistaken for results.
+fn run_live(config: &Config) {
+    let mut streams: Vec<Box<dyn std::io::Read + Send>> = Vec::with_capacity(2);
+    for source in config.sources.iter() {
+        match open_live_source(source) {
+            Ok(s)  => { streams.push(s); }
+            Err(e) => { eprintln!("{e}"); return; }
         }
     }
+    let streams: [Box<dyn std::io::Read + Send>; 2] = match streams.try_into() {
+        Ok(s)  => { s }
+        Err(_) => { eprintln!("--live expects two streams."); return; }
+    };
 
-    Result::Ok(())
+    let hop: u32 = config.hop.unwrap_or(config.fft_size);
+    let mut stdout = std::io::stdout();
+    if let Err(e) = run_live_comparison(streams, config.fft_size, hop, config.window, &mut stdout) {
+        eprintln!("{e}");
+    }
 }
 
-fn test(sample1: &String, sample2: &String) {
-    let track1: TrackBuffer = match import_track(sample1) {
-        Ok(b)  => { b }
-        Err(e) => { println!("{e}"); panic!("{e}"); }
+// Compares every pair listed in `manifest` and displays one row per pair; The results are also written to a .csv
+// file next to the manifest. Pairs that fail are reported and left out of the results, and the program exits with an
+// error code once the others are displayed.
+fn run_batch(manifest: &String, stem_names: &[String], config: &Config) {
+    let entries = match read_batch_manifest(manifest) {
+        Ok(e)  => { e }
+        Err(e) => { println!("{e}"); std::process::exit(1); }
+    };
+
+    let start_time = Instant::now();
+    let (songs, time_errors, freq_errors, skipped) = collect_pair_results(&entries, stem_names, config);
+
+    statusln!("\rDone processing {}/{} pairs! Time elapsed: {:.2} ms\n", entries.len() - skipped, entries.len(), start_time.elapsed().as_millis());
+    if songs.is_empty() {
+        println!("\nNone of the {} pairs could be compared.", entries.len());
+        std::process::exit(1);
+    }
+
+    // Display final results
+    print!("\n-- Batch Results ----------------------------------------\n");
+    match format_batch_table(&songs, &time_errors, &freq_errors, &config.stem_weights, config.precision) {
+        Ok(t)  => { println!("{}", t); }
+        Err(e) => { println!("{e}"); std::process::exit(1); }
+    }
+
+    // Save them as well
+    let csv_path: String = Path::new(manifest).with_extension("results.csv").to_string_lossy().to_string();
+    let csv = match format_batch_csv(stem_names, &songs, &time_errors, &freq_errors, &config.stem_weights, config.precision) {
+        Ok(c)  => { c }
+        Err(e) => { println!("{e}"); std::process::exit(1); }
     };
+    match File::create(&csv_path).and_then(|mut f| f.write_all(csv.as_bytes())) {
+        Ok(_)  => { statusln!("Results saved to {}\n", csv_path); }
+        Err(e) => { println!("Could not write {} ({}).\n", csv_path, e); }
+    }
+
+    let gated: Vec<(String, &[f32], &[f32])> = songs.iter().zip(time_errors.iter().zip(freq_errors.iter()))
+        .map(|(song, (t, f))| (song.clone(), t.as_slice(), f.as_slice()))
+        .collect();
+    if skipped > 0 {
+        println!("{} of {} pairs were skipped.\n", skipped, entries.len());
+    }
+    gate_thresholds(&gated, stem_names, config);
+    if skipped > 0 { std::process::exit(1); }
+}
+
+// Compares every candidate (the sources after the first one) against the reference (the first source) and displays
+// them ranked by their time error; Like in batch mode, candidates that fail are reported and left out, and make the
+// program exit with an error code.
+fn run_candidates(stem_names: &[String], config: &Config) {
+    let reference: &String = &config.sources[0];
+    let start_time = Instant::now();
+
+    let pairs: Vec<(String, String, String)> = config.sources.iter().skip(1)
+        .map(|candidate| (reference.clone(), candidate.clone(), candidate.clone()))
+        .collect();
+    let (candidates, time_errors, freq_errors, skipped) = collect_pair_results(&pairs, stem_names, config);
+
+    statusln!("\rDone processing {}/{} candidates! Time elapsed: {:.2} ms\n", pairs.len() - skipped, pairs.len(), start_time.elapsed().as_millis());
+    if candidates.is_empty() {
+        println!("\nNone of the {} candidates could be compared.", pairs.len());
+        std::process::exit(1);
+    }
+
+    print!("\n-- Ranking against {} ----------------------------------------\n", reference);
+    match format_ranking_table(&candidates, &time_errors, &freq_errors, &config.stem_weights, config.precision) {
+        Ok(t)  => { println!("{}", t); }
+        Err(e) => { println!("{e}"); std::process::exit(1); }
+    }
+
+    let gated: Vec<(String, &[f32], &[f32])> = candidates.iter().zip(time_errors.iter().zip(freq_errors.iter()))
+        .map(|(candidate, (t, f))| (candidate.clone(), t.as_slice(), f.as_slice()))
+        .collect();
+    if skipped > 0 {
+        println!("{} of {} candidates were skipped.\n", skipped, pairs.len());
+    }
+    gate_thresholds(&gated, stem_names, config);
+    if skipped > 0 { std::process::exit(1); }
+}
+
+// Rows of `collect_pair_results()`: the label, the time errors and the frequency errors of each row and the number
+// of pairs that were skipped
+type PairRows = (Vec<String>, Vec<Vec<f32>>, Vec<Vec<f32>>, usize);
+
+// Compares every pair of `pairs` (a reference, a test source and a name) for the batch and candidate modes; Every
+// pair gets its own output directories, named after the last component of its name (see `config_for_pair()`).
+// Returns one label and the time and frequency errors of each row (one per pair, or one per channel with
+// `--split-channels`) and the number of pairs that failed, which are reported and left out.
+fn collect_pair_results(pairs: &[(String, String, String)], stem_names: &[String], config: &Config) -> PairRows {
+    let mut names: Vec<String> = vec![];
+    let mut time_errors: Vec<Vec<f32>> = vec![];
+    let mut freq_errors: Vec<Vec<f32>> = vec![];
+    let mut skipped: usize = 0;
+    for (reference, test, name) in pairs {
+        statusln!("\n-- {} ----------------------------------------", name);
+        let dir_name: String = Path::new(name).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or(name.clone());
+        let pair_config: Config = match config_for_pair(config, &dir_name) {
+            Ok(c)  => { c }
+            Err(e) => {
+                println!("\n{}\nSkipping {}.", e, name);
+                skipped += 1;
+                continue;
+            }
+        };
+
+        match compare_sources(reference, test, stem_names, &pair_config) {
+            Ok(r) => {
+                match r.right_channel {
+                    // One row per channel
+                    Some((time_right, freq_right)) => {
+                        names.push(format!("{} (L)", name));
+                        time_errors.push(r.time_mean_error);
+                        freq_errors.push(r.freq_mean_error);
+                        names.push(format!("{} (R)", name));
+                        time_errors.push(time_right);
+                        freq_errors.push(freq_right);
+                    }
+                    None => {
+                        names.push(name.clone());
+                        time_errors.push(r.time_mean_error);
+                        freq_errors.push(r.freq_mean_error);
+                    }
+                }
+            }
+            Err(e) => {
+                println!("\n{}\nSkipping {}.", e, name);
+                skipped += 1;
+            }
+        }
+    }
+    (names, time_errors, freq_errors, skipped)
+}
+
+// Plots the spectogram of every stem of the only source to `dir`, one `<stem>.png` per stem; Nothing is compared
+fn run_spectogram_only(stem_names: &[String], dir: &String, config: &Config) -> Result<(), SpecCompError> {
+    let source: &String = &config.sources[0];
+    prepare_output_dir(dir)?;
 
-    let track2: TrackBuffer = match import_track(sample2) {
-        Ok(b)  => { b }
-        Err(e) => { println!("{e}"); panic!("{e}"); }
+    let mut tracks: Vec<Track> = import_source(source, config)?;
+    for t in tracks.iter_mut() {
+        if t.encoder_delay.is_none() {
+            if let Some(d) = config.encoder_delay { t.skip_samples(d); }
+        }
+        if config.trim_silence {
+            trim_silence(&mut t.samples, config.silence_threshold);
+        }
+        if let Some((start, end)) = config.range {
+            t.trim(start, end)?;
+        }
+    }
+    let tracks: Vec<TrackBuffer> = tracks.into_iter().map(|t| t.samples).collect();
+
+    let fft_size: u32 = config.fft_size;
+    let spectograms: Vec<StereoSpectogram> = match config.in_parallel {
+        true  => { mt_track_to_spec(fft_size, config.window, tracks, config.threads, config.split_channels) }
+        false => { tracks.iter().map(|t| track_to_spec(fft_size, config.window, t)).collect() }
     };
 
-    // Get and store spectograms
-    println!("");
-    let fft_size: u32 = 4096;
-    let tracks_for_spec = vec![track1, track2];
-    let spectograms: Vec<StereoSpectogram> = mt_track_to_spec(fft_size, tracks_for_spec);
+    let scale = MagnitudeScale::Decibel { floor_db: -80.0 };
+    for (i, name) in stem_names.iter().enumerate() {
+        let file: String = Path::new(dir).join(format!("{}.png", name.to_lowercase())).to_string_lossy().to_string();
+        plot_spectogram(&spectograms[i], fft_size / 2, config.colormap, scale, config.plot_width, &file)?;
+    }
+    statusln!("Spectograms of {} saved to {}", source, dir);
+    Result::Ok(())
+}
 
-    // `spectograms` has the reverse order from `tracks_for_spec`
-    let _ = export_error_csv(&String::from("sepctogram2.csv"), &spectograms[0].right);
-    let _ = export_error_csv(&String::from("sepctogram1.csv"), &spectograms[1].right);
+// Probes the stems of every source (the two sources or every source of the manifest) and displays their format,
+// sample rate and duration; Nothing is decoded. Exits with an error code if any source is incomplete or unreadable.
+fn run_check(config: &Config, stem_names: &[String]) {
+    let sources: Vec<String> = match &config.batch {
+        None => { config.sources.clone() }
+        Some(manifest) => {
+            match read_batch_manifest(manifest) {
+                Ok(entries) => { entries.into_iter().flat_map(|(reference, test, _)| [reference, test]).collect() }
+                Err(e)      => { println!("{e}"); std::process::exit(1); }
+            }
+        }
+    };
 
-    // Create time comparison
-    match time_compare_spectogram(fft_size/2, &spectograms[1], &spectograms[0]) {
-        Ok((c, _)) => { 
-            let _ = export_error_csv(&String::from("time-comp.csv"), &c);   
+    let mut failed: usize = 0;
+    for source in &sources {
+        println!("{}:", source);
+        match probe_stems(source, config.stem_pattern.as_deref()) {
+            Ok(stems) => {
+                for (name, stem) in stem_names.iter().zip(stems.iter()) {
+                    let duration: String = match stem.duration {
+                        Some(d) => { format!("{:.2}s", d) }
+                        None    => { String::from("unknown duration") }
+                    };
+                    println!("  {:<8} {} ({}, {} Hz, {} channels, {})", name, stem.path, stem.codec, stem.sample_rate, stem.channels, duration);
+                }
+            }
+            Err(e) => {
+                println!("  {e}");
+                failed += 1;
+            }
         }
-        Err(e) => { panic!("{e}") }
     }
 
-    // Create frequency comparison
-    match freq_compare_spectogram(fft_size/2, &spectograms[1], &spectograms[0]) {
-        Ok((c, _)) => { 
-            let _ = export_error_csv(&String::from("freq-comp.csv"), &c);   
+    println!("\n{}/{} sources are ready.", sources.len() - failed, sources.len());
+    if failed > 0 { std::process::exit(1); }
+}
+
+// Runs the whole pipeline on two sources: imports their stems and compares them (see `compare_tracks()`)
+fn compare_sources(source_1: &String, source_2: &String, stem_names: &[String], config: &Config) -> Result<PairResults, SpecCompError> {
+    // Import files; every track will be loaded into `input_tracks`.
+    let mut input_tracks: Vec<Track> = vec![];
+    input_tracks.append(&mut import_source(source_1, config)?);
+    input_tracks.append(&mut import_source(source_2, config)?);
+
+    compare_tracks(input_tracks, [source_1, source_2], stem_names, config)
+}
+
+// Checks the errors of every stem against `--threshold` (see `check_thresholds()`) and exits with an error code after
+// listing every exceeded threshold, so that the program can gate CI runs; `results` holds the label of each result
+// (a pair, a candidate or a channel; empty for a single result) with its time and frequency errors.
+fn gate_thresholds(results: &[(String, &[f32], &[f32])], stem_names: &[String], config: &Config) {
+    if config.thresholds.is_empty() { return; }
+
+    let mut failures: Vec<String> = vec![];
+    for (label, time_errs, freq_errs) in results {
+        for failure in check_thresholds(stem_names, time_errs, freq_errs, &config.thresholds, config.precision) {
+            match label.is_empty() {
+                true  => { failures.push(failure); }
+                false => { failures.push(format!("{} - {}", label, failure)); }
+            }
         }
-        Err(e) => { panic!("{e}") }
     }
 
-}
\ No newline at end of file
+    if failures.is_empty() {
+        println!("All thresholds are met.\n");
+        return;
+    }
+    println!("Thresholds exceeded:");
+    for failure in &failures {
+        println!("  {}", failure);
+    }
+    println!();
+    std::process::exit(1);
+}
+
+// Creates an output directory (for plots or .npy files) if needed and makes sure files can be written to it
+fn prepare_output_dir(dir: &String) -> Result<(), SpecCompError> {
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        return Result::Err(SpecCompError::Io(format!("Could not create the output directory {} ({}).", dir, e)));
+    }
+
+    let probe = Path::new(dir).join(".speccomp-write-test");
+    match File::create(&probe) {
+        Ok(_)  => { let _ = std::fs::remove_file(&probe); Result::Ok(()) }
+        Err(e) => { Result::Err(SpecCompError::Io(format!("Can't write to the output directory {} ({}).", dir, e))) }
+    }
+}
+
+// Settings of one pair of a batch or one candidate: the plots and .npy files go to a subdirectory named `name` of
+// those of `config`, which is created
+fn config_for_pair(config: &Config, name: &str) -> Result<Config, SpecCompError> {
+    let mut pair_config: Config = config.clone();
+    for d in [&mut pair_config.plot, &mut pair_config.npy].into_iter().flatten() {
+        let pair_dir: String = Path::new(d).join(name).to_string_lossy().to_string();
+        prepare_output_dir(&pair_dir)?;
+        *d = pair_dir;
+    }
+    Result::Ok(pair_config)
+}
+
+// Formats a timestamp in seconds as mm:ss
+fn format_timestamp(seconds: f32) -> String {
+    let total = seconds as u32;
+    format!("{:02}:{:02}", total / 60, total % 60)
+}
+
+// Imports the 4 stems of a source; A source is a directory or a .zip archive (see `detect_source_kind()`)
+fn import_source(source: &String, config: &Config) -> Result<Vec<Track>, SpecCompError> {
+    let (pattern, limit): (Option<&str>, Option<f32>) = (config.stem_pattern.as_deref(), config.limit_duration);
+    match detect_source_kind(source, pattern)? {
+        SourceKind::StemDirectory => {}
+        // Archives are read sequentially
+        SourceKind::StemArchive => { return import_from_zip(source, limit); }
+        SourceKind::MultiTrackFile(file) => { return Result::Err(multitrack_unsupported("import_source()", &file)); }
+        SourceKind::Ambiguous(reason) => { return Result::Err(SpecCompError::InvalidArgument(format!("import_source(): {}", reason))); }
+    }
+
+    match config.in_parallel {
+        // Load the 4 tracks in parallel
+        true  => { mt_import_from_directory(source, pattern, config.threads, limit) }
+        // Load everything sequentially
+        false => { import_from_directory(source, pattern, limit) }
+    }
+}
+
+// Whether `a` and `b` point to the same source, e.g. "dir" and "./dir/"
+fn same_source(a: &String, b: &String) -> bool {
+    match (Path::new(a).canonicalize(), Path::new(b).canonicalize()) {
+        (Ok(path_a), Ok(path_b)) => { path_a == path_b }
+        _ => { a == b }
+    }
+}
+
+fn print_usage() {
+    println!("usage: spec-compare source1 source2 [--serial] [--mel] [--threads N] [--split-channels] [--fft-size N] [--resolutions N1,N2,... [--resolution-weights w1,w2,...]] [--window name] [--channels mode] [--relative | --db [floor_dB]] [--frame-mismatch mode] [--weighting name] [--weights w1,w2,w3,w4] [--alpha a] [--range start_sec:end_sec] [--limit-duration secs] [--stem-pattern pattern] [--trim-silence] [--silence-threshold dBFS] [--encoder-delay samples] [--jitter frames] [--warp frames] [--pad] [--normalize] [--normalize-frames] [--fix-polarity] [--reference source] [--plot dir] [--smooth frames] [--plot-spectograms] [--plot-width pixels] [--colormap name] [--export-spectograms] [--npy dir] [--precision N] [--markdown] [--threshold metric=limit,...] [--check] [--quiet | --verbose]");
+    println!("       spec-compare reference candidate1 candidate2 ... [options]");
+    println!("       spec-compare --batch manifest.csv [options]");
+    println!("       spec-compare source --spectogram-only dir [--fft-size N] [--window name] [--range start_sec:end_sec] [--colormap name] [--plot-width pixels]");
+    println!("       spec-compare --live stream1 stream2 [--fft-size N] [--hop N] [--window name]");
+    println!("    A source can be a directory or a .zip archive with separated stems.");
+    println!("    Each line of a batch manifest is `reference_dir,test_dir,name`.");
+    println!("    A live stream is `tcp://host:port` or a file (e.g. a named pipe) of interleaved f32 samples.\n");
+}
@@ -0,0 +1,410 @@
+use std::path::Path;
+use std::time::Instant;
+
+use crate::types::*;
+use crate::importerts::*;
+use crate::spectograms::*;
+use crate::results::*;
+use crate::graphs::*;
+use crate::config::Config;
+use crate::error::SpecCompError;
+use crate::{statusln, verboseln};
+
+// The comparison of two sources once their stems have been decoded: the tracks are aligned and trimmed as `config`
+// says, turned into spectograms and compared in every mode, and the plots are saved. The program imports the stems
+// from files first (see `importerts`), but tracks that are already in memory can be passed the same way, e.g.
+// `Track::from_buffer()` for samples from another decoder.
+
+// Number of Mel bands used when comparing with "--mel"
+pub const MEL_BANDS: u32 = 128;
+
+// Number of buckets of the time error histogram saved with "--plot"
+pub const HISTOGRAM_BUCKETS: usize = 40;
+
+// Results of comparing two sources; One entry per stem
+pub struct PairResults {
+    pub time_mean_error: Vec<f32>,
+    pub freq_mean_error: Vec<f32>,
+    pub worst_frames: Vec<(usize, f32, f32)>,
+    // Distribution of the per-frame errors in time mode
+    pub time_stats: Vec<ErrorStats>,
+    // SNR of the second source against the first one, in dB
+    pub snr: Vec<f32>,
+    // Frequency error of each octave band (see `aggregate_octave_bands()`); Empty in Mel space
+    pub freq_bands: Vec<Vec<(f32, f32)>>,
+    // Mean panning error (see `panning_compare()`), from 0.0 (same stereo image) to 1.0
+    pub panning: Vec<f32>,
+    // Mean time error when every frame may be matched up to `--jitter` frames away (see `time_compare_tolerant()`)
+    // and the share of frames that were matched to a neighbour; Empty without `--jitter`
+    pub jitter: Vec<(f32, f32)>,
+    // Mean time error along the warping path of `--warp` (see `time_compare_warped()`) and the largest distance (in
+    // frames) between two paired frames; Empty without `--warp`
+    pub warped: Vec<(f32, usize)>,
+    // Time error at every size of `--resolutions` and the weighted score (see `multi_resolution_compare()`); Empty
+    // without `--resolutions`
+    pub multi_resolution: Vec<(Vec<f32>, f32)>,
+    // Frames compared and the share of each source's frames they cover (see `frame_coverage()`)
+    pub coverage: Vec<FrameCoverage>,
+    // Mean weight of the frequency comparison, which normalizes the frequency totals in the combined score
+    pub freq_scale: f32,
+    // Time and frequency errors of the right channel when the channels are compared independently; The errors
+    // above then belong to the left channel
+    pub right_channel: Option<(Vec<f32>, Vec<f32>)>
+}
+
+// Compares the 4 stems of two sources, `input_tracks` holding those of the first source and then those of the
+// second one (in the order of `stem_names`); `sources` names the sources in messages. Returns the results of every
+// stem. Files are only written if `config.plot` or `config.npy` is set, to that directory, which must already exist.
+pub fn compare_tracks(mut input_tracks: Vec<Track>, sources: [&String; 2], stem_names: &[String], config: &Config) -> Result<PairResults, SpecCompError> {
+    if input_tracks.len() != 8 || stem_names.len() != 4 {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("compare_tracks(): Expected 4 stems of 2 sources and 4 stem names, got {} tracks and {} names",
+            input_tracks.len(), stem_names.len())));
+    }
+    let (source_1, source_2) = (sources[0], sources[1]);
+    let (in_parallel, threads) = (config.in_parallel, config.threads);
+
+    // The importers stop decoding at the duration limit, but tracks from memory may be longer
+    if let Some(seconds) = config.limit_duration {
+        for t in input_tracks.iter_mut() {
+            t.samples.truncate(2 * (seconds.max(0.0) * t.sample_rate as f32) as usize);
+        }
+    }
+
+    // NaN and friends have been replaced while decoding, but the results of such a track are questionable
+    for (i, t) in input_tracks.iter().enumerate() {
+        if t.bad_samples > 0 {
+            statusln!("\nWarning: {} ({}) had {} NaN, infinite or subnormal samples, which were replaced with 0.", stem_names[i % 4], [source_1, source_2][i / 4], t.bad_samples);
+        }
+        if t.skipped_packets > 0 {
+            statusln!("\nWarning: {} ({}) had {} corrupt packets, which were replaced by silence.", stem_names[i % 4], [source_1, source_2][i / 4], t.skipped_packets);
+        }
+    }
+
+    // Encoder delays found in the metadata have already been trimmed; Otherwise, skip the given number of samples
+    for (i, t) in input_tracks.iter_mut().enumerate() {
+        match (t.encoder_delay, config.encoder_delay) {
+            (Some(d), _) => { verboseln!("\t{} ({}): trimmed an encoder delay of {} samples", stem_names[i % 4], [source_1, source_2][i / 4], d); }
+            (None, Some(d)) => { t.skip_samples(d); }
+            (None, None) => {}
+        }
+    }
+
+    // All tracks should share the same sample rate; Those above the lowest one are resampled to it (see `resample()`)
+    let sample_rate: u32 = input_tracks.iter().map(|t| t.sample_rate).min().unwrap_or(0);
+    if input_tracks.iter().any(|t| t.sample_rate != sample_rate) {
+        statusln!("\nWarning: The input tracks have different sample rates, resampling them to {} Hz.", sample_rate);
+        for t in input_tracks.iter_mut().filter(|t| t.sample_rate != sample_rate) {
+            t.samples = resample(&t.samples, t.sample_rate, sample_rate);
+            t.sample_rate = sample_rate;
+        }
+    }
+
+    // A lossy stem against a lossless one is likely misaligned by the encoder's padding
+    warn_on_codec_mismatch(&input_tracks, stem_names, [source_1, source_2], config.encoder_delay);
+
+    // Silent padding differs between tools and shifts the frames
+    if config.trim_silence {
+        for t in input_tracks.iter_mut() {
+            trim_silence(&mut t.samples, config.silence_threshold);
+        }
+    }
+
+    // Stems of slightly different lengths would produce different frame counts
+    if config.pad {
+        pad_to_longest(&mut input_tracks);
+    }
+
+    // Only keep the requested section
+    if let Some((start, end)) = config.range {
+        for t in input_tracks.iter_mut() {
+            t.trim(start, end)?;
+        }
+    }
+    // Level differences would otherwise show up as spectral errors
+    if config.normalize {
+        for (i, t) in input_tracks.iter_mut().enumerate() {
+            let gain: f32 = rms_normalize(&mut t.samples, DEFAULT_TARGET_RMS);
+            verboseln!("\t{} ({}): gain {:.3} ({:+.2} dB)", stem_names[i % 4], [source_1, source_2][i / 4], gain, 20.0 * gain.log10());
+        }
+    }
+
+    // The spectograms hide an inverted stem, but waveform comparisons don't
+    for i in 0..4 {
+        let (first, second) = input_tracks.split_at_mut(4);
+        if detect_polarity(&first[i].samples, &second[i].samples) {
+            match config.fix_polarity {
+                true  => {
+                    invert_polarity(&mut second[i].samples);
+                    verboseln!("\t{} ({}): inverted the polarity", stem_names[i], source_2);
+                }
+                false => { statusln!("\nWarning: {} of {} seems to have the opposite polarity of {} (see --fix-polarity).", stem_names[i], source_2, source_1); }
+            }
+        }
+    }
+    let input_tracks: Vec<TrackBuffer> = input_tracks.into_iter().map(|t| t.samples).collect();
+
+    // Errors of silent frames say little
+    for i in 0..4 {
+        let silent: Vec<bool> = silent_frames(&input_tracks[i], config.fft_size, config.silence_threshold);
+        verboseln!("\t{} ({}): {} of {} frames are silent", stem_names[i], source_1, silent.iter().filter(|s| **s).count(), silent.len());
+    }
+
+    // Comparison at several FFT sizes; It needs the tracks, which are consumed by the spectograms below
+    let mut multi_resolution: Vec<(Vec<f32>, f32)> = vec![];
+    if !config.resolutions.is_empty() {
+        for i in 0..4 {
+            multi_resolution.push(multi_resolution_compare(&input_tracks[i], &input_tracks[4 + i], &config.resolutions, &config.resolution_weights, config.window)?);
+        }
+    }
+
+    statusln!("");
+    let spec_start = Instant::now();
+    let fft_size: u32 = config.fft_size;
+    let mut bins: u32 = fft_size / 2;
+
+    // Calculate spectograms
+    let mut spectograms_ret = match in_parallel {
+        // All spectograms are calculated in parallel
+        true  => { mt_track_to_spec(fft_size, config.window, input_tracks, threads, config.split_channels) }
+
+        // Sequential...
+        false => {
+            // Create a return buffer and allocate memory for it
+            let mut ret: Vec<StereoSpectogram> = Vec::with_capacity(input_tracks.len());
+
+            for i in &input_tracks {
+                ret.push(track_to_spec(fft_size, config.window, i));
+            }
+            ret
+        }
+    };
+    
+
+    verboseln!("\tSpectograms took {} ms", spec_start.elapsed().as_millis());
+
+    // Both ways keep the order of `input_tracks`: the 4 stems of the first source, then those of the second one
+    let mut spectograms_2: Vec<StereoSpectogram> = spectograms_ret.split_off(4);
+    let mut spectograms_1: Vec<StereoSpectogram> = spectograms_ret;
+
+    // Move to Mel space if requested; From here on each frame has `MEL_BANDS` bins
+    if config.use_mel {
+        spectograms_1 = spectograms_1.iter().map(|s| to_mel(s, bins, sample_rate, MEL_BANDS)).collect::<Result<_, _>>()?;
+        spectograms_2 = spectograms_2.iter().map(|s| to_mel(s, bins, sample_rate, MEL_BANDS)).collect::<Result<_, _>>()?;
+        bins = MEL_BANDS;
+    }
+
+    // The comparisons only use the frames both spectograms have, unless the missing ones should count as silence
+    let mut coverage: Vec<FrameCoverage> = vec![];
+    for (spec_1, spec_2) in spectograms_1.iter_mut().zip(spectograms_2.iter_mut()) {
+        coverage.push(frame_coverage(bins, spec_1, spec_2, config.frame_mismatch)?);
+        match_frame_counts(bins, spec_1, spec_2, config.frame_mismatch);
+    }
+
+    // Loudness changes from frame to frame would otherwise outweigh the spectral balance
+    if config.normalize_frames {
+        for spec in spectograms_1.iter_mut().chain(spectograms_2.iter_mut()) {
+            spec.normalize_frames(bins);
+        }
+    }
+
+    // Compare spectograms
+    // Two methods are used: In "Time Mode" all bin differences influene the final result in the same way
+    // In "Frequency Mode" bin differences of higher frequencies influence the final result less, since they are less
+    // noticable by the human ear. 
+    statusln!("");
+    let compare_start = Instant::now();
+    let mut results = PairResults {
+        time_mean_error: vec![],
+        freq_mean_error: vec![],
+        worst_frames: vec![],
+        time_stats: vec![],
+        snr: vec![],
+        panning: vec![],
+        jitter: vec![],
+        warped: vec![],
+        multi_resolution,
+        coverage,
+        freq_scale: 1.0,
+        freq_bands: vec![],
+        right_channel: Option::None
+    };
+    let mut time_right: Vec<f32> = vec![];
+    let mut freq_right: Vec<f32> = vec![];
+
+    // Vectors for graph exporting
+    let mut graphdata_time: Vec<GraphData> = vec![];  
+    let mut graphdata_freq: Vec<GraphData> = vec![];
+    let mut frame_errors: Vec<Vec<f32>> = vec![];
+    let mut bin_errors: Vec<Vec<f32>> = vec![];
+    for i in 0..4 {
+        // Comparison through time; The first result is either the averaged, the worse or the left channel
+        let mut channels = time_compare_channels(bins, &spectograms_1[i], &spectograms_2[i], config.channel_mode, config.error_scale)?.into_iter();
+        let (v, e) = channels.next().unwrap();
+        if let Some((_, e_right)) = channels.next() { time_right.push(e_right); }
+        results.time_mean_error.push(e);
+        results.worst_frames.push(worst_frame(&v, fft_size, sample_rate));
+        results.time_stats.push(error_stats(&v));
+        frame_errors.push(v.clone());
+        graphdata_time.push(
+            GraphData::new(v, stem_names[i].clone())
+        );
+
+        // Comparison through frequencies
+        let mut channels = freq_compare_channels(bins, sample_rate, &spectograms_1[i], &spectograms_2[i], config.channel_mode, config.weighting, config.error_scale)?.into_iter();
+        let (v, e) = channels.next().unwrap();
+        if let Some((_, e_right)) = channels.next() { freq_right.push(e_right); }
+        if !config.use_mel {
+            results.freq_bands.push(aggregate_octave_bands(&v, sample_rate, fft_size, 1));
+        }
+        bin_errors.push(v.clone());
+        graphdata_freq.push(
+            GraphData::new(v, stem_names[i].clone())    
+        );
+        results.freq_mean_error.push(e);
+
+        // Source 1 is the reference
+        results.snr.push(snr_compare(bins, &spectograms_1[i], &spectograms_2[i])?);
+        results.panning.push(panning_compare(bins, &spectograms_1[i], &spectograms_2[i])?.1);
+        if config.jitter > 0 {
+            let (_, offsets, e) = time_compare_tolerant(bins, &spectograms_1[i], &spectograms_2[i], config.jitter)?;
+            let shifted: usize = offsets.iter().filter(|o| **o != 0).count();
+            results.jitter.push((e, shifted as f32 / offsets.len() as f32));
+        }
+        if config.warp > 0 {
+            let (_, path, e) = time_compare_warped(bins, &spectograms_1[i], &spectograms_2[i], config.warp)?;
+            let drift: usize = path.iter().map(|(r, t)| r.abs_diff(*t)).max().unwrap_or(0);
+            results.warped.push((e, drift));
+        }
+    }
+    let freq_weights: Vec<f32> = config.weighting.weights(bins, sample_rate)?;
+    results.freq_scale = freq_weights.iter().sum::<f32>() / freq_weights.len() as f32;
+    if config.channel_mode == ChannelMode::Independent {
+        results.right_channel = Option::Some((time_right, freq_right));
+    }
+    verboseln!("\tComparisons took {} ms", compare_start.elapsed().as_millis());
+
+    // Raw error curves for analysis in Python
+    if let Some(dir) = &config.npy {
+        for (i, name) in stem_names.iter().enumerate() {
+            let file = |suffix: &str| Path::new(dir).join(format!("{}-{}.npy", name.to_lowercase(), suffix)).to_string_lossy().to_string();
+            export_npy(&file("time"), &frame_errors[i])?;
+            export_npy(&file("freq"), &bin_errors[i])?;
+        }
+        statusln!("Error curves saved to {}", dir);
+    }
+
+    // Plots
+    if let Some(dir) = &config.plot {
+        let plot_start = Instant::now();
+        let hz_per_bin: Option<f32> = match config.use_mel {
+            true  => { Option::None }
+            false => { Option::Some(sample_rate as f32 / fft_size as f32) }
+        };
+        // Frames don't overlap, so they start every `fft_size` samples
+        plot_time_error(graphdata_time, fft_size, sample_rate, config.smooth, dir)?;
+
+        // Low errors often are silent frames
+        let energies: Vec<GraphData> = stem_names.iter().zip(spectograms_1.iter())
+            .map(|(name, spec)| GraphData::new(spec.frame_energies(bins), name.clone()))
+            .collect();
+        plot_frame_energy(energies, fft_size, sample_rate, dir)?;
+
+        // Same buckets for every stem, so that the histograms can be compared
+        let low: f32 = results.time_stats.iter().map(|s| s.min).fold(f32::INFINITY, f32::min);
+        let high: f32 = results.time_stats.iter().map(|s| s.max).fold(f32::NEG_INFINITY, f32::max);
+        let histograms: Vec<(String, Vec<(f32, u32)>)> = stem_names.iter().zip(frame_errors.iter())
+            .map(|(name, errors)| (name.clone(), error_histogram(errors, HISTOGRAM_BUCKETS, Option::Some((low, high)))))
+            .collect();
+        plot_error_histogram(&histograms, dir)?;
+        export_frame_errors_csv(stem_names, &frame_errors, fft_size, sample_rate, config.precision, &Path::new(dir).join("time-error.csv").to_string_lossy().to_string())?;
+        plot_freq_error(graphdata_freq, hz_per_bin, dir)?;
+
+        if config.plot_spectograms {
+            // kHz guides don't apply to Mel bands
+            let guide_rate: u32 = if config.use_mel { 0 } else { sample_rate };
+            let scale = MagnitudeScale::Decibel { floor_db: -80.0 };
+            for (i, name) in stem_names.iter().enumerate() {
+                let file = |suffix: &str| Path::new(dir).join(format!("{}-{}.png", name.to_lowercase(), suffix)).to_string_lossy().to_string();
+                plot_spectogram(&spectograms_1[i], bins, config.colormap, scale, config.plot_width, &file("1"))?;
+                plot_spectogram(&spectograms_2[i], bins, config.colormap, scale, config.plot_width, &file("2"))?;
+                plot_spectogram_diff(&spectograms_1[i], &spectograms_2[i], bins, guide_rate, true, config.plot_width, &file("diff"))?;
+            }
+        }
+
+        if config.export_spectograms {
+            // Mel bands aren't evenly spaced, so they are only numbered
+            let header_rate: u32 = if config.use_mel { 0 } else { sample_rate };
+            for (i, name) in stem_names.iter().enumerate() {
+                let file = |suffix: &str| Path::new(dir).join(format!("{}-{}.csv", name.to_lowercase(), suffix)).to_string_lossy().to_string();
+                export_spectogram_csv(&spectograms_1[i], bins, header_rate, &file("1"))?;
+                export_spectogram_csv(&spectograms_2[i], bins, header_rate, &file("2"))?;
+            }
+        }
+        statusln!("Plots saved to {}", dir);
+        verboseln!("\tPlots took {} ms", plot_start.elapsed().as_millis());
+    }
+
+    Result::Ok(results)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    // Interleaved stereo sine of `frames` samples per channel
+    fn sine(frames: usize, frequency: f32, gain: f32) -> TrackBuffer {
+        (0..frames).flat_map(|i| {
+            let s: f32 = gain * (2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE as f32).sin();
+            [s, s]
+        }).collect()
+    }
+
+    fn stems(gain: f32) -> Vec<Track> {
+        [55.0, 180.0, 440.0, 1000.0].iter().map(|f| Track::from_buffer(&sine(SAMPLE_RATE as usize / 2, *f, gain), SAMPLE_RATE)).collect()
+    }
+
+    #[test]
+    fn compare_tracks_from_memory() {
+        let stem_names: Vec<String> = STEM_NAMES.iter().map(|n| stem_display_name(n)).collect();
+        let sources: [&String; 2] = [&String::from("reference"), &String::from("estimate")];
+        let config = Config { fft_size: 256, ..Config::default() };
+
+        // Same stems in both sources
+        let mut tracks: Vec<Track> = stems(0.5);
+        tracks.extend(stems(0.5));
+        let results = compare_tracks(tracks, sources, &stem_names, &config).unwrap();
+        assert_eq!(results.time_mean_error.len(), 4);
+        assert!(results.time_mean_error.iter().all(|e| *e == 0.0));
+        assert!(results.freq_mean_error.iter().all(|e| *e == 0.0));
+
+        // Quieter estimate
+        let mut tracks: Vec<Track> = stems(0.5);
+        tracks.extend(stems(0.25));
+        let results = compare_tracks(tracks, sources, &stem_names, &config).unwrap();
+        assert!(results.time_mean_error.iter().all(|e| *e > 0.0));
+    }
+
+    #[test]
+    fn duration_limit_applies_to_tracks_from_memory() {
+        let stem_names: Vec<String> = STEM_NAMES.iter().map(|n| stem_display_name(n)).collect();
+        let sources: [&String; 2] = [&String::from("reference"), &String::from("estimate")];
+        let config = Config { fft_size: 256, limit_duration: Option::Some(0.25), ..Config::default() };
+
+        let mut tracks: Vec<Track> = stems(0.5);
+        tracks.extend(stems(0.5));
+        let results = compare_tracks(tracks, sources, &stem_names, &config).unwrap();
+        // 0.25s are 2000 samples, or 7 full frames and a partial one
+        assert!(results.coverage.iter().all(|c| c.spec_a_frames == 8 && c.spec_b_frames == 8));
+    }
+
+    #[test]
+    fn compare_tracks_needs_8_tracks() {
+        let stem_names: Vec<String> = STEM_NAMES.iter().map(|n| stem_display_name(n)).collect();
+        let sources: [&String; 2] = [&String::from("reference"), &String::from("estimate")];
+        let result = compare_tracks(stems(0.5), sources, &stem_names, &Config::default());
+        assert!(matches!(result, Err(SpecCompError::DimensionMismatch(_))));
+    }
+}
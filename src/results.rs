@@ -0,0 +1,573 @@
+use crate::error::SpecCompError;
+use crate::spectograms::{ErrorStats, bin_frequencies, frame_timestamp};
+use crate::types::StereoSpectogram;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Formatting of the final results ----------------------------------------------------------------------------------------
+// Largest number of decimals `--precision` accepts
+pub const MAX_PRECISION: usize = 10;
+
+// Formats a result with `precision` decimals, or `default_precision` if None; Every table and .csv file goes through
+// this, so that `--precision` applies to all of them while each keeps its own default.
+pub fn format_value(value: f32, precision: Option<usize>, default_precision: usize) -> String {
+    format!("{:.*}", precision.unwrap_or(default_precision), value)
+}
+
+// Renders the final results as a table with one column per stem and one row per comparison mode; The last column
+// holds the mean error of all stems, weighted by `stem_weights` (see `weighted_mean()`). Column widths follow the
+// widest value, so every row has the same length. Values have 4 decimals and totals 3, unless `precision` is given.
+pub fn format_results_table(stem_names: &[String], time_errs: &[f32], freq_errs: &[f32], stem_weights: &[f32], precision: Option<usize>) -> Result<String, SpecCompError> {
+    let time_total: f32 = weighted_mean(time_errs, stem_weights)?;
+    let freq_total: f32 = weighted_mean(freq_errs, stem_weights)?;
+
+    // Label column, then one column per stem, then the totals
+    let mut header: Vec<String> = vec![String::new()];
+    header.extend(stem_names.iter().cloned());
+    header.push(String::from("Total"));
+
+    let mut time_row: Vec<String> = vec![String::from("Time")];
+    time_row.extend(time_errs.iter().map(|e| format_value(*e, precision, 4)));
+    time_row.push(format_value(time_total, precision, 3));
+
+    let mut freq_row: Vec<String> = vec![String::from("Freq")];
+    freq_row.extend(freq_errs.iter().map(|e| format_value(*e, precision, 4)));
+    freq_row.push(format_value(freq_total, precision, 3));
+
+    Result::Ok(render_table(&[header, time_row, freq_row], true))
+}
+
+// GitHub-flavored Markdown version of `format_results_table()`, e.g. for reports and issues; The same rows and values,
+// but cells aren't padded since Markdown aligns the columns itself (numbers to the right).
+pub fn format_results_markdown(stem_names: &[String], time_errs: &[f32], freq_errs: &[f32], stem_weights: &[f32], precision: Option<usize>) -> Result<String, SpecCompError> {
+    let time_total: f32 = weighted_mean(time_errs, stem_weights)?;
+    let freq_total: f32 = weighted_mean(freq_errs, stem_weights)?;
+
+    let mut header: Vec<String> = vec![String::from("Mode")];
+    header.extend(stem_names.iter().cloned());
+    header.push(String::from("Total"));
+
+    let mut separator: Vec<String> = vec![String::from("---")];
+    separator.extend((0..stem_names.len() + 1).map(|_| String::from("---:")));
+
+    let mut rows: Vec<Vec<String>> = vec![header, separator];
+    for (label, errs, total) in [("Time", time_errs, time_total), ("Freq", freq_errs, freq_total)] {
+        let mut row: Vec<String> = vec![String::from(label)];
+        row.extend(errs.iter().map(|e| format_value(*e, precision, 4)));
+        row.push(format_value(total, precision, 3));
+        rows.push(row);
+    }
+
+    Result::Ok(rows.iter().map(|r| format!("| {} |\n", r.join(" | "))).collect())
+}
+
+// Renders the results of a batch run with one row per song and the mean of all songs as the last row.
+// `time_errs` and `freq_errs` hold the per-stem errors of each song, which are combined with `stem_weights`.
+// Values have 4 decimals unless `precision` is given.
+pub fn format_batch_table(songs: &[String], time_errs: &[Vec<f32>], freq_errs: &[Vec<f32>], stem_weights: &[f32], precision: Option<usize>) -> Result<String, SpecCompError> {
+    let mut rows: Vec<Vec<String>> = vec![
+        vec![String::from("Song"), String::from("Time"), String::from("Freq")]
+    ];
+
+    let time_totals: Vec<f32> = time_errs.iter().map(|e| weighted_mean(e, stem_weights)).collect::<Result<Vec<f32>, SpecCompError>>()?;
+    let freq_totals: Vec<f32> = freq_errs.iter().map(|e| weighted_mean(e, stem_weights)).collect::<Result<Vec<f32>, SpecCompError>>()?;
+    for (i, song) in songs.iter().enumerate() {
+        rows.push(vec![song.clone(), format_value(time_totals[i], precision, 4), format_value(freq_totals[i], precision, 4)]);
+    }
+
+    rows.push(vec![String::from("Mean"), format_value(mean(&time_totals), precision, 4), format_value(mean(&freq_totals), precision, 4)]);
+    Result::Ok(render_table(&rows, false))
+}
+
+// CSV version of `format_batch_table()`, with the error of every stem as well as the (weighted) totals; Values have
+// 6 decimals unless `precision` is given.
+pub fn format_batch_csv(stem_names: &[String], songs: &[String], time_errs: &[Vec<f32>], freq_errs: &[Vec<f32>], stem_weights: &[f32], precision: Option<usize>) -> Result<String, SpecCompError> {
+    // Header
+    let mut csv = String::from("name");
+    for mode in ["time", "freq"] {
+        for stem in stem_names {
+            csv.push_str(&format!(",{}_{}", mode, stem.to_lowercase()));
+        }
+        csv.push_str(&format!(",{}_total", mode));
+    }
+    csv.push('\n');
+
+    // One line per song, then the mean of every column
+    let mut lines: Vec<(String, Vec<f32>)> = vec![];
+    for (i, song) in songs.iter().enumerate() {
+        let mut values: Vec<f32> = vec![];
+        values.extend_from_slice(&time_errs[i]);
+        values.push(weighted_mean(&time_errs[i], stem_weights)?);
+        values.extend_from_slice(&freq_errs[i]);
+        values.push(weighted_mean(&freq_errs[i], stem_weights)?);
+        lines.push((song.clone(), values));
+    }
+
+    let column_count: usize = 2 * (stem_names.len() + 1);
+    let column_means: Vec<f32> = (0..column_count)
+        .map(|c| mean(&lines.iter().map(|(_, v)| v[c]).collect::<Vec<f32>>()))
+        .collect();
+    lines.push((String::from("mean"), column_means));
+
+    for (name, values) in lines {
+        csv.push_str(&name);
+        for v in values {
+            csv.push_str(&format!(",{}", format_value(v, precision, 6)));
+        }
+        csv.push('\n');
+    }
+
+    Result::Ok(csv)
+}
+
+// Ranks candidates compared against the same reference: one row per candidate, best (lowest weighted time error)
+// first, with the weighted time and frequency errors. Candidates with the same time error keep their order.
+pub fn format_ranking_table(candidates: &[String], time_errs: &[Vec<f32>], freq_errs: &[Vec<f32>], stem_weights: &[f32], precision: Option<usize>) -> Result<String, SpecCompError> {
+    if candidates.len() != time_errs.len() || candidates.len() != freq_errs.len() {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("format_ranking_table(): Got {} candidates but {} time and {} frequency results",
+            candidates.len(), time_errs.len(), freq_errs.len())));
+    }
+
+    let time_totals: Vec<f32> = time_errs.iter().map(|e| weighted_mean(e, stem_weights)).collect::<Result<Vec<f32>, SpecCompError>>()?;
+    let freq_totals: Vec<f32> = freq_errs.iter().map(|e| weighted_mean(e, stem_weights)).collect::<Result<Vec<f32>, SpecCompError>>()?;
+    let mut order: Vec<usize> = (0..candidates.len()).collect();
+    order.sort_by(|a, b| time_totals[*a].total_cmp(&time_totals[*b]));
+
+    let mut rows: Vec<Vec<String>> = vec![
+        vec![String::from("Candidate"), String::from("Time"), String::from("Freq")]
+    ];
+    for (rank, c) in order.iter().enumerate() {
+        rows.push(vec![format!("{}. {}", rank + 1, candidates[*c]), format_value(time_totals[*c], precision, 4), format_value(freq_totals[*c], precision, 4)]);
+    }
+    Result::Ok(render_table(&rows, false))
+}
+
+// Renders the output of `aggregate_octave_bands()` for every stem as a table with one row per stem and one column per
+// band; Every stem is expected to have the same bands. Errors have 3 decimals unless `precision` is given.
+pub fn format_band_table(stem_names: &[String], bands: &[Vec<(f32, f32)>], precision: Option<usize>) -> String {
+    let mut header: Vec<String> = vec![String::from("Hz")];
+    if let Some(first) = bands.first() {
+        header.extend(first.iter().map(|(center, _)| format_frequency(*center)));
+    }
+
+    let mut rows: Vec<Vec<String>> = vec![header];
+    for (name, stem_bands) in stem_names.iter().zip(bands.iter()) {
+        let mut row: Vec<String> = vec![name.clone()];
+        row.extend(stem_bands.iter().map(|(_, e)| format_value(*e, precision, 3)));
+        rows.push(row);
+    }
+
+    render_table(&rows, false)
+}
+
+// Renders the distribution of each stem's per-frame errors (see `error_stats()`) with one row per stem; Values have
+// 4 decimals unless `precision` is given.
+pub fn format_stats_table(stem_names: &[String], stats: &[ErrorStats], precision: Option<usize>) -> String {
+    let mut rows: Vec<Vec<String>> = vec![
+        ["", "Min", "Median", "Mean", "P90", "P99", "Max"].iter().map(|h| h.to_string()).collect()
+    ];
+    for (name, s) in stem_names.iter().zip(stats.iter()) {
+        let mut row: Vec<String> = vec![name.clone()];
+        row.extend([s.min, s.median, s.mean, s.p90, s.p99, s.max].iter().map(|v| format_value(*v, precision, 4)));
+        rows.push(row);
+    }
+
+    render_table(&rows, false)
+}
+
+// Short label of a frequency, e.g. "63" or "16k"
+fn format_frequency(hz: f32) -> String {
+    match hz < 1000.0 {
+        true  => { format!("{}", hz.round()) }
+        false => {
+            let khz: f32 = hz / 1000.0;
+            match khz.fract() < 0.05 || khz >= 10.0 {
+                true  => { format!("{}k", khz.round()) }
+                false => { format!("{:.1}k", khz) }
+            }
+        }
+    }
+}
+
+// Aligns the cells of `rows` into columns as wide as their widest cell; The first column is set apart with a bar,
+// and so is the last one if it holds totals.
+fn render_table(rows: &[Vec<String>], total_column: bool) -> String {
+    // Find the width of each column
+    let column_count: usize = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let mut widths: Vec<usize> = vec![0; column_count];
+    for row in rows {
+        for (c, cell) in row.iter().enumerate() {
+            widths[c] = widths[c].max(cell.len());
+        }
+    }
+
+    let mut table = String::new();
+    for row in rows {
+        let mut line = String::new();
+        for (c, width) in widths.iter().enumerate() {
+            let cell: &str = row.get(c).map(|s| s.as_str()).unwrap_or("");
+            if c == 0 { line.push_str(&format!("{:<w$} |", cell, w = width)); }
+            else if c == column_count - 1 && total_column { line.push_str(&format!("  | {:>w$}", cell, w = width)); }
+            else { line.push_str(&format!("  {:>w$}", cell, w = width)); }
+        }
+        table.push_str(&line);
+        table.push('\n');
+    }
+
+    table
+}
+
+// Mean of `values` weighted by `weights`, normalized by the sum of the weights; Equal weights give the plain mean.
+// There has to be one weight per value, none of them negative, and their sum has to be positive.
+pub fn weighted_mean(values: &[f32], weights: &[f32]) -> Result<f32, SpecCompError> {
+    if values.len() != weights.len() {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("weighted_mean(): Got {} weights for {} values", weights.len(), values.len())));
+    }
+    let weight_sum: f32 = weights.iter().sum();
+    if weights.iter().any(|w| *w < 0.0 || !w.is_finite()) || weight_sum <= 0.0 {
+        return Result::Err(SpecCompError::InvalidArgument(format!("weighted_mean(): The weights must be non-negative with a positive sum ({:?})", weights)));
+    }
+
+    Result::Ok(values.iter().zip(weights.iter()).map(|(v, w)| v * w).sum::<f32>() / weight_sum)
+}
+
+// Single figure of merit out of the time and frequency totals: `alpha * time + (1 - alpha) * freq`. The frequency
+// total is a mean of weighted bin errors, so it's first divided by `freq_scale`, the mean of the frequency weights,
+// which puts it on the scale of the time total (a plain mean of the bin errors). `alpha` 1 gives the time total and
+// 0 the normalized frequency total.
+pub fn combined_score(time_total: f32, freq_total: f32, alpha: f32, freq_scale: f32) -> Result<f32, SpecCompError> {
+    if !(0.0..=1.0).contains(&alpha) {
+        return Result::Err(SpecCompError::InvalidArgument(format!("combined_score(): alpha must be between 0 and 1 (got {})", alpha)));
+    }
+    if freq_scale <= 0.0 || !freq_scale.is_finite() {
+        return Result::Err(SpecCompError::InvalidArgument(format!("combined_score(): The frequency scale must be positive (got {})", freq_scale)));
+    }
+
+    Result::Ok(alpha * time_total + (1.0 - alpha) * freq_total / freq_scale)
+}
+
+// Mean of a vector; 0.0 when it's empty
+fn mean(values: &[f32]) -> f32 {
+    match values.len() {
+        0 => { 0.0 }
+        n => { values.iter().sum::<f32>() / n as f32 }
+    }
+}
+
+// Regression gating -------------------------------------------------------------------------------------------------------
+// Mean error a threshold of `--threshold` applies to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThresholdMetric {
+    Time,
+    Freq
+}
+
+impl ThresholdMetric {
+    // Parses "time" or "freq"
+    pub fn from_name(name: &str) -> Option<ThresholdMetric> {
+        match name.to_lowercase().as_str() {
+            "time" => { Option::Some(ThresholdMetric::Time) }
+            "freq" => { Option::Some(ThresholdMetric::Freq) }
+            _ => { Option::None }
+        }
+    }
+}
+
+// Largest acceptable mean error of one metric; It applies to `stem` only, or to every stem that has no threshold of
+// its own for the metric if `stem` is None.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    pub stem: Option<String>,
+    pub metric: ThresholdMetric,
+    pub limit: f32
+}
+
+// Checks the mean errors of every stem against `thresholds` and returns one message per exceeded threshold, e.g.
+// "Vocals: time error 0.0612 > 0.05"; Empty if every threshold is met. Stems are matched case-insensitively and a
+// NaN error always fails. Errors have 4 decimals unless `precision` is given.
+pub fn check_thresholds(stem_names: &[String], time_errs: &[f32], freq_errs: &[f32], thresholds: &[Threshold], precision: Option<usize>) -> Vec<String> {
+    let mut failures: Vec<String> = vec![];
+    for (s, name) in stem_names.iter().enumerate() {
+        for (metric, label, errs) in [(ThresholdMetric::Time, "time", time_errs), (ThresholdMetric::Freq, "freq", freq_errs)] {
+            let for_metric = || thresholds.iter().filter(|t| t.metric == metric);
+            let threshold: Option<&Threshold> = for_metric()
+                .find(|t| t.stem.as_ref().is_some_and(|stem| stem.eq_ignore_ascii_case(name)))
+                .or_else(|| for_metric().find(|t| t.stem.is_none()));
+
+            if let (Some(t), Some(e)) = (threshold, errs.get(s)) {
+                if e.is_nan() || *e > t.limit {
+                    failures.push(format!("{}: {} error {} > {}", name, label, format_value(*e, precision, 4), t.limit));
+                }
+            }
+        }
+    }
+
+    failures
+}
+
+// Export -----------------------------------------------------------------------------------------------------------------
+// Writes the per-frame errors of every stem (as returned by `time_compare_spectogram()`) to a .csv file, one row per
+// frame: "frame,time_sec" followed by the error of each stem, where `time_sec` is the start of the frame (frames are
+// `hop` samples apart, see `frame_timestamp()`). Stems with fewer frames than the others leave their cells empty.
+// Errors have 6 decimals unless `precision` is given.
+pub fn export_frame_errors_csv(stem_names: &[String], errors: &[Vec<f32>], hop: u32, sample_rate: u32, precision: Option<usize>, path: &String) -> Result<(), SpecCompError> {
+    if stem_names.len() != errors.len() {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("export_frame_errors_csv(): {} stem names for {} error vectors", stem_names.len(), errors.len())));
+    }
+
+    let mut csv = String::from("frame,time_sec");
+    for stem in stem_names {
+        csv.push_str(&format!(",{}", stem.to_lowercase()));
+    }
+    csv.push('\n');
+
+    let frame_count: usize = errors.iter().map(|e| e.len()).max().unwrap_or(0);
+    for f in 0..frame_count {
+        csv.push_str(&format!("{},{:.6}", f, frame_timestamp(f, hop, sample_rate)));
+        for stem_errors in errors {
+            match stem_errors.get(f) {
+                Some(e) => { csv.push_str(&format!(",{}", format_value(*e, precision, 6))); }
+                None    => { csv.push(','); }
+            }
+        }
+        csv.push('\n');
+    }
+
+    match File::create(path).and_then(|mut f| f.write_all(csv.as_bytes())) {
+        Ok(_)  => { Result::Ok(()) }
+        Err(e) => { Result::Err(SpecCompError::Io(format!("export_frame_errors_csv(): Could not write {} ({}).", path, e))) }
+    }
+}
+
+// Writes a whole spectogram to a .csv file, for analysis with other tools; Every row is one frame of one channel:
+// "channel,frame" ("L" or "R" and the frame's index), followed by the `bins` bins of the frame. All the left channel's
+// frames come first. If `sample_rate` isn't 0 the header names each bin column after its center frequency in Hz
+// (bin i is at i * sample_rate / (2*bins) Hz), otherwise after its index (e.g. for Mel bands).
+pub fn export_spectogram_csv(spec: &StereoSpectogram, bins: u32, sample_rate: u32, path: &String) -> Result<(), SpecCompError> {
+    let bins_us = bins as usize;
+    if bins_us == 0 || spec.left.len() < bins_us {
+        return Result::Err(SpecCompError::EmptyInput(format!("export_spectogram_csv(): Nothing to export to {} ({} values, {} bins)", path, spec.left.len(), bins)));
+    }
+    if spec.left.len() != spec.right.len() {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("export_spectogram_csv(): The channels have different lengths ({} and {})", spec.left.len(), spec.right.len())));
+    }
+
+    let f = match File::create(path) {
+        Ok(f)  => { f }
+        Err(_) => { return Result::Err(SpecCompError::Io(format!("export_spectogram_csv(): Could not create {}.", path))); }
+    };
+    let mut writer = BufWriter::new(f);
+
+    let frequencies: Vec<f32> = bin_frequencies(bins, sample_rate, 2 * bins);
+    let write_csv = |writer: &mut BufWriter<File>| -> std::io::Result<()> {
+        write!(writer, "channel,frame")?;
+        for (i, frequency) in frequencies.iter().enumerate() {
+            match sample_rate {
+                0 => { write!(writer, ",{}", i)?; }
+                _ => { write!(writer, ",{}", frequency)?; }
+            }
+        }
+        writeln!(writer)?;
+
+        for (channel, values) in [("L", &spec.left), ("R", &spec.right)] {
+            for (f, frame) in values.chunks_exact(bins_us).enumerate() {
+                write!(writer, "{},{}", channel, f)?;
+                for bin in frame { write!(writer, ",{}", bin)?; }
+                writeln!(writer)?;
+            }
+        }
+        writer.flush()
+    };
+
+    match write_csv(&mut writer) {
+        Ok(_)  => { Result::Ok(()) }
+        Err(e) => { Result::Err(SpecCompError::Io(format!("export_spectogram_csv(): I/O Error ({}).", e))) }
+    }
+}
+
+// Writes a 1-D array to a NumPy .npy file (format version 1.0), e.g. to carry on with the error curves in Python:
+// the magic string, the version, the length of the header and the header itself, a Python dict giving the type
+// (little-endian f32), the order and the shape. The header is padded with spaces and ends with a newline, so that
+// the data starts at a multiple of 64 bytes. The values follow as little-endian f32.
+pub fn export_npy(path: &String, data: &[f32]) -> Result<(), SpecCompError> {
+    let mut header: String = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({},), }}", data.len());
+    // Magic string (6 bytes), version (2) and header length (2) come before the header
+    let unpadded: usize = 10 + header.len() + 1;
+    header.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+    header.push('\n');
+
+    let mut buffer: Vec<u8> = Vec::with_capacity(10 + header.len() + 4 * data.len());
+    buffer.extend_from_slice(b"\x93NUMPY\x01\x00");
+    buffer.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    buffer.extend_from_slice(header.as_bytes());
+    for v in data {
+        buffer.extend_from_slice(&v.to_le_bytes());
+    }
+
+    match File::create(path).and_then(|mut f| f.write_all(&buffer)) {
+        Ok(_)  => { Result::Ok(()) }
+        Err(e) => { Result::Err(SpecCompError::Io(format!("export_npy(): Could not write {} ({}).", path, e))) }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stem_names() -> Vec<String> {
+        ["Bass", "Drums", "Vocals", "Other"].iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn table_columns_stay_aligned() {
+        let time_errs: Vec<f32> = vec![0.0001, 12345.678, 3.5, 987654.3];
+        let freq_errs: Vec<f32> = vec![1e7, 0.0, 42.0, 0.25];
+        let table: String = format_results_table(&stem_names(), &time_errs, &freq_errs, &[1.0; 4], Option::None).unwrap();
+
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|l| l.len() == lines[0].len()), "{}", table);
+        // Values are right-aligned, so every column ends at the same place in every row
+        let bars = |l: &str| -> Vec<usize> { l.match_indices('|').map(|(i, _)| i).collect() };
+        assert!(lines.iter().all(|l| bars(l) == bars(lines[0])), "{}", table);
+    }
+
+    #[test]
+    fn batch_results_have_every_song_and_the_mean() {
+        let songs: Vec<String> = vec![String::from("First"), String::from("Second")];
+        let time_errs: Vec<Vec<f32>> = vec![vec![1.0; 4], vec![3.0; 4]];
+        let freq_errs: Vec<Vec<f32>> = vec![vec![0.5; 4], vec![0.25; 4]];
+
+        let table: String = format_batch_table(&songs, &time_errs, &freq_errs, &[1.0; 4], Option::None).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].starts_with("First") && lines[2].starts_with("Second"), "{}", table);
+        assert!(lines[3].starts_with("Mean") && lines[3].contains("2.0000") && lines[3].contains("0.3750"), "{}", table);
+
+        let csv: String = format_batch_csv(&stem_names(), &songs, &time_errs, &freq_errs, &[1.0; 4], Option::Some(2)).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "name,time_bass,time_drums,time_vocals,time_other,time_total,freq_bass,freq_drums,freq_vocals,freq_other,freq_total");
+        assert_eq!(lines[1], "First,1.00,1.00,1.00,1.00,1.00,0.50,0.50,0.50,0.50,0.50");
+        assert!(lines[2].starts_with("Second,3.00"));
+        assert_eq!(lines[3], "mean,2.00,2.00,2.00,2.00,2.00,0.38,0.38,0.38,0.38,0.38");
+    }
+
+    #[test]
+    fn weighted_mean_of_the_stems() {
+        let errors: [f32; 4] = [1.0, 2.0, 3.0, 6.0];
+        assert_eq!(weighted_mean(&errors, &[1.0; 4]).unwrap(), 3.0);
+        assert_eq!(weighted_mean(&errors, &[0.25; 4]).unwrap(), 3.0);
+        // Moving weight to the stem with the largest error raises the total
+        assert!((weighted_mean(&errors, &[0.2, 0.2, 0.2, 0.4]).unwrap() - 3.6).abs() < 1e-6);
+        assert!((weighted_mean(&errors, &[1.0, 0.0, 0.0, 0.0]).unwrap() - 1.0).abs() < 1e-6);
+
+        assert!(matches!(weighted_mean(&errors, &[1.0; 3]), Err(SpecCompError::DimensionMismatch(_))));
+        assert!(matches!(weighted_mean(&errors, &[0.0; 4]), Err(SpecCompError::InvalidArgument(_))));
+        assert!(matches!(weighted_mean(&errors, &[1.0, -1.0, 1.0, 1.0]), Err(SpecCompError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn spectogram_csv_has_a_row_per_frame_and_a_column_per_bin() {
+        let path: String = std::env::temp_dir().join(format!("speccomp-results-csv-{}.csv", std::process::id())).to_string_lossy().to_string();
+        let spec: StereoSpectogram = StereoSpectogram { left: (0..20).map(|v| v as f32).collect(), right: vec![0.5; 20], bins: 4 };
+        export_spectogram_csv(&spec, 4, 8000, &path).unwrap();
+        let csv: String = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let rows: Vec<Vec<&str>> = csv.lines().map(|l| l.split(',').collect()).collect();
+        // A header, then 5 frames of each channel
+        assert_eq!(rows.len(), 1 + 2 * 5);
+        assert!(rows.iter().all(|r| r.len() == 2 + 4));
+        assert_eq!(rows[0], vec!["channel", "frame", "0", "1000", "2000", "3000"]);
+        assert_eq!(rows[2], vec!["L", "1", "4", "5", "6", "7"]);
+        assert_eq!(rows.iter().filter(|r| r[0] == "R").count(), 5);
+    }
+
+    #[test]
+    fn frame_errors_csv_has_the_timestamp_of_each_frame() {
+        // Frame 100 of 2048 sample hops at 44.1 kHz starts at 204800 / 44100 seconds
+        assert!((frame_timestamp(100, 2048, 44100) - 4.643991).abs() < 1e-5);
+        assert_eq!(frame_timestamp(100, 2048, 0), 0.0);
+
+        let path: String = std::env::temp_dir().join(format!("speccomp-results-time-{}.csv", std::process::id())).to_string_lossy().to_string();
+        let errors: Vec<Vec<f32>> = vec![vec![0.5; 101], vec![0.25; 100]];
+        export_frame_errors_csv(&stem_names()[..2], &errors, 2048, 44100, Option::Some(2), &path).unwrap();
+        let csv: String = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "frame,time_sec,bass,drums");
+        assert_eq!(lines.len(), 1 + 101);
+        // The shorter stem leaves its cell of the last frame empty
+        assert_eq!(lines[101], "100,4.643991,0.50,");
+    }
+
+    #[test]
+    fn values_have_the_requested_decimals() {
+        let value: f32 = 0.123456;
+        assert_eq!(format_value(value, Option::None, 4), "0.1235");
+        assert_eq!(format_value(value, Option::Some(0), 4), "0");
+        assert_eq!(format_value(value, Option::Some(2), 4), "0.12");
+        assert_eq!(format_value(0.5, Option::Some(MAX_PRECISION), 4), "0.5000000000");
+
+        // The table follows the precision too
+        let table: String = format_results_table(&stem_names(), &[value; 4], &[value; 4], &[1.0; 4], Option::Some(2)).unwrap();
+        assert!(table.contains("0.12") && !table.contains("0.123"), "{}", table);
+    }
+
+    #[test]
+    fn combined_score_between_time_and_frequency() {
+        // Frequency weights averaging 0.5 halve the frequency total, which is scaled back up
+        let (time_total, freq_total, freq_scale): (f32, f32, f32) = (0.3, 0.25, 0.5);
+        assert_eq!(combined_score(time_total, freq_total, 1.0, freq_scale).unwrap(), 0.3);
+        assert_eq!(combined_score(time_total, freq_total, 0.0, freq_scale).unwrap(), 0.5);
+        assert!((combined_score(time_total, freq_total, 0.5, freq_scale).unwrap() - 0.4).abs() < 1e-6);
+
+        assert!(combined_score(time_total, freq_total, 1.5, freq_scale).is_err());
+        assert!(combined_score(time_total, freq_total, 0.5, 0.0).is_err());
+    }
+
+    #[test]
+    fn markdown_table_rows() {
+        let markdown: String = format_results_markdown(&stem_names(), &[0.1, 0.2, 0.3, 0.4], &[1.0, 2.0, 3.0, 4.0], &[1.0; 4], Option::None).unwrap();
+        let rows: Vec<Vec<&str>> = markdown.lines()
+            .map(|l| { assert!(l.starts_with("| ") && l.ends_with(" |"), "{}", l); l[2..l.len() - 2].split(" | ").collect() })
+            .collect();
+
+        // Header, separator and one row per mode, all with a label, 4 stems and the total
+        assert_eq!(rows.len(), 4);
+        assert!(rows.iter().all(|r| r.len() == 6));
+        assert_eq!(rows[0], vec!["Mode", "Bass", "Drums", "Vocals", "Other", "Total"]);
+        assert_eq!(rows[1], vec!["---", "---:", "---:", "---:", "---:", "---:"]);
+        assert_eq!(rows[2], vec!["Time", "0.1000", "0.2000", "0.3000", "0.4000", "0.250"]);
+        assert_eq!(rows[3][0], "Freq");
+    }
+
+    // Minimal .npy reader for 1-D little-endian f32 arrays: checks the magic string, the version and the header dict,
+    // and returns the shape and the values
+    fn read_npy(bytes: &[u8]) -> (usize, Vec<f32>) {
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len: usize = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header: &str = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert!(header.ends_with('\n') && header.contains("'descr': '<f4'") && header.contains("'fortran_order': False"), "{}", header);
+
+        let shape: &str = header.split("'shape': (").nth(1).unwrap().split(')').next().unwrap();
+        let len: usize = shape.trim_end_matches(',').trim().parse().unwrap();
+        let values: Vec<f32> = bytes[10 + header_len..].chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+        (len, values)
+    }
+
+    #[test]
+    fn npy_array_reads_back() {
+        let path: String = std::env::temp_dir().join(format!("speccomp-results-npy-{}.npy", std::process::id())).to_string_lossy().to_string();
+        for data in [vec![], vec![0.25], (0..1000).map(|i| i as f32 * -0.125).collect::<Vec<f32>>()] {
+            export_npy(&path, &data).unwrap();
+            let (len, values) = read_npy(&std::fs::read(&path).unwrap());
+            assert_eq!(len, data.len());
+            assert_eq!(values, data);
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+}
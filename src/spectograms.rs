@@ -1,104 +1,222 @@
 use crate::types::*;
+use crate::error::SpecCompError;
+use crate::{status, statusln};
+use crate::workers::*;
 
 use std::{
+    fs::File, io::Write,
     time::Duration, f32::consts::PI,
-    thread::JoinHandle, sync::{Arc, Mutex}, cmp::min,
-    thread, sync::mpsc::{Sender, Receiver, channel}
+    sync::{Arc, Mutex}, cmp::min,
+    thread, sync::mpsc::{Sender, Receiver, TryRecvError, channel}
 };
 
 // FFT algorithms for STFT
-use rustfft::{FftPlanner, num_complex::Complex};
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+
+// Windows ----------------------------------------------------------------------------------------------------------------
+// Analysis windows applied to every STFT frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowFunction {
+    // Periodic Hann window, 0.5 * (1 - cos(2*pi*n/N)) for n in 0..N; The standard window for spectral analysis, as
+    // if taken from a window of N + 1 samples with the last one dropped, so that consecutive frames add up evenly
+    Hann,
+    // Symmetric Hann window, 0.5 * (1 - cos(2*pi*n/(N-1))); Both ends are 0, as used for filter design
+    HannSymmetric,
+    // The window this program used before the periodic one; Built over -size/2..size/2, which puts the peaks at the
+    // edges of the frame and 0 at its center. Only kept to reproduce earlier results
+    HannLegacy,
+    // Periodic Hamming window
+    Hamming,
+    // Tapered cosine window; `alpha` is the tapered fraction of the window, from a rectangular window (0.0) to a
+    // symmetric Hann window (1.0)
+    Tukey { alpha: f32 }
+}
+
+impl WindowFunction {
+    // Parses "hann" (or "hann:periodic"), "hann:symmetric", "hann:legacy", "hamming", "tukey" (alpha = 0.5) or
+    // "tukey:alpha"
+    pub fn from_name(name: &str) -> Option<WindowFunction> {
+        let name = name.to_lowercase();
+        match name.split_once(':') {
+            Some(("hann", "periodic"))  => { Option::Some(WindowFunction::Hann) }
+            Some(("hann", "symmetric")) => { Option::Some(WindowFunction::HannSymmetric) }
+            Some(("hann", "legacy"))    => { Option::Some(WindowFunction::HannLegacy) }
+            Some(("tukey", alpha)) => {
+                match alpha.parse::<f32>() {
+                    Ok(a) if (0.0..=1.0).contains(&a) => { Option::Some(WindowFunction::Tukey { alpha: a }) }
+                    _ => { Option::None }
+                }
+            }
+            Some(_) => { Option::None }
+            None => {
+                match name.as_str() {
+                    "hann"    => { Option::Some(WindowFunction::Hann) }
+                    "hamming" => { Option::Some(WindowFunction::Hamming) }
+                    "tukey"   => { Option::Some(WindowFunction::Tukey { alpha: 0.5 }) }
+                    _ => { Option::None }
+                }
+            }
+        }
+    }
+
+    // The `size` coefficients of the window; `size` may be odd
+    pub fn coefficients(&self, size: usize) -> Vec<f32> {
+        let size_f32: f32 = size as f32;
+        match self {
+            // A single coefficient would be 0 and silence the frame
+            WindowFunction::Hann => {
+                match size {
+                    1 => { vec![1.0] }
+                    _ => { periodic_hann(size) }
+                }
+            }
+            WindowFunction::HannSymmetric => {
+                match size {
+                    1 => { vec![1.0] }
+                    _ => {
+                        (0..size)
+                            .map(|n| 0.5 - 0.5 * (2f32*PI*n as f32 / (size_f32 - 1.0)).cos())
+                            .collect()
+                    }
+                }
+            }
+            WindowFunction::HannLegacy => {
+                // For an odd size the window has one more coefficient after the center than before it
+                let window_edge: i64 = size as i64 / 2;
+                (-window_edge..size as i64 - window_edge)
+                    .map(|n| 0.5 - 0.5 * (2f32*PI*n as f32 / size_f32).cos())
+                    .collect()
+            }
+            WindowFunction::Hamming => {
+                (0..size)
+                    .map(|n| 0.54 - 0.46 * (2f32*PI*n as f32 / size_f32).cos())
+                    .collect()
+            }
+            WindowFunction::Tukey { alpha } => {
+                let alpha: f32 = alpha.clamp(0.0, 1.0);
+                (0..size)
+                    .map(|n| {
+                        // Position within the window, from 0 to 1
+                        let x: f32 = match size > 1 { true => { n as f32 / (size_f32 - 1.0) } false => { 0.5 } };
+                        if alpha == 0.0 { 1.0 }
+                        else if x < alpha / 2.0 { 0.5 * (1.0 - (2f32*PI*x / alpha).cos()) }
+                        else if x > 1.0 - alpha / 2.0 { 0.5 * (1.0 - (2f32*PI*(1.0 - x) / alpha).cos()) }
+                        else { 1.0 }
+                    })
+                    .collect()
+            }
+        }
+    }
+}
 
 
 // Multithreaded variants ---------------------------------------------------------------------------------------------------
-// Calculates the spectogram of each track in `input_tracks` in parallel.
-// The returned spectograms are stored in the reverse order from which their inputs were given.
+// Calculates the spectogram of each track in `input_tracks` in parallel, using at most `threads` threads.
+// If `split_channels` is set, the two channels of each track are also transformed in parallel (see
+// `track_to_spec_split()`), on one extra thread per track on top of the `threads` worker threads.
+// Every track has its own slot, so the returned spectograms are in the order their inputs were given, whichever
+// thread finishes first.
 // `input_tracks` is consumed (no need to go the extra mile so that it doesn't.)
-pub fn mt_track_to_spec(fft_size_u32: u32, input_tracks: Vec<TrackBuffer>) -> Vec<StereoSpectogram> {
+pub fn mt_track_to_spec(fft_size_u32: u32, window: WindowFunction, input_tracks: Vec<TrackBuffer>, threads: usize, split_channels: bool) -> Vec<StereoSpectogram> {
     let fft_size: usize = fft_size_u32 as usize;
     let input_count: usize = input_tracks.len();
 
     // Use 4 MPSC pairs, one for each input track
-    let mut receivers: Vec<Receiver<i32>> = vec![];
-    receivers.reserve(input_count);
+    let mut receivers: Vec<Receiver<i32>> = Vec::with_capacity(input_count);
 
     // Create the shared vectors each thread will use
-    let mut shared_buffers: Vec<Arc<Mutex<StereoSpectogram>>> = vec![];
-    shared_buffers.reserve(input_count);
+    let mut shared_buffers: Vec<Arc<Mutex<StereoSpectogram>>> = Vec::with_capacity(input_count);
+
+    // Queue one job per track
+    let mut jobs: Vec<Job> = Vec::with_capacity(input_count);
 
-    // Spawn threads
-    let mut handles: Vec<JoinHandle<_>> = vec![];
-    handles.reserve(input_count);
+    // Longer tracks weigh more in the overall progress
+    let track_lengths: Vec<usize> = input_tracks.iter().map(|t| t.len()).collect();
 
-    // Start threads
     for input in input_tracks {
-        let new_buffer: Arc<Mutex<StereoSpectogram>> = Arc::new(Mutex::<StereoSpectogram>::new(StereoSpectogram::new()));
+        let new_buffer: Arc<Mutex<StereoSpectogram>> = Arc::new(Mutex::<StereoSpectogram>::new(StereoSpectogram::new(fft_size as u32 / 2)));
         shared_buffers.push(new_buffer.clone());
 
         let (tx, rx) = channel();
         receivers.push(rx);
 
-        handles.push(
-            thread::spawn(move || mt_track_to_spec_thread(fft_size, &input, tx, new_buffer.clone()))
-        );
+        match split_channels {
+            true  => {
+                jobs.push(Box::new(move || {
+                    let spec: StereoSpectogram = split_channels_to_spec(fft_size, window, &input, Option::Some(&tx));
+                    *new_buffer.lock().unwrap() = spec;
+                    let _ = tx.send(100);
+                }));
+            }
+            false => { jobs.push(Box::new(move || mt_track_to_spec_thread(fft_size, window, &input, tx, new_buffer.clone()))); }
+        }
     }
 
-    // Wait for threads and poll their progress
-    let mut thread_progress: Vec<i32> = vec![];
-    thread_progress.resize(input_count, 0);
+    // Start the worker threads
+    let handles = spawn_workers(jobs, threads);
 
-    let mut threads_finished: usize = 0;
-    let mut overall_progress: usize;
-    while threads_finished < input_count {
-        threads_finished = 0;
-        overall_progress = 0;
+    // Wait for threads and poll their progress
+    let mut thread_progress: Vec<i32> = vec![0; input_count];
 
+    // A job is over once its channel is closed
+    let mut jobs_finished: Vec<bool> = vec![false; input_count];
+    let mut shown_progress: usize = 0;
+    while jobs_finished.contains(&false) {
         for i in 0..input_count {
-            // Check if the thread finished
-            if handles[i].is_finished() { 
-                threads_finished += 1;
-                continue;
-            }
-
-            // Read thread's channel
-            match receivers[i].recv() {
-                Ok(r) => {
-                    thread_progress[i] = r;
-                    overall_progress += r as usize
-                }
-                Err(_) => {
-                    println!("\nWarning: Thread {} is not responding.", i);
-                    thread_progress[i] = -1;
+            if jobs_finished[i] { continue; }
+
+            // Read everything the job sent since the last poll
+            loop {
+                match receivers[i].try_recv() {
+                    Ok(r) => { thread_progress[i] = r; }
+                    Err(TryRecvError::Empty) => { break; }
+                    Err(TryRecvError::Disconnected) => { jobs_finished[i] = true; thread_progress[i] = 100; break; }
                 }
             }
         }
-            // Print state
-        print!("\r Calculating spectograms ({}%)... ", overall_progress/input_count);
+
+        // Print state; The percentage never goes backwards
+        shown_progress = shown_progress.max(overall_progress(&thread_progress, &track_lengths));
+        status!("\r Calculating spectograms ({:>3}%)... ", shown_progress);
 
         // Sleep
         thread::sleep(Duration::from_millis(1));
     }
-    print!("\rAll spectograms are ready.                                         \n");
+    for h in handles { let _ = h.join(); }
+    status!("\rAll spectograms are ready.                                         \n");
 
     // Return the shared buffers
-    let mut spectograms: Vec<StereoSpectogram> = vec![];
-    spectograms.reserve(input_count);
-    for _ in 0..input_count {
-        // Take the arc out of the vector first
-        let popped_arc = shared_buffers.pop().unwrap();
+    let mut spectograms: Vec<StereoSpectogram> = Vec::with_capacity(input_count);
+    for shared in shared_buffers {
         // Extract mutex from arc
-        let spec = Arc::try_unwrap(popped_arc).unwrap();
+        let spec = Arc::try_unwrap(shared).unwrap();
         // Extract spectogram from mutex
         let spec = spec.into_inner().unwrap();
         spectograms.push(spec);
     }
 
-    return spectograms;
+    spectograms
+}
+
+// Overall percentage of a group of jobs; `progress` holds the percentage of each job and `weights` its size (e.g. the
+// number of samples), so that a long track counts more than a short one. Jobs without a size are counted as size 1.
+pub fn overall_progress(progress: &[i32], weights: &[usize]) -> usize {
+    let weights: Vec<u64> = weights.iter().map(|w| (*w).max(1) as u64).collect();
+    let total: u64 = weights.iter().sum();
+    if total == 0 {
+        return 100;
+    }
+
+    let done: u64 = progress.iter().zip(weights.iter())
+        .map(|(p, w)| (*p).clamp(0, 100) as u64 * w)
+        .sum();
+    (done / total) as usize
 }
 
 // Function each thread executes; Samples in `input_track` are converted to a stereo spectogram
-// STFT is done with a Hann Window.
-fn mt_track_to_spec_thread(fft_size: usize, input_track: &TrackBuffer, tx: Sender<i32>, output_buffer: Arc<Mutex<StereoSpectogram>>) {
+// STFT is done with the given window.
+fn mt_track_to_spec_thread(fft_size: usize, window: WindowFunction, input_track: &TrackBuffer, tx: Sender<i32>, output_buffer: Arc<Mutex<StereoSpectogram>>) {
     // Number of samples and number of samples per channel
     let buffer_size: usize = input_track.len();
     let buffer_duration: usize = buffer_size / 2;
@@ -106,22 +224,15 @@ fn mt_track_to_spec_thread(fft_size: usize, input_track: &TrackBuffer, tx: Sende
     // Lock the return buffer
     let mut return_buffer = output_buffer.lock().unwrap();
 
-    // Create a Hann window
-    let a0 :f32 = 0.5;
-    let a1: f32 = 1f32 - a0;
-
-    let window_size: f32 = fft_size as f32;
-    let window_edge: i64 = fft_size as i64 / 2;
-
-    let mut hann_window: Vec<f32> = vec![];
-    for n in -1*window_edge..window_edge {
-        let n_f32: f32 = n as f32;
-
-        let temp: f32 = 2f32*PI*n_f32 / window_size;
-        let w_n: f32 = a0 - a1 * temp.cos();
-        hann_window.push(w_n);
+    // Nothing to transform (the spectogram stays empty); A track shorter than `fft_size` still gives one zero-padded frame
+    if buffer_duration == 0 || fft_size == 0 {
+        let _ = tx.send(100);
+        return;
     }
 
+    // Create the window
+    let coefficients: Vec<f32> = window.coefficients(fft_size);
+
     // Create rustfft::fft object
     let mut fft_planner: FftPlanner<f32> = FftPlanner::new();
     let fft = fft_planner.plan_fft_forward(fft_size);
@@ -130,11 +241,8 @@ fn mt_track_to_spec_thread(fft_size: usize, input_track: &TrackBuffer, tx: Sende
     let mut samples_processed: usize = 0;
     let source = input_track.as_slice();
 
-    let mut window_buffer_l: Vec<Complex<f32>> = vec![];
-    window_buffer_l.reserve(fft_size);
-
-    let mut window_buffer_r: Vec<Complex<f32>> = vec![];
-    window_buffer_r.reserve(fft_size);
+    let mut window_buffer_l: Vec<Complex<f32>> = Vec::with_capacity(fft_size);
+    let mut window_buffer_r: Vec<Complex<f32>> = Vec::with_capacity(fft_size);
 
     // Buffers to store the result spectograms
     return_buffer.left.reserve(fft_size * buffer_duration/fft_size); // yes, this is redundant but conveys that this buffer isn't about samples
@@ -143,8 +251,8 @@ fn mt_track_to_spec_thread(fft_size: usize, input_track: &TrackBuffer, tx: Sende
     let mut last_percentage: i32 = 0;
     let mut new_percentage: i32;
 
-    // Create STFT windows
-    loop {
+    // Create STFT windows until every sample has been used
+    while samples_processed < buffer_duration {
         // Calculate local progress to let the parent thread know the overall progress of the program
         new_percentage = (samples_processed * 100 / buffer_duration) as i32;
         if new_percentage > last_percentage {
@@ -152,28 +260,16 @@ fn mt_track_to_spec_thread(fft_size: usize, input_track: &TrackBuffer, tx: Sende
             last_percentage = new_percentage;
         }
 
-        // Check if this window will exceed the input buffer's size
-        match samples_processed + fft_size > buffer_duration {
-            false => { // No need to pad
-                for i in 0..fft_size {
-                    let idx = 2*(i + samples_processed);
-                    window_buffer_l.push(Complex::new(source[idx] * hann_window[i],   0.0f32));
-                    window_buffer_r.push(Complex::new(source[idx+1] * hann_window[i], 0.0f32));
-                }
-            }
-            true => { // Will have to pad
-                // Get all remaining samples
-                for i in 0..(buffer_duration % fft_size) {
-                    let idx = 2*(i + samples_processed);
-                    window_buffer_l.push(Complex::new(source[idx] * hann_window[i],   0.0f32));
-                    window_buffer_r.push(Complex::new(source[idx+1] * hann_window[i], 0.0f32));
-                }
-                // Pad with 0
-                for _i in (buffer_duration%fft_size)..fft_size {
-                    window_buffer_l.push(Complex::new(0f32, 0f32));
-                    window_buffer_r.push(Complex::new(0f32, 0f32));
-                }
-            }
+        // Get the samples of this window; The last window is padded with 0 if it exceeds the input buffer's size
+        let remaining: usize = min(fft_size, buffer_duration - samples_processed);
+        for (i, w) in coefficients.iter().enumerate().take(remaining) {
+            let idx = 2*(i + samples_processed);
+            window_buffer_l.push(Complex::new(source[idx] * w,   0.0f32));
+            window_buffer_r.push(Complex::new(source[idx+1] * w, 0.0f32));
+        }
+        for _i in remaining..fft_size {
+            window_buffer_l.push(Complex::new(0f32, 0f32));
+            window_buffer_r.push(Complex::new(0f32, 0f32));
         }
         
         // Perform the FFT operation
@@ -191,7 +287,6 @@ fn mt_track_to_spec_thread(fft_size: usize, input_track: &TrackBuffer, tx: Sende
         window_buffer_r.clear();
 
         samples_processed += fft_size;
-        if samples_processed > buffer_duration { break; }
     }
 
     // Let parent thread know this thread is done
@@ -201,77 +296,142 @@ fn mt_track_to_spec_thread(fft_size: usize, input_track: &TrackBuffer, tx: Sende
 }
 
 
+// Per channel variant ----------------------------------------------------------------------------------------------------
+// Same as `track_to_spec()` but the left and the right channel are transformed on two threads; The planned FFT is
+// shared. The result is bit-identical to `track_to_spec()`. Only pays off when there are more free cores than tracks
+// being transformed; Otherwise both threads compete for the same cores and the extra thread is pure overhead.
+pub fn track_to_spec_split(fft_size_u32: u32, window: WindowFunction, sample_buffer: &TrackBuffer) -> StereoSpectogram {
+    split_channels_to_spec(fft_size_u32 as usize, window, sample_buffer, Option::None)
+}
+
+// Runs `channel_to_spec()` for both channels at the same time; Progress (if any) is reported by the left channel
+fn split_channels_to_spec(fft_size: usize, window: WindowFunction, sample_buffer: &TrackBuffer, progress: Option<&Sender<i32>>) -> StereoSpectogram {
+    if sample_buffer.len() < 2 || fft_size == 0 {
+        return StereoSpectogram::new(fft_size as u32 / 2);
+    }
+
+    let coefficients: Vec<f32> = window.coefficients(fft_size);
+    let mut fft_planner: FftPlanner<f32> = FftPlanner::new();
+    let fft: Arc<dyn Fft<f32>> = fft_planner.plan_fft_forward(fft_size);
+
+    let (left, right) = thread::scope(|scope| {
+        let right = scope.spawn(|| channel_to_spec(&fft, &coefficients, sample_buffer, 1, Option::None));
+        let left: Vec<f32> = channel_to_spec(&fft, &coefficients, sample_buffer, 0, progress);
+        (left, right.join().unwrap())
+    });
+
+    StereoSpectogram { left, right, bins: fft_size as u32 / 2 }
+}
+
+// Spectogram of one channel (0 for left, 1 for right) of the interleaved `source`, frame by frame like `track_to_spec()`
+fn channel_to_spec(fft: &Arc<dyn Fft<f32>>, coefficients: &[f32], source: &[f32], channel: usize, progress: Option<&Sender<i32>>) -> Vec<f32> {
+    let fft_size: usize = coefficients.len();
+    let buffer_duration: usize = source.len() / 2;
+
+    let mut window_buffer: Vec<Complex<f32>> = Vec::with_capacity(fft_size);
+    let mut spectogram: Vec<f32> = Vec::with_capacity(fft_size * buffer_duration/fft_size);
+
+    let mut samples_processed: usize = 0;
+    let mut last_percentage: i32 = 0;
+    while samples_processed < buffer_duration {
+        if let Some(tx) = progress {
+            let new_percentage: i32 = (samples_processed * 100 / buffer_duration) as i32;
+            if new_percentage > last_percentage {
+                let _ = tx.send(new_percentage);
+                last_percentage = new_percentage;
+            }
+        }
+
+        // The last window is padded with 0 if it exceeds the input buffer's size
+        let remaining: usize = min(fft_size, buffer_duration - samples_processed);
+        for (i, w) in coefficients.iter().enumerate().take(remaining) {
+            window_buffer.push(Complex::new(source[2*(i + samples_processed) + channel] * w, 0.0f32));
+        }
+        window_buffer.resize(fft_size, Complex::new(0f32, 0f32));
+
+        fft.process(&mut window_buffer);
+        for bin in window_buffer.iter().take(fft_size/2) {
+            spectogram.push(bin.re.powi(2));
+        }
+
+        window_buffer.clear();
+        samples_processed += fft_size;
+    }
+
+    spectogram
+}
+
 // Single core variant -----------------------------------------------------------------------------------------------------
 // Convert a track to a spectogram
-pub fn track_to_spec(fft_size_u32: u32, sample_buffer: &TrackBuffer) -> StereoSpectogram {
-    let fft_size: usize = fft_size_u32 as usize;
+pub fn track_to_spec(fft_size_u32: u32, window: WindowFunction, sample_buffer: &TrackBuffer) -> StereoSpectogram {
+    track_to_spec_padded(fft_size_u32, 1, window, sample_buffer)
+}
 
-    // Number of samples and number of samples per channel
-    let buffer_size: usize = sample_buffer.len();
-    let buffer_duration: usize = buffer_size / 2;
+// Same as `track_to_spec()` but every windowed frame of `window_size` samples is zero-padded to
+// `pad_factor * window_size` samples before the FFT. Frames still start every `window_size` samples, but have
+// `pad_factor` times as many bins, so the spectrum is interpolated (which looks nicer in plots but adds no
+// resolution). Each bin is `sample_rate / (pad_factor * window_size)` Hz wide. A `pad_factor` of 1 is `track_to_spec()`.
+pub fn track_to_spec_padded(window_size_u32: u32, pad_factor: u32, window: WindowFunction, sample_buffer: &TrackBuffer) -> StereoSpectogram {
+    let coefficients: Vec<f32> = window.coefficients(window_size_u32 as usize);
+    spec_with_coefficients(&coefficients, pad_factor, sample_buffer)
+}
+
+// Same as `track_to_spec()` but with the coefficients of the window given as they are, e.g. to reproduce the analysis
+// window of another tool exactly; `coefficients` has to hold one value per sample of a frame (`fft_size` of them).
+pub fn track_to_spec_with_window(fft_size_u32: u32, coefficients: &[f32], sample_buffer: &TrackBuffer) -> Result<StereoSpectogram, SpecCompError> {
+    if coefficients.len() != fft_size_u32 as usize {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("track_to_spec_with_window(): Got {} window coefficients for an FFT size of {}",
+            coefficients.len(), fft_size_u32)));
+    }
+    if coefficients.iter().any(|c| !c.is_finite()) {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("track_to_spec_with_window(): The window coefficients must be finite")));
+    }
 
-    // Create a Hann window
-    let a0 :f32 = 0.5;
-    let a1: f32 = 1f32 - a0;
+    Result::Ok(spec_with_coefficients(coefficients, 1, sample_buffer))
+}
 
-    let window_size: f32 = fft_size as f32;
-    let window_edge: i64 = fft_size as i64 / 2;
+// STFT of `track_to_spec_padded()` with the window's coefficients given; Frames are `coefficients.len()` samples long
+fn spec_with_coefficients(coefficients: &[f32], pad_factor: u32, sample_buffer: &TrackBuffer) -> StereoSpectogram {
+    let window_size: usize = coefficients.len();
+    let fft_size: usize = window_size * pad_factor as usize;
 
-    let mut hann_window: Vec<f32> = vec![];
-    for n in -1*window_edge..window_edge {
-        let n_f32: f32 = n as f32;
+    // Number of samples and number of samples per channel
+    let buffer_size: usize = sample_buffer.len();
+    let buffer_duration: usize = buffer_size / 2;
 
-        let temp: f32 = 2f32*PI*n_f32 / window_size;
-        let w_n: f32 = a0 - a1 * temp.cos();
-        hann_window.push(w_n);
+    // Nothing to transform; A track shorter than `window_size` still gives one (zero-padded) frame below
+    if buffer_duration == 0 || fft_size == 0 {
+        return StereoSpectogram::new(fft_size as u32 / 2);
     }
 
     // Create rustfft::fft object
     let mut fft_planner: FftPlanner<f32> = FftPlanner::new();
     let fft = fft_planner.plan_fft_forward(fft_size);
 
-    // We'll create `fft_size` length windows until `sample_buffer` has been iterated to its entirety
-    let mut window_buffer_l: Vec<Complex<f32>> = vec![];
-    window_buffer_l.reserve(fft_size);
-
-    let mut window_buffer_r: Vec<Complex<f32>> = vec![];
-    window_buffer_r.reserve(fft_size);
+    // We'll create `window_size` length windows until `sample_buffer` has been iterated to its entirety
+    let mut window_buffer_l: Vec<Complex<f32>> = Vec::with_capacity(fft_size);
+    let mut window_buffer_r: Vec<Complex<f32>> = Vec::with_capacity(fft_size);
 
     // Buffers to store the result spectograms
-    let mut spectogram_buffer_l: Vec<f32> = vec![];
-    spectogram_buffer_l.reserve(fft_size * buffer_duration/fft_size); // yes, this is redundant but conveys that this buffer isn't about samples
-
-    let mut spectogram_buffer_r: Vec<f32> = vec![];
-    spectogram_buffer_r.reserve(fft_size * buffer_duration/fft_size);
+    let mut spectogram_buffer_l: Vec<f32> = Vec::with_capacity(fft_size/2 * buffer_duration.div_ceil(window_size));
+    let mut spectogram_buffer_r: Vec<f32> = Vec::with_capacity(fft_size/2 * buffer_duration.div_ceil(window_size));
 
     let mut samples_processed: usize = 0;
     let source = sample_buffer.as_slice();
 
-    // Create spectogram by computing STFT frames
-    loop {
-        // Check if this window will exceed the input buffer's size
-        match samples_processed + fft_size > buffer_duration {
-            false => { // No need to pad
-                for i in 0..fft_size {
-                    let idx = 2*(i + samples_processed);
-                    window_buffer_l.push(Complex::new(source[idx] * hann_window[i],   0.0f32));
-                    window_buffer_r.push(Complex::new(source[idx+1] * hann_window[i], 0.0f32));
-                }
-            }
-
-            true => { // Will have to pad
-                // Get all remaining samples
-                for i in 0..(buffer_duration % fft_size) {
-                    let idx = 2*(i + samples_processed);
-                    window_buffer_l.push(Complex::new(source[idx] * hann_window[i],   0.0f32));
-                    window_buffer_r.push(Complex::new(source[idx+1] * hann_window[i], 0.0f32));
-                }
-                // Pad with 0
-                for _i in (buffer_duration%fft_size)..fft_size {
-                    window_buffer_l.push(Complex::new(0f32, 0f32));
-                    window_buffer_r.push(Complex::new(0f32, 0f32));
-                }
-            }
+    // Create spectogram by computing STFT frames until every sample has been used
+    while samples_processed < buffer_duration {
+        // Get the samples of this window; The last window is padded with 0 if it exceeds the input buffer's size, and
+        // every window is padded up to `fft_size`
+        let remaining: usize = min(window_size, buffer_duration - samples_processed);
+        for (i, w) in coefficients.iter().enumerate().take(remaining) {
+            let idx = 2*(i + samples_processed);
+            window_buffer_l.push(Complex::new(source[idx] * w,   0.0f32));
+            window_buffer_r.push(Complex::new(source[idx+1] * w, 0.0f32));
+        }
+        for _i in remaining..fft_size {
+            window_buffer_l.push(Complex::new(0f32, 0f32));
+            window_buffer_r.push(Complex::new(0f32, 0f32));
         }
         
         // Perform the FFT operation
@@ -288,12 +448,75 @@ pub fn track_to_spec(fft_size_u32: u32, sample_buffer: &TrackBuffer) -> StereoSp
         window_buffer_l.clear();
         window_buffer_r.clear();
 
-        samples_processed += fft_size;
-        if samples_processed > buffer_duration { break; }
+        samples_processed += window_size;
     }
 
     // Return sepctograms
-    StereoSpectogram {left: spectogram_buffer_l, right: spectogram_buffer_r}
+    StereoSpectogram {left: spectogram_buffer_l, right: spectogram_buffer_r, bins: fft_size as u32 / 2}
+}
+
+
+// Same as `track_to_spec()` but keeps the complex value of each bin instead of the power, so that phase isn't lost.
+// Frames line up with the ones of `track_to_spec()`.
+pub fn track_to_complex_spec(fft_size_u32: u32, window: WindowFunction, sample_buffer: &TrackBuffer) -> ComplexSpectogram {
+    let fft_size: usize = fft_size_u32 as usize;
+
+    // Number of samples and number of samples per channel
+    let buffer_size: usize = sample_buffer.len();
+    let buffer_duration: usize = buffer_size / 2;
+
+    // Nothing to transform; A track shorter than `fft_size` still gives one (zero-padded) frame below
+    if buffer_duration == 0 || fft_size == 0 {
+        return ComplexSpectogram::new(fft_size_u32 / 2);
+    }
+
+    // Create the window
+    let coefficients: Vec<f32> = window.coefficients(fft_size);
+
+    // Create rustfft::fft object
+    let mut fft_planner: FftPlanner<f32> = FftPlanner::new();
+    let fft = fft_planner.plan_fft_forward(fft_size);
+
+    let mut window_buffer_l: Vec<Complex<f32>> = Vec::with_capacity(fft_size);
+    let mut window_buffer_r: Vec<Complex<f32>> = Vec::with_capacity(fft_size);
+
+    let mut spectogram = ComplexSpectogram {
+        left:  Vec::with_capacity(buffer_duration),
+        right: Vec::with_capacity(buffer_duration),
+        bins:  fft_size_u32 / 2
+    };
+
+    let mut samples_processed: usize = 0;
+    let source = sample_buffer.as_slice();
+
+    // Create spectogram by computing STFT frames until every sample has been used
+    while samples_processed < buffer_duration {
+        // Copy the samples of this window; If it exceeds the input buffer's size, pad with 0
+        let remaining: usize = min(fft_size, buffer_duration - samples_processed);
+        for (i, w) in coefficients.iter().enumerate().take(remaining) {
+            let idx = 2*(i + samples_processed);
+            window_buffer_l.push(Complex::new(source[idx] * w,   0.0f32));
+            window_buffer_r.push(Complex::new(source[idx+1] * w, 0.0f32));
+        }
+        window_buffer_l.resize(fft_size, Complex::new(0f32, 0f32));
+        window_buffer_r.resize(fft_size, Complex::new(0f32, 0f32));
+
+        // Perform the FFT operation
+        fft.process(&mut window_buffer_l);
+        fft.process(&mut window_buffer_r);
+
+        // Keep the first half of the bins
+        spectogram.left.extend_from_slice(&window_buffer_l[..fft_size/2]);
+        spectogram.right.extend_from_slice(&window_buffer_r[..fft_size/2]);
+
+        // Reset input/processing buffer; no need to re-allocate
+        window_buffer_l.clear();
+        window_buffer_r.clear();
+
+        samples_processed += fft_size;
+    }
+
+    spectogram
 }
 
 
@@ -302,8 +525,17 @@ pub fn track_to_spec(fft_size_u32: u32, sample_buffer: &TrackBuffer) -> StereoSp
 
 // Compares two stereo spectograms; Returns a tuple: a vector with the mean error of each frame and the total mean error
 // The error of each channel is calculated independantly and the mean of the two is kept
-pub fn time_compare_spectogram(bins: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram) -> Result<(Vec<f32>, f32), String> {
+pub fn time_compare_spectogram(bins: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram) -> Result<(Vec<f32>, f32), SpecCompError> {
+    time_compare_reduced(bins, spec_a, spec_b, ChannelReduce::Averaged, ErrorScale::Absolute)
+}
+
+fn time_compare_reduced(bins: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram, reduce: ChannelReduce, scale: ErrorScale) -> Result<(Vec<f32>, f32), SpecCompError> {
     let bins_us = bins as usize;
+    if bins_us == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("time_compare_spectogram(): The number of bins must be greater than 0")));
+    }
+    spec_a.check_bins(bins, "time_compare_spectogram()", "spec_a")?;
+    spec_b.check_bins(bins, "time_compare_spectogram()", "spec_b")?;
 
     // Name these burrows for more readable code
     let (spec_a_l, spec_a_r) = (&spec_a.left, &spec_a.right);
@@ -316,62 +548,346 @@ pub fn time_compare_spectogram(bins: u32, spec_a: &StereoSpectogram, spec_b: &St
 
     // Check the numbers add up
     if spec_a_l.len() % bins_us != 0 { 
-        return Result::Err(format!("time_compare_spectogram(): The number of bins in input a ({}) doesn't match the size of the input vector ({} / {} = {})",
-            bins, spec_a_l.len(), bins, spec_a_l.len() as f32 / bins as f32));
+        return Result::Err(SpecCompError::DimensionMismatch(format!("time_compare_spectogram(): The number of bins in input a ({}) doesn't match the size of the input vector ({} / {} = {})",
+            bins, spec_a_l.len(), bins, spec_a_l.len() as f32 / bins as f32)));
     }
 
     if spec_b_l.len() % bins_us != 0 { 
-        return Result::Err(format!("time_compare_spectogram(): The number of bins in input b ({}) doesn't match the size of the input vector ({} / {} = {})",
-            bins, spec_b_l.len(), bins, spec_b_l.len() as f32 / bins as f32));
+        return Result::Err(SpecCompError::DimensionMismatch(format!("time_compare_spectogram(): The number of bins in input b ({}) doesn't match the size of the input vector ({} / {} = {})",
+            bins, spec_b_l.len(), bins, spec_b_l.len() as f32 / bins as f32)));
+    }
+
+    // Nothing to compare
+    if usable_frames == 0 {
+        return Result::Err(SpecCompError::EmptyInput(format!("time_compare_spectogram(): At least one of the inputs is empty (spec_a: {} frames, spec_b: {} frames)",
+            spec_a_frame_count, spec_b_frame_count)));
     }
 
     // Warn user if a frame count mismatch occurred; Sometimes a difference of one frame appears due to
     // rounding errors in X-UMX but these can be ignored
     if spec_a_frame_count != spec_b_frame_count { 
-        println!("\nWarning: Different input sizes (spec_a: {} frames, spec_b: {} frames), using {} frames.", 
+        statusln!("\nWarning: Different input sizes (spec_a: {} frames, spec_b: {} frames), using {} frames.", 
         spec_a_frame_count, spec_b_frame_count, usable_frames); 
     }
 
     // Start calculation
-    let mut mean_err_vec: Vec<f32> = vec![];
-    mean_err_vec.reserve(usable_frames as usize);
-
-    let mut mean_error: f32 = 0.0;
-    let mut a_it_l = spec_a_l.iter();
-    let mut b_it_l = spec_b_l.iter();
-    let mut a_it_r = spec_a_r.iter();
-    let mut b_it_r = spec_b_r.iter();
-    let mut a_st: f32;
-    let mut b_st: f32;
+    let mut mean_err_vec: Vec<f32> = Vec::with_capacity(usable_frames as usize);
+
+    let mut bin_errors: Vec<f32> = vec![0.0; bins_us];
     for f in 0..usable_frames {
-        if f % 16 == 0 { print!("\rComparing... {}%", f*100/usable_frames); }
+        if f % 16 == 0 { status!("\rComparing... {}%", f*100/usable_frames); }
 
-        let mut frame_error: f32 = 0.0;
-        for _ in 0..bins {
-            a_st = (a_it_l.next().unwrap() + a_it_r.next().unwrap()) / 2.0;
-            b_st = (b_it_l.next().unwrap() + b_it_r.next().unwrap()) / 2.0;
-            frame_error += (a_st - b_st).abs();
-        }
-        frame_error /= bins as f32;
+        let frame = f*bins_us..(f + 1)*bins_us;
+        reduce.frame_errors(scale, [&spec_a_l[frame.clone()], &spec_a_r[frame.clone()]], [&spec_b_l[frame.clone()], &spec_b_r[frame]], &mut bin_errors);
+        let frame_error: f32 = lane_sum(&bin_errors) / bins as f32;
 
         // Store error
         mean_err_vec.push(frame_error);
-
-        // Find mean error for a quick check
-        mean_error += frame_error;
     }
-    mean_error /= usable_frames as f32;
+
+    // Find mean error for a quick check; A corrupt frame must not wreck the total
+    let (mean_error, excluded) = finite_mean(&mean_err_vec);
+    warn_on_excluded(excluded, usable_frames, "frames");
 
     // Clear the leftover "Comparing... " message
-    print!("\r                                                          ");
+    status!("\r                                                          ");
 
     Result::Ok((mean_err_vec, mean_error))
 }
 
+// Mean of the finite values of `errors` and the number of values that were left out; A corrupt frame or bin (NaN or
+// infinity) doesn't count at all, rather than as a perfect 0.0. 0.0 if no value is finite.
+fn finite_mean(errors: &[f32]) -> (f32, usize) {
+    let (sum, count) = errors.iter().filter(|e| e.is_finite()).fold((0.0f32, 0usize), |(sum, count), e| (sum + e, count + 1));
+    match count {
+        0 => { (0.0, errors.len()) }
+        _ => { (sum / count as f32, errors.len() - count) }
+    }
+}
+
+// Tells the user that `excluded` of `total` errors (`what`, e.g. "frames") weren't finite and were left out of a mean
+fn warn_on_excluded(excluded: usize, total: usize, what: &str) {
+    if excluded > 0 {
+        statusln!("\nWarning: {} of {} {} have no finite error (e.g. NaN) and were left out of the mean.", excluded, total, what);
+    }
+}
+
+// Mean error of the bins of frame `r` of `reference` and frame `t` of `test`, as `time_compare_spectogram()` computes it
+fn frame_pair_error(bins_us: usize, reference: &StereoSpectogram, r: usize, test: &StereoSpectogram, t: usize) -> f32 {
+    let (r_range, t_range) = (r*bins_us..(r+1)*bins_us, t*bins_us..(t+1)*bins_us);
+    let error: f32 = reference.left[r_range.clone()].iter().zip(reference.right[r_range].iter())
+        .zip(test.left[t_range.clone()].iter().zip(test.right[t_range].iter()))
+        .map(|((r_l, r_r), (t_l, t_r))| ChannelReduce::Averaged.error(ErrorScale::Absolute, *r_l, *r_r, *t_l, *t_r))
+        .sum();
+    error / bins_us as f32
+}
+
+// Variant of `time_compare_spectogram()` that tolerates small time shifts: every frame of `test` is compared with the
+// frames of `reference` up to `max_offset` frames before or after it, and the smallest frame error is kept.
+// Returns the error of every frame of `test`, the offset (in frames, reference minus test) each error was found at,
+// and the mean error. With a `max_offset` of 0 the errors are the same as `time_compare_spectogram()`'s.
+pub fn time_compare_tolerant(bins: u32, reference: &StereoSpectogram, test: &StereoSpectogram, max_offset: usize) -> Result<(Vec<f32>, Vec<i32>, f32), SpecCompError> {
+    let bins_us = bins as usize;
+    if bins_us == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("time_compare_tolerant(): The number of bins must be greater than 0")));
+    }
+    reference.check_bins(bins, "time_compare_tolerant()", "reference")?;
+    test.check_bins(bins, "time_compare_tolerant()", "test")?;
+    if !reference.left.len().is_multiple_of(bins_us) || !test.left.len().is_multiple_of(bins_us) {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("time_compare_tolerant(): The inputs ({} and {} values) aren't made of whole {} bin frames",
+            reference.left.len(), test.left.len(), bins)));
+    }
+    let reference_frames: usize = reference.left.len() / bins_us;
+    let test_frames: usize = test.left.len() / bins_us;
+    if reference_frames == 0 || test_frames == 0 {
+        return Result::Err(SpecCompError::EmptyInput(format!("time_compare_tolerant(): At least one of the inputs is empty (reference: {} frames, test: {} frames)",
+            reference_frames, test_frames)));
+    }
+
+    let mut errors: Vec<f32> = Vec::with_capacity(test_frames);
+    let mut offsets: Vec<i32> = Vec::with_capacity(test_frames);
+    for t in 0..test_frames {
+        // Neighbours of `t` that the reference has; At the end of a longer test, only its last frames are left
+        let first: usize = min(t.saturating_sub(max_offset), reference_frames - 1);
+        let last: usize = min(t + max_offset, reference_frames - 1);
+
+        // The offset closest to 0 wins ties
+        let mut best: (f32, i32) = (f32::INFINITY, 0);
+        for r in first..=last {
+            let error: f32 = frame_pair_error(bins_us, reference, r, test, t);
+            let offset: i32 = r as i32 - t as i32;
+            if error < best.0 || (error == best.0 && offset.abs() < best.1.abs()) { best = (error, offset); }
+        }
+        errors.push(best.0);
+        offsets.push(best.1);
+    }
+
+    let finite: Vec<f32> = errors.iter().copied().filter(|e| e.is_finite()).collect();
+    let mean_error: f32 = finite.iter().sum::<f32>() / finite.len().max(1) as f32;
+    Result::Ok((errors, offsets, mean_error))
+}
+
+// Time warping ----
+// Pairs of (reference frame, test frame)
+pub type WarpingPath = Vec<(usize, usize)>;
+
+// Coarse dynamic time warping of two frame energy envelopes (e.g. from `StereoSpectogram::frame_energies()`), for
+// sources that drift apart because one plays slightly faster. The envelopes are compared in dB, so the path follows
+// the shape of the dynamics rather than the level. Every reference frame may only be matched with test frames up to
+// `band` frames away from the diagonal that joins both ends, which keeps the cost at O(frames * band) instead of
+// O(frames^2); The band has to cover the largest drift between the sources.
+// Returns the warping path as (reference frame, test frame) pairs, from (0, 0) to the last frames of both.
+pub fn dtw_align(reference: &[f32], test: &[f32], band: usize) -> Result<WarpingPath, SpecCompError> {
+    let (n, m) = (reference.len(), test.len());
+    if n == 0 || m == 0 {
+        return Result::Err(SpecCompError::EmptyInput(format!("dtw_align(): At least one of the envelopes is empty (reference: {} frames, test: {} frames)", n, m)));
+    }
+    let to_db = |e: &f32| -> f32 { 10.0 * (e.max(0.0) + DEFAULT_EPSILON).log10() };
+    let reference_db: Vec<f32> = reference.iter().map(to_db).collect();
+    let test_db: Vec<f32> = test.iter().map(to_db).collect();
+
+    // Test frames each reference frame may be matched with; Every row reaches at least one frame past the start of
+    // the next one, so a path always exists
+    let rows: Vec<(usize, usize)> = (0..n).map(|i| {
+        let first: usize = (i * m / n).saturating_sub(band);
+        let last: usize = min(((i + 1) * m).div_ceil(n).saturating_sub(1) + band, m - 1);
+        (first, last.max(first))
+    }).collect();
+
+    // Accumulated cost of the cheapest path to every cell in the band
+    let mut cost: Vec<Vec<f32>> = Vec::with_capacity(n);
+    let at = |cost: &Vec<Vec<f32>>, i: usize, j: usize| -> f32 {
+        let (first, last) = rows[i];
+        match j >= first && j <= last {
+            true  => { cost[i][j - first] }
+            false => { f32::INFINITY }
+        }
+    };
+    for i in 0..n {
+        let (first, last) = rows[i];
+        let mut row: Vec<f32> = Vec::with_capacity(last - first + 1);
+        for j in first..=last {
+            let local: f32 = (reference_db[i] - test_db[j]).abs();
+            let previous: f32 = match (i, j) {
+                (0, 0) => { 0.0 }
+                (0, _) => { row[j - first - 1] }
+                (_, 0) => { at(&cost, i - 1, 0) }
+                (_, _) => {
+                    let left: f32 = match j > first { true => { row[j - first - 1] } false => { f32::INFINITY } };
+                    at(&cost, i - 1, j - 1).min(at(&cost, i - 1, j)).min(left)
+                }
+            };
+            row.push(previous + local);
+        }
+        cost.push(row);
+    }
+
+    // Walk back from the end; Diagonal steps win ties
+    let mut path: WarpingPath = vec![(n - 1, m - 1)];
+    let (mut i, mut j) = (n - 1, m - 1);
+    while i > 0 || j > 0 {
+        (i, j) = match (i, j) {
+            (0, _) => { (0, j - 1) }
+            (_, 0) => { (i - 1, 0) }
+            (_, _) => {
+                let steps: [(usize, usize); 3] = [(i - 1, j - 1), (i - 1, j), (i, j - 1)];
+                let mut best: (usize, usize) = steps[0];
+                for step in &steps[1..] {
+                    if at(&cost, step.0, step.1) < at(&cost, best.0, best.1) { best = *step; }
+                }
+                best
+            }
+        };
+        path.push((i, j));
+    }
+    path.reverse();
+    Result::Ok(path)
+}
+
+// Variant of `time_compare_spectogram()` for sources that differ in tempo: the frames are paired along the path
+// `dtw_align()` finds on the energy envelopes (with a band of `band` frames) instead of by their index.
+// Returns the error of every frame of `reference` (the mean error of the test frames it was paired with), the warping
+// path and the mean error.
+pub fn time_compare_warped(bins: u32, reference: &StereoSpectogram, test: &StereoSpectogram, band: usize) -> Result<(Vec<f32>, WarpingPath, f32), SpecCompError> {
+    let bins_us = bins as usize;
+    if bins_us == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("time_compare_warped(): The number of bins must be greater than 0")));
+    }
+    reference.check_bins(bins, "time_compare_warped()", "reference")?;
+    test.check_bins(bins, "time_compare_warped()", "test")?;
+    if !reference.left.len().is_multiple_of(bins_us) || !test.left.len().is_multiple_of(bins_us) {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("time_compare_warped(): The inputs ({} and {} values) aren't made of whole {} bin frames",
+            reference.left.len(), test.left.len(), bins)));
+    }
+    let path: WarpingPath = dtw_align(&reference.frame_energies(bins), &test.frame_energies(bins), band)?;
+
+    let reference_frames: usize = reference.left.len() / bins_us;
+    let mut sums: Vec<(f32, usize)> = vec![(0.0, 0); reference_frames];
+    for (r, t) in &path {
+        sums[*r].0 += frame_pair_error(bins_us, reference, *r, test, *t);
+        sums[*r].1 += 1;
+    }
+    let errors: Vec<f32> = sums.iter().map(|(sum, count)| sum / *count as f32).collect();
+
+    let finite: Vec<f32> = errors.iter().copied().filter(|e| e.is_finite()).collect();
+    let mean_error: f32 = finite.iter().sum::<f32>() / finite.len().max(1) as f32;
+    Result::Ok((errors, path, mean_error))
+}
+
+// Finds the frame with the largest error in the output of `time_compare_spectogram()`.
+// Returns the frame's index, its error and its timestamp in seconds; STFT frames don't overlap, so the hop is `fft_size`.
+// An empty error vector yields (0, 0.0, 0.0).
+pub fn worst_frame(errors: &[f32], fft_size: u32, sample_rate: u32) -> (usize, f32, f32) {
+    let mut worst: (usize, f32) = (0, 0.0);
+    for (i, e) in errors.iter().enumerate() {
+        if *e > worst.1 { worst = (i, *e); }
+    }
+
+    (worst.0, worst.1, frame_timestamp(worst.0, fft_size, sample_rate))
+}
+
+// Start of frame `frame` in seconds, for frames `hop` samples apart (`fft_size` for the spectograms of this program);
+// 0.0 if the sample rate is 0
+pub fn frame_timestamp(frame: usize, hop: u32, sample_rate: u32) -> f32 {
+    match sample_rate {
+        0 => { 0.0 }
+        _ => { (frame as f64 * hop as f64 / sample_rate as f64) as f32 }
+    }
+}
+
+// Distribution of the per-frame errors of `time_compare_spectogram()`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ErrorStats {
+    // Number of (finite) errors the statistics are based on; Everything else is 0.0 if this is 0
+    pub count: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub median: f32,
+    pub p90: f32,
+    pub p99: f32
+}
+
+// Summary statistics of `errors`; Non-finite values are ignored. Percentiles interpolate linearly between the two
+// closest ranks (the percentile p lies at position p * (count - 1) of the sorted errors), so the median of an even
+// number of errors is the mean of the middle two.
+pub fn error_stats(errors: &[f32]) -> ErrorStats {
+    let mut sorted: Vec<f32> = errors.iter().copied().filter(|e| e.is_finite()).collect();
+    if sorted.is_empty() {
+        return ErrorStats::default();
+    }
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    let percentile = |p: f32| -> f32 {
+        let position: f32 = p * (sorted.len() - 1) as f32;
+        let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+        sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f32)
+    };
+
+    ErrorStats {
+        count: sorted.len(),
+        min: sorted[0],
+        max: sorted[sorted.len() - 1],
+        mean: sorted.iter().sum::<f32>() / sorted.len() as f32,
+        median: percentile(0.5),
+        p90: percentile(0.9),
+        p99: percentile(0.99)
+    }
+}
+
+// Histogram of `errors` with `buckets` buckets of the same width between range.0 and range.1 (from the smallest to
+// the largest error if None); Errors outside of the range are counted in the first or the last bucket, so that only
+// non-finite errors are left out. Returns (center of the bucket, count) for every bucket.
+pub fn error_histogram(errors: &[f32], buckets: usize, range: Option<(f32, f32)>) -> Vec<(f32, u32)> {
+    let finite: Vec<f32> = errors.iter().copied().filter(|e| e.is_finite()).collect();
+    if buckets == 0 || finite.is_empty() {
+        return vec![];
+    }
+
+    let (low, high) = match range {
+        Some(r) => { r }
+        None    => { (finite.iter().fold(f32::INFINITY, |m, e| m.min(*e)), finite.iter().fold(f32::NEG_INFINITY, |m, e| m.max(*e))) }
+    };
+    // A single value (or an empty range) still gets buckets of some width
+    let width: f32 = match high > low {
+        true  => { (high - low) / buckets as f32 }
+        false => { 1.0 / buckets as f32 }
+    };
+
+    let mut counts: Vec<u32> = vec![0; buckets];
+    for e in finite {
+        let bucket: usize = ((e - low) / width).floor().clamp(0.0, (buckets - 1) as f32) as usize;
+        counts[bucket] += 1;
+    }
+
+    counts.iter().enumerate()
+        .map(|(i, c)| (low + (i as f32 + 0.5) * width, *c))
+        .collect()
+}
+
 // Compares two stereo spectograms in terms of frequency; For each bin, the mean error from all frames is returned.
 // This function gives smaller weights to higher frequencies since differences in them are less noticable.
-pub fn freq_compare_spectogram(bins: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram) -> Result<(Vec<f32>, f32), String> {
+pub fn freq_compare_spectogram(bins: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram) -> Result<(Vec<f32>, f32), SpecCompError> {
+    if bins == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("freq_compare_spectogram(): The number of bins must be greater than 0")));
+    }
+    freq_compare_reduced(bins, spec_a, spec_b, ChannelReduce::Averaged, ErrorScale::Absolute, &frequency_weights(bins))
+}
+
+// Same as `freq_compare_spectogram()` with a choice of weighting; `sample_rate` is the one the spectograms were
+// calculated at and is needed to find each bin's frequency (the FFT size is `2 * bins`).
+pub fn freq_compare_weighted(bins: u32, sample_rate: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram, weighting: FrequencyWeighting) -> Result<(Vec<f32>, f32), SpecCompError> {
+    let weights: Vec<f32> = weighting.weights(bins, sample_rate)?;
+    freq_compare_reduced(bins, spec_a, spec_b, ChannelReduce::Averaged, ErrorScale::Absolute, &weights)
+}
+
+fn freq_compare_reduced(bins: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram, reduce: ChannelReduce, scale: ErrorScale, w: &[f32]) -> Result<(Vec<f32>, f32), SpecCompError> {
     let bins_us = bins as usize;
+    if bins_us == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("freq_compare_spectogram(): The number of bins must be greater than 0")));
+    }
+    spec_a.check_bins(bins, "freq_compare_spectogram()", "spec_a")?;
+    spec_b.check_bins(bins, "freq_compare_spectogram()", "spec_b")?;
 
     let (spec_a_l, spec_a_r) = (&spec_a.left, &spec_a.right);
     let (spec_b_l, spec_b_r) = (&spec_b.left, &spec_b.right);
@@ -383,61 +899,2120 @@ pub fn freq_compare_spectogram(bins: u32, spec_a: &StereoSpectogram, spec_b: &St
 
     // Check the numbers add up
     if spec_a_l.len() % bins_us != 0 { 
-        return Result::Err(format!("time_compare_spectogram(): The number of bins in input a ({}) doesn't match the size of the input vector ({} / {} = {})",
-            bins, spec_a_l.len(), bins, spec_a_l.len() as f32 / bins as f32));
+        return Result::Err(SpecCompError::DimensionMismatch(format!("freq_compare_spectogram(): The number of bins in input a ({}) doesn't match the size of the input vector ({} / {} = {})",
+            bins, spec_a_l.len(), bins, spec_a_l.len() as f32 / bins as f32)));
     }
 
     if spec_b_l.len() % bins_us != 0 { 
-        return Result::Err(format!("time_compare_spectogram(): The number of bins in input b ({}) doesn't match the size of the input vector ({} / {} = {})",
-            bins, spec_b_l.len(), bins, spec_b_l.len() as f32 / bins as f32));
+        return Result::Err(SpecCompError::DimensionMismatch(format!("freq_compare_spectogram(): The number of bins in input b ({}) doesn't match the size of the input vector ({} / {} = {})",
+            bins, spec_b_l.len(), bins, spec_b_l.len() as f32 / bins as f32)));
+    }
+
+    // Nothing to compare
+    if usable_frames == 0 {
+        return Result::Err(SpecCompError::EmptyInput(format!("freq_compare_spectogram(): At least one of the inputs is empty (spec_a: {} frames, spec_b: {} frames)",
+            spec_a_frame_count, spec_b_frame_count)));
     }
 
     // Warn user if a frame count mismatch occurred
     if spec_a_frame_count != spec_b_frame_count { 
-        println!("\nWarning: Inputs of compare_spectogram have different sizes (spec_a: {} frames, spec_b: {} frames), only {} frames will be used.", 
+        statusln!("\nWarning: Inputs of compare_spectogram have different sizes (spec_a: {} frames, spec_b: {} frames), only {} frames will be used.", 
             spec_a_frame_count, spec_b_frame_count, usable_frames); 
     }
 
-    // Create weights
-    let mut w: Vec<f32> = vec![];
-    w.resize(bins as usize, 1.0);
-    // Audio information above 4KHz is less usefull; 4KHz ~= bin 371
-    for i in 0..bins as usize {
-        let i_f = i as f32;
-        w[i] = 1.0 - ((i_f * PI + 370.0)/(bins as f32)).cos();
-        w[i] = 1.0 - w[i].powi(2) / 4.0;
-    }
-
     // Iteration through the vectors still happens from bin to bin in each frame; allocate all result bins now
     let mut mean_err_vec: Vec<f32> = vec![];
     mean_err_vec.resize(bins as usize, 0.0);
 
-    let mut a_it_l = spec_a_l.iter();
-    let mut b_it_l = spec_b_l.iter();
-    let mut a_it_r = spec_a_r.iter();
-    let mut b_it_r = spec_b_r.iter();
-    let mut a_st: f32;
-    let mut b_st: f32;
+    let mut bin_errors: Vec<f32> = vec![0.0; bins_us];
     for f in 0..usable_frames {
-        if f % 16 == 0 { print!("\rComparing... {}%", f*100/usable_frames); }
+        if f % 16 == 0 { status!("\rComparing... {}%", f*100/usable_frames); }
 
-        for bin in 0..bins {
-            a_st = (a_it_l.next().unwrap() + a_it_r.next().unwrap()) / 2.0;
-            b_st = (b_it_l.next().unwrap() + b_it_r.next().unwrap()) / 2.0;
-            mean_err_vec[bin as usize] += (a_st - b_st).abs() * w[bin as usize];
-        }  
+        let frame = f*bins_us..(f + 1)*bins_us;
+        reduce.frame_errors(scale, [&spec_a_l[frame.clone()], &spec_a_r[frame.clone()]], [&spec_b_l[frame.clone()], &spec_b_r[frame]], &mut bin_errors);
+        for ((sum, error), weight) in mean_err_vec.iter_mut().zip(bin_errors.iter()).zip(w.iter()) {
+            *sum += error * weight;
+        }
     }
 
     // Divide each bin's error sum error to get the mean
-    let mut mean_error: f32 = 0.0;
-    for b in 0..bins {
-        mean_err_vec[b as usize] /= usable_frames as f32;
-        mean_error += mean_err_vec[b as usize];
+    for e in mean_err_vec.iter_mut() {
+        *e /= usable_frames as f32;
     }
-    mean_error /= bins as f32;
+    let (mean_error, excluded) = finite_mean(&mean_err_vec);
+    warn_on_excluded(excluded, bins_us, "bins");
 
     // Clear the leftover "Comparing... " message
-    print!("\r                                                          ");
+    status!("\r                                                          ");
 
     Result::Ok((mean_err_vec, mean_error))
 }
+
+
+// Same as `freq_compare_spectogram()` but also returns the standard deviation of each bin's error over all frames,
+// which tells a bin that is consistently off from one that only spikes now and then. Uses Welford's online algorithm
+// so the frames are only iterated once. Returns the mean error of each bin, the standard deviation of each bin and
+// the mean error of all bins.
+pub fn freq_compare_spectogram_std(bins: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram) -> Result<(Vec<f32>, Vec<f32>, f32), SpecCompError> {
+    let bins_us = bins as usize;
+    if bins_us == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("freq_compare_spectogram_std(): The number of bins must be greater than 0")));
+    }
+    spec_a.check_bins(bins, "freq_compare_spectogram_std()", "spec_a")?;
+    spec_b.check_bins(bins, "freq_compare_spectogram_std()", "spec_b")?;
+    if !spec_a.left.len().is_multiple_of(bins_us) || !spec_b.left.len().is_multiple_of(bins_us) {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("freq_compare_spectogram_std(): The number of bins ({}) doesn't match the size of the input vectors ({} and {})",
+            bins, spec_a.left.len(), spec_b.left.len())));
+    }
+
+    let usable_frames = min(spec_a.left.len(), spec_b.left.len()) / bins_us;
+    if usable_frames == 0 {
+        return Result::Err(SpecCompError::EmptyInput(String::from("freq_compare_spectogram_std(): At least one of the inputs is empty")));
+    }
+
+    let w: Vec<f32> = frequency_weights(bins);
+
+    // Running mean and sum of squared differences from the mean of each bin
+    let mut mean: Vec<f32> = vec![0.0; bins_us];
+    let mut m2: Vec<f32> = vec![0.0; bins_us];
+    let frames_a = spec_a.left.chunks_exact(bins_us).zip(spec_a.right.chunks_exact(bins_us));
+    let frames_b = spec_b.left.chunks_exact(bins_us).zip(spec_b.right.chunks_exact(bins_us));
+    for (n, ((a_l, a_r), (b_l, b_r))) in frames_a.zip(frames_b).take(usable_frames).enumerate() {
+        for bin in 0..bins_us {
+            let error: f32 = ((a_l[bin] + a_r[bin]) / 2.0 - (b_l[bin] + b_r[bin]) / 2.0).abs() * w[bin];
+            let delta: f32 = error - mean[bin];
+            mean[bin] += delta / (n + 1) as f32;
+            m2[bin] += delta * (error - mean[bin]);
+        }
+    }
+
+    // Population standard deviation
+    let std: Vec<f32> = m2.iter().map(|m| (m / usable_frames as f32).sqrt()).collect();
+    let mean_error: f32 = mean.iter().sum::<f32>() / bins as f32;
+
+    Result::Ok((mean, std, mean_error))
+}
+
+// Frequency axis ---------------------------------------------------------------------------------------------------------
+// Center frequency (in Hz) of each of the first `bins` bins of an FFT of `fft_size` samples at `sample_rate`; Bin i is
+// at i * sample_rate / fft_size Hz, so bin 0 is DC and the bins of `track_to_spec()` (`fft_size / 2` of them) stop
+// one bin short of the Nyquist frequency. Empty if `fft_size` is 0.
+pub fn bin_frequencies(bins: u32, sample_rate: u32, fft_size: u32) -> Vec<f32> {
+    if fft_size == 0 {
+        return vec![];
+    }
+    let bin_width: f64 = sample_rate as f64 / fft_size as f64;
+    (0..bins).map(|i| (i as f64 * bin_width) as f32).collect()
+}
+
+// Weights of the frequency mode; Audio information above 4KHz is less usefull. The curve is fixed to bins, not to Hz:
+// at 44.1KHz with 4096 samples 4KHz ~= bin 371 (see `bin_frequencies()`)
+fn frequency_weights(bins: u32) -> Vec<f32> {
+    (0..bins as usize)
+        .map(|i| {
+            let w: f32 = 1.0 - ((i as f32 * PI + 370.0)/(bins as f32)).cos();
+            1.0 - w.powi(2) / 4.0
+        })
+        .collect()
+}
+
+// Weighting of the bins in the frequency comparisons
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrequencyWeighting {
+    // The crate's own curve (see `frequency_weights()`); Doesn't depend on the sample rate
+    Cosine,
+    // The standard A-weighting curve (IEC 61672), normalized to 1.0 at 1KHz
+    A
+}
+
+impl FrequencyWeighting {
+    // Parses the name given on the command line
+    pub fn from_name(name: &str) -> Option<FrequencyWeighting> {
+        match name.to_lowercase().as_str() {
+            "cosine"            => { Option::Some(FrequencyWeighting::Cosine) }
+            "a" | "a-weighting" => { Option::Some(FrequencyWeighting::A) }
+            _ => { Option::None }
+        }
+    }
+
+    // Weight of each of the `bins` bins of a spectogram calculated at `sample_rate`
+    pub fn weights(&self, bins: u32, sample_rate: u32) -> Result<Vec<f32>, SpecCompError> {
+        if bins == 0 {
+            return Result::Err(SpecCompError::InvalidArgument(String::from("FrequencyWeighting::weights(): The number of bins must be greater than 0")));
+        }
+        match self {
+            FrequencyWeighting::Cosine => { Result::Ok(frequency_weights(bins)) }
+            FrequencyWeighting::A => {
+                if sample_rate == 0 {
+                    return Result::Err(SpecCompError::InvalidArgument(String::from("FrequencyWeighting::weights(): A-weighting needs the sample rate")));
+                }
+                Result::Ok(bin_frequencies(bins, sample_rate, 2 * bins).into_iter().map(a_weighting).collect())
+            }
+        }
+    }
+}
+
+// A-weighting of a frequency in Hz as a power ratio (the bins of a spectogram hold power), relative to 1KHz
+pub fn a_weighting(frequency: f32) -> f32 {
+    let response = |f: f64| -> f64 {
+        let f2: f64 = f * f;
+        (12194.0f64.powi(2) * f2 * f2) /
+            ((f2 + 20.6f64.powi(2)) * ((f2 + 107.7f64.powi(2)) * (f2 + 737.9f64.powi(2))).sqrt() * (f2 + 12194.0f64.powi(2)))
+    };
+    (response(frequency as f64) / response(1000.0)).powi(2) as f32
+}
+
+// Summarizes the per-bin `errors` of a frequency comparison into 1/`fraction` octave bands (1 for octaves, 3 for
+// third-octaves), centered on 1KHz; Bin i is at i * sample_rate / fft_size Hz and belongs to the band whose center
+// is the closest on a logarithmic scale. The DC bin is skipped, and so are bands without any bin.
+// Returns (center frequency in Hz, mean error of the band's bins) for each band, from low to high frequencies.
+pub fn aggregate_octave_bands(errors: &[f32], sample_rate: u32, fft_size: u32, fraction: u32) -> Vec<(f32, f32)> {
+    if sample_rate == 0 || fft_size == 0 || fraction == 0 {
+        return vec![];
+    }
+
+    // Band index relative to 1KHz -> (error sum, bin count)
+    let frequencies: Vec<f32> = bin_frequencies(errors.len() as u32, sample_rate, fft_size);
+    let mut bands: Vec<(i32, f32, usize)> = vec![];
+    for (frequency, e) in frequencies.iter().zip(errors.iter()).skip(1) {
+        let band: i32 = (fraction as f32 * (frequency / 1000.0).log2()).round() as i32;
+        match bands.last_mut() {
+            Some((b, sum, count)) if *b == band => { *sum += e; *count += 1; }
+            _ => { bands.push((band, *e, 1)); }
+        }
+    }
+
+    bands.iter()
+        .map(|(b, sum, count)| (1000.0 * 2.0f32.powf(*b as f32 / fraction as f32), sum / *count as f32))
+        .collect()
+}
+
+// What happens to the frames that only the longer of two spectograms has; The comparisons themselves truncate to the
+// shorter spectogram, which hides differences at the end of the longer one (e.g. a stem that goes on after the
+// other one stopped)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameMismatch {
+    // Only compare the frames both spectograms have
+    Truncate,
+    // Treat the missing frames of the shorter spectogram as silence, so that every frame is compared
+    ZeroExtend
+}
+
+impl FrameMismatch {
+    // Parses "truncate" or "extend"
+    pub fn from_name(name: &str) -> Option<FrameMismatch> {
+        match name.to_lowercase().as_str() {
+            "truncate" => { Option::Some(FrameMismatch::Truncate) }
+            "extend"   => { Option::Some(FrameMismatch::ZeroExtend) }
+            _ => { Option::None }
+        }
+    }
+}
+
+// Prepares two spectograms of `bins` bins for the comparisons according to `mode`; With `ZeroExtend` the shorter one
+// gets silent frames up to the length of the longer one, with `Truncate` nothing changes.
+pub fn match_frame_counts(bins: u32, spec_a: &mut StereoSpectogram, spec_b: &mut StereoSpectogram, mode: FrameMismatch) {
+    if mode == FrameMismatch::Truncate || bins == 0 {
+        return;
+    }
+
+    let frames: usize = spec_a.left.len().max(spec_b.left.len()).div_ceil(bins as usize);
+    spec_a.zero_extend(bins, frames);
+    spec_b.zero_extend(bins, frames);
+}
+
+// How much of two spectograms a comparison covers (see `frame_coverage()`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameCoverage {
+    // Frames that are compared
+    pub usable_frames: usize,
+    // Frames of each spectogram
+    pub spec_a_frames: usize,
+    pub spec_b_frames: usize,
+    // Share of the frames of each spectogram that are compared, from 0.0 to 1.0; 0.0 for an empty spectogram
+    pub coverage_a: f32,
+    pub coverage_b: f32
+}
+
+// Number of frames of two spectograms of `bins` bins that the comparisons use once `match_frame_counts()` has prepared
+// them with `mode`, and the share of each spectogram that this covers; With `Truncate` the frames only the longer one
+// has are left out, with `ZeroExtend` every frame is compared. Call it before `match_frame_counts()`.
+pub fn frame_coverage(bins: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram, mode: FrameMismatch) -> Result<FrameCoverage, SpecCompError> {
+    if bins == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("frame_coverage(): The number of bins must be greater than 0")));
+    }
+    spec_a.check_bins(bins, "frame_coverage()", "spec_a")?;
+    spec_b.check_bins(bins, "frame_coverage()", "spec_b")?;
+
+    let spec_a_frames: usize = spec_a.left.len() / bins as usize;
+    let spec_b_frames: usize = spec_b.left.len() / bins as usize;
+    let usable_frames: usize = match mode {
+        FrameMismatch::Truncate   => { min(spec_a_frames, spec_b_frames) }
+        FrameMismatch::ZeroExtend => { spec_a_frames.max(spec_b_frames) }
+    };
+    let coverage = |frames: usize| -> f32 {
+        match frames {
+            0 => { 0.0 }
+            _ => { min(usable_frames, frames) as f32 / frames as f32 }
+        }
+    };
+
+    Result::Ok(FrameCoverage { usable_frames, spec_a_frames, spec_b_frames, coverage_a: coverage(spec_a_frames), coverage_b: coverage(spec_b_frames) })
+}
+
+// How the two channels are combined by `time_compare_channels()` and `freq_compare_channels()`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelMode {
+    // Average the channels before comparing them, like `time_compare_spectogram()` and `freq_compare_spectogram()`
+    Averaged,
+    // Compare each channel on its own; Errors are returned for the left and then the right channel
+    Independent,
+    // Compare each channel on its own and keep the worse of the two for every bin
+    MaxOfChannels
+}
+
+impl ChannelMode {
+    // Parses "averaged", "independent" or "max"
+    pub fn from_name(name: &str) -> Option<ChannelMode> {
+        match name.to_lowercase().as_str() {
+            "averaged"    => { Option::Some(ChannelMode::Averaged) }
+            "independent" => { Option::Some(ChannelMode::Independent) }
+            "max"         => { Option::Some(ChannelMode::MaxOfChannels) }
+            _ => { Option::None }
+        }
+    }
+}
+
+// Reference values (after combining the channels) below this are treated as this in relative errors
+pub const RELATIVE_EPSILON: f32 = 1e-6;
+// Largest relative error of a single bin; Keeps near-silent reference bins from outweighing everything else
+pub const MAX_RELATIVE_ERROR: f32 = 10.0;
+
+// How the difference between a bin of the reference (`spec_a`) and the same bin of the test (`spec_b`) is measured
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorScale {
+    // |a - b|; Loud bins weigh more, even when the difference is small compared to them
+    Absolute,
+    // |a - b| / (a + RELATIVE_EPSILON), at most MAX_RELATIVE_ERROR; Scale invariant, e.g. a test that is 10% louder
+    // than the reference everywhere gives 0.1 at every bin
+    Relative,
+    // |dB(a) - dB(b)|, where dB(p) = 10 * log10(p) and anything below `floor_db` counts as `floor_db`; The error is in
+    // dB, which follows loudness more closely, e.g. a test that is twice as loud (in amplitude) everywhere gives
+    // 6.02 dB at every bin. Bins below the floor in both spectograms (e.g. silence) give 0.
+    Decibel { floor_db: f32 }
+}
+
+// Floor of `ErrorScale::Decibel` when none is given, in dB of bin power (as computed by `track_to_spec()`, where a
+// full scale sine peaks at about +60 dB with an FFT size of 4096); Well above the noise of 16-bit audio
+pub const DEFAULT_DB_FLOOR: f32 = -40.0;
+
+impl ErrorScale {
+    pub fn error(&self, a: f32, b: f32) -> f32 {
+        match self {
+            ErrorScale::Absolute => { absolute_error(a, b) }
+            ErrorScale::Relative => { relative_error(a, b) }
+            ErrorScale::Decibel { floor_db } => { decibel_error(a, b, db_to_power(*floor_db)) }
+        }
+    }
+}
+
+fn absolute_error(a: f32, b: f32) -> f32 {
+    (a - b).abs()
+}
+
+fn relative_error(a: f32, b: f32) -> f32 {
+    ((a - b).abs() / (a.abs() + RELATIVE_EPSILON)).min(MAX_RELATIVE_ERROR)
+}
+
+// `floor` is the power of the floor, so that a single logarithm is needed
+fn decibel_error(a: f32, b: f32, floor: f32) -> f32 {
+    (10.0 * (a.max(floor) / b.max(floor)).log10()).abs()
+}
+
+fn db_to_power(db: f32) -> f32 {
+    10f32.powf(db / 10.0)
+}
+
+// Number of partial sums kept by `lane_sum()`
+const SUM_LANES: usize = 8;
+
+// Sum of `values`, added up in `SUM_LANES` interleaved partial sums; Unlike a single running sum these don't depend
+// on each other, so the compiler can vectorize the loop. The order of the additions differs from a plain
+// `iter().sum()`, so the result may differ from it in the last bits.
+fn lane_sum(values: &[f32]) -> f32 {
+    let mut lanes: [f32; SUM_LANES] = [0.0; SUM_LANES];
+    let chunks = values.chunks_exact(SUM_LANES);
+    let rest: f32 = chunks.remainder().iter().sum();
+    for chunk in chunks {
+        for (lane, v) in lanes.iter_mut().zip(chunk) {
+            *lane += v;
+        }
+    }
+    lanes.iter().sum::<f32>() + rest
+}
+
+// Error of a single bin given the left and right values of both spectograms
+#[derive(Debug, Clone, Copy)]
+enum ChannelReduce {
+    Averaged,
+    Left,
+    Right,
+    Max
+}
+
+impl ChannelReduce {
+    fn error(&self, scale: ErrorScale, a_l: f32, a_r: f32, b_l: f32, b_r: f32) -> f32 {
+        match self {
+            ChannelReduce::Averaged => { scale.error((a_l + a_r) / 2.0, (b_l + b_r) / 2.0) }
+            ChannelReduce::Left     => { scale.error(a_l, b_l) }
+            ChannelReduce::Right    => { scale.error(a_r, b_r) }
+            ChannelReduce::Max      => { scale.error(a_l, b_l).max(scale.error(a_r, b_r)) }
+        }
+    }
+
+    // Error of every bin of a frame, given the left and right channels of the frame in both spectograms; Written to
+    // `out`, which has as many bins as the frame. Same as calling `error()` for every bin, but the loop is chosen once
+    // per frame and has no branches, so it can be vectorized.
+    fn frame_errors(&self, scale: ErrorScale, a: [&[f32]; 2], b: [&[f32]; 2], out: &mut [f32]) {
+        match scale {
+            ErrorScale::Absolute => { self.frame_errors_with(absolute_error, a, b, out); }
+            ErrorScale::Relative => { self.frame_errors_with(relative_error, a, b, out); }
+            ErrorScale::Decibel { floor_db } => {
+                let floor: f32 = db_to_power(floor_db);
+                self.frame_errors_with(|a, b| decibel_error(a, b, floor), a, b, out);
+            }
+        }
+    }
+
+    fn frame_errors_with<E: Fn(f32, f32) -> f32>(&self, error: E, a: [&[f32]; 2], b: [&[f32]; 2], out: &mut [f32]) {
+        let bins = out.iter_mut().zip(a[0]).zip(a[1]).zip(b[0]).zip(b[1]);
+        match self {
+            ChannelReduce::Averaged => {
+                for ((((o, a_l), a_r), b_l), b_r) in bins { *o = error((a_l + a_r) / 2.0, (b_l + b_r) / 2.0); }
+            }
+            ChannelReduce::Left => {
+                for ((((o, a_l), _), b_l), _) in bins { *o = error(*a_l, *b_l); }
+            }
+            ChannelReduce::Right => {
+                for ((((o, _), a_r), _), b_r) in bins { *o = error(*a_r, *b_r); }
+            }
+            ChannelReduce::Max => {
+                for ((((o, a_l), a_r), b_l), b_r) in bins { *o = error(*a_l, *b_l).max(error(*a_r, *b_r)); }
+            }
+        }
+    }
+
+    // What to compute for each `ChannelMode`
+    fn for_mode(mode: ChannelMode) -> Vec<ChannelReduce> {
+        match mode {
+            ChannelMode::Averaged      => { vec![ChannelReduce::Averaged] }
+            ChannelMode::Independent   => { vec![ChannelReduce::Left, ChannelReduce::Right] }
+            ChannelMode::MaxOfChannels => { vec![ChannelReduce::Max] }
+        }
+    }
+}
+
+// `time_compare_spectogram()` with a choice of how the channels are combined and how bins are told apart; Returns one
+// (errors, mean error) pair, or two (left, then right) if `mode` is Independent.
+pub fn time_compare_channels(bins: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram, mode: ChannelMode, scale: ErrorScale) -> Result<Vec<(Vec<f32>, f32)>, SpecCompError> {
+    ChannelReduce::for_mode(mode).into_iter()
+        .map(|reduce| time_compare_reduced(bins, spec_a, spec_b, reduce, scale))
+        .collect()
+}
+
+// `freq_compare_weighted()` with a choice of how the channels are combined and how bins are told apart; Returns one
+// (errors, mean error) pair, or two (left, then right) if `mode` is Independent.
+pub fn freq_compare_channels(bins: u32, sample_rate: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram, mode: ChannelMode, weighting: FrequencyWeighting, scale: ErrorScale) -> Result<Vec<(Vec<f32>, f32)>, SpecCompError> {
+    let weights: Vec<f32> = weighting.weights(bins, sample_rate)?;
+    ChannelReduce::for_mode(mode).into_iter()
+        .map(|reduce| freq_compare_reduced(bins, spec_a, spec_b, reduce, scale, &weights))
+        .collect()
+}
+
+// Fused variant ------------------------------------------------------------------------------------------------------------
+// What `time_compare_spectogram()` and `freq_compare_spectogram()` return: the error of every frame and their mean, then
+// the error of every bin and their mean
+pub type FusedResult = ((Vec<f32>, f32), (Vec<f32>, f32));
+
+// `track_to_spec()` of both tracks followed by `time_compare_channels()` and `freq_compare_channels()` in a single pass:
+// every frame of both tracks is transformed, compared and dropped right away, so only O(fft_size) spectral values are
+// held instead of two whole spectograms (for very long tracks). Frames line up with those of `track_to_spec()` and, as
+// with `FrameMismatch::Truncate`, only the frames both tracks have are compared; The results are the same as those of
+// the separate stages. Returns one result of the time and frequency comparisons (see `FusedResult`), or two (left, then
+// right) if `mode` is Independent. `weights` holds the weight of each of the `fft_size / 2` bins in the frequency
+// comparison, e.g. from `FrequencyWeighting::weights()`.
+pub fn fused_compare(fft_size_u32: u32, window: WindowFunction, track_a: &TrackBuffer, track_b: &TrackBuffer, mode: ChannelMode, scale: ErrorScale, weights: &[f32]) -> Result<Vec<FusedResult>, SpecCompError> {
+    let fft_size: usize = fft_size_u32 as usize;
+    if fft_size < 2 || !fft_size.is_multiple_of(2) {
+        return Result::Err(SpecCompError::InvalidArgument(format!("fused_compare(): The FFT size must be even and at least 2 (got {})", fft_size)));
+    }
+    let bins: usize = fft_size / 2;
+    if weights.len() != bins {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("fused_compare(): Got {} frequency weights for {} bins", weights.len(), bins)));
+    }
+
+    // Same frame counts as `track_to_spec()`, the last frame being padded with 0
+    let spec_a_frame_count: usize = (track_a.len() / 2).div_ceil(fft_size);
+    let spec_b_frame_count: usize = (track_b.len() / 2).div_ceil(fft_size);
+    let usable_frames: usize = min(spec_a_frame_count, spec_b_frame_count);
+    if usable_frames == 0 {
+        return Result::Err(SpecCompError::EmptyInput(format!("fused_compare(): At least one of the inputs is empty (track_a: {} frames, track_b: {} frames)",
+            spec_a_frame_count, spec_b_frame_count)));
+    }
+    if spec_a_frame_count != spec_b_frame_count {
+        statusln!("\nWarning: Different input sizes (track_a: {} frames, track_b: {} frames), using {} frames.",
+            spec_a_frame_count, spec_b_frame_count, usable_frames);
+    }
+
+    let coefficients: Vec<f32> = window.coefficients(fft_size);
+    let mut fft_planner: FftPlanner<f32> = FftPlanner::new();
+    let fft = fft_planner.plan_fft_forward(fft_size);
+
+    // Power of every bin of the current frame of each channel of both tracks; The only spectral values held
+    let mut window_buffer: Vec<Complex<f32>> = Vec::with_capacity(fft_size);
+    let mut frame: [Vec<f32>; 4] = [vec![0.0; bins], vec![0.0; bins], vec![0.0; bins], vec![0.0; bins]];
+    let mut transform = |source: &[f32], start: usize, channel: usize, out: &mut [f32]| {
+        let remaining: usize = min(fft_size, source.len() / 2 - start);
+        for (i, w) in coefficients.iter().enumerate().take(remaining) {
+            window_buffer.push(Complex::new(source[2*(i + start) + channel] * w, 0.0f32));
+        }
+        window_buffer.resize(fft_size, Complex::new(0f32, 0f32));
+        fft.process(&mut window_buffer);
+        for (o, bin) in out.iter_mut().zip(window_buffer.iter()) {
+            *o = bin.re.powi(2);
+        }
+        window_buffer.clear();
+    };
+
+    let reduces: Vec<ChannelReduce> = ChannelReduce::for_mode(mode);
+    let mut time_errors: Vec<Vec<f32>> = vec![Vec::with_capacity(usable_frames); reduces.len()];
+    let mut freq_sums: Vec<Vec<f32>> = vec![vec![0.0; bins]; reduces.len()];
+    let mut bin_errors: Vec<f32> = vec![0.0; bins];
+    for f in 0..usable_frames {
+        if f % 16 == 0 { status!("\rComparing... {}%", f*100/usable_frames); }
+
+        let [a_l, a_r, b_l, b_r] = &mut frame;
+        transform(track_a, f * fft_size, 0, a_l);
+        transform(track_a, f * fft_size, 1, a_r);
+        transform(track_b, f * fft_size, 0, b_l);
+        transform(track_b, f * fft_size, 1, b_r);
+
+        for (r, reduce) in reduces.iter().enumerate() {
+            reduce.frame_errors(scale, [a_l, a_r], [b_l, b_r], &mut bin_errors);
+
+            // Time mode, as in `time_compare_spectogram()`
+            let frame_error: f32 = lane_sum(&bin_errors) / bins as f32;
+            time_errors[r].push(frame_error);
+
+            // Frequency mode, as in `freq_compare_spectogram()`
+            for ((sum, error), weight) in freq_sums[r].iter_mut().zip(bin_errors.iter()).zip(weights.iter()) {
+                *sum += error * weight;
+            }
+        }
+    }
+    status!("\r                                                          ");
+
+    let results = time_errors.into_iter().zip(freq_sums)
+        .map(|(time, mut freq)| {
+            for bin in freq.iter_mut() {
+                *bin /= usable_frames as f32;
+            }
+            let (time_mean, excluded_frames) = finite_mean(&time);
+            let (freq_mean, excluded_bins) = finite_mean(&freq);
+            warn_on_excluded(excluded_frames, usable_frames, "frames");
+            warn_on_excluded(excluded_bins, bins, "bins");
+            ((time, time_mean), (freq, freq_mean))
+        })
+        .collect();
+    Result::Ok(results)
+}
+
+// Compares the time-averaged spectra of two spectograms (see `StereoSpectogram::average_spectrum()`); Much cheaper than
+// the frame by frame comparisons and less sensitive to timing differences. The channels are averaged like in the other
+// comparisons. Returns the error of each bin and the mean error of all bins.
+pub fn spectrum_compare(bins: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram) -> Result<(Vec<f32>, f32), SpecCompError> {
+    if bins == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("spectrum_compare(): The number of bins must be greater than 0")));
+    }
+    spec_a.check_bins(bins, "spectrum_compare()", "spec_a")?;
+    spec_b.check_bins(bins, "spectrum_compare()", "spec_b")?;
+
+    let (a_l, a_r) = spec_a.average_spectrum(bins);
+    let (b_l, b_r) = spec_b.average_spectrum(bins);
+    if a_l.is_empty() || b_l.is_empty() {
+        return Result::Err(SpecCompError::EmptyInput(format!("spectrum_compare(): At least one of the inputs is empty (spec_a: {} frames, spec_b: {} frames)",
+            spec_a.left.len() / bins as usize, spec_b.left.len() / bins as usize)));
+    }
+
+    let errors: Vec<f32> = (0..bins as usize)
+        .map(|bin| ((a_l[bin] + a_r[bin]) / 2.0 - (b_l[bin] + b_r[bin]) / 2.0).abs())
+        .collect();
+    let mean_error: f32 = errors.iter().sum::<f32>() / errors.len() as f32;
+
+    Result::Ok((errors, mean_error))
+}
+
+
+// Signal-to-noise ratio in dB of `estimate` against the ground truth `reference`; Unlike the error metrics above this is
+// asymmetric: the power of `reference` is divided by the power of the magnitude difference, both summed over all
+// usable frames and bins. The channels are averaged. Higher is better.
+pub fn snr_compare(bins: u32, reference: &StereoSpectogram, estimate: &StereoSpectogram) -> Result<f32, SpecCompError> {
+    let bins_us = bins as usize;
+    if bins_us == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("snr_compare(): The number of bins must be greater than 0")));
+    }
+    reference.check_bins(bins, "snr_compare()", "reference")?;
+    estimate.check_bins(bins, "snr_compare()", "estimate")?;
+
+    let usable_frames = min(reference.left.len(), estimate.left.len()) / bins_us;
+    if usable_frames == 0 {
+        return Result::Err(SpecCompError::EmptyInput(format!("snr_compare(): At least one of the inputs is empty (reference: {} frames, estimate: {} frames)",
+            reference.left.len() / bins_us, estimate.left.len() / bins_us)));
+    }
+
+    let mut signal: f32 = 0.0;
+    let mut noise: f32 = 0.0;
+    for (r, e) in [(&reference.left, &estimate.left), (&reference.right, &estimate.right)] {
+        for (r_bin, e_bin) in r.iter().zip(e.iter()).take(usable_frames * bins_us) {
+            signal += r_bin;
+            noise += (r_bin.sqrt() - e_bin.sqrt()).powi(2);
+        }
+    }
+
+    Result::Ok(power_to_db(signal, noise, DEFAULT_EPSILON))
+}
+
+// Compares the stereo image of two spectograms; For every bin the panning `left / (left + right)` is found (0.0 is hard
+// right, 1.0 hard left and bins silent in both channels count as centered) and the absolute difference between the two
+// spectograms is kept. Returns the mean panning error of each frame and the mean of all frames; 0.0 means the same
+// stereo image, 1.0 that every bin moved from one side to the other.
+pub fn panning_compare(bins: u32, spec_a: &StereoSpectogram, spec_b: &StereoSpectogram) -> Result<(Vec<f32>, f32), SpecCompError> {
+    let bins_us = bins as usize;
+    if bins_us == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("panning_compare(): The number of bins must be greater than 0")));
+    }
+    spec_a.check_bins(bins, "panning_compare()", "spec_a")?;
+    spec_b.check_bins(bins, "panning_compare()", "spec_b")?;
+
+    let usable_frames = min(spec_a.left.len(), spec_b.left.len()) / bins_us;
+    if usable_frames == 0 {
+        return Result::Err(SpecCompError::EmptyInput(format!("panning_compare(): At least one of the inputs is empty (spec_a: {} frames, spec_b: {} frames)",
+            spec_a.left.len() / bins_us, spec_b.left.len() / bins_us)));
+    }
+
+    let pan = |l: f32, r: f32| -> f32 {
+        match l + r < DEFAULT_EPSILON {
+            true  => { 0.5 }
+            false => { safe_ratio(l, l + r, DEFAULT_EPSILON) }
+        }
+    };
+
+    let frames_a = spec_a.left.chunks_exact(bins_us).zip(spec_a.right.chunks_exact(bins_us));
+    let frames_b = spec_b.left.chunks_exact(bins_us).zip(spec_b.right.chunks_exact(bins_us));
+    let errors: Vec<f32> = frames_a.zip(frames_b).take(usable_frames)
+        .map(|((a_l, a_r), (b_l, b_r))| {
+            (0..bins_us).map(|b| (pan(a_l[b], a_r[b]) - pan(b_l[b], b_r[b])).abs()).sum::<f32>() / bins as f32
+        })
+        .collect();
+    let mean_error: f32 = errors.iter().sum::<f32>() / usable_frames as f32;
+
+    Result::Ok((errors, mean_error))
+}
+
+
+// Streaming variant -------------------------------------------------------------------------------------------------------
+// Builds the same spectogram as `track_to_spec` from interleaved samples that arrive in chunks of any size; Only the
+// samples of the current window are kept, so memory for samples is O(fft_size) instead of O(track length).
+// Windows start every `hop` samples (per channel); With a hop smaller than the FFT size consecutive windows overlap and
+// the last `fft_size - hop` samples of a window are kept for the next one.
+pub struct StreamingSpectogram {
+    fft_size: usize,
+    hop: usize,
+    coefficients: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    pending: Vec<f32>,
+    // Interleaved samples pushed since the last transformed window
+    unprocessed: usize,
+    spectogram: StereoSpectogram
+}
+
+impl StreamingSpectogram {
+    pub fn new(fft_size_u32: u32, window: WindowFunction) -> StreamingSpectogram {
+        StreamingSpectogram::with_hop(fft_size_u32, fft_size_u32, window)
+    }
+
+    // Same as `new` but with windows `hop` samples apart; `hop` is clamped to 1..=fft_size
+    pub fn with_hop(fft_size_u32: u32, hop_u32: u32, window: WindowFunction) -> StreamingSpectogram {
+        let fft_size: usize = fft_size_u32 as usize;
+
+        let mut fft_planner: FftPlanner<f32> = FftPlanner::new();
+        StreamingSpectogram {
+            fft_size,
+            hop: (hop_u32 as usize).clamp(1, fft_size.max(1)),
+            coefficients: window.coefficients(fft_size),
+            fft: fft_planner.plan_fft_forward(fft_size),
+            pending: Vec::with_capacity(2*fft_size),
+            unprocessed: 0,
+            spectogram: StereoSpectogram::new(fft_size_u32 / 2)
+        }
+    }
+
+    // Number of whole frames transformed so far (and not taken with `take_frames`)
+    pub fn frame_count(&self) -> usize {
+        match self.fft_size / 2 {
+            0 => { 0 }
+            bins => { self.spectogram.left.len() / bins }
+        }
+    }
+
+    // Removes and returns the frames transformed so far; The samples of the current window are kept
+    pub fn take_frames(&mut self) -> StereoSpectogram {
+        let bins: u32 = self.spectogram.bins;
+        std::mem::replace(&mut self.spectogram, StereoSpectogram::new(bins))
+    }
+
+    // Feeds interleaved L/R samples; A chunk may end in the middle of a L/R pair. A full window is transformed as
+    // soon as it is available
+    pub fn push(&mut self, samples: &[f32]) {
+        if self.fft_size == 0 { return; }
+        for sample in samples {
+            self.pending.push(*sample);
+            self.unprocessed += 1;
+            if self.pending.len() == 2*self.fft_size {
+                self.process_window();
+            }
+        }
+    }
+
+    // Transforms the leftover samples (padded with 0) and returns the spectogram
+    pub fn finish(mut self) -> StereoSpectogram {
+        if self.unprocessed >= 2 {
+            self.process_window();
+        }
+        self.spectogram
+    }
+
+    fn process_window(&mut self) {
+        let mut window_buffer_l: Vec<Complex<f32>> = vec![Complex::new(0.0, 0.0); self.fft_size];
+        let mut window_buffer_r: Vec<Complex<f32>> = vec![Complex::new(0.0, 0.0); self.fft_size];
+        for (i, pair) in self.pending.chunks_exact(2).enumerate() {
+            window_buffer_l[i] = Complex::new(pair[0] * self.coefficients[i], 0.0);
+            window_buffer_r[i] = Complex::new(pair[1] * self.coefficients[i], 0.0);
+        }
+
+        self.fft.process(&mut window_buffer_l);
+        self.fft.process(&mut window_buffer_r);
+
+        for i in 0..self.fft_size/2 {
+            self.spectogram.left.push(window_buffer_l[i].re.powi(2));
+            self.spectogram.right.push(window_buffer_r[i].re.powi(2));
+        }
+
+        // Keep the overlap for the next window
+        let consumed: usize = min(2*self.hop, self.pending.len());
+        self.pending.drain(..consumed);
+        self.unprocessed = 0;
+    }
+}
+
+
+// Audio export -----------------------------------------------------------------------------------------------------------
+// Reconstructs the difference between two tracks as audio and writes it to a 16-bit PCM .wav file.
+// Both tracks go through an STFT with a Hann window and 50% overlap; for every bin the magnitude difference
+// | |A| - |B| | is kept and the phase of `track_a` is re-applied before the inverse transform.
+// The output is as long as the shorter of the two tracks.
+pub fn export_difference_wav(fft_size_u32: u32, sample_rate: u32, track_a: &TrackBuffer, track_b: &TrackBuffer, path: &String) -> Result<(), SpecCompError> {
+    let fft_size: usize = fft_size_u32 as usize;
+    let hop: usize = fft_size / 2;
+    if fft_size < 2 || !fft_size.is_multiple_of(2) {
+        return Result::Err(SpecCompError::InvalidArgument(format!("export_difference_wav(): FFT size must be even and at least 2 (got {})", fft_size)));
+    }
+    check_cola(&periodic_hann(fft_size), hop)?;
+
+    // Only compare the samples both tracks have
+    let length: usize = min(track_a.len(), track_b.len()) / 2;
+    if length == 0 {
+        return Result::Err(SpecCompError::EmptyInput(String::from("export_difference_wav(): Nothing to export, one of the tracks is empty.")));
+    }
+
+    let mut output: Vec<f32> = vec![0.0; 2*length];
+    for channel in 0..2 {
+        // Split the channel out of the interleaved buffers
+        let a: Vec<f32> = track_a.iter().skip(channel).step_by(2).take(length).copied().collect();
+        let b: Vec<f32> = track_b.iter().skip(channel).step_by(2).take(length).copied().collect();
+
+        let frames_a = stft(fft_size, hop, &a)?;
+        let frames_b = stft(fft_size, hop, &b)?;
+
+        // Keep the magnitude difference, borrow the phase from `a`
+        let difference: Vec<Complex<f32>> = frames_a.iter().zip(frames_b.iter())
+            .map(|(bin_a, bin_b)| {
+                let magnitude = (bin_a.norm() - bin_b.norm()).abs();
+                match bin_a.norm() > 0.0 {
+                    true  => { bin_a / bin_a.norm() * magnitude }
+                    false => { Complex::new(magnitude, 0.0) }
+                }
+            })
+            .collect();
+
+        // Interleave the reconstructed channel back into the output
+        let reconstructed = istft(fft_size, hop, &difference, length)?;
+        for (i, s) in reconstructed.iter().enumerate() {
+            output[2*i + channel] = *s;
+        }
+    }
+
+    write_wav(path, sample_rate, 2, &output)
+}
+
+// Short-Time Fourier Transform of a single channel with a periodic Hann window and a custom hop.
+// The signal is zero-padded so that every sample is covered by two windows; frames are stored one
+// after the other, each with all `fft_size` complex bins. The hop has to be from 1 to `fft_size`.
+pub fn stft(fft_size: usize, hop: usize, samples: &[f32]) -> Result<Vec<Complex<f32>>, SpecCompError> {
+    check_hop("stft()", fft_size, hop)?;
+    if samples.is_empty() {
+        return Result::Err(SpecCompError::EmptyInput(String::from("stft(): Nothing to transform, the signal is empty")));
+    }
+    let window = periodic_hann(fft_size);
+
+    let mut fft_planner: FftPlanner<f32> = FftPlanner::new();
+    let fft = fft_planner.plan_fft_forward(fft_size);
+
+    // The first window starts `fft_size - hop` samples before the signal so its start is fully overlapped too
+    let offset: usize = fft_size - hop;
+    let frame_count: usize = (samples.len() + offset).div_ceil(hop);
+
+    let mut frames: Vec<Complex<f32>> = Vec::with_capacity(frame_count * fft_size);
+    let mut window_buffer: Vec<Complex<f32>> = Vec::with_capacity(fft_size);
+    for f in 0..frame_count {
+        for (i, w) in window.iter().enumerate() {
+            // Position of this sample in the original (unpadded) signal
+            let sample = match (f*hop + i).checked_sub(offset) {
+                Some(idx) if idx < samples.len() => { samples[idx] }
+                _ => { 0.0 }
+            };
+            window_buffer.push(Complex::new(sample * w, 0.0));
+        }
+
+        fft.process(&mut window_buffer);
+        frames.extend_from_slice(&window_buffer);
+        window_buffer.clear();
+    }
+
+    Result::Ok(frames)
+}
+
+// Makes sure `hop` is from 1 to `fft_size`, so that the frames of `stft()` overlap or touch
+fn check_hop(caller: &str, fft_size: usize, hop: usize) -> Result<(), SpecCompError> {
+    match hop > 0 && hop <= fft_size {
+        true  => { Result::Ok(()) }
+        false => { Result::Err(SpecCompError::InvalidArgument(format!("{}: The hop must be from 1 to the FFT size {} (got {})", caller, fft_size, hop))) }
+    }
+}
+
+// Inverse of `stft()`; Frames are transformed back and combined with a weighted overlap-add.
+// `length` is the number of samples of the original signal.
+pub fn istft(fft_size: usize, hop: usize, frames: &[Complex<f32>], length: usize) -> Result<Vec<f32>, SpecCompError> {
+    check_hop("istft()", fft_size, hop)?;
+    if frames.is_empty() || !frames.len().is_multiple_of(fft_size) {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("istft(): Expected whole frames of {} bins (got {} bins)", fft_size, frames.len())));
+    }
+    let window = periodic_hann(fft_size);
+
+    let mut fft_planner: FftPlanner<f32> = FftPlanner::new();
+    let ifft = fft_planner.plan_fft_inverse(fft_size);
+
+    let offset: usize = fft_size - hop;
+    let frame_count: usize = frames.len() / fft_size;
+
+    // Overlap-add buffers for the signal and the squared window (used for normalization)
+    let padded_length: usize = (frame_count - 1) * hop + fft_size;
+    let mut signal: Vec<f32> = vec![0.0; padded_length];
+    let mut window_sum: Vec<f32> = vec![0.0; padded_length];
+
+    let mut window_buffer: Vec<Complex<f32>> = Vec::with_capacity(fft_size);
+    for (f, frame) in frames.chunks_exact(fft_size).enumerate() {
+        window_buffer.extend_from_slice(frame);
+        ifft.process(&mut window_buffer);
+
+        // rustfft doesn't normalize, so divide by `fft_size` here
+        for (i, w) in window.iter().enumerate() {
+            signal[f*hop + i] += window_buffer[i].re / fft_size as f32 * w;
+            window_sum[f*hop + i] += w * w;
+        }
+        window_buffer.clear();
+    }
+
+    // Drop the padding and undo the window gain
+    Result::Ok(signal.iter().zip(window_sum.iter())
+        .skip(offset)
+        .take(length)
+        .map(|(s, w)| if *w > 1e-6 { s / w } else { 0.0 })
+        .collect())
+}
+
+// Largest relative deviation of `cola_factor()` from its mean that still counts as constant
+pub const COLA_TOLERANCE: f32 = 1e-3;
+
+// Overlap-add sum of copies of `window` that are `hop` samples apart, over one period of `hop` samples; Away from the
+// edges of the signal, sample n is weighted by the n % hop-th value. Empty if the hop is 0.
+pub fn cola_factor(window: &[f32], hop: usize) -> Vec<f32> {
+    if hop == 0 { return vec![]; }
+    (0..hop)
+        .map(|n| window.iter().skip(n).step_by(hop).sum())
+        .collect()
+}
+
+// Whether `window` with a hop of `hop` satisfies the constant-overlap-add constraint, i.e. whether `cola_factor()` stays
+// within `tolerance` (relative to its mean) of a constant. If it doesn't, a resynthesized signal is amplitude modulated.
+pub fn is_cola(window: &[f32], hop: usize, tolerance: f32) -> bool {
+    let factor: Vec<f32> = cola_factor(window, hop);
+    if factor.is_empty() { return false; }
+    let mean: f32 = factor.iter().sum::<f32>() / factor.len() as f32;
+    if mean <= 0.0 { return false; }
+    factor.iter().all(|f| (f - mean).abs() <= tolerance * mean)
+}
+
+// Fails if `window` and `hop` don't satisfy COLA within COLA_TOLERANCE; Tells by how much the overlap-add sum deviates
+pub fn check_cola(window: &[f32], hop: usize) -> Result<(), SpecCompError> {
+    if is_cola(window, hop, COLA_TOLERANCE) {
+        return Result::Ok(());
+    }
+    let factor: Vec<f32> = cola_factor(window, hop);
+    let min_factor: f32 = factor.iter().fold(f32::INFINITY, |m, f| m.min(*f));
+    let max_factor: f32 = factor.iter().fold(0.0, |m: f32, f| m.max(*f));
+    Result::Err(SpecCompError::InvalidArgument(format!("check_cola(): A window of {} samples with a hop of {} doesn't add up to a constant (between {} and {}); The reconstruction would be amplitude modulated",
+        window.len(), hop, min_factor, max_factor)))
+}
+
+// Periodic Hann window; Overlapping copies of it with a hop of `size/2` add up to a constant
+fn periodic_hann(size: usize) -> Vec<f32> {
+    (0..size)
+        .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / size as f32).cos())
+        .collect()
+}
+
+// Writes interleaved samples to a 16-bit PCM .wav file; samples outside [-1, 1] are clipped
+fn write_wav(path: &String, sample_rate: u32, channels: u16, samples: &[f32]) -> Result<(), SpecCompError> {
+    let data_size: u32 = (samples.len() * 2) as u32;
+    let block_align: u16 = channels * 2;
+
+    // RIFF header and format chunk
+    let mut write_buffer: Vec<u8> = Vec::with_capacity(44 + data_size as usize);
+    write_buffer.extend_from_slice(b"RIFF");
+    write_buffer.extend_from_slice(&(36 + data_size).to_le_bytes());
+    write_buffer.extend_from_slice(b"WAVE");
+    write_buffer.extend_from_slice(b"fmt ");
+    write_buffer.extend_from_slice(&16u32.to_le_bytes());
+    write_buffer.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    write_buffer.extend_from_slice(&channels.to_le_bytes());
+    write_buffer.extend_from_slice(&sample_rate.to_le_bytes());
+    write_buffer.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    write_buffer.extend_from_slice(&block_align.to_le_bytes());
+    write_buffer.extend_from_slice(&16u16.to_le_bytes());
+
+    // Data chunk
+    write_buffer.extend_from_slice(b"data");
+    write_buffer.extend_from_slice(&data_size.to_le_bytes());
+    for s in samples {
+        let pcm: i16 = (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        write_buffer.extend_from_slice(&pcm.to_le_bytes());
+    }
+
+    // Open file, write and close
+    let mut f = match File::create(path) {
+        Ok(f)  => { f }
+        Err(_) => { return Result::Err(SpecCompError::Io(format!("write_wav(): Could not create {}.", path))); }
+    };
+
+    match f.write_all(&write_buffer) {
+        Ok(_)  => { Result::Ok(()) }
+        Err(e) => { Result::Err(SpecCompError::Io(format!("write_wav(): I/O Error ({}).", e))) }
+    }
+}
+
+
+// Resolution matching ----------------------------------------------------------------------------------------------------
+// Resamples `spec` (with `bins` bins per frame) onto a grid of `target_bins` bins and `target_frames` frames by
+// linear interpolation along both axes. Frames are assumed not to overlap, so a frame of `bins` bins lasts 2*`bins`
+// samples, and values are scaled by `target_bins / bins` so the total power of a frame follows the FFT size.
+// Accuracy caveats: interpolation smooths narrow peaks, the scaling is exact for broadband content but not for pure
+// tones (whose peak power grows with the square of the FFT size), and the window shape still differs between sizes.
+pub fn resample_spectogram(spec: &StereoSpectogram, bins: u32, target_bins: u32, target_frames: usize) -> StereoSpectogram {
+    let (bins_us, target_bins_us) = (bins as usize, target_bins as usize);
+    if bins_us == 0 || target_bins_us == 0 || spec.left.len() < bins_us {
+        return StereoSpectogram::new(target_bins);
+    }
+    let frame_count: usize = spec.left.len() / bins_us;
+
+    // Target bins/frames per source bin/frame
+    let ratio: f32 = target_bins as f32 / bins as f32;
+
+    // Samples `channel` at a fractional (frame, bin) position
+    let sample = |channel: &Vec<f32>, frame: f32, bin: f32| -> f32 {
+        let frame = frame.clamp(0.0, (frame_count - 1) as f32);
+        let bin = bin.clamp(0.0, (bins_us - 1) as f32);
+        let (f0, b0) = (frame as usize, bin as usize);
+        let (f1, b1) = (min(f0 + 1, frame_count - 1), min(b0 + 1, bins_us - 1));
+        let (tf, tb) = (frame - f0 as f32, bin - b0 as f32);
+
+        let low  = channel[f0*bins_us + b0] * (1.0 - tb) + channel[f0*bins_us + b1] * tb;
+        let high = channel[f1*bins_us + b0] * (1.0 - tb) + channel[f1*bins_us + b1] * tb;
+        low * (1.0 - tf) + high * tf
+    };
+
+    let mut resampled = StereoSpectogram {
+        left:  Vec::with_capacity(target_frames * target_bins_us),
+        right: Vec::with_capacity(target_frames * target_bins_us),
+        bins:  target_bins
+    };
+    for f in 0..target_frames {
+        // Centers of the target frames/bins in source units
+        let frame: f32 = (f as f32 + 0.5) * ratio - 0.5;
+        for b in 0..target_bins_us {
+            let bin: f32 = b as f32 / ratio;
+            resampled.left.push(sample(&spec.left, frame, bin) * ratio);
+            resampled.right.push(sample(&spec.right, frame, bin) * ratio);
+        }
+    }
+
+    resampled
+}
+
+// Prepares `spec_b` for comparison against `spec_a` when they were computed with different FFT sizes.
+// If `interpolate` is set, `spec_b` is resampled onto the bins of `spec_a` and onto as many frames as cover the same
+// duration (capped at the length of `spec_a`); Otherwise a bin count mismatch is an error. Returns `spec_b` on the grid of `spec_a`.
+pub fn match_resolution(bins_a: u32, spec_a: &StereoSpectogram, bins_b: u32, spec_b: &StereoSpectogram, interpolate: bool) -> Result<StereoSpectogram, SpecCompError> {
+    if bins_a == bins_b {
+        return Result::Ok(StereoSpectogram { left: spec_b.left.clone(), right: spec_b.right.clone(), bins: bins_b });
+    }
+    if !interpolate {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("match_resolution(): The spectograms have different bin counts ({} and {}); enable interpolation to compare them", bins_a, bins_b)));
+    }
+    if bins_a == 0 || bins_b == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("match_resolution(): The number of bins must be greater than 0")));
+    }
+
+    // Same duration on the grid of `spec_a`, no longer than `spec_a` itself
+    let frames_a: usize = spec_a.left.len() / bins_a as usize;
+    let frames_b: usize = spec_b.left.len() / bins_b as usize;
+    let target_frames: usize = min(frames_a, (frames_b as f32 * bins_b as f32 / bins_a as f32).round() as usize);
+    Result::Ok(resample_spectogram(spec_b, bins_b, bins_a, target_frames))
+}
+
+
+// Mel scale --------------------------------------------------------------------------------------------------------------
+// Converts a frequency in Hz to Mels (HTK formula) and back
+pub fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+pub fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+// Builds a triangular Mel filterbank; Returns `n_mels` rows of `bins` weights each.
+// The filters' centers are evenly spaced on the Mel scale between 0 Hz and the Nyquist frequency and each
+// filter peaks at 1.0, so neighbouring filters add up to 1.0 on every bin between the first and last center.
+pub fn mel_filterbank(bins: u32, sample_rate: u32, n_mels: u32) -> Vec<Vec<f32>> {
+    let n_mels_us = n_mels as usize;
+
+    // Frequency of each bin; `bins` is half the FFT size
+    let bin_hz: Vec<f32> = bin_frequencies(bins, sample_rate, 2 * bins);
+
+    // `n_mels` + 2 edges: each filter spans from the previous center to the next one
+    let mel_max = hz_to_mel(sample_rate as f32 / 2.0);
+    let edges: Vec<f32> = (0..n_mels_us + 2)
+        .map(|m| mel_to_hz(mel_max * m as f32 / (n_mels_us + 1) as f32))
+        .collect();
+
+    let mut filterbank: Vec<Vec<f32>> = Vec::with_capacity(n_mels_us);
+    for m in 0..n_mels_us {
+        let (low, center, high) = (edges[m], edges[m+1], edges[m+2]);
+
+        let row: Vec<f32> = bin_hz.iter()
+            .map(|&f| {
+                if f <= low || f >= high { 0.0 }
+                else if f <= center { (f - low) / (center - low) }
+                else { (high - f) / (high - center) }
+            })
+            .collect();
+        filterbank.push(row);
+    }
+
+    filterbank
+}
+
+// Applies a Mel filterbank on every frame of `spec`; The returned spectogram has `n_mels` bins per frame
+// and can be passed to the comparison functions with `bins = n_mels`.
+pub fn to_mel(spec: &StereoSpectogram, bins: u32, sample_rate: u32, n_mels: u32) -> Result<StereoSpectogram, SpecCompError> {
+    spec.check_bins(bins, "to_mel()", "spec")?;
+    let bins_us = bins as usize;
+    if bins_us == 0 { return Result::Ok(StereoSpectogram::new(n_mels)); }
+    let filterbank = mel_filterbank(bins, sample_rate, n_mels);
+    let frame_count = spec.left.len() / bins_us;
+
+    let mut mel_spec = StereoSpectogram {
+        left:  Vec::with_capacity(frame_count * n_mels as usize),
+        right: Vec::with_capacity(frame_count * n_mels as usize),
+        bins:  n_mels
+    };
+
+    for (frame_l, frame_r) in spec.left.chunks_exact(bins_us).zip(spec.right.chunks_exact(bins_us)) {
+        for row in &filterbank {
+            mel_spec.left.push(row.iter().zip(frame_l).map(|(w, p)| w * p).sum());
+            mel_spec.right.push(row.iter().zip(frame_r).map(|(w, p)| w * p).sum());
+        }
+    }
+
+    Result::Ok(mel_spec)
+}
+
+
+// Coherence --------------------------------------------------------------------------------------------------------------
+// Magnitude-squared coherence between two complex spectograms: |S_ab|^2 / (S_aa * S_bb) for each bin, where the
+// (cross-)spectra are summed over all usable frames. Returns one value in [0, 1] per bin; the two channels are
+// computed independently and averaged. Bins where either spectogram is silent have a coherence of 0 (see `DEFAULT_EPSILON`).
+pub fn coherence_compare(bins: u32, spec_a: &ComplexSpectogram, spec_b: &ComplexSpectogram) -> Result<Vec<f32>, SpecCompError> {
+    let bins_us = bins as usize;
+
+    // Check the numbers add up
+    if bins_us == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("coherence_compare(): The number of bins must be greater than 0")));
+    }
+    spec_a.check_bins(bins, "coherence_compare()", "spec_a")?;
+    spec_b.check_bins(bins, "coherence_compare()", "spec_b")?;
+    if !spec_a.left.len().is_multiple_of(bins_us) || !spec_b.left.len().is_multiple_of(bins_us) {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("coherence_compare(): The number of bins ({}) doesn't match the size of the input vectors ({} and {})",
+            bins, spec_a.left.len(), spec_b.left.len())));
+    }
+
+    let usable_frames = min(spec_a.left.len(), spec_b.left.len()) / bins_us;
+    if usable_frames == 0 {
+        return Result::Err(SpecCompError::EmptyInput(String::from("coherence_compare(): At least one of the inputs is empty")));
+    }
+    if spec_a.left.len() != spec_b.left.len() {
+        statusln!("\nWarning: Inputs of coherence_compare have different sizes, only {} frames will be used.", usable_frames);
+    }
+
+    let mut coherence: Vec<f32> = vec![0.0; bins_us];
+    for (a, b) in [(&spec_a.left, &spec_b.left), (&spec_a.right, &spec_b.right)] {
+        // Accumulate the auto- and cross-spectra of each bin
+        let mut s_ab: Vec<Complex<f32>> = vec![Complex::new(0.0, 0.0); bins_us];
+        let mut s_aa: Vec<f32> = vec![0.0; bins_us];
+        let mut s_bb: Vec<f32> = vec![0.0; bins_us];
+
+        for (frame_a, frame_b) in a.chunks_exact(bins_us).zip(b.chunks_exact(bins_us)).take(usable_frames) {
+            for bin in 0..bins_us {
+                s_ab[bin] += frame_a[bin] * frame_b[bin].conj();
+                s_aa[bin] += frame_a[bin].norm_sqr();
+                s_bb[bin] += frame_b[bin].norm_sqr();
+            }
+        }
+
+        for bin in 0..bins_us {
+            coherence[bin] += safe_ratio(s_ab[bin].norm_sqr(), s_aa[bin] * s_bb[bin], DEFAULT_EPSILON).clamp(0.0, 1.0) / 2.0;
+        }
+    }
+
+    Result::Ok(coherence)
+}
+
+
+// Correlation ------------------------------------------------------------------------------------------------------------
+// Pearson correlation between the magnitudes of each bin of `reference` and `test` over all usable frames, i.e. how
+// well the rise and fall of every frequency is preserved regardless of its level. Returns one value in [-1, 1] per
+// bin; the two channels are computed independently and averaged. Bins that are constant in either spectogram (e.g.
+// silent) have no defined correlation and get 0.
+pub fn bin_correlation(bins: u32, reference: &StereoSpectogram, test: &StereoSpectogram) -> Result<Vec<f32>, SpecCompError> {
+    let bins_us = bins as usize;
+
+    // Check the numbers add up
+    if bins_us == 0 {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("bin_correlation(): The number of bins must be greater than 0")));
+    }
+    reference.check_bins(bins, "bin_correlation()", "reference")?;
+    test.check_bins(bins, "bin_correlation()", "test")?;
+    if !reference.left.len().is_multiple_of(bins_us) || !test.left.len().is_multiple_of(bins_us) {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("bin_correlation(): The number of bins ({}) doesn't match the size of the input vectors ({} and {})",
+            bins, reference.left.len(), test.left.len())));
+    }
+
+    let usable_frames = min(reference.left.len(), test.left.len()) / bins_us;
+    if usable_frames == 0 {
+        return Result::Err(SpecCompError::EmptyInput(String::from("bin_correlation(): At least one of the inputs is empty")));
+    }
+    if reference.left.len() != test.left.len() {
+        statusln!("\nWarning: Inputs of bin_correlation have different sizes, only {} frames will be used.", usable_frames);
+    }
+
+    let mut correlation: Vec<f32> = vec![0.0; bins_us];
+    for (a, b) in [(&reference.left, &test.left), (&reference.right, &test.right)] {
+        // Mean magnitude of each bin
+        let mut mean_a: Vec<f64> = vec![0.0; bins_us];
+        let mut mean_b: Vec<f64> = vec![0.0; bins_us];
+        for (frame_a, frame_b) in a.chunks_exact(bins_us).zip(b.chunks_exact(bins_us)).take(usable_frames) {
+            for bin in 0..bins_us {
+                mean_a[bin] += frame_a[bin].sqrt() as f64 / usable_frames as f64;
+                mean_b[bin] += frame_b[bin].sqrt() as f64 / usable_frames as f64;
+            }
+        }
+
+        // Covariance and variances of each bin
+        let mut cov: Vec<f64> = vec![0.0; bins_us];
+        let mut var_a: Vec<f64> = vec![0.0; bins_us];
+        let mut var_b: Vec<f64> = vec![0.0; bins_us];
+        for (frame_a, frame_b) in a.chunks_exact(bins_us).zip(b.chunks_exact(bins_us)).take(usable_frames) {
+            for bin in 0..bins_us {
+                let da: f64 = frame_a[bin].sqrt() as f64 - mean_a[bin];
+                let db: f64 = frame_b[bin].sqrt() as f64 - mean_b[bin];
+                cov[bin] += da * db;
+                var_a[bin] += da * da;
+                var_b[bin] += db * db;
+            }
+        }
+
+        for bin in 0..bins_us {
+            let spread: f64 = (var_a[bin] * var_b[bin]).sqrt();
+            if spread > DEFAULT_EPSILON as f64 {
+                correlation[bin] += ((cov[bin] / spread).clamp(-1.0, 1.0) / 2.0) as f32;
+            }
+        }
+    }
+
+    Result::Ok(correlation)
+}
+
+
+// Spectral flatness ------------------------------------------------------------------------------------------------------
+// Geometric mean over arithmetic mean of the power of every frame of `bins` bins, i.e. how noise-like the frame is:
+// close to 1.0 for white noise and close to 0.0 for a pure tone. `DEFAULT_EPSILON` is added to every bin so that an empty
+// bin doesn't zero the geometric mean; A silent frame is therefore perfectly flat (1.0). The two channels are computed
+// independently and averaged. Returns one value in [0, 1] per whole frame, nothing if `bins` is 0.
+pub fn spectral_flatness(spec: &StereoSpectogram, bins: u32) -> Vec<f32> {
+    let bins_us = bins as usize;
+    if bins_us == 0 {
+        return vec![];
+    }
+
+    let flatness = |frame: &[f32]| -> f64 {
+        let eps: f64 = DEFAULT_EPSILON as f64;
+        let log_mean: f64 = frame.iter().map(|p| (p.max(0.0) as f64 + eps).ln()).sum::<f64>() / bins_us as f64;
+        let mean: f64 = frame.iter().map(|p| p.max(0.0) as f64 + eps).sum::<f64>() / bins_us as f64;
+        (log_mean.exp() / mean).clamp(0.0, 1.0)
+    };
+
+    spec.left.chunks_exact(bins_us).zip(spec.right.chunks_exact(bins_us))
+        .map(|(l, r)| ((flatness(l) + flatness(r)) / 2.0) as f32)
+        .collect()
+}
+
+
+// Multi-resolution -------------------------------------------------------------------------------------------------------
+// Compares two tracks at several FFT sizes and combines the time errors into one score: the error at each size
+// weighted by `weights` (one per size) and summed, like the multi-resolution STFT losses used to train separation
+// models. Short windows resolve transients and long ones tonal content, so together they see both. The bins of
+// larger FFTs hold more power, so the weights also set how the sizes are scaled against each other; Weights that add
+// up to 1 give a weighted mean. Returns the mean time error at every size and the score.
+pub fn multi_resolution_compare(reference: &TrackBuffer, test: &TrackBuffer, fft_sizes: &[u32], weights: &[f32], window: WindowFunction) -> Result<(Vec<f32>, f32), SpecCompError> {
+    if fft_sizes.is_empty() {
+        return Result::Err(SpecCompError::InvalidArgument(String::from("multi_resolution_compare(): At least one FFT size is needed")));
+    }
+    if fft_sizes.len() != weights.len() {
+        return Result::Err(SpecCompError::DimensionMismatch(format!("multi_resolution_compare(): Got {} weights for {} FFT sizes", weights.len(), fft_sizes.len())));
+    }
+    if let Some(size) = fft_sizes.iter().find(|s| **s < 2 || !s.is_multiple_of(2)) {
+        return Result::Err(SpecCompError::InvalidArgument(format!("multi_resolution_compare(): The FFT sizes must be even (got {})", size)));
+    }
+
+    let mut errors: Vec<f32> = vec![];
+    for fft_size in fft_sizes {
+        let spec_ref: StereoSpectogram = track_to_spec(*fft_size, window, reference);
+        let spec_test: StereoSpectogram = track_to_spec(*fft_size, window, test);
+        errors.push(time_compare_spectogram(fft_size / 2, &spec_ref, &spec_test)?.1);
+    }
+    let score: f32 = errors.iter().zip(weights.iter()).map(|(e, w)| e * w).sum();
+
+    Result::Ok((errors, score))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Interleaved stereo track of `frames` samples per channel
+    fn stereo_ramp(frames: usize) -> TrackBuffer {
+        (0..2*frames).map(|i| ((i % 97) as f32 / 97.0) - 0.5).collect()
+    }
+
+    // Interleaved stereo white noise in [-0.5, 0.5); The same seed gives the same noise
+    fn stereo_noise(frames: usize, seed: u64) -> TrackBuffer {
+        let mut state: u64 = seed;
+        (0..2*frames).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+        }).collect()
+    }
+
+    #[test]
+    fn stft_frames_at_fft_size_boundaries() {
+        let fft_size: usize = 64;
+        let bins: usize = fft_size / 2;
+        for (length, frames) in [(4*fft_size, 4), (4*fft_size + 1, 5), (4*fft_size - 1, 4)] {
+            let track = stereo_ramp(length);
+
+            let spec = track_to_spec(fft_size as u32, WindowFunction::Hann, &track);
+            assert_eq!(spec.left.len(), frames * bins, "track_to_spec() with {} samples", length);
+            assert_eq!(spec.right.len(), frames * bins, "track_to_spec() with {} samples", length);
+
+            let spec = mt_track_to_spec(fft_size as u32, WindowFunction::Hann, vec![track], 1, false).remove(0);
+            assert_eq!(spec.left.len(), frames * bins, "mt_track_to_spec() with {} samples", length);
+            assert_eq!(spec.right.len(), frames * bins, "mt_track_to_spec() with {} samples", length);
+        }
+    }
+
+    #[test]
+    fn mel_filters_add_up_to_1() {
+        let (bins, sample_rate, n_mels) = (512u32, 16000u32, 40u32);
+        let filterbank = mel_filterbank(bins, sample_rate, n_mels);
+        assert_eq!(filterbank.len(), n_mels as usize);
+        assert!(filterbank.iter().all(|row| row.len() == bins as usize && row.iter().sum::<f32>() > 0.0));
+
+        // Between the centers of the first and the last filter, every bin is split between two neighbouring filters
+        let mel_max: f32 = hz_to_mel(sample_rate as f32 / 2.0);
+        let first_center: f32 = mel_to_hz(mel_max / (n_mels + 1) as f32);
+        let last_center: f32 = mel_to_hz(mel_max * n_mels as f32 / (n_mels + 1) as f32);
+        for (b, f) in bin_frequencies(bins, sample_rate, 2 * bins).iter().enumerate() {
+            if *f < first_center || *f > last_center { continue; }
+            let sum: f32 = filterbank.iter().map(|row| row[b]).sum();
+            assert!((sum - 1.0).abs() < 1e-4, "bin {} ({} Hz) sums to {}", b, f, sum);
+        }
+    }
+
+    #[test]
+    fn tone_lands_in_its_mel_band() {
+        let (fft_size, sample_rate, n_mels) = (1024u32, 16000u32, 40u32);
+        let frequency: f32 = 1000.0;
+        let track: TrackBuffer = (0..sample_rate as usize).flat_map(|i| {
+            let s: f32 = (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin();
+            [s, s]
+        }).collect();
+        let spec = track_to_spec(fft_size, WindowFunction::Hann, &track);
+        let mel = to_mel(&spec, fft_size / 2, sample_rate, n_mels).unwrap();
+        assert_eq!(mel.bins, n_mels);
+
+        let (average, _) = mel.average_spectrum(n_mels);
+        let loudest: usize = (0..average.len()).max_by(|a, b| average[*a].total_cmp(&average[*b])).unwrap();
+
+        // The tone lies within the loudest filter
+        let mel_max: f32 = hz_to_mel(sample_rate as f32 / 2.0);
+        let edge = |m: usize| mel_to_hz(mel_max * m as f32 / (n_mels + 1) as f32);
+        assert!(edge(loudest) < frequency && frequency < edge(loudest + 2), "band {} spans {}..{} Hz", loudest, edge(loudest), edge(loudest + 2));
+    }
+
+    #[test]
+    fn to_mel_checks_the_bins() {
+        let spec = track_to_spec(64, WindowFunction::Hann, &vec![0.5; 256]);
+        assert!(matches!(to_mel(&spec, 16, 8000, 8), Err(SpecCompError::DimensionMismatch(_))));
+    }
+
+    #[test]
+    fn silence_gives_finite_errors() {
+        let silent = track_to_spec(64, WindowFunction::Hann, &vec![0.0; 2 * 256]);
+        let other = track_to_spec(64, WindowFunction::Hann, &vec![0.0; 2 * 256]);
+
+        assert!(snr_compare(32, &silent, &other).unwrap().is_finite());
+        for scale in [ErrorScale::Absolute, ErrorScale::Relative, ErrorScale::Decibel { floor_db: DEFAULT_DB_FLOOR }] {
+            let (errors, mean) = time_compare_channels(32, &silent, &other, ChannelMode::Averaged, scale).unwrap().remove(0);
+            assert!(mean.is_finite() && errors.iter().all(|e| e.is_finite()), "{:?}", scale);
+            let (errors, mean) = freq_compare_channels(32, 8000, &silent, &other, ChannelMode::Averaged, FrequencyWeighting::A, scale).unwrap().remove(0);
+            assert!(mean.is_finite() && errors.iter().all(|e| e.is_finite()), "{:?}", scale);
+        }
+        assert!(panning_compare(32, &silent, &other).unwrap().1.is_finite());
+    }
+
+    #[test]
+    fn stft_last_frame_not_zero_on_exact_multiple() {
+        let fft_size: usize = 64;
+        let bins: usize = fft_size / 2;
+        let track: TrackBuffer = vec![1.0; 2 * 2*fft_size];
+
+        let spec = track_to_spec(fft_size as u32, WindowFunction::Hann, &track);
+        let last_frame: f32 = spec.left[bins..].iter().sum();
+        assert!(last_frame > 0.0);
+    }
+
+    #[test]
+    fn stft_round_trip_with_50_percent_overlap() {
+        let (fft_size, hop) = (256usize, 128usize);
+        let signal: Vec<f32> = (0..1000).map(|i| (2.0 * PI * 440.0 * i as f32 / 8000.0).sin() * 0.5 + ((i % 7) as f32 - 3.0) / 30.0).collect();
+
+        let frames = stft(fft_size, hop, &signal).unwrap();
+        assert_eq!(frames.len() % fft_size, 0);
+        let reconstructed = istft(fft_size, hop, &frames, signal.len()).unwrap();
+        assert_eq!(reconstructed.len(), signal.len());
+        let worst: f32 = signal.iter().zip(reconstructed.iter()).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+        assert!(worst < 1e-4, "largest reconstruction error is {}", worst);
+    }
+
+    #[test]
+    fn stft_rejects_bad_hops_and_empty_input() {
+        let signal: Vec<f32> = vec![0.5; 100];
+        assert!(matches!(stft(64, 0, &signal), Result::Err(SpecCompError::InvalidArgument(_))));
+        assert!(matches!(stft(64, 65, &signal), Result::Err(SpecCompError::InvalidArgument(_))));
+        assert!(matches!(stft(64, 64, &[]), Result::Err(SpecCompError::EmptyInput(_))));
+        assert!(stft(64, 64, &signal).is_ok());
+
+        let frames: Vec<Complex<f32>> = stft(64, 32, &signal).unwrap();
+        assert!(matches!(istft(64, 0, &frames, 100), Result::Err(SpecCompError::InvalidArgument(_))));
+        assert!(matches!(istft(64, 65, &frames, 100), Result::Err(SpecCompError::InvalidArgument(_))));
+        assert!(matches!(istft(64, 32, &[], 100), Result::Err(SpecCompError::DimensionMismatch(_))));
+        assert!(matches!(istft(64, 32, &frames[..63], 100), Result::Err(SpecCompError::DimensionMismatch(_))));
+    }
+
+    #[test]
+    fn worst_frame_and_its_timestamp() {
+        let errors: Vec<f32> = vec![0.1, 0.4, 0.2, 0.9, 0.3, 0.9, 0.0];
+        let (frame, error, timestamp) = worst_frame(&errors, 2048, 44100);
+        assert_eq!((frame, error), (3, 0.9));
+        assert!((timestamp - 3.0 * 2048.0 / 44100.0).abs() < 1e-6);
+
+        assert_eq!(worst_frame(&[], 2048, 44100), (0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn one_thread_gives_the_same_spectograms() {
+        let tracks: Vec<TrackBuffer> = (1..=4).map(|n| stereo_ramp(300 * n)).collect();
+        let serial: Vec<StereoSpectogram> = mt_track_to_spec(64, WindowFunction::Hann, tracks.clone(), 1, false);
+        let parallel: Vec<StereoSpectogram> = mt_track_to_spec(64, WindowFunction::Hann, tracks.clone(), 4, false);
+        for ((s, p), track) in serial.iter().zip(parallel.iter()).zip(tracks.iter()) {
+            let single: StereoSpectogram = track_to_spec(64, WindowFunction::Hann, track);
+            assert_eq!((&s.left, &s.right), (&p.left, &p.right));
+            assert_eq!((&s.left, &s.right), (&single.left, &single.right));
+        }
+    }
+
+    #[test]
+    fn magnitude_and_phase_rebuild_the_bins() {
+        let complex: ComplexSpectogram = track_to_complex_spec(64, WindowFunction::Hann, &stereo_ramp(200));
+        assert_eq!(complex.left.len(), 4 * 32);
+        let rebuilt: ComplexSpectogram = ComplexSpectogram::from_polar(&complex.magnitude(), &complex.phase());
+        assert_eq!(rebuilt.bins, complex.bins);
+        for (original, rebuilt) in [(&complex.left, &rebuilt.left), (&complex.right, &rebuilt.right)] {
+            assert_eq!(original.len(), rebuilt.len());
+            for (a, b) in original.iter().zip(rebuilt.iter()) {
+                assert!((a - b).norm() <= 1e-4 * a.norm().max(1.0), "{} rebuilt as {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn coherence_of_equal_and_unrelated_signals() {
+        let spec_a: ComplexSpectogram = track_to_complex_spec(64, WindowFunction::Hann, &stereo_noise(64 * 400, 1));
+        let same: ComplexSpectogram = track_to_complex_spec(64, WindowFunction::Hann, &stereo_noise(64 * 400, 1));
+        let other: ComplexSpectogram = track_to_complex_spec(64, WindowFunction::Hann, &stereo_noise(64 * 400, 2));
+
+        let coherence: Vec<f32> = coherence_compare(32, &spec_a, &same).unwrap();
+        assert_eq!(coherence.len(), 32);
+        assert!(coherence.iter().all(|c| (c - 1.0).abs() < 1e-4), "{:?}", coherence);
+
+        let coherence: Vec<f32> = coherence_compare(32, &spec_a, &other).unwrap();
+        assert!(coherence.iter().all(|c| (0.0..0.05).contains(c)), "{:?}", coherence);
+    }
+
+    #[test]
+    fn empty_inputs_are_errors() {
+        let empty: StereoSpectogram = StereoSpectogram::new(32);
+        let spec: StereoSpectogram = track_to_spec(64, WindowFunction::Hann, &stereo_ramp(256));
+
+        assert!(matches!(time_compare_spectogram(32, &empty, &spec), Err(SpecCompError::EmptyInput(_))));
+        assert!(matches!(time_compare_spectogram(32, &spec, &empty), Err(SpecCompError::EmptyInput(_))));
+        assert!(matches!(freq_compare_spectogram(32, &empty, &spec), Err(SpecCompError::EmptyInput(_))));
+        assert!(matches!(time_compare_spectogram(0, &spec, &spec), Err(SpecCompError::InvalidArgument(_))));
+        assert!(matches!(freq_compare_spectogram(0, &spec, &spec), Err(SpecCompError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn different_fft_sizes_compare_after_interpolation() {
+        let sample_rate: f32 = 16000.0;
+        let tone = |frequency: f32| -> TrackBuffer {
+            (0..2 * 16384).flat_map(|i| { let s: f32 = (2.0 * PI * frequency * i as f32 / sample_rate).cos(); [s, s] }).collect()
+        };
+        let mut spec_a: StereoSpectogram = track_to_spec(4096, WindowFunction::Hann, &tone(1000.0));
+        let spec_b: StereoSpectogram = track_to_spec(8192, WindowFunction::Hann, &tone(1000.0));
+        let spec_c: StereoSpectogram = track_to_spec(8192, WindowFunction::Hann, &tone(3000.0));
+        assert!(match_resolution(2048, &spec_a, 4096, &spec_b, false).is_err());
+
+        let mut matched_b: StereoSpectogram = match_resolution(2048, &spec_a, 4096, &spec_b, true).unwrap();
+        let mut matched_c: StereoSpectogram = match_resolution(2048, &spec_a, 4096, &spec_c, true).unwrap();
+        assert_eq!(matched_b.bins, 2048);
+        assert_eq!(matched_b.left.len(), spec_a.left.len());
+
+        // The tone stays in its bin
+        let loudest = |spec: &StereoSpectogram| -> usize {
+            let (average, _) = spec.average_spectrum(2048);
+            (0..average.len()).max_by(|a, b| average[*a].total_cmp(&average[*b])).unwrap()
+        };
+        assert_eq!(loudest(&matched_b), loudest(&spec_a));
+        assert_eq!(loudest(&spec_a), 256);
+
+        // A longer window puts more power in the tone's bin, so only the spectral balance is compared; The longer
+        // window's peak is also narrower, which leaves some error, but far less than for another tone
+        for spec in [&mut spec_a, &mut matched_b, &mut matched_c] { spec.normalize_frames(2048); }
+        let (_, same) = time_compare_spectogram(2048, &spec_a, &matched_b).unwrap();
+        let (_, other) = time_compare_spectogram(2048, &spec_a, &matched_c).unwrap();
+        assert!(same < other / 2.0, "same tone: {}, other tone: {}", same, other);
+    }
+
+    #[test]
+    fn streaming_in_odd_chunks_gives_the_same_spectogram() {
+        let track: TrackBuffer = stereo_ramp(1000);
+        let mut streaming: StreamingSpectogram = StreamingSpectogram::new(64, WindowFunction::Hann);
+        // Chunks that end in the middle of a L/R pair and of a window
+        for chunk in track.chunks(37) {
+            streaming.push(chunk);
+        }
+        let streamed: StereoSpectogram = streaming.finish();
+        let whole: StereoSpectogram = track_to_spec(64, WindowFunction::Hann, &track);
+        assert_eq!((streamed.left, streamed.right), (whole.left, whole.right));
+    }
+
+    #[test]
+    fn averaged_spectrum_peaks_at_the_tone() {
+        let (fft_size, sample_rate, frequency) = (1024u32, 16000.0f32, 1000.0f32);
+        let track: TrackBuffer = (0..16000).flat_map(|i| {
+            let s: f32 = (2.0 * PI * frequency * i as f32 / sample_rate).cos();
+            [s, 0.5 * s]
+        }).collect();
+        let spec: StereoSpectogram = track_to_spec(fft_size, WindowFunction::Hann, &track);
+
+        let (left, right) = spec.average_spectrum(fft_size / 2);
+        assert_eq!(left.len(), 512);
+        let loudest = |v: &Vec<f32>| (0..v.len()).max_by(|a, b| v[*a].total_cmp(&v[*b])).unwrap();
+        let tone_bin: usize = (frequency * fft_size as f32 / sample_rate) as usize;
+        assert_eq!((loudest(&left), loudest(&right)), (tone_bin, tone_bin));
+
+        let (errors, mean) = spectrum_compare(512, &spec, &spec).unwrap();
+        assert_eq!(errors.len(), 512);
+        assert_eq!(mean, 0.0);
+    }
+
+    #[test]
+    fn snr_is_taken_against_the_reference() {
+        let reference: StereoSpectogram = track_to_spec(64, WindowFunction::Hann, &stereo_ramp(512));
+        let half: TrackBuffer = stereo_ramp(512).iter().map(|s| s * 0.5).collect();
+        let estimate: StereoSpectogram = track_to_spec(64, WindowFunction::Hann, &half);
+
+        // The difference has a quarter of the reference's power, but as much as the estimate's
+        assert!((snr_compare(32, &reference, &estimate).unwrap() - 6.0206).abs() < 1e-2);
+        assert!(snr_compare(32, &estimate, &reference).unwrap().abs() < 1e-2);
+    }
+
+    #[test]
+    fn per_bin_std_of_the_error() {
+        // spec_b is off by 1.0 in bin 0 in every frame, and by 0.0 or 2.0 in turns in bin 1
+        let (bins, frames) = (4u32, 6usize);
+        let spec_a: StereoSpectogram = StereoSpectogram { left: vec![0.0; 4 * frames], right: vec![0.0; 4 * frames], bins };
+        let b: Vec<f32> = (0..frames).flat_map(|f| [1.0, 2.0 * (f % 2) as f32, 0.0, 0.0]).collect();
+        let spec_b: StereoSpectogram = StereoSpectogram { left: b.clone(), right: b, bins };
+        let w: Vec<f32> = frequency_weights(bins);
+
+        let (mean, std, _) = freq_compare_spectogram_std(bins, &spec_a, &spec_b).unwrap();
+        assert!((mean[0] - w[0]).abs() < 1e-6 && std[0].abs() < 1e-6);
+        assert!((mean[1] - w[1]).abs() < 1e-6 && (std[1] - w[1]).abs() < 1e-6);
+
+        // Against a two-pass reference on irregular data; The means are those of `freq_compare_spectogram()`
+        let spec_a: StereoSpectogram = track_to_spec(64, WindowFunction::Hann, &stereo_noise(64 * 50, 3));
+        let spec_b: StereoSpectogram = track_to_spec(64, WindowFunction::Hann, &stereo_noise(64 * 50, 4));
+        let (mean, std, total) = freq_compare_spectogram_std(32, &spec_a, &spec_b).unwrap();
+        let (reference_mean, reference_total) = freq_compare_spectogram(32, &spec_a, &spec_b).unwrap();
+        assert!((total - reference_total).abs() <= 1e-4 * reference_total);
+        let w: Vec<f32> = frequency_weights(32);
+        for bin in 0..32 {
+            let errors: Vec<f32> = (0..50).map(|f| {
+                let i: usize = f * 32 + bin;
+                ((spec_a.left[i] + spec_a.right[i]) / 2.0 - (spec_b.left[i] + spec_b.right[i]) / 2.0).abs() * w[bin]
+            }).collect();
+            let two_pass_mean: f32 = errors.iter().sum::<f32>() / 50.0;
+            let two_pass_std: f32 = (errors.iter().map(|e| (e - two_pass_mean).powi(2)).sum::<f32>() / 50.0).sqrt();
+            assert!((mean[bin] - reference_mean[bin]).abs() <= 1e-4 * reference_mean[bin].max(1e-6), "bin {}", bin);
+            assert!((std[bin] - two_pass_std).abs() <= 1e-3 * two_pass_std.max(1e-6), "bin {}: {} against {}", bin, std[bin], two_pass_std);
+        }
+    }
+
+    #[test]
+    fn tukey_between_rectangular_and_hann() {
+        assert!(WindowFunction::Tukey { alpha: 0.0 }.coefficients(64).iter().all(|c| *c == 1.0));
+
+        let tukey: Vec<f32> = WindowFunction::Tukey { alpha: 1.0 }.coefficients(64);
+        let hann: Vec<f32> = WindowFunction::HannSymmetric.coefficients(64);
+        for n in [0, 1, 7, 16, 31, 32, 50, 63] {
+            assert!((tukey[n] - hann[n]).abs() < 1e-5, "coefficient {}: {} against {}", n, tukey[n], hann[n]);
+        }
+
+        // Half the window is tapered, the middle is flat
+        let half: Vec<f32> = WindowFunction::Tukey { alpha: 0.5 }.coefficients(65);
+        assert_eq!((half[0], half[32], half[64]), (0.0, 1.0, 0.0));
+        assert!(half[16..=48].iter().all(|c| *c == 1.0) && half[1] < 1.0 && half[15] < 1.0);
+    }
+
+    #[test]
+    fn channel_modes_on_a_right_channel_difference() {
+        let spec_a: StereoSpectogram = StereoSpectogram { left: vec![1.0; 32], right: vec![1.0; 32], bins: 8 };
+        let spec_b: StereoSpectogram = StereoSpectogram { left: vec![1.0; 32], right: vec![1.5; 32], bins: 8 };
+
+        let independent = time_compare_channels(8, &spec_a, &spec_b, ChannelMode::Independent, ErrorScale::Absolute).unwrap();
+        assert_eq!(independent.len(), 2);
+        assert_eq!((independent[0].1, independent[1].1), (0.0, 0.5));
+        let averaged = time_compare_channels(8, &spec_a, &spec_b, ChannelMode::Averaged, ErrorScale::Absolute).unwrap();
+        assert_eq!(averaged.len(), 1);
+        assert_eq!(averaged[0].1, 0.25);
+        let max = time_compare_channels(8, &spec_a, &spec_b, ChannelMode::MaxOfChannels, ErrorScale::Absolute).unwrap();
+        assert_eq!(max[0].1, 0.5);
+
+        let independent = freq_compare_channels(8, 8000, &spec_a, &spec_b, ChannelMode::Independent, FrequencyWeighting::Cosine, ErrorScale::Absolute).unwrap();
+        let averaged = freq_compare_channels(8, 8000, &spec_a, &spec_b, ChannelMode::Averaged, FrequencyWeighting::Cosine, ErrorScale::Absolute).unwrap();
+        assert_eq!(independent[0].1, 0.0);
+        assert!(independent[1].1 > 0.0 && (averaged[0].1 - independent[1].1 / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn overall_progress_is_weighted_by_samples() {
+        // A finished short track barely moves the total; A finished long one almost completes it
+        assert_eq!(overall_progress(&[100, 0], &[1000, 9000]), 10);
+        assert_eq!(overall_progress(&[0, 100], &[1000, 9000]), 90);
+        assert_eq!(overall_progress(&[50, 50, 50], &[10, 20, 30]), 50);
+        // Out of range values are clamped and unsized jobs count as 1
+        assert_eq!(overall_progress(&[150, -20], &[0, 0]), 50);
+        assert_eq!(overall_progress(&[], &[]), 100);
+        assert_eq!(format!("({:>3}%)", overall_progress(&[12], &[1])), "( 12%)");
+    }
+
+    #[test]
+    fn a_weighting_at_1khz_and_100hz() {
+        assert!((a_weighting(1000.0) - 1.0).abs() < 1e-4);
+        // A-weighting is about -19.1 dB at 100Hz; The weights are power ratios
+        let db_100: f32 = 10.0 * a_weighting(100.0).log10();
+        assert!((db_100 + 19.1).abs() < 0.2, "{} dB", db_100);
+        assert!(a_weighting(20.0) < a_weighting(100.0));
+
+        let weights: Vec<f32> = FrequencyWeighting::A.weights(256, 8000).unwrap();
+        assert_eq!(weights.len(), 256);
+        assert!(weights[32] > weights[3]);
+        assert!(FrequencyWeighting::A.weights(256, 0).is_err());
+    }
+
+    #[test]
+    fn empty_and_short_buffers() {
+        let empty: TrackBuffer = TrackBuffer::new();
+        let spec: StereoSpectogram = track_to_spec(256, WindowFunction::Hann, &empty);
+        assert!(spec.left.is_empty() && spec.right.is_empty());
+
+        // 200 frames are short of one 256 sample frame; They are zero-padded to exactly one
+        let spec: StereoSpectogram = track_to_spec(256, WindowFunction::Hann, &stereo_ramp(200));
+        assert_eq!(spec.left.len(), spec.bins as usize);
+        assert_eq!(spec.right.len(), spec.bins as usize);
+        assert!(spec.left.iter().all(|v| v.is_finite()) && spec.left.iter().any(|v| *v > 0.0));
+
+        let specs: Vec<StereoSpectogram> = mt_track_to_spec(256, WindowFunction::Hann, vec![empty, stereo_ramp(200)], 2, false);
+        assert!(specs[0].left.is_empty());
+        assert_eq!(specs[1].left.len(), specs[1].bins as usize);
+    }
+
+    #[test]
+    fn panning_of_swapped_and_mono_channels() {
+        // Hard left against hard right is the largest possible error
+        let left_only: StereoSpectogram = StereoSpectogram { left: vec![2.0; 32], right: vec![0.0; 32], bins: 8 };
+        let swapped: StereoSpectogram = StereoSpectogram { left: left_only.right.clone(), right: left_only.left.clone(), bins: 8 };
+        let (errors, mean) = panning_compare(8, &left_only, &swapped).unwrap();
+        assert_eq!(errors.len(), 4);
+        assert!((mean - 1.0).abs() < 1e-6);
+
+        // Mono pairs stay centered whatever their level
+        let mono: StereoSpectogram = StereoSpectogram { left: vec![1.0; 32], right: vec![1.0; 32], bins: 8 };
+        let louder: StereoSpectogram = StereoSpectogram { left: vec![4.0; 32], right: vec![4.0; 32], bins: 8 };
+        let (_, mean) = panning_compare(8, &mono, &louder).unwrap();
+        assert!(mean.abs() < 1e-6);
+    }
+
+    #[test]
+    fn octave_bands_of_known_errors() {
+        // 8 bins 500Hz apart; The DC bin (9.0) is left out
+        let errors: [f32; 8] = [9.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        let bands: Vec<(f32, f32)> = aggregate_octave_bands(&errors, 8000, 16, 1);
+        assert_eq!(bands, vec![(500.0, 1.0), (1000.0, 2.0), (2000.0, 4.0), (4000.0, 6.5)]);
+
+        // Third-octaves give 1500 Hz and 2500 Hz bands of their own, while 3000 Hz and 3500 Hz still share one
+        let thirds: Vec<(f32, f32)> = aggregate_octave_bands(&errors, 8000, 16, 3);
+        let centers: Vec<f32> = thirds.iter().map(|(c, _)| c.round()).collect();
+        assert_eq!(centers, vec![500.0, 1000.0, 1587.0, 2000.0, 2520.0, 3175.0]);
+        assert_eq!(thirds[5].1, 6.5);
+        assert!(aggregate_octave_bands(&errors, 0, 16, 1).is_empty());
+    }
+
+    #[test]
+    fn split_channels_are_bit_identical_to_serial() {
+        let track: TrackBuffer = stereo_noise(5000, 7);
+        for window in [WindowFunction::Hann, WindowFunction::Hamming] {
+            let serial: StereoSpectogram = track_to_spec(1024, window, &track);
+            let split: StereoSpectogram = track_to_spec_split(1024, window, &track);
+            assert_eq!(split.bins, serial.bins);
+            assert_eq!((&split.left, &split.right), (&serial.left, &serial.right));
+        }
+    }
+
+    #[test]
+    fn zero_padding_doubles_the_bins_at_the_same_frequency() {
+        let tone: TrackBuffer = (0..8192).flat_map(|i| { let s: f32 = (2.0 * PI * 1000.0 * i as f32 / 8000.0).cos(); [s, s] }).collect();
+        let plain: StereoSpectogram = track_to_spec_padded(256, 1, WindowFunction::Hann, &tone);
+        let padded: StereoSpectogram = track_to_spec_padded(256, 2, WindowFunction::Hann, &tone);
+        assert_eq!(padded.bins, 2 * plain.bins);
+        // Frames still start every 256 samples
+        assert_eq!(padded.left.len() / padded.bins as usize, plain.left.len() / plain.bins as usize);
+
+        let peak_hz = |spec: &StereoSpectogram, fft_size: u32| -> f32 {
+            let (average, _) = spec.average_spectrum(spec.bins);
+            let loudest: usize = (0..average.len()).max_by(|a, b| average[*a].total_cmp(&average[*b])).unwrap();
+            bin_frequencies(spec.bins, 8000, fft_size)[loudest]
+        };
+        assert_eq!(peak_hz(&plain, 256), 1000.0);
+        assert_eq!(peak_hz(&padded, 512), 1000.0);
+    }
+
+    #[test]
+    fn error_stats_of_a_known_vector() {
+        // Shuffled 1..=10 and a NaN, which is ignored
+        let errors: [f32; 11] = [7.0, 3.0, 10.0, f32::NAN, 1.0, 5.0, 9.0, 2.0, 8.0, 4.0, 6.0];
+        let stats: ErrorStats = error_stats(&errors);
+        assert_eq!(stats.count, 10);
+        assert_eq!((stats.min, stats.max, stats.mean), (1.0, 10.0, 5.5));
+        // The median of an even count is the mean of the middle two; p90 lies at position 8.1 of the sorted errors
+        assert_eq!(stats.median, 5.5);
+        assert!((stats.p90 - 9.1).abs() < 1e-5, "{}", stats.p90);
+        assert!((stats.p99 - 9.91).abs() < 1e-5, "{}", stats.p99);
+
+        let odd: ErrorStats = error_stats(&[3.0, 1.0, 2.0]);
+        assert_eq!(odd.median, 2.0);
+        assert_eq!(error_stats(&[]).count, 0);
+        assert_eq!(error_stats(&[]).median, 0.0);
+    }
+
+    #[test]
+    fn cola_of_common_windows() {
+        let hann: Vec<f32> = WindowFunction::Hann.coefficients(1024);
+        assert!(is_cola(&hann, 512, COLA_TOLERANCE));
+        assert!(check_cola(&hann, 512).is_ok());
+        assert!(is_cola(&WindowFunction::Hamming.coefficients(1024), 512, COLA_TOLERANCE));
+
+        // A rectangular window overlapping by 90% with a hop that doesn't divide its length
+        let rectangular: Vec<f32> = vec![1.0; 1000];
+        assert!(!is_cola(&rectangular, 90, COLA_TOLERANCE));
+        assert!(matches!(check_cola(&rectangular, 90), Err(SpecCompError::InvalidArgument(_))));
+        assert!(cola_factor(&hann, 0).is_empty());
+    }
+
+    #[test]
+    fn tolerant_compare_forgives_a_one_frame_shift() {
+        let reference: StereoSpectogram = track_to_spec(256, WindowFunction::Hann, &stereo_noise(256 * 20, 3));
+        // The test starts one frame late
+        let bins: usize = reference.bins as usize;
+        let shifted: StereoSpectogram = StereoSpectogram { left: reference.left[bins..].to_vec(), right: reference.right[bins..].to_vec(), bins: reference.bins };
+
+        let (_, strict) = time_compare_spectogram(reference.bins, &reference, &shifted).unwrap();
+        let (errors, offsets, tolerant) = time_compare_tolerant(reference.bins, &reference, &shifted, 1).unwrap();
+        assert!(strict > 0.0);
+        assert!(tolerant < strict * 1e-6, "tolerant: {}, strict: {}", tolerant, strict);
+        assert_eq!(errors.len(), 19);
+        assert!(offsets.iter().all(|o| *o == 1), "{:?}", offsets);
+
+        // Without any tolerance it is the strict comparison
+        let (_, _, none) = time_compare_tolerant(reference.bins, &reference, &shifted, 0).unwrap();
+        assert!((none - strict).abs() <= strict * 1e-6);
+    }
+
+    #[test]
+    fn bad_samples_are_zeroed_and_counted() {
+        let mut samples: TrackBuffer = stereo_ramp(2048);
+        samples[10] = f32::NAN;
+        samples[777] = f32::INFINITY;
+        samples[2000] = f32::NEG_INFINITY;
+        samples[3001] = 1e-40;
+
+        let track: Track = Track::from_buffer(&samples, 8000);
+        assert_eq!(track.bad_samples, 4);
+        assert_eq!(track.samples.len(), samples.len());
+        assert_eq!((track.samples[10], track.samples[3001]), (0.0, 0.0));
+
+        let spec: StereoSpectogram = track_to_spec(512, WindowFunction::Hann, &track.samples);
+        assert!(spec.left.iter().chain(spec.right.iter()).all(|v| v.is_finite()));
+        // Unsanitized, the NaN spreads to its frame
+        assert!(track_to_spec(512, WindowFunction::Hann, &samples).left.iter().any(|v| v.is_nan()));
+    }
+
+    #[test]
+    fn histogram_of_known_errors() {
+        // Buckets [0, 1), [1, 2), [2, 3), [3, 4]; The largest error lands in the last bucket and the NaN nowhere
+        let errors: [f32; 8] = [0.1, 0.9, 1.5, 2.0, 2.5, 2.9, 4.0, f32::NAN];
+        let histogram: Vec<(f32, u32)> = error_histogram(&errors, 4, Option::Some((0.0, 4.0)));
+        assert_eq!(histogram, vec![(0.5, 2), (1.5, 1), (2.5, 3), (3.5, 1)]);
+        assert_eq!(histogram.iter().map(|(_, c)| c).sum::<u32>(), 7);
+
+        // Without a range the buckets go from the smallest to the largest error
+        let spread: Vec<(f32, u32)> = error_histogram(&errors, 3, Option::None);
+        assert!((spread[0].0 - 0.75).abs() < 1e-6 && (spread[2].0 - 3.35).abs() < 1e-6);
+        assert_eq!(spread.iter().map(|(_, c)| *c).collect::<Vec<u32>>(), vec![2, 3, 2]);
+
+        // Errors outside of a given range go to the edge buckets
+        let ranged: Vec<(f32, u32)> = error_histogram(&errors, 2, Option::Some((1.0, 3.0)));
+        assert_eq!(ranged, vec![(1.5, 3), (2.5, 4)]);
+        assert!(error_histogram(&[], 4, Option::None).is_empty());
+    }
+
+    #[test]
+    fn bin_correlation_of_a_scaled_copy_and_a_constant() {
+        // 3 bins over 50 frames; Bin 0 of the test is 4 times as powerful, bin 1 is constant and bin 2 inverted
+        let power: Vec<f32> = stereo_noise(75, 11).iter().map(|v| v + 0.5).collect();
+        let reference: StereoSpectogram = StereoSpectogram { left: power.clone(), right: power.clone(), bins: 3 };
+        let test_values: Vec<f32> = power.iter().enumerate()
+            .map(|(i, p)| match i % 3 { 0 => { 4.0 * p } 1 => { 0.25 } _ => { (1.0 - p.sqrt()).powi(2) } })
+            .collect();
+        let test: StereoSpectogram = StereoSpectogram { left: test_values.clone(), right: test_values, bins: 3 };
+
+        let correlation: Vec<f32> = bin_correlation(3, &reference, &test).unwrap();
+        assert!((correlation[0] - 1.0).abs() < 1e-5, "{:?}", correlation);
+        assert_eq!(correlation[1], 0.0);
+        assert!((correlation[2] + 1.0).abs() < 1e-5, "{:?}", correlation);
+        assert!(correlation.iter().all(|c| c.is_finite()));
+    }
+
+    #[test]
+    fn zero_extend_compares_every_frame() {
+        let frames = |count: usize| -> StereoSpectogram { StereoSpectogram { left: vec![1.0; 4 * count], right: vec![1.0; 4 * count], bins: 4 } };
+
+        // Truncating hides the 5 frames only the longer spectogram has
+        let (mut short, mut long) = (frames(10), frames(15));
+        match_frame_counts(4, &mut short, &mut long, FrameMismatch::Truncate);
+        let (errors, mean) = time_compare_spectogram(4, &short, &long).unwrap();
+        assert_eq!((errors.len(), mean), (10, 0.0));
+
+        match_frame_counts(4, &mut short, &mut long, FrameMismatch::ZeroExtend);
+        assert_eq!((short.left.len(), short.right.len()), (60, 60));
+        let (errors, mean) = time_compare_spectogram(4, &short, &long).unwrap();
+        assert_eq!(errors.len(), 15);
+        assert!(errors[..10].iter().all(|e| *e == 0.0) && errors[10..].iter().all(|e| *e > 0.0));
+        assert!(mean > 0.0);
+        // The longer spectogram is left as it was
+        assert!(long.left.iter().all(|v| *v == 1.0));
+    }
+
+    #[test]
+    fn multi_resolution_score_is_the_weighted_sum() {
+        let (reference, test) = (stereo_noise(8192, 1), stereo_noise(8192, 2));
+        let sizes: [u32; 3] = [256, 512, 1024];
+        let weights: [f32; 3] = [0.5, 0.3, 0.2];
+        let (errors, score) = multi_resolution_compare(&reference, &test, &sizes, &weights, WindowFunction::Hann).unwrap();
+
+        // Each error is the plain comparison at that size
+        for (size, error) in sizes.iter().zip(errors.iter()) {
+            let (a, b) = (track_to_spec(*size, WindowFunction::Hann, &reference), track_to_spec(*size, WindowFunction::Hann, &test));
+            assert_eq!(*error, time_compare_spectogram(size / 2, &a, &b).unwrap().1);
+        }
+        let expected: f32 = 0.5 * errors[0] + 0.3 * errors[1] + 0.2 * errors[2];
+        assert!((score - expected).abs() <= expected * 1e-6);
+
+        assert!(multi_resolution_compare(&reference, &test, &sizes, &weights[..2], WindowFunction::Hann).is_err());
+        assert!(multi_resolution_compare(&reference, &test, &[255], &[1.0], WindowFunction::Hann).is_err());
+    }
+
+    #[test]
+    fn odd_and_non_power_of_two_sizes() {
+        // 10000 samples per channel: 3 frames of 4095 and 4 frames of 3000
+        let samples: TrackBuffer = stereo_ramp(10000);
+        for (size, bins, frames) in [(4095u32, 2047usize, 3usize), (3000, 1500, 4)] {
+            let window: Vec<f32> = WindowFunction::Hann.coefficients(size as usize);
+            assert_eq!(window.len(), size as usize);
+            assert!(window.iter().all(|c| c.is_finite()));
+
+            let spec: StereoSpectogram = track_to_spec(size, WindowFunction::Hann, &samples);
+            assert_eq!(spec.bins as usize, bins);
+            assert_eq!(spec.left.len(), bins * frames);
+            assert_eq!(spec.right.len(), bins * frames);
+
+            let complex: ComplexSpectogram = track_to_complex_spec(size, WindowFunction::Hann, &samples);
+            assert_eq!(complex.bins as usize, bins);
+            assert_eq!(complex.left.len(), bins * frames);
+
+            let mut streaming: StreamingSpectogram = StreamingSpectogram::new(size, WindowFunction::Hann);
+            streaming.push(&samples);
+            let streamed: StereoSpectogram = streaming.finish();
+            assert_eq!(streamed.bins as usize, bins);
+            assert_eq!(streamed.left.len(), bins * frames);
+        }
+    }
+
+    #[test]
+    fn bin_frequencies_of_a_4096_fft() {
+        let frequencies: Vec<f32> = bin_frequencies(2048, 44100, 4096);
+        assert_eq!(frequencies.len(), 2048);
+        assert_eq!(frequencies[0], 0.0);
+        assert!((frequencies[1] - 44100.0 / 4096.0).abs() < 1e-3);
+        // The last bin is one bin width below Nyquist
+        assert!((frequencies[2047] - (22050.0 - 44100.0 / 4096.0)).abs() < 1e-2, "{}", frequencies[2047]);
+        assert!(frequencies.windows(2).all(|f| f[0] < f[1]));
+        assert!(bin_frequencies(2048, 44100, 0).is_empty());
+    }
+
+    #[test]
+    fn relative_error_of_a_louder_copy() {
+        let reference: Vec<f32> = (0..64).map(|i| 0.5 + (i % 7) as f32 * 20.0).collect();
+        let louder: Vec<f32> = reference.iter().map(|p| p * 1.1).collect();
+        let spec_a: StereoSpectogram = StereoSpectogram { left: reference.clone(), right: reference, bins: 8 };
+        let spec_b: StereoSpectogram = StereoSpectogram { left: louder.clone(), right: louder, bins: 8 };
+
+        for mode in [ChannelMode::Averaged, ChannelMode::Independent, ChannelMode::MaxOfChannels] {
+            for (frame_errors, error) in time_compare_channels(8, &spec_a, &spec_b, mode, ErrorScale::Relative).unwrap() {
+                assert!((error - 0.1).abs() < 1e-4, "{:?}: {}", mode, error);
+                assert!(frame_errors.iter().all(|e| (e - 0.1).abs() < 1e-4));
+            }
+        }
+
+        // With a reference of 1 the relative error is the absolute one, so each bin gets 0.1 times its weight
+        let ones: StereoSpectogram = StereoSpectogram { left: vec![1.0; 64], right: vec![1.0; 64], bins: 8 };
+        let tenth_up: StereoSpectogram = StereoSpectogram { left: vec![1.1; 64], right: vec![1.1; 64], bins: 8 };
+        let relative = freq_compare_channels(8, 8000, &ones, &tenth_up, ChannelMode::Averaged, FrequencyWeighting::Cosine, ErrorScale::Relative).unwrap();
+        let absolute = freq_compare_channels(8, 8000, &ones, &tenth_up, ChannelMode::Averaged, FrequencyWeighting::Cosine, ErrorScale::Absolute).unwrap();
+        assert!(relative[0].0.iter().zip(&absolute[0].0).all(|(r, a)| (r - a).abs() < 1e-5));
+
+        // A silent reference bin is capped
+        assert_eq!(relative_error(0.0, 1.0), MAX_RELATIVE_ERROR);
+    }
+
+    #[test]
+    fn lane_sum_matches_the_plain_sum() {
+        // Small integers add up exactly in any order, including the lengths around a whole number of lanes
+        for len in 0..=3*SUM_LANES + 1 {
+            let values: Vec<f32> = (0..len).map(|i| i as f32).collect();
+            assert_eq!(lane_sum(&values), values.iter().sum::<f32>(), "{}", len);
+        }
+        // Other values only differ in the last bits
+        let values: Vec<f32> = (0..4099).map(|i| ((i * 7919) % 1000) as f32 / 997.0).collect();
+        let plain: f32 = values.iter().sum();
+        assert!((lane_sum(&values) - plain).abs() <= plain * 2e-6, "{} {}", lane_sum(&values), plain);
+
+        // The frame errors of a comparison are the plain means of the bin errors
+        let spec_a: StereoSpectogram = StereoSpectogram { left: values[..4096].to_vec(), right: values[1..4097].to_vec(), bins: 1024 };
+        let spec_b: StereoSpectogram = StereoSpectogram { left: values[3..4099].to_vec(), right: values[2..4098].to_vec(), bins: 1024 };
+        let (frame_errors, _) = time_compare_spectogram(1024, &spec_a, &spec_b).unwrap();
+        for (f, error) in frame_errors.iter().enumerate() {
+            let frame = f*1024..(f + 1)*1024;
+            let expected: f32 = frame.map(|i| ((spec_a.left[i] + spec_a.right[i]) / 2.0 - (spec_b.left[i] + spec_b.right[i]) / 2.0).abs()).sum::<f32>() / 1024.0;
+            assert!((error - expected).abs() <= expected * 1e-5, "{} {}", error, expected);
+        }
+    }
+
+    #[test]
+    fn spectograms_of_different_fft_sizes_are_not_compared() {
+        // 8192 values are whole frames of both 1024 and 2048 bins, so only the stored bin count tells them apart
+        let samples: TrackBuffer = stereo_ramp(8192);
+        let (spec_2048, spec_1024) = (track_to_spec(4096, WindowFunction::Hann, &samples), track_to_spec(2048, WindowFunction::Hann, &samples));
+        assert_eq!(spec_2048.left.len(), spec_1024.left.len());
+
+        let message = |result: Result<(Vec<f32>, f32), SpecCompError>| -> String {
+            match result {
+                Result::Err(SpecCompError::DimensionMismatch(m)) => { m }
+                other => { panic!("{:?}", other.map(|_| ())); }
+            }
+        };
+        let time: String = message(time_compare_spectogram(1024, &spec_2048, &spec_1024));
+        assert!(time.contains("spec_a has 2048 bins per frame, expected 1024"), "{}", time);
+        let freq: String = message(freq_compare_spectogram(2048, &spec_2048, &spec_1024));
+        assert!(freq.contains("spec_b has 1024 bins per frame, expected 2048"), "{}", freq);
+
+        let mut joined: StereoSpectogram = StereoSpectogram::new(2048);
+        joined.append(&spec_2048, 2048).unwrap();
+        assert!(matches!(joined.append(&spec_1024, 2048), Result::Err(SpecCompError::DimensionMismatch(_))));
+        assert_eq!(joined.left.len(), spec_2048.left.len());
+    }
+
+    #[test]
+    fn flatness_of_a_flat_and_a_tonal_spectrum() {
+        let flat: StereoSpectogram = StereoSpectogram { left: vec![0.3; 64], right: vec![0.3; 64], bins: 32 };
+        assert!(spectral_flatness(&flat, 32).iter().all(|f| (f - 1.0).abs() < 1e-6));
+
+        // A tone at the center of bin 16 of a 64 sample FFT
+        let tone: TrackBuffer = (0..2*256).map(|i| (2.0 * PI * 16.0 * (i / 2) as f32 / 64.0).cos()).collect();
+        let tonal: Vec<f32> = spectral_flatness(&track_to_spec(64, WindowFunction::Hann, &tone), 32);
+        assert_eq!(tonal.len(), 4);
+        assert!(tonal.iter().all(|f| *f < 0.01), "{:?}", tonal);
+
+        // Silent frames count as flat, and there is nothing to measure without bins
+        let silence: StereoSpectogram = StereoSpectogram { left: vec![0.0; 32], right: vec![0.0; 32], bins: 32 };
+        assert_eq!(spectral_flatness(&silence, 32), vec![1.0]);
+        assert!(spectral_flatness(&flat, 0).is_empty());
+    }
+
+    #[test]
+    fn hann_coefficients_against_reference_values() {
+        // 0.5 * (1 - cos(2*pi*n/8)) and 0.5 * (1 - cos(2*pi*n/7)) for n in 0..8
+        let periodic: [f32; 8] = [0.0, 0.1464466, 0.5, 0.8535534, 1.0, 0.8535534, 0.5, 0.1464466];
+        let symmetric: [f32; 8] = [0.0, 0.1882551, 0.6112605, 0.9504844, 0.9504844, 0.6112605, 0.1882551, 0.0];
+        for (window, expected) in [(WindowFunction::Hann, periodic), (WindowFunction::HannSymmetric, symmetric)] {
+            let coefficients: Vec<f32> = window.coefficients(8);
+            assert!(coefficients.iter().zip(expected.iter()).all(|(c, e)| (c - e).abs() < 1e-6), "{:?}: {:?}", window, coefficients);
+        }
+        assert_eq!(WindowFunction::from_name("hann"), Option::Some(WindowFunction::Hann));
+        assert_eq!(WindowFunction::from_name("Hann:Periodic"), Option::Some(WindowFunction::Hann));
+
+        // The legacy window is the periodic one rotated by half a frame
+        let legacy: Vec<f32> = WindowFunction::HannLegacy.coefficients(8);
+        assert!((0..8).all(|n| (legacy[n] - periodic[(n + 4) % 8]).abs() < 1e-6), "{:?}", legacy);
+
+        assert_eq!(WindowFunction::Hann.coefficients(1), vec![1.0]);
+        assert_eq!(WindowFunction::HannSymmetric.coefficients(1), vec![1.0]);
+    }
+
+    #[test]
+    fn dtw_follows_a_shift_and_a_stretch() {
+        // Noise envelope; The test starts 5 frames later
+        let noise: Vec<f32> = stereo_noise(200, 7).iter().map(|s| s + 0.6).collect();
+        let reference: Vec<f32> = noise[..200].to_vec();
+        let shifted: Vec<f32> = [&noise[200..205], &noise[..200]].concat();
+        let path: WarpingPath = dtw_align(&reference, &shifted, 10).unwrap();
+        assert_eq!((path[0], path[path.len() - 1]), ((0, 0), (199, 204)));
+        assert!(path.windows(2).all(|p| p[1].0 - p[0].0 <= 1 && p[1].1 - p[0].1 <= 1 && p[1] != p[0]));
+        assert!((5..200).all(|i| path.contains(&(i, i + 5))));
+
+        // The test is 2% slower: its frame j holds the content of the reference at frame j / 1.02
+        let frame = |t: f32| -> Vec<f32> {
+            let level: f32 = 1.0 + 0.9 * (t / 7.0).sin() * (t / 23.0).cos();
+            let peak: f32 = 16.0 + 12.0 * (t / 31.0).sin();
+            (0..32).map(|b| level * (-((b as f32 - peak) / 3.0).powi(2)).exp()).collect()
+        };
+        let reference: Vec<f32> = (0..400).flat_map(|f| frame(f as f32)).collect();
+        let stretched: Vec<f32> = (0..408).flat_map(|f| frame(f as f32 / 1.02)).collect();
+        let spec_a: StereoSpectogram = StereoSpectogram { left: reference.clone(), right: reference, bins: 32 };
+        let spec_b: StereoSpectogram = StereoSpectogram { left: stretched.clone(), right: stretched, bins: 32 };
+
+        let (_, naive) = time_compare_spectogram(32, &spec_a, &spec_b).unwrap();
+        let (errors, _, warped) = time_compare_warped(32, &spec_a, &spec_b, 16).unwrap();
+        assert_eq!(errors.len(), 400);
+        assert!(warped * 10.0 < naive, "warped {} naive {}", warped, naive);
+    }
+
+    #[test]
+    fn fused_compare_equals_the_staged_comparison() {
+        // The test has one frame less than the reference
+        let (track_a, track_b) = (stereo_noise(64*10, 3), stereo_noise(64*9, 4));
+        let (spec_a, spec_b) = (track_to_spec(64, WindowFunction::Hann, &track_a), track_to_spec(64, WindowFunction::Hann, &track_b));
+        let weights: Vec<f32> = FrequencyWeighting::Cosine.weights(32, 8000).unwrap();
+        let close = |a: &[f32], b: &[f32]| -> bool { a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() <= 1e-5 * y.abs().max(1e-3)) };
+
+        for mode in [ChannelMode::Averaged, ChannelMode::Independent, ChannelMode::MaxOfChannels] {
+            for scale in [ErrorScale::Absolute, ErrorScale::Relative] {
+                let fused: Vec<FusedResult> = fused_compare(64, WindowFunction::Hann, &track_a, &track_b, mode, scale, &weights).unwrap();
+                let time = time_compare_channels(32, &spec_a, &spec_b, mode, scale).unwrap();
+                let freq = freq_compare_channels(32, 8000, &spec_a, &spec_b, mode, FrequencyWeighting::Cosine, scale).unwrap();
+                assert_eq!(fused.len(), time.len());
+                for (((fused_time, fused_freq), staged_time), staged_freq) in fused.iter().zip(&time).zip(&freq) {
+                    assert_eq!(fused_time.0.len(), 9);
+                    assert!(close(&fused_time.0, &staged_time.0) && close(&[fused_time.1], &[staged_time.1]), "{:?} {:?}", mode, scale);
+                    assert!(close(&fused_freq.0, &staged_freq.0) && close(&[fused_freq.1], &[staged_freq.1]), "{:?} {:?}", mode, scale);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn coverage_of_mismatched_frame_counts() {
+        let spec_a: StereoSpectogram = StereoSpectogram { left: vec![1.0; 22*4], right: vec![1.0; 22*4], bins: 4 };
+        let spec_b: StereoSpectogram = StereoSpectogram { left: vec![1.0; 18*4], right: vec![1.0; 18*4], bins: 4 };
+
+        let truncated: FrameCoverage = frame_coverage(4, &spec_a, &spec_b, FrameMismatch::Truncate).unwrap();
+        assert_eq!((truncated.usable_frames, truncated.spec_a_frames, truncated.spec_b_frames), (18, 22, 18));
+        assert!((truncated.coverage_a - 18.0 / 22.0).abs() < 1e-6 && truncated.coverage_b == 1.0);
+        // The comparison covers as many frames as reported
+        let (frame_errors, _) = time_compare_spectogram(4, &spec_a, &spec_b).unwrap();
+        assert_eq!(frame_errors.len(), truncated.usable_frames);
+
+        let extended: FrameCoverage = frame_coverage(4, &spec_a, &spec_b, FrameMismatch::ZeroExtend).unwrap();
+        assert_eq!((extended.usable_frames, extended.coverage_a, extended.coverage_b), (22, 1.0, 1.0));
+
+        let empty: FrameCoverage = frame_coverage(4, &StereoSpectogram::new(4), &spec_b, FrameMismatch::Truncate).unwrap();
+        assert_eq!((empty.usable_frames, empty.coverage_a, empty.coverage_b), (0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn a_6_db_level_difference_in_db_mode() {
+        let db: ErrorScale = ErrorScale::Decibel { floor_db: DEFAULT_DB_FLOOR };
+        let six_db: f32 = 10.0 * 4f32.log10();
+
+        // Twice the amplitude is four times the power
+        let (track_a, track_b) = (stereo_noise(64*20, 9), stereo_noise(64*20, 9).iter().map(|s| s * 2.0).collect::<TrackBuffer>());
+        let (spec_a, spec_b) = (track_to_spec(64, WindowFunction::Hann, &track_a), track_to_spec(64, WindowFunction::Hann, &track_b));
+        let time = time_compare_channels(32, &spec_a, &spec_b, ChannelMode::Averaged, db).unwrap();
+        assert!((time[0].1 - six_db).abs() < 0.1, "{}", time[0].1);
+
+        // Each bin of the frequency comparison gets 6 dB times its weight
+        let ones: StereoSpectogram = StereoSpectogram { left: vec![1.0; 64], right: vec![1.0; 64], bins: 32 };
+        let fours: StereoSpectogram = StereoSpectogram { left: vec![4.0; 64], right: vec![4.0; 64], bins: 32 };
+        let twos: StereoSpectogram = StereoSpectogram { left: vec![2.0; 64], right: vec![2.0; 64], bins: 32 };
+        let freq = freq_compare_channels(32, 8000, &ones, &fours, ChannelMode::Averaged, FrequencyWeighting::Cosine, db).unwrap();
+        let unit = freq_compare_channels(32, 8000, &ones, &twos, ChannelMode::Averaged, FrequencyWeighting::Cosine, ErrorScale::Absolute).unwrap();
+        assert!(freq[0].0.iter().zip(&unit[0].0).all(|(e, w)| (e - six_db * w).abs() < 1e-4));
+
+        // Silence against silence is no error, and power below the floor counts as the floor
+        let silence: StereoSpectogram = StereoSpectogram { left: vec![0.0; 64], right: vec![0.0; 64], bins: 32 };
+        let below_floor: StereoSpectogram = StereoSpectogram { left: vec![1e-6; 64], right: vec![1e-6; 64], bins: 32 };
+        assert_eq!(time_compare_channels(32, &silence, &below_floor, ChannelMode::Averaged, db).unwrap()[0].1, 0.0);
+    }
+
+    #[test]
+    fn custom_window_is_applied_as_a_product() {
+        // An asymmetric window no built-in one matches
+        let window: Vec<f32> = (0..32).map(|n| 0.2 + (n as f32 / 31.0).powi(2)).collect();
+        let samples: TrackBuffer = stereo_noise(32*3, 11);
+        let spec: StereoSpectogram = track_to_spec_with_window(32, &window, &samples).unwrap();
+
+        // The same as multiplying every frame by the window and leaving it as it is
+        let product: TrackBuffer = samples.iter().enumerate().map(|(i, s)| s * window[(i / 2) % 32]).collect();
+        let rectangular: StereoSpectogram = track_to_spec_with_window(32, &[1.0; 32], &product).unwrap();
+        assert_eq!((spec.left.clone(), spec.right.clone()), (rectangular.left, rectangular.right));
+
+        // And to a plain DFT of the product (the bins hold the squared real part)
+        for (frame, k) in [(0usize, 0usize), (1, 3), (2, 15)] {
+            let re: f32 = (0..32).map(|n| product[2*(32*frame + n)] * (2.0 * PI * (k * n) as f32 / 32.0).cos()).sum();
+            assert!((spec.left[16*frame + k] - re.powi(2)).abs() < 1e-4, "frame {} bin {}", frame, k);
+        }
+
+        // The coefficients of a built-in window give the same spectogram as the window itself
+        let hann: StereoSpectogram = track_to_spec_with_window(32, &WindowFunction::Hann.coefficients(32), &samples).unwrap();
+        assert_eq!(hann.left, track_to_spec(32, WindowFunction::Hann, &samples).left);
+
+        assert!(matches!(track_to_spec_with_window(64, &window, &samples), Result::Err(SpecCompError::DimensionMismatch(_))));
+        let mut broken: Vec<f32> = window.clone();
+        broken[5] = f32::NAN;
+        assert!(matches!(track_to_spec_with_window(32, &broken, &samples), Result::Err(SpecCompError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn non_finite_frames_are_left_out_of_the_mean() {
+        // Frame 1 of the test is corrupt
+        let spec_a: StereoSpectogram = StereoSpectogram { left: vec![1.0; 16], right: vec![1.0; 16], bins: 4 };
+        let mut test: Vec<f32> = vec![1.5; 16];
+        test[5] = f32::NAN;
+        let spec_b: StereoSpectogram = StereoSpectogram { left: test.clone(), right: test, bins: 4 };
+
+        let (frame_errors, mean) = time_compare_spectogram(4, &spec_a, &spec_b).unwrap();
+        assert!(frame_errors[1].is_nan());
+        assert_eq!(mean, 0.5);
+        let (bin_errors, mean) = freq_compare_channels(4, 8000, &spec_a, &spec_b, ChannelMode::Averaged, FrequencyWeighting::Cosine, ErrorScale::Absolute).unwrap().remove(0);
+        assert!(bin_errors[1].is_nan() && mean > 0.0);
+        let finite: Vec<f32> = [bin_errors[0], bin_errors[2], bin_errors[3]].to_vec();
+        assert!((mean - finite.iter().sum::<f32>() / 3.0).abs() < 1e-6);
+
+        assert_eq!(finite_mean(&[1.0, f32::INFINITY, 3.0]), (2.0, 1));
+        assert_eq!(finite_mean(&[f32::NAN]), (0.0, 1));
+        assert_eq!(finite_mean(&[]), (0.0, 0));
+    }
+}
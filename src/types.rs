@@ -1,12 +1,186 @@
+use rustfft::num_complex::Complex;
+use crate::error::SpecCompError;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::cmp::min;
+
 #[derive(Debug)]
 pub struct StereoSpectogram {
     pub left: Vec<f32>,
-    pub right: Vec<f32>
+    pub right: Vec<f32>,
+    // Bins per frame, i.e. half the FFT size the spectogram was calculated with (or the number of bands after
+    // `to_mel()`); The comparisons refuse a spectogram whose bin count isn't the one they are asked to use
+    pub bins: u32
 }
 
 impl StereoSpectogram {
-    pub fn new() -> StereoSpectogram {
-        StereoSpectogram { left:vec![], right:vec![] }
+    pub fn new(bins: u32) -> StereoSpectogram {
+        StereoSpectogram { left:vec![], right:vec![], bins }
+    }
+
+    // Makes sure the frames have `bins` bins; `caller` (e.g. "time_compare_spectogram()") and `name` (e.g. "spec_a")
+    // tell the spectogram apart in the error
+    pub fn check_bins(&self, bins: u32, caller: &str, name: &str) -> Result<(), SpecCompError> {
+        check_bin_count(self.bins, bins, caller, name)
+    }
+
+    // Time-averaged power spectrum (Welch-style); Returns the mean of each of the `bins` bins over all frames,
+    // for the left and the right channel. Both vectors are empty if there isn't a single whole frame.
+    pub fn average_spectrum(&self, bins: u32) -> (Vec<f32>, Vec<f32>) {
+        let bins_us = bins as usize;
+        if bins_us == 0 || self.left.len() < bins_us {
+            return (vec![], vec![]);
+        }
+
+        let average = |channel: &Vec<f32>| -> Vec<f32> {
+            let mut sums: Vec<f32> = vec![0.0; bins_us];
+            let frames = channel.chunks_exact(bins_us);
+            let frame_count = frames.len();
+            for frame in frames {
+                for (sum, bin) in sums.iter_mut().zip(frame.iter()) { *sum += bin; }
+            }
+            sums.iter().map(|sum| sum / frame_count as f32).collect()
+        };
+
+        (average(&self.left), average(&self.right))
+    }
+
+    // Appends the frames of `other` after the frames of `self`, e.g. to compare a whole playlist as one spectogram;
+    // Both spectograms must be made of whole frames of `bins` bins, with as many values in each channel.
+    pub fn append(&mut self, other: &StereoSpectogram, bins: u32) -> Result<(), SpecCompError> {
+        let bins_us = bins as usize;
+        if bins_us == 0 {
+            return Result::Err(SpecCompError::InvalidArgument(String::from("StereoSpectogram::append(): The number of bins must be greater than 0")));
+        }
+        for (name, spec) in [("self", &*self), ("other", other)] {
+            spec.check_bins(bins, "StereoSpectogram::append()", name)?;
+            if spec.left.len() != spec.right.len() || !spec.left.len().is_multiple_of(bins_us) {
+                return Result::Err(SpecCompError::DimensionMismatch(format!("StereoSpectogram::append(): The channels of {} ({} and {} values) aren't made of whole {} bin frames",
+                    name, spec.left.len(), spec.right.len(), bins)));
+            }
+        }
+
+        self.left.extend_from_slice(&other.left);
+        self.right.extend_from_slice(&other.right);
+        Result::Ok(())
+    }
+
+    // Total power of every frame of `bins` bins, averaged over the two channels; One value per whole frame, so it
+    // lines up with the per-frame errors of `time_compare_spectogram()`. Silent frames have an energy of 0.0.
+    pub fn frame_energies(&self, bins: u32) -> Vec<f32> {
+        if bins == 0 {
+            return vec![];
+        }
+
+        self.left.chunks_exact(bins as usize).zip(self.right.chunks_exact(bins as usize))
+            .map(|(l, r)| (l.iter().sum::<f32>() + r.iter().sum::<f32>()) / 2.0)
+            .collect()
+    }
+
+    // Appends silent frames (zeros) until the spectogram has `frames` frames of `bins` bins; Longer spectograms are
+    // left as they are.
+    pub fn zero_extend(&mut self, bins: u32, frames: usize) {
+        let length: usize = frames * bins as usize;
+        if self.left.len() < length { self.left.resize(length, 0.0); }
+        if self.right.len() < length { self.right.resize(length, 0.0); }
+    }
+
+    // Divides every bin by the total power of its frame (each channel on its own), so that every frame sums to 1.0 and
+    // only the spectral balance is left of it, not its loudness; Silent frames (total below epsilon) stay silent.
+    pub fn normalize_frames(&mut self, bins: u32) {
+        if bins == 0 {
+            return;
+        }
+
+        for channel in [&mut self.left, &mut self.right] {
+            for frame in channel.chunks_exact_mut(bins as usize) {
+                let total: f32 = frame.iter().sum();
+                if total < DEFAULT_EPSILON { continue; }
+                for bin in frame.iter_mut() { *bin /= total; }
+            }
+        }
+    }
+
+    // Lowers the frequency resolution by summing every `group_size` adjacent bins of each frame (the bins hold power,
+    // which adds up); The result has `bins.div_ceil(group_size)` bins per frame, the last of which holds the remaining
+    // bins if `group_size` doesn't divide `bins`. A `group_size` of 0 or 1 keeps the bins as they are, and an
+    // incomplete trailing frame is dropped.
+    pub fn group_bins(&self, bins: u32, group_size: u32) -> StereoSpectogram {
+        let (bins_us, group_us) = (bins as usize, group_size.max(1) as usize);
+        if bins_us == 0 {
+            return StereoSpectogram::new(0);
+        }
+
+        let group = |channel: &Vec<f32>| -> Vec<f32> {
+            channel.chunks_exact(bins_us)
+                .flat_map(|frame| frame.chunks(group_us).map(|g| g.iter().sum::<f32>()))
+                .collect()
+        };
+
+        StereoSpectogram { left: group(&self.left), right: group(&self.right), bins: bins.div_ceil(group_size.max(1)) }
+    }
+}
+
+fn check_bin_count(found: u32, bins: u32, caller: &str, name: &str) -> Result<(), SpecCompError> {
+    match found == bins {
+        true  => { Result::Ok(()) }
+        false => { Result::Err(SpecCompError::DimensionMismatch(format!("{}: {} has {} bins per frame, expected {} (was it calculated with a different FFT size?)",
+            caller, name, found, bins))) }
+    }
+}
+
+// Joins spectograms of `bins` bins one after the other (see `StereoSpectogram::append()`)
+pub fn concat_spectograms(spectograms: Vec<StereoSpectogram>, bins: u32) -> Result<StereoSpectogram, SpecCompError> {
+    spectograms.iter().try_fold(StereoSpectogram::new(bins), |mut joined, spec| {
+        joined.append(spec, bins)?;
+        Result::Ok(joined)
+    })
+}
+
+// Spectogram that keeps the complex value of every bin, so both magnitude and phase are available.
+// Bins are laid out like in StereoSpectogram: one frame after the other.
+#[derive(Debug, Default)]
+pub struct ComplexSpectogram {
+    pub left: Vec<Complex<f32>>,
+    pub right: Vec<Complex<f32>>,
+    // Bins per frame, like `StereoSpectogram::bins`
+    pub bins: u32
+}
+
+impl ComplexSpectogram {
+    pub fn new(bins: u32) -> ComplexSpectogram {
+        ComplexSpectogram { left:vec![], right:vec![], bins }
+    }
+
+    // See `StereoSpectogram::check_bins()`
+    pub fn check_bins(&self, bins: u32, caller: &str, name: &str) -> Result<(), SpecCompError> {
+        check_bin_count(self.bins, bins, caller, name)
+    }
+
+    // Magnitude |X| of every bin
+    pub fn magnitude(&self) -> StereoSpectogram {
+        StereoSpectogram {
+            left:  self.left.iter().map(|c| c.norm()).collect(),
+            right: self.right.iter().map(|c| c.norm()).collect(),
+            bins: self.bins
+        }
+    }
+
+    // Phase atan2(im, re) of every bin, in radians
+    pub fn phase(&self) -> StereoSpectogram {
+        StereoSpectogram {
+            left:  self.left.iter().map(|c| c.im.atan2(c.re)).collect(),
+            right: self.right.iter().map(|c| c.im.atan2(c.re)).collect(),
+            bins: self.bins
+        }
+    }
+
+    // Rebuilds the complex bins from the output of `magnitude()` and `phase()`
+    pub fn from_polar(magnitude: &StereoSpectogram, phase: &StereoSpectogram) -> ComplexSpectogram {
+        ComplexSpectogram {
+            left:  magnitude.left.iter().zip(phase.left.iter()).map(|(m, p)| Complex::from_polar(*m, *p)).collect(),
+            right: magnitude.right.iter().zip(phase.right.iter()).map(|(m, p)| Complex::from_polar(*m, *p)).collect(),
+            bins: magnitude.bins
+        }
     }
 }
 
@@ -22,7 +196,7 @@ impl GraphData {
     pub fn new(v: Vec<f32>, label: String) -> GraphData {
         GraphData {
             data: v,
-            label: label,
+            label,
             max:    Option::None,
 
             // For the iterator
@@ -30,36 +204,59 @@ impl GraphData {
         }
     }
 
+    // Cached variant of `max_value()`
     pub fn get_max(&mut self) -> f32 {
         match self.max {
             Option::Some(m) => m,
 
             // The max value has not been found yet
             Option::None => {
-                let mut self_it = self.data.iter();
-
-                let mut temp_max: f32 = 0.0;
-                loop {
-                    match self_it.next () {
-                        Option::Some(f) => { temp_max = if *f >temp_max {*f} else {temp_max}; }
-                        Option::None => { break; }
-                    }
-                }
-
+                let temp_max: f32 = self.max_value();
                 self.max = Option::Some(temp_max);
                 temp_max
             }
         }
     }
 
+    // Largest value in the data, found on every call; Never less than 0.0, so empty data yields 0.0
+    // (Not called `max()` since that would be shadowed by `Iterator::max()`.)
+    pub fn max_value(&self) -> f32 {
+        match self.max {
+            Option::Some(m) => m,
+            Option::None => { self.data.iter().fold(0.0, |m, f| if *f > m {*f} else {m}) }
+        }
+    }
+
+    // Smallest value in the data; Never more than 0.0, so empty data yields 0.0
+    pub fn min_value(&self) -> f32 {
+        self.data.iter().fold(0.0, |m, f| if *f < m {*f} else {m})
+    }
+
     pub fn data_len(&self) -> usize {
         self.data.len()
     }
 
+    // Empty data can't be plotted; callers should check this first
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
     pub fn get_label(&self) -> &String {
         &self.label
     }
 
+    // Centered moving average over `width` values (rounded up to the next odd number); Near the edges only the values
+    // that exist are averaged, so the length is kept. The data of `self` is left untouched.
+    pub fn smoothed(&self, width: usize) -> GraphData {
+        let half: usize = width / 2;
+        let len: usize = self.data.len();
+        let smoothed_data: Vec<f32> = (0..len).map(|i| {
+            let window: &[f32] = &self.data[i.saturating_sub(half)..min(i + half + 1, len)];
+            window.iter().sum::<f32>() / window.len() as f32
+        }).collect();
+        GraphData::new(smoothed_data, self.label.clone())
+    }
+
 }
 
 impl Iterator for GraphData {
@@ -78,4 +275,289 @@ impl Iterator for GraphData {
 }
 
 // Type for storing tracks with the channels interleaved (e.g. [L0, R0, L1, R1, ...])
-pub type TrackBuffer = Vec<f32>;
\ No newline at end of file
+pub type TrackBuffer = Vec<f32>;
+
+// A decoded track along with the sample rate it was decoded at
+#[derive(Debug, Default)]
+pub struct Track {
+    pub samples:     TrackBuffer,
+    pub sample_rate: u32,
+    // Channels the file was decoded with; The importers turn mono tracks into stereo ones (see `upmix_mono()`), so
+    // every track that reaches the comparison has 2
+    pub channels:    usize,
+    // Short name of the codec the track was decoded from ("mp3", "flac", "pcm_s16le"...); Empty if unknown
+    pub codec:       String,
+    // Encoder delay (per channel) found in the file's metadata; It has already been trimmed from `samples`.
+    // None if the file doesn't tell, in which case the samples may still start with the encoder's priming samples.
+    pub encoder_delay: Option<u32>,
+    // Decoded samples that were NaN, infinite or subnormal and have been replaced with 0.0; Anything but 0 hints at a
+    // corrupt file
+    pub bad_samples: usize,
+    // Packets that failed to decode and were replaced by silence of the same length
+    pub skipped_packets: usize
+}
+
+impl Track {
+    pub fn new() -> Track {
+        Track { samples: vec![], sample_rate: 0, channels: 0, codec: String::new(), encoder_delay: Option::None, bad_samples: 0, skipped_packets: 0 }
+    }
+
+    // Track of interleaved stereo samples that are already in memory, e.g. from another decoder; Samples that aren't
+    // normal floats are replaced like when decoding (see `extend_sanitized()`)
+    pub fn from_buffer(samples: &[f32], sample_rate: u32) -> Track {
+        let mut track: Track = Track::new();
+        track.extend_sanitized(samples);
+        track.sample_rate = sample_rate;
+        track.channels = 2;
+        track
+    }
+
+    // Turns a mono track into a stereo one with the same samples in both channels
+    pub fn upmix_mono(&mut self) {
+        self.samples = self.samples.iter().flat_map(|s| [*s, *s]).collect();
+        self.channels = 2;
+    }
+
+    // Appends decoded samples; Samples that aren't normal floats (or 0.0) are replaced with 0.0 and counted in
+    // `bad_samples`, since a single NaN would spread to every bin of its frames
+    pub fn extend_sanitized(&mut self, chunk: &[f32]) {
+        self.samples.reserve(chunk.len());
+        for s in chunk {
+            match s.is_normal() || *s == 0.0 {
+                true  => { self.samples.push(*s); }
+                false => { self.samples.push(0.0); self.bad_samples += 1; }
+            }
+        }
+    }
+
+    // Removes the first `count` samples of each channel; Used to skip a known encoder delay that isn't in the metadata
+    pub fn skip_samples(&mut self, count: usize) {
+        let count: usize = min(2*count, self.samples.len());
+        self.samples.drain(..count);
+    }
+
+    // Duration of the (stereo) track in seconds
+    pub fn duration(&self) -> f32 {
+        match self.sample_rate {
+            0 => { 0.0 }
+            _ => { (self.samples.len() / 2) as f32 / self.sample_rate as f32 }
+        }
+    }
+
+    // Keeps only the samples between `start_sec` and `end_sec`; Both channels are kept interleaved.
+    pub fn trim(&mut self, start_sec: f32, end_sec: f32) -> Result<(), SpecCompError> {
+        if start_sec < 0.0 || end_sec <= start_sec {
+            return Result::Err(SpecCompError::InvalidArgument(format!("Track::trim(): Invalid range {}s - {}s", start_sec, end_sec)));
+        }
+        if end_sec > self.duration() {
+            return Result::Err(SpecCompError::InvalidArgument(format!("Track::trim(): Range {}s - {}s exceeds the track's duration ({:.2}s)", start_sec, end_sec, self.duration())));
+        }
+
+        // Convert to sample indices; every sample is a L/R pair
+        let start: usize = (start_sec * self.sample_rate as f32) as usize;
+        let end: usize = (end_sec * self.sample_rate as f32) as usize;
+
+        self.samples.truncate(2*end);
+        self.samples.drain(..2*start);
+        Result::Ok(())
+    }
+}
+
+// Numerical safety -------------------------------------------------------------------------------------------------------
+// Small value added wherever a power sum is divided by or a log is taken, so silent frames don't produce NaN/Inf;
+// `safe_ratio()` and `power_to_db()` take it as an argument, the rest of the library uses this default.
+pub const DEFAULT_EPSILON: f32 = 1e-10;
+
+// `numerator / denominator`, with the denominator pushed away from 0 (keeping its sign)
+pub fn safe_ratio(numerator: f32, denominator: f32, epsilon: f32) -> f32 {
+    match denominator < 0.0 {
+        true  => { numerator / (denominator - epsilon) }
+        false => { numerator / (denominator + epsilon) }
+    }
+}
+
+// Power ratio in dB; Both sides are offset by `epsilon` so silence on either side stays finite
+pub fn power_to_db(power: f32, reference: f32, epsilon: f32) -> f32 {
+    10.0 * ((power.max(0.0) + epsilon) / (reference.max(0.0) + epsilon)).log10()
+}
+
+// Level below which audio counts as silent, in dBFS; Every silence-related option uses this unless told otherwise
+pub const DEFAULT_SILENCE_DBFS: f32 = -60.0;
+
+// Linear amplitude of a level in dBFS, full scale being 1.0; e.g. -60 dBFS is 0.001
+pub fn dbfs_to_amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+// Linear power of a level in dBFS, full scale being 1.0; The square of `dbfs_to_amplitude()`, for values that hold
+// power instead of amplitude, like mean squares or the bins of a spectogram scaled to full scale
+pub fn dbfs_to_power(db: f32) -> f32 {
+    10f32.powf(db / 10.0)
+}
+
+// Console output ---------------------------------------------------------------------------------------------------------
+// How much is printed to stdout; Errors are always printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    // Nothing but the final results
+    Quiet,
+    // Progress and status messages
+    Normal,
+    // Additionally per-file timings and sample counts
+    Verbose
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => { Verbosity::Quiet }
+        1 => { Verbosity::Normal }
+        _ => { Verbosity::Verbose }
+    }
+}
+
+pub fn set_verbosity(level: Verbosity) {
+    VERBOSITY.store(level as u8, Ordering::Relaxed);
+}
+
+// print!() unless the verbosity is Quiet
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::types::verbosity() >= $crate::types::Verbosity::Normal { print!($($arg)*); }
+    };
+}
+
+// println!() unless the verbosity is Quiet
+#[macro_export]
+macro_rules! statusln {
+    ($($arg:tt)*) => {
+        if $crate::types::verbosity() >= $crate::types::Verbosity::Normal { println!($($arg)*); }
+    };
+}
+
+// println!() only if the verbosity is Verbose
+#[macro_export]
+macro_rules! verboseln {
+    ($($arg:tt)*) => {
+        if $crate::types::verbosity() >= $crate::types::Verbosity::Verbose { println!($($arg)*); }
+    };
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epsilon_keeps_ratios_finite() {
+        assert!(safe_ratio(1.0, 0.0, DEFAULT_EPSILON).is_finite());
+        assert!(safe_ratio(-1.0, -0.0, DEFAULT_EPSILON).is_finite());
+        assert_eq!(power_to_db(0.0, 0.0, DEFAULT_EPSILON), 0.0);
+        assert!(power_to_db(1.0, 0.0, DEFAULT_EPSILON).is_finite());
+        assert!((power_to_db(100.0, 1.0, 1e-10) - 20.0).abs() < 1e-4);
+        // A larger epsilon pulls quiet ratios towards 0 dB
+        assert!(power_to_db(1e-6, 1e-3, 1e-2).abs() < 1.0);
+    }
+
+    #[test]
+    fn trim_keeps_the_range() {
+        let sample_rate: u32 = 8000;
+        let samples: Vec<f32> = (0..2 * 10 * sample_rate as usize).map(|i| (i / 2) as f32 / 1e5).collect();
+        let mut track: Track = Track::from_buffer(&samples, sample_rate);
+        track.trim(2.0, 4.0).unwrap();
+        assert_eq!(track.samples.len(), 2 * 2 * sample_rate as usize);
+        assert_eq!(track.samples[..2], samples[2 * 2 * sample_rate as usize..][..2]);
+
+        assert!(track.trim(1.0, 3.0).is_err());
+        assert!(track.trim(1.0, 0.5).is_err());
+    }
+
+    #[test]
+    fn graph_data_max() {
+        let data: GraphData = GraphData::new(vec![0.5, 2.25, -1.0, 1.5], String::from("Bass"));
+        assert_eq!(data.max_value(), 2.25);
+        assert_eq!(data.min_value(), -1.0);
+        let mut cached: GraphData = GraphData::new(vec![0.5, 2.25, -1.0, 1.5], String::from("Bass"));
+        assert_eq!(cached.get_max(), data.max_value());
+
+        assert_eq!(GraphData::new(vec![], String::from("Bass")).max_value(), 0.0);
+    }
+
+    #[test]
+    fn smoothing_a_step() {
+        let step: GraphData = GraphData::new(vec![0.0, 0.0, 0.0, 3.0, 3.0, 3.0], String::from("Bass"));
+        let smoothed: GraphData = step.smoothed(3);
+        assert_eq!(smoothed.data, vec![0.0, 0.0, 1.0, 2.0, 3.0, 3.0]);
+        assert_eq!(smoothed.label, "Bass");
+        // The raw data is kept
+        assert_eq!(step.data, vec![0.0, 0.0, 0.0, 3.0, 3.0, 3.0]);
+        assert_eq!(step.smoothed(1).data, step.data);
+    }
+
+    #[test]
+    fn appended_frames_add_up() {
+        let frames = |count: usize, value: f32| -> StereoSpectogram { StereoSpectogram { left: vec![value; 4 * count], right: vec![value; 4 * count], bins: 4 } };
+        let mut first: StereoSpectogram = frames(3, 1.0);
+        first.append(&frames(5, 2.0), 4).unwrap();
+        assert_eq!(first.left.len() / 4, 8);
+        assert_eq!((first.left[11], first.left[12]), (1.0, 2.0));
+
+        let playlist: StereoSpectogram = concat_spectograms(vec![frames(3, 1.0), frames(5, 2.0), frames(2, 3.0)], 4).unwrap();
+        assert_eq!(playlist.right.len() / 4, 10);
+        // Frames of another size don't fit
+        let wider: StereoSpectogram = StereoSpectogram { left: vec![0.0; 8], right: vec![0.0; 8], bins: 8 };
+        assert!(first.append(&wider, 4).is_err());
+        assert_eq!(first.left.len() / 4, 8);
+    }
+
+    #[test]
+    fn grouped_bins_are_summed() {
+        // Two frames of 10 bins holding their index, and frame 1 has 100 added
+        let values: Vec<f32> = (0..20).map(|i| (i % 10) as f32 + 100.0 * (i / 10) as f32).collect();
+        let spec: StereoSpectogram = StereoSpectogram { left: values.clone(), right: values.iter().map(|v| v * 2.0).collect(), bins: 10 };
+
+        let grouped: StereoSpectogram = spec.group_bins(10, 5);
+        assert_eq!(grouped.bins, 2);
+        assert_eq!(grouped.left, vec![10.0, 35.0, 510.0, 535.0]);
+        assert_eq!(grouped.right, vec![20.0, 70.0, 1020.0, 1070.0]);
+
+        // 4 doesn't divide 10, so the last group holds the 2 remaining bins
+        let uneven: StereoSpectogram = spec.group_bins(10, 4);
+        assert_eq!(uneven.bins, 3);
+        assert_eq!(uneven.left.len(), 2 * 3);
+        assert_eq!(uneven.left[0..3], [6.0, 22.0, 17.0]);
+        assert_eq!(spec.group_bins(10, 1).left, spec.left);
+    }
+
+    #[test]
+    fn normalized_frames_sum_to_one() {
+        // A loud frame, a silent one and a quiet one
+        let values: Vec<f32> = vec![1.0, 2.0, 3.0, 4.0, 0.0, 0.0, 0.0, 0.0, 0.001, 0.0, 0.0, 0.003];
+        let mut spec: StereoSpectogram = StereoSpectogram { left: values.clone(), right: values.iter().map(|v| v * 10.0).collect(), bins: 4 };
+        spec.normalize_frames(4);
+
+        for channel in [&spec.left, &spec.right] {
+            assert!(channel.iter().all(|v| v.is_finite()));
+            let sums: Vec<f32> = channel.chunks_exact(4).map(|f| f.iter().sum()).collect();
+            assert!((sums[0] - 1.0).abs() < 1e-6 && (sums[2] - 1.0).abs() < 1e-6, "{:?}", sums);
+            assert_eq!(sums[1], 0.0);
+        }
+        // Only the balance is left, so both channels are the same
+        assert!(spec.left.iter().zip(spec.right.iter()).all(|(l, r)| (l - r).abs() < 1e-6));
+        assert!((spec.left[3] - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn energies_of_alternating_frames() {
+        // 6 frames of 4 bins, loud and silent in turn; The right channel is twice as loud
+        let left: Vec<f32> = (0..24).map(|i| if (i / 4) % 2 == 0 { 1.0 } else { 0.0 }).collect();
+        let spec: StereoSpectogram = StereoSpectogram { right: left.iter().map(|v| v * 2.0).collect(), left, bins: 4 };
+        assert_eq!(spec.frame_energies(4), vec![6.0, 0.0, 6.0, 0.0, 6.0, 0.0]);
+        // An incomplete trailing frame is left out
+        let partial: StereoSpectogram = StereoSpectogram { left: spec.left[..22].to_vec(), right: spec.right[..22].to_vec(), bins: 4 };
+        assert_eq!(partial.frame_energies(4).len(), 5);
+        assert!(spec.frame_energies(0).is_empty());
+    }
+}
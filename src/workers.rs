@@ -0,0 +1,70 @@
+use std::{
+    any::Any, thread, thread::JoinHandle, sync::{Arc, Mutex}
+};
+
+// A unit of work for the worker threads
+pub type Job = Box<dyn FnOnce() + Send>;
+
+// Number of threads to use when the user doesn't ask for a specific number; one per logical core
+pub fn default_thread_count() -> usize {
+    match thread::available_parallelism() {
+        Ok(n)  => { n.get() }
+        Err(_) => { 1 }
+    }
+}
+
+// Runs `jobs` on at most `threads` worker threads; Each worker keeps taking jobs from a shared queue until it is empty.
+// Jobs are started in the order they were given. Returns the workers' handles so the caller can wait on them.
+pub fn spawn_workers(jobs: Vec<Job>, threads: usize) -> Vec<JoinHandle<()>> {
+    let worker_count: usize = threads.clamp(1, jobs.len().max(1));
+
+    // Reverse the queue so that popping returns the jobs in their original order
+    let queue: Arc<Mutex<Vec<Job>>> = Arc::new(Mutex::new(jobs.into_iter().rev().collect()));
+
+    let mut handles: Vec<JoinHandle<()>> = Vec::with_capacity(worker_count);
+    for _ in 0..worker_count {
+        let queue = Arc::clone(&queue);
+        handles.push(thread::spawn(move || {
+            loop {
+                // Only hold the lock while taking a job, not while running it
+                let job = queue.lock().unwrap().pop();
+                match job {
+                    Some(j) => { j(); }
+                    None    => { break; }
+                }
+            }
+        }));
+    }
+
+    handles
+}
+
+// Message of a panic caught with `std::panic::catch_unwind()` or `JoinHandle::join()`; Panics raised with `panic!()`
+// carry a `&str` or a `String`, anything else has no message to show.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(m), _) => { m.to_string() }
+        (_, Some(m)) => { m.clone() }
+        (None, None) => { String::from("no message") }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_worker_runs_every_job_in_order() {
+        let done: Arc<Mutex<Vec<usize>>> = Arc::new(Mutex::new(vec![]));
+        let jobs: Vec<Job> = (0..6).map(|i| {
+            let done = Arc::clone(&done);
+            Box::new(move || done.lock().unwrap().push(i)) as Job
+        }).collect();
+
+        let handles = spawn_workers(jobs, 1);
+        assert_eq!(handles.len(), 1);
+        for h in handles { h.join().unwrap(); }
+        assert_eq!(*done.lock().unwrap(), vec![0, 1, 2, 3, 4, 5]);
+    }
+}
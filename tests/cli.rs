@@ -0,0 +1,262 @@
+// Runs the speccomp binary on stems written to a temporary directory
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// Empty directory of the system's temporary directory for the files of a test
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir: PathBuf = std::env::temp_dir().join(format!("speccomp-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Writes a 16-bit stereo WAV file of a tone at `frequency` Hz, `frames` samples per channel at 8 kHz
+fn write_wav(path: &Path, frequency: f32, gain: f32, frames: usize) {
+    let data_size: u32 = (frames * 4) as u32;
+    let mut bytes: Vec<u8> = Vec::with_capacity(44 + data_size as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&8000u32.to_le_bytes());
+    bytes.extend_from_slice(&32000u32.to_le_bytes());
+    bytes.extend_from_slice(&4u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_size.to_le_bytes());
+    for i in 0..frames {
+        let s: i16 = ((2.0 * std::f32::consts::PI * frequency * i as f32 / 8000.0).sin() * gain * 16000.0) as i16;
+        bytes.extend_from_slice(&s.to_le_bytes());
+        bytes.extend_from_slice(&s.to_le_bytes());
+    }
+    std::fs::write(path, bytes).unwrap();
+}
+
+// Directory `name` in `dir` with the four stems, each a tone of its own scaled by `gain`
+fn write_source(dir: &Path, name: &str, gain: f32) -> String {
+    let source: PathBuf = dir.join(name);
+    std::fs::create_dir_all(&source).unwrap();
+    for (i, stem) in ["bass", "drums", "vocals", "other"].iter().enumerate() {
+        write_wav(&source.join(format!("{}.wav", stem)), 220.0 * (i + 1) as f32, gain, 16000);
+    }
+    source.to_string_lossy().to_string()
+}
+
+fn speccomp(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_speccomp")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+#[test]
+fn quiet_prints_only_the_results() {
+    let dir: PathBuf = scratch_dir("quiet");
+    let (reference, test) = (write_source(&dir, "reference", 0.5), write_source(&dir, "test", 0.4));
+
+    let normal: Output = speccomp(&[&reference, &test, "--fft-size", "1024"]);
+    assert!(normal.status.success(), "{}", stdout(&normal));
+    assert!(stdout(&normal).contains("Spectogram Compare"));
+
+    let quiet: Output = speccomp(&[&reference, &test, "--fft-size", "1024", "--quiet"]);
+    assert!(quiet.status.success(), "{}", stdout(&quiet));
+    let out: String = stdout(&quiet);
+    assert!(out.trim_start().starts_with("-- Final Results"), "{}", out);
+    assert!(!out.contains("Spectogram Compare") && !out.contains("Done processing") && !out.contains('\r'), "{}", out);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// The lines of the SNR section of the output
+fn snr_lines(out: &str) -> Vec<String> {
+    out.lines().skip_while(|l| !l.starts_with("SNR against")).skip(1).take(4).map(|l| l.to_string()).collect()
+}
+
+#[test]
+fn reference_source_is_the_snr_ground_truth() {
+    let dir: PathBuf = scratch_dir("reference");
+    let (reference, estimate) = (write_source(&dir, "reference", 0.5), write_source(&dir, "estimate", 0.25));
+
+    let in_order: String = stdout(&speccomp(&[&reference, &estimate, "--quiet"]));
+    let swapped: String = stdout(&speccomp(&[&estimate, &reference, "--reference", &reference, "--quiet"]));
+    assert!(swapped.contains(&format!("SNR against {}:", reference)), "{}", swapped);
+    assert_eq!(snr_lines(&swapped), snr_lines(&in_order));
+    assert!(snr_lines(&in_order).iter().all(|l| l.ends_with("6.02 dB")), "{:?}", snr_lines(&in_order));
+
+    // Without --reference the first source is the ground truth
+    let unmarked: String = stdout(&speccomp(&[&estimate, &reference, "--quiet"]));
+    assert!(snr_lines(&unmarked).iter().all(|l| l.ends_with(" 0.00 dB") || l.ends_with("-0.00 dB")), "{:?}", snr_lines(&unmarked));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn check_probes_the_stems_without_comparing() {
+    let dir: PathBuf = scratch_dir("check");
+    let (reference, test) = (write_source(&dir, "reference", 0.5), write_source(&dir, "test", 0.4));
+    // The header of this stem claims 10 minutes that the file doesn't have; Only a full decode would notice
+    let vocals: PathBuf = Path::new(&test).join("vocals.wav");
+    let mut bytes: Vec<u8> = std::fs::read(&vocals).unwrap();
+    bytes.truncate(44 + 400);
+    bytes[4..8].copy_from_slice(&(36 + 600u32 * 8000 * 4).to_le_bytes());
+    bytes[40..44].copy_from_slice(&(600u32 * 8000 * 4).to_le_bytes());
+    std::fs::write(&vocals, bytes).unwrap();
+
+    let output: Output = speccomp(&[&reference, &test, "--check"]);
+    let out: String = stdout(&output);
+    assert!(output.status.success(), "{}", out);
+    for stem in ["Bass", "Drums", "Vocals", "Other"] {
+        assert_eq!(out.lines().filter(|l| l.trim_start().starts_with(stem) && l.contains("8000 Hz")).count(), 2, "{}", out);
+    }
+    assert!(out.contains("2.00s") && out.contains("600.00s"), "{}", out);
+    assert!(out.contains("2/2 sources are ready."), "{}", out);
+    assert!(!out.contains("Final Results"), "{}", out);
+
+    // A source without its stems fails the check
+    std::fs::remove_file(Path::new(&reference).join("bass.wav")).unwrap();
+    let output: Output = speccomp(&[&reference, &test, "--check"]);
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("1/2 sources are ready."), "{}", stdout(&output));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn plot_writes_every_graph() {
+    let dir: PathBuf = scratch_dir("plot");
+    let (reference, test) = (write_source(&dir, "reference", 0.5), write_source(&dir, "test", 0.4));
+    // The directory doesn't exist yet
+    let plots: PathBuf = dir.join("out").join("plots");
+    let plots_arg: String = plots.to_string_lossy().to_string();
+
+    let output: Output = speccomp(&[&reference, &test, "--fft-size", "1024", "--plot", &plots_arg, "--plot-spectograms", "--quiet"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    let mut expected: Vec<String> = ["time-error.png", "time-energy.png", "time-error-histogram.png", "freq-error.png", "time-error.csv"]
+        .iter().map(|f| f.to_string()).collect();
+    for stem in ["bass", "drums", "vocals", "other"] {
+        expected.extend(["1", "2", "diff"].iter().map(|s| format!("{}-{}.png", stem, s)));
+    }
+    for file in &expected {
+        let size: u64 = std::fs::metadata(plots.join(file)).map(|m| m.len()).unwrap_or(0);
+        assert!(size > 0, "{} is missing or empty", file);
+    }
+
+    // A file in the way of the directory is a clean error
+    let blocked: String = dir.join("reference").join("bass.wav").join("plots").to_string_lossy().to_string();
+    let output: Output = speccomp(&[&reference, &test, "--plot", &blocked]);
+    assert!(!output.status.success());
+    let out: String = format!("{}{}", stdout(&output), String::from_utf8_lossy(&output.stderr));
+    assert!(out.contains("output directory") && !out.contains("panicked"), "{}", out);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn parallel_runs_give_identical_output() {
+    let dir: PathBuf = scratch_dir("deterministic");
+    let (reference, test) = (write_source(&dir, "reference", 0.5), write_source(&dir, "test", 0.3));
+
+    let run = || -> String {
+        let output: Output = speccomp(&[&reference, &test, "--fft-size", "512", "--threads", "4", "--quiet"]);
+        assert!(output.status.success(), "{}", stdout(&output));
+        stdout(&output)
+    };
+    let first: String = run();
+    assert!(first.contains("Final Results"), "{}", first);
+    for _ in 0..3 {
+        assert_eq!(run(), first);
+    }
+    // The serial path gives the same results
+    let serial: Output = speccomp(&[&reference, &test, "--fft-size", "512", "--serial", "--quiet"]);
+    assert_eq!(stdout(&serial), first);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn candidates_are_ranked_against_the_reference() {
+    let dir: PathBuf = scratch_dir("ranking");
+    let reference: String = write_source(&dir, "reference", 0.5);
+    let (far, near) = (write_source(&dir, "far", 0.1), write_source(&dir, "near", 0.45));
+
+    let output: Output = speccomp(&[&reference, &far, &near, "--fft-size", "1024", "--quiet"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    let out: String = stdout(&output);
+    let rows: Vec<&str> = out.lines().skip_while(|l| !l.starts_with("-- Ranking against")).skip(2).take_while(|l| !l.is_empty()).collect();
+
+    // One row per candidate, the closer one first whatever the order of the arguments
+    assert_eq!(rows.len(), 2, "{}", out);
+    assert!(rows[0].starts_with(&format!("1. {} ", near)), "{}", out);
+    assert!(rows[1].starts_with(&format!("2. {} ", far)), "{}", out);
+    let total = |row: &str| -> f32 { row.split('|').nth(1).unwrap().split_whitespace().next().unwrap().parse().unwrap() };
+    assert!(total(rows[0]) < total(rows[1]));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn spectogram_only_saves_one_image_per_stem() {
+    let dir: PathBuf = scratch_dir("spectogram-only");
+    let source: String = write_source(&dir, "source", 0.5);
+    let images: PathBuf = dir.join("images");
+    let images_arg: String = images.to_string_lossy().to_string();
+
+    let output: Output = speccomp(&[&source, "--spectogram-only", &images_arg, "--fft-size", "1024", "--quiet"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    let mut files: Vec<String> = std::fs::read_dir(&images).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().to_string()).collect();
+    files.sort();
+    assert_eq!(files, vec!["bass.png", "drums.png", "other.png", "vocals.png"]);
+    for file in &files {
+        let bytes: Vec<u8> = std::fs::read(images.join(file)).unwrap();
+        assert!(bytes.starts_with(b"\x89PNG"), "{} isn't a PNG", file);
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn exceeded_threshold_fails_the_run() {
+    let dir: PathBuf = scratch_dir("threshold");
+    let (reference, test) = (write_source(&dir, "reference", 0.5), write_source(&dir, "test", 0.5));
+    // Only the vocals differ
+    write_wav(&dir.join("test").join("vocals.wav"), 660.0, 0.1, 16000);
+
+    let output: Output = speccomp(&[&reference, &test, "--fft-size", "1024", "--quiet", "--threshold", "time=0.05,freq=0.03"]);
+    assert_eq!(output.status.code(), Option::Some(1), "{}", stdout(&output));
+    let out: String = stdout(&output);
+    let failures: Vec<&str> = out.lines().skip_while(|l| *l != "Thresholds exceeded:").skip(1).take_while(|l| !l.is_empty()).collect();
+    assert_eq!(failures.len(), 2, "{}", out);
+    assert!(failures[0].trim().starts_with("Vocals: time error") && failures[0].ends_with("> 0.05"), "{}", out);
+    assert!(failures[1].trim().starts_with("Vocals: freq error") && failures[1].ends_with("> 0.03"), "{}", out);
+
+    // A looser limit for the vocals alone passes
+    let output: Output = speccomp(&[&reference, &test, "--fft-size", "1024", "--quiet", "--threshold", "time=0.05,freq=0.03",
+        "--threshold", "vocals.time=100,vocals.freq=100"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(stdout(&output).contains("All thresholds are met."));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn skipped_pairs_fail_the_run() {
+    let dir: PathBuf = scratch_dir("skipped");
+    let (reference, test) = (write_source(&dir, "reference", 0.5), write_source(&dir, "test", 0.4));
+    let missing: String = dir.join("missing").to_string_lossy().to_string();
+
+    // One pair can't be compared; The other one is still displayed
+    let manifest: String = dir.join("pairs.csv").to_string_lossy().to_string();
+    std::fs::write(&manifest, format!("{},{},good\n{},{},bad\n", reference, test, reference, missing)).unwrap();
+    let output: Output = speccomp(&["--batch", &manifest, "--fft-size", "1024", "--quiet"]);
+    assert_eq!(output.status.code(), Option::Some(1), "{}", stdout(&output));
+    let out: String = stdout(&output);
+    assert!(out.contains("Skipping bad.") && out.contains("1 of 2 pairs were skipped."), "{}", out);
+    assert!(out.lines().any(|l| l.starts_with("good")), "{}", out);
+
+    // None of them can
+    std::fs::write(&manifest, format!("{},{},bad\n", reference, missing)).unwrap();
+    let output: Output = speccomp(&["--batch", &manifest, "--fft-size", "1024", "--quiet"]);
+    assert_eq!(output.status.code(), Option::Some(1), "{}", stdout(&output));
+    assert!(stdout(&output).contains("None of the 1 pairs could be compared."));
+
+    // The same goes for candidates
+    let output: Output = speccomp(&[&reference, &test, &missing, "--fft-size", "1024", "--quiet"]);
+    assert_eq!(output.status.code(), Option::Some(1), "{}", stdout(&output));
+    assert!(stdout(&output).contains("1 of 2 candidates were skipped."));
+    let _ = std::fs::remove_dir_all(&dir);
+}